@@ -0,0 +1,28 @@
+/*
+ * Generate Nixpacks build plans in-process. Strings are NUL-terminated UTF-8, and the strings returned are freed with
+ * nixpacks_string_free.
+ *
+ * `options` is a JSON object of `envs`, `plan`, `configFile`, and `appDir`, or NULL for none. On failure, the functions
+ * return NULL and set `*error` to the message when `error` isn't NULL.
+ */
+
+#ifndef NIXPACKS_H
+#define NIXPACKS_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The build plan of the app at `path`, as JSON. */
+char *nixpacks_generate_build_plan(const char *path, const char *options, char **error);
+
+/* The names of the providers of the app at `path`, as a JSON array. */
+char *nixpacks_get_plan_providers(const char *path, const char *options, char **error);
+
+void nixpacks_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,223 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions, clippy::missing_errors_doc)]
+
+//! A C ABI for generating Nixpacks build plans in-process, for platforms that aren't written in Rust. The functions
+//! take and return JSON, as NUL-terminated UTF-8 strings. See `nixpacks.h`.
+
+use anyhow::{anyhow, Context, Result};
+use nixpacks::nixpacks::plan::{generator::GeneratePlanOptions, BuildPlan};
+use serde::Deserialize;
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+/// The options of a call, like the `nixpacks plan` options of the same names.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct PlanOptions {
+    /// Environment variables, as `NAME=value` or the name of one to take from the calling process.
+    pub envs: Vec<String>,
+    /// A plan merged on top of the one generated, like the `--install-cmd` and other plan options of the CLI.
+    pub plan: Option<BuildPlan>,
+    pub config_file: Option<String>,
+    pub app_dir: Option<String>,
+}
+
+impl PlanOptions {
+    fn generate_plan_options(&self) -> GeneratePlanOptions {
+        GeneratePlanOptions {
+            plan: self.plan.clone(),
+            config_file: self.config_file.clone(),
+            exact_plan: None,
+            app_dir: self.app_dir.clone(),
+        }
+    }
+
+    fn envs(&self) -> Vec<&str> {
+        self.envs.iter().map(String::as_str).collect()
+    }
+}
+
+/// Generates the build plan of the app at `path`, returning it as JSON.
+///
+/// `options` is a JSON object of `envs`, `plan`, `configFile`, and `appDir`, or NULL for none. On failure, NULL is
+/// returned and `*error` is set to the message when `error` isn't NULL. Strings returned through either are freed with
+/// `nixpacks_string_free`.
+///
+/// # Safety
+///
+/// `path` and `options` must be NULL or NUL-terminated strings, and `error` NULL or valid to write a pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn nixpacks_generate_build_plan(
+    path: *const c_char,
+    options: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    call(error, || {
+        let path = read_path(path)?;
+        let options = read_options(options)?;
+        let plan =
+            nixpacks::generate_build_plan(path, options.envs(), &options.generate_plan_options())?;
+        Ok(serde_json::to_string(&plan)?)
+    })
+}
+
+/// Gets the providers of the app at `path`, returning their names as a JSON array, with the same options and errors as
+/// `nixpacks_generate_build_plan`.
+///
+/// # Safety
+///
+/// `path` and `options` must be NULL or NUL-terminated strings, and `error` NULL or valid to write a pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn nixpacks_get_plan_providers(
+    path: *const c_char,
+    options: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    call(error, || {
+        let path = read_path(path)?;
+        let options = read_options(options)?;
+        let providers =
+            nixpacks::get_plan_providers(path, options.envs(), &options.generate_plan_options())?;
+        Ok(serde_json::to_string(&providers)?)
+    })
+}
+
+/// Frees a string returned by the other functions. Passing NULL does nothing.
+///
+/// # Safety
+///
+/// `string` must be NULL or a string returned by this library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nixpacks_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Runs `f`, returning its result as a string owned by the caller, or NULL with the error written to `error`.
+///
+/// Panics are returned as errors too, since unwinding into the caller would abort it.
+unsafe fn call<F: FnOnce() -> Result<String>>(error: *mut *mut c_char, f: F) -> *mut c_char {
+    if !error.is_null() {
+        *error = ptr::null_mut();
+    }
+
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        Err(anyhow!("Nixpacks panicked: {message}"))
+    });
+
+    match result.and_then(|result| Ok(CString::new(result)?)) {
+        Ok(result) => result.into_raw(),
+        Err(e) => {
+            if !error.is_null() {
+                // C strings end at the first NUL, which the message only has if a file of the app did
+                let message = format!("{e:#}").replace('\0', "");
+                *error = CString::new(message).unwrap_or_default().into_raw();
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn read_path<'a>(path: *const c_char) -> Result<&'a str> {
+    if path.is_null() {
+        return Err(anyhow!("path is required"));
+    }
+    CStr::from_ptr(path).to_str().context("path isn't UTF-8")
+}
+
+unsafe fn read_options(options: *const c_char) -> Result<PlanOptions> {
+    if options.is_null() {
+        return Ok(PlanOptions::default());
+    }
+    let options = CStr::from_ptr(options)
+        .to_str()
+        .context("options aren't UTF-8")?;
+    serde_json::from_str(options).context("Parsing options")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn take(string: *mut c_char) -> String {
+        let result = CStr::from_ptr(string).to_str().unwrap().to_string();
+        nixpacks_string_free(string);
+        result
+    }
+
+    #[test]
+    fn test_generate_build_plan() {
+        let path = CString::new("../../examples/node-npm").unwrap();
+        let options = CString::new(r#"{ "envs": ["NODE_ENV=test"] }"#).unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let plan = nixpacks_generate_build_plan(path.as_ptr(), options.as_ptr(), &mut error);
+            assert!(error.is_null());
+            let plan: BuildPlan = serde_json::from_str(&take(plan)).unwrap();
+            assert_eq!(plan.variables.unwrap()["NODE_ENV"], "test");
+        }
+    }
+
+    #[test]
+    fn test_get_plan_providers() {
+        let path = CString::new("../../examples/node-npm").unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let providers = nixpacks_get_plan_providers(path.as_ptr(), ptr::null(), &mut error);
+            assert!(error.is_null());
+            assert_eq!(take(providers), r#"["node"]"#);
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        let path = CString::new("../../examples/node-npm").unwrap();
+        let options = CString::new(r#"{ "unknown": true }"#).unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let plan = nixpacks_generate_build_plan(path.as_ptr(), options.as_ptr(), &mut error);
+            assert!(plan.is_null());
+            assert!(take(error).starts_with("Parsing options"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_path() {
+        let path = CString::new(vec![0xff, 0xfe]).unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let plan = nixpacks_generate_build_plan(path.as_ptr(), ptr::null(), &mut error);
+            assert!(plan.is_null());
+            assert!(take(error).starts_with("path isn't UTF-8"));
+
+            let plan = nixpacks_generate_build_plan(ptr::null(), ptr::null(), &mut error);
+            assert!(plan.is_null());
+            assert_eq!(take(error), "path is required");
+        }
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let result = call(&mut error, || panic!("oops"));
+            assert!(result.is_null());
+            assert_eq!(take(error), "Nixpacks panicked: oops");
+        }
+    }
+}
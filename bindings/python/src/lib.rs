@@ -0,0 +1,84 @@
+//! Python bindings for generating Nixpacks build plans in-process. Plans are returned as the dicts of their JSON.
+
+use nixpacks_core::nixpacks::plan::{generator::GeneratePlanOptions, BuildPlan};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+/// The options of a call, like the `nixpacks plan` options of the same names. `plan` is a dict merged on top of the
+/// plan generated.
+fn plan_options(
+    py: Python<'_>,
+    config_file: Option<String>,
+    app_dir: Option<String>,
+    plan: Option<&PyAny>,
+) -> PyResult<GeneratePlanOptions> {
+    let plan = match plan {
+        Some(plan) => {
+            let json: String = py
+                .import("json")?
+                .call_method1("dumps", (plan,))?
+                .extract()?;
+            Some(
+                serde_json::from_str::<BuildPlan>(&json)
+                    .map_err(|e| PyValueError::new_err(format!("Invalid plan: {e}")))?,
+            )
+        }
+        None => None,
+    };
+
+    Ok(GeneratePlanOptions {
+        plan,
+        config_file,
+        exact_plan: None,
+        app_dir,
+    })
+}
+
+fn to_python<T: serde::Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+/// The build plan of the app at `path`.
+#[pyfunction]
+#[pyo3(signature = (path, envs = Vec::new(), config_file = None, app_dir = None, plan = None))]
+fn generate_build_plan(
+    py: Python<'_>,
+    path: &str,
+    envs: Vec<String>,
+    config_file: Option<String>,
+    app_dir: Option<String>,
+    plan: Option<&PyAny>,
+) -> PyResult<PyObject> {
+    let options = plan_options(py, config_file, app_dir, plan)?;
+    let envs = envs.iter().map(String::as_str).collect();
+    let plan = nixpacks_core::generate_build_plan(path, envs, &options)
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    to_python(py, &plan)
+}
+
+/// The names of the providers of the app at `path`.
+#[pyfunction]
+#[pyo3(signature = (path, envs = Vec::new(), config_file = None, app_dir = None, plan = None))]
+fn get_plan_providers(
+    py: Python<'_>,
+    path: &str,
+    envs: Vec<String>,
+    config_file: Option<String>,
+    app_dir: Option<String>,
+    plan: Option<&PyAny>,
+) -> PyResult<Vec<String>> {
+    let options = plan_options(py, config_file, app_dir, plan)?;
+    let envs = envs.iter().map(String::as_str).collect();
+    nixpacks_core::get_plan_providers(path, envs, &options)
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+#[pymodule]
+fn nixpacks(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(generate_build_plan, module)?)?;
+    module.add_function(wrap_pyfunction!(get_plan_providers, module)?)?;
+    Ok(())
+}
@@ -29,7 +29,11 @@ export const sidebarItems: ISidebarSection[] = [
         href: "/docs/configuration/environment",
       },
       { text: "Procfile", href: "/docs/configuration/procfile" },
+      { text: "Dev Containers", href: "/docs/configuration/devcontainer" },
+      { text: "Tool Versions", href: "/docs/configuration/tool-versions" },
+      { text: "Heroku and Buildpacks", href: "/docs/configuration/buildpacks" },
       { text: "Caching", href: "/docs/configuration/caching" },
+      { text: "Provider Plugins", href: "/docs/configuration/plugins" },
     ],
   },
   { text: "CLI Reference", href: "/docs/cli" },
@@ -51,9 +55,11 @@ export const sidebarItems: ISidebarSection[] = [
       { href: "/docs/providers/node", text: "Node" },
       { href: "/docs/providers/php", text: "PHP" },
       { href: "/docs/providers/python", text: "Python" },
+      { href: "/docs/providers/r", text: "R" },
       { href: "/docs/providers/ruby", text: "Ruby" },
       { href: "/docs/providers/rust", text: "Rust" },
       { href: "/docs/providers/staticfile", text: "Staticfile" },
+      { href: "/docs/providers/staticsite", text: "Static Sites" },
       { href: "/docs/providers/swift", text: "Swift" },
       { href: "/docs/providers/scala", text: "Scala" },
       { href: "/docs/providers/zig-lang", text: "Zig" },
@@ -0,0 +1,4 @@
+const db = require("./db.json");
+
+console.log(db.message);
+console.log(`Node ${process.version}`);
@@ -0,0 +1,3 @@
+const fs = require("fs");
+
+fs.writeFileSync("db.json", JSON.stringify({ message: "Hello from the dev container" }));
@@ -0,0 +1,8 @@
+defmodule ElixirRelease.Application do
+  use Application
+
+  def start(_type, _args) do
+    IO.puts("Hello from Elixir release")
+    Supervisor.start_link([], strategy: :one_for_one)
+  end
+end
@@ -0,0 +1,29 @@
+defmodule ElixirRelease.MixProject do
+  use Mix.Project
+
+  def project do
+    [
+      app: :elixir_release,
+      version: "0.1.0",
+      elixir: "~> 1.12",
+      start_permanent: Mix.env() == :prod,
+      deps: deps(),
+      releases: [
+        hello: [
+          include_executables_for: [:unix]
+        ]
+      ]
+    ]
+  end
+
+  def application do
+    [
+      mod: {ElixirRelease.Application, []},
+      extra_applications: [:logger]
+    ]
+  end
+
+  defp deps do
+    []
+  end
+end
@@ -0,0 +1,15 @@
+package main
+
+/*
+#include <stdio.h>
+
+static void hello() {
+	printf("Hello from cgo!\n");
+	fflush(stdout);
+}
+*/
+import "C"
+
+func main() {
+	C.hello()
+}
@@ -0,0 +1,3 @@
+module go-static
+
+go 1.18
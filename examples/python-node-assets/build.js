@@ -0,0 +1,4 @@
+const fs = require("fs");
+
+fs.mkdirSync("static", { recursive: true });
+fs.writeFileSync("static/message.txt", "Hello from the Node asset pipeline\n");
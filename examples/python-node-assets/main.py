@@ -0,0 +1,4 @@
+with open("static/message.txt") as f:
+    print(f.read().strip())
+
+print("Hello from Python")
@@ -0,0 +1,18 @@
+import Foundation
+import Hummingbird
+
+@main
+struct Server {
+    static func main() throws {
+        let port = Int(ProcessInfo.processInfo.environment["PORT"] ?? "8080") ?? 8080
+        let app = HBApplication(configuration: .init(address: .hostname("0.0.0.0", port: port)))
+
+        app.router.get("/") { _ in
+            "Hello from Hummingbird"
+        }
+
+        print("Hello from Hummingbird, listening on port \(port)")
+        try app.start()
+        app.wait()
+    }
+}
@@ -18,36 +18,78 @@
     clippy::missing_panics_doc,
 )]
 
+#[cfg(feature = "devenv")]
+use crate::nixpacks::devenv::{DevenvOptions, DevenvProvisioner};
+#[cfg(any(feature = "docker", feature = "devenv"))]
+use crate::nixpacks::logger::Logger;
 use crate::nixpacks::{
     app::App,
-    builder::{
-        docker::{docker_image_builder::DockerImageBuilder, DockerBuilderOptions},
-        ImageBuilder,
-    },
+    app_source::AppSource,
+    compose::{parse_services_config, service_name, ComposeService, ServiceConfig},
     environment::Environment,
-    logger::Logger,
+    error::NixpacksError,
     nix::pkg::Pkg,
     plan::{
+        detect::ProviderDetection,
+        explain::PlanExplanation,
         generator::{GeneratePlanOptions, NixpacksBuildPlanGenerator},
         BuildPlan, PlanGenerator,
     },
 };
-use anyhow::{bail, Result};
+#[cfg(feature = "docker")]
+use crate::nixpacks::{
+    builder::{
+        docker::{
+            built_images::{
+                get_build_labels, get_plan_hash, record_build, BuiltImage, APP_LABEL,
+                BUILT_BY_LABEL,
+            },
+            docker_image_builder::DockerImageBuilder,
+            incremental_cache::{IncrementalCache, RemoteCache},
+            progress::LogSink,
+            utils::get_oci_labels,
+            watch::{changes_plan, AppContainer, WatchOptions},
+            DockerBuilderOptions,
+        },
+        BuildResult, ImageBuilder,
+    },
+    compose::{get_built_services, to_compose_yaml},
+    devenv::terminal::is_terminal,
+    nix::check::{check_packages, PackageStatus},
+    plan::APP_DIR,
+    snapshot::{diff, snapshot, Snapshot, WATCH_INTERVAL},
+    NIX_PACKS_VERSION,
+};
+use anyhow::Result;
+#[cfg(feature = "docker")]
+use anyhow::{anyhow, bail, Context};
+#[cfg(feature = "docker")]
+use colored::Colorize;
 use providers::{
     clojure::ClojureProvider, cobol::CobolProvider, crystal::CrystalProvider,
-    csharp::CSharpProvider, dart::DartProvider, deno::DenoProvider, elixir::ElixirProvider,
-    fsharp::FSharpProvider, gleam::GleamProvider, go::GolangProvider,
-    haskell::HaskellStackProvider, java::JavaProvider, lunatic::LunaticProvider,
-    node::NodeProvider, php::PhpProvider, python::PythonProvider, ruby::RubyProvider,
-    rust::RustProvider, scala::ScalaProvider, staticfile::StaticfileProvider, swift::SwiftProvider,
-    zig::ZigProvider, Provider,
+    csharp::CSharpProvider, dart::DartProvider, declarative::DeclarativeProvider,
+    deno::DenoProvider, elixir::ElixirProvider, fsharp::FSharpProvider, gleam::GleamProvider,
+    go::GolangProvider, haskell::HaskellStackProvider, java::JavaProvider,
+    lunatic::LunaticProvider, node::NodeProvider, php::PhpProvider, plugin::PluginProvider,
+    python::PythonProvider, r::RProvider, ruby::RubyProvider, rust::RustProvider,
+    scala::ScalaProvider, staticfile::StaticfileProvider, staticsite::StaticSiteProvider,
+    swift::SwiftProvider, zig::ZigProvider,
 };
+use std::collections::BTreeMap;
+#[cfg(feature = "docker")]
+use std::{fs, process::Command};
+#[cfg(feature = "docker")]
+use tempdir::TempDir;
+#[cfg(feature = "docker")]
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 mod chain;
 #[macro_use]
 pub mod nixpacks;
 pub mod providers;
 
+pub use providers::{Provider, ProviderMetadata};
+
 /// Supplies all currently-defined providers to build plan generators and image builders.
 pub fn get_providers() -> &'static [&'static (dyn Provider)] {
     &[
@@ -64,10 +106,12 @@ pub fn get_providers() -> &'static [&'static (dyn Provider)] {
         &JavaProvider {},
         &LunaticProvider {},
         &ScalaProvider {},
+        &StaticSiteProvider {},
         &PhpProvider {},
         &RubyProvider {},
         &NodeProvider {},
         &PythonProvider {},
+        &RProvider {},
         &RustProvider {},
         &SwiftProvider {},
         &StaticfileProvider {},
@@ -76,48 +120,292 @@ pub fn get_providers() -> &'static [&'static (dyn Provider)] {
     ]
 }
 
+/// The providers to detect apps with: the app's declarative providers first, then provider plugins found on `PATH`, so
+/// in-house providers take precedence, then the built-in providers.
+fn with_custom_providers<'a>(
+    declared: &'a [DeclarativeProvider],
+    plugins: &'a [PluginProvider],
+) -> Vec<&'a dyn Provider> {
+    with_embedded_providers(declared, &[], plugins)
+}
+
+/// Like [`with_custom_providers`], with the providers of a program embedding nixpacks after the app's declarative
+/// providers.
+fn with_embedded_providers<'a>(
+    declared: &'a [DeclarativeProvider],
+    embedded: &[&'a dyn Provider],
+    plugins: &'a [PluginProvider],
+) -> Vec<&'a dyn Provider> {
+    declared
+        .iter()
+        .map(|provider| provider as &dyn Provider)
+        .chain(embedded.iter().copied())
+        .chain(plugins.iter().map(|plugin| plugin as &dyn Provider))
+        .chain(get_providers().iter().copied())
+        .collect()
+}
+
 /// Produces a build plan for the project based on environment variables and CLI options.
-pub fn generate_build_plan(
-    path: &str,
+pub fn generate_build_plan<S: AppSource>(
+    source: S,
     envs: Vec<&str>,
     options: &GeneratePlanOptions,
-) -> Result<BuildPlan> {
-    let app = App::new(path)?;
+) -> Result<BuildPlan, NixpacksError> {
+    let app = source.into_app()?;
     let environment = Environment::from_envs(envs)?;
 
-    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let mut generator = NixpacksBuildPlanGenerator::new(&providers, options.clone());
     let plan = generator.generate_plan(&app, &environment)?;
 
     Ok(plan.0)
 }
 
+/// Produces a build plan like [`generate_build_plan`], detecting the app with `providers` as well, for platforms
+/// embedding nixpacks with their own providers. They're tried before provider plugins and the built-in providers,
+/// and after the providers the app declares itself.
+pub fn generate_build_plan_with_providers<S: AppSource>(
+    source: S,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+    providers: &[&dyn Provider],
+) -> Result<BuildPlan, NixpacksError> {
+    let app = source.into_app()?;
+    let environment = Environment::from_envs(envs)?;
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_embedded_providers(&declared, providers, &plugins);
+    let mut generator = NixpacksBuildPlanGenerator::new(&providers, options.clone());
+    let plan = generator.generate_plan(&app, &environment)?;
+
+    Ok(plan.0)
+}
+
+/// Explains where each package, variable, and command in the project's build plan comes from.
+pub fn explain_build_plan(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<PlanExplanation>, NixpacksError> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let generator = NixpacksBuildPlanGenerator::new(&providers, options.clone());
+    Ok(generator.explain_plan(&app, &environment)?)
+}
+
 /// Get all specified and detected providers for a project.
 pub fn get_plan_providers(
     path: &str,
     envs: Vec<&str>,
     options: &GeneratePlanOptions,
-) -> Result<Vec<String>> {
+) -> Result<Vec<String>, NixpacksError> {
     let app = App::new(path)?;
     let environment = Environment::from_envs(envs)?;
 
-    let generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let generator = NixpacksBuildPlanGenerator::new(&providers, options.clone());
 
-    generator.get_plan_providers(&app, &environment)
+    Ok(generator.get_plan_providers(&app, &environment)?)
 }
 
-/// Builds a Docker image based on environment data and build options from config files or existing build plans.
-pub async fn create_docker_image(
+/// Get the providers for a project, with the files each one detected it by, the version it resolved, and the phases it
+/// adds to the build plan.
+pub fn detect_providers(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<ProviderDetection>, NixpacksError> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let generator = NixpacksBuildPlanGenerator::new(&providers, options.clone());
+
+    Ok(generator.detect_providers(&app, &environment)?)
+}
+
+/// Find the apps in the workspaces of a monorepo that can be built with `--app`, and the providers for each of them.
+///
+/// Packages that no provider detects, or that have no start command, like shared libraries, are left out.
+pub fn get_workspace_apps(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<(String, Vec<String>)>, NixpacksError> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+
+    let mut apps = Vec::new();
+    for dir in NodeProvider::get_workspace_dirs(&app)? {
+        let options = GeneratePlanOptions {
+            app_dir: Some(dir.clone()),
+            ..options.clone()
+        };
+        let mut generator = NixpacksBuildPlanGenerator::new(&providers, options);
+
+        let providers = generator.get_plan_providers(&app, &environment)?;
+        if providers.is_empty() {
+            continue;
+        }
+        let (plan, _) = generator.generate_plan(&app, &environment)?;
+        if plan.start_phase.and_then(|start| start.cmd).is_some() {
+            apps.push((dir, providers));
+        }
+    }
+
+    Ok(apps)
+}
+
+/// Find the services of a `docker-compose.yml` for a repo: the `[services]` table of its config file if it has one, or
+/// else the apps in the workspaces of a monorepo, or else the repo itself. Apps publish the ports of their plans unless
+/// the table sets their ports, and their images are named after the repo and the service.
+pub fn get_compose_services(
     path: &str,
     envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<ComposeService>, NixpacksError> {
+    let app = App::new(path)?;
+    let project = service_name(
+        &std::fs::canonicalize(path)
+            .map_err(anyhow::Error::from)?
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    );
+    let config_file = options
+        .config_file
+        .clone()
+        .unwrap_or_else(|| "nixpacks.toml".to_string());
+    let is_toml = std::path::Path::new(&config_file)
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("toml"));
+    let mut config = if is_toml && app.includes_file(&config_file) {
+        parse_services_config(&app.read_file(&config_file)?)?
+    } else {
+        BTreeMap::new()
+    };
+
+    // Without a services table, each app of a monorepo is a service, or else the repo itself
+    if config.is_empty() {
+        let mut dirs = get_workspace_apps(path, envs.clone(), options)?
+            .into_iter()
+            .map(|(dir, _)| dir)
+            .collect::<Vec<_>>();
+        if dirs.is_empty() {
+            dirs.push(".".to_string());
+        }
+        for dir in dirs {
+            let mut name = match dir.as_str() {
+                "." => project.clone(),
+                dir => service_name(dir.rsplit('/').next().unwrap_or(dir)),
+            };
+            if config.contains_key(&name) {
+                name = service_name(&dir);
+            }
+            config.insert(
+                name,
+                ServiceConfig {
+                    app: Some(dir),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let plan_ports = move |dir: &str| -> Result<Vec<String>, NixpacksError> {
+        let options = GeneratePlanOptions {
+            app_dir: if dir == "." {
+                None
+            } else {
+                Some(dir.to_string())
+            },
+            ..options.clone()
+        };
+        let plan = generate_build_plan(path, envs.clone(), &options)?;
+        Ok(plan
+            .start_phase
+            .and_then(|start| start.ports)
+            .unwrap_or_default())
+    };
+    config
+        .into_iter()
+        .map(|(name, service)| {
+            let ports = match (service.ports, &service.app) {
+                (Some(ports), _) => ports,
+                (None, Some(dir)) => plan_ports(dir)?,
+                (None, None) => Vec::new(),
+            };
+            Ok(ComposeService {
+                image: service.image.unwrap_or_else(|| {
+                    if name == project {
+                        project.clone()
+                    } else {
+                        format!("{project}-{name}")
+                    }
+                }),
+                name,
+                app: service.app,
+                ports,
+                variables: service.variables,
+                depends_on: service.depends_on,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "docker")]
+/// Builds a Docker image based on environment data and build options from config files or existing build plans.
+pub async fn create_docker_image<S: AppSource>(
+    source: S,
+    envs: Vec<&str>,
     plan_options: &GeneratePlanOptions,
     build_options: &DockerBuilderOptions,
-) -> Result<()> {
-    let app = App::new(path)?;
+) -> Result<BuildResult, NixpacksError> {
+    let app = source.into_app()?;
+    // The engine builds from a directory, so the files of an app in memory are written to one first
+    let written_source = if app.is_in_memory() {
+        Some(TempDir::new("nixpacks-source").map_err(anyhow::Error::from)?)
+    } else {
+        None
+    };
+    let app = match &written_source {
+        Some(dir) => {
+            app.write_files(dir.path())?;
+            App::new(&dir.path().to_string_lossy())?
+        }
+        None => app,
+    };
     let environment = Environment::from_envs(envs)?;
     let orig_path = app.source.clone();
 
-    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), plan_options.clone());
-    let (plan, app) = generator.generate_plan(&app, &environment)?;
+    if build_options.prefer_dockerfile && app.includes_file("Dockerfile") {
+        return Ok(build_app_dockerfile(&app, &environment, build_options).await?);
+    }
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let mut generator = NixpacksBuildPlanGenerator::new(&providers, plan_options.clone());
+    let (mut plan, app) = generator.generate_plan(&app, &environment)?;
+    let plan_providers = generator.get_plan_providers(&app, &environment)?;
+    if build_options.cache_only {
+        plan.keep_install_phases();
+    }
 
     if let Ok(subdir) = app.source.strip_prefix(orig_path) {
         if subdir != std::path::Path::new("") {
@@ -125,16 +413,45 @@ pub async fn create_docker_image(
         }
     }
 
+    let mut build_options = build_options.clone();
+    if !build_options.no_oci_labels {
+        let created = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .map_err(anyhow::Error::from)?;
+        let oci_labels = get_oci_labels(&app.source, &build_options.labels, &created);
+        build_options.labels.extend(oci_labels);
+    }
+    // Describe the build for `nixpacks images` and `nixpacks clean`
+    let app_path = app.source.to_string_lossy().to_string();
+    build_options
+        .labels
+        .extend(get_build_labels(&app_path, &plan_providers, &plan)?);
+    let build_options = &build_options;
+
     let logger = Logger::new();
     let builder = DockerImageBuilder::new(logger, build_options.clone());
 
     let phase_count = plan.phases.clone().map_or(0, |phases| phases.len());
     if phase_count > 0 {
-        println!("{}", plan.get_build_string()?);
+        // Leave stdout to the Dockerfile or the build's events
+        if !build_options.print_dockerfile && !build_options.emits_events() {
+            println!("{}", plan.get_build_string()?);
+        }
 
         let start = plan.start_phase.clone().unwrap_or_default();
-        if start.cmd.is_none() && !build_options.no_error_without_start {
-            bail!("No start command could be found")
+        if start.cmd.is_none() && !build_options.no_error_without_start && !build_options.cache_only
+        {
+            return Err(NixpacksError::plan_invalid(
+                "No start command could be found",
+            ));
+        }
+
+        if build_options.cache_only {
+            warn_uncacheable_phases(&plan);
+        }
+
+        if build_options.check_packages {
+            check_plan_packages(&plan)?;
         }
     } else {
         println!("\nNixpacks was unable to generate a build plan for this app.\nPlease check the documentation for supported languages: https://nixpacks.com");
@@ -149,14 +466,437 @@ pub async fn create_docker_image(
             );
         }
 
-        std::process::exit(1);
+        return Err(NixpacksError::NoBuildPlan);
     }
 
-    builder
+    let result = builder
         .create_image(app.source.to_str().unwrap(), &plan, &environment)
         .await?;
+    record_built_image(&result, app_path, plan_providers);
+
+    Ok(result)
+}
+
+#[cfg(feature = "docker")]
+/// Records the image for `nixpacks images`, if one was built. Failing to is only a warning, since the image was built.
+fn record_built_image(result: &BuildResult, app: String, providers: Vec<String>) {
+    let name = match result.tags.first() {
+        Some(name) => name.clone(),
+        None => return,
+    };
+
+    let record = get_plan_hash(&result.plan).and_then(|plan_hash| {
+        record_build(BuiltImage {
+            name,
+            image_id: result.image_id.clone(),
+            tags: result.tags.clone(),
+            app,
+            providers,
+            plan_hash,
+            built_at: OffsetDateTime::now_utc().format(&Rfc3339)?,
+            nixpacks_version: NIX_PACKS_VERSION.to_string(),
+            local: false,
+        })
+    });
+    if let Err(error) = record {
+        eprintln!(
+            "{}",
+            format!(" Warning: Couldn't record the image for nixpacks images: {error:#}")
+                .bright_yellow()
+        );
+    }
+}
+
+#[cfg(feature = "docker")]
+/// Builds a Docker image like [`create_docker_image`], sending the events of the build to `sink` instead of printing
+/// them, for services streaming the build to their own UIs.
+pub async fn create_docker_image_with_logs<S: AppSource>(
+    source: S,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    sink: LogSink,
+) -> Result<BuildResult, NixpacksError> {
+    let build_options = DockerBuilderOptions {
+        log_sink: Some(sink),
+        ..build_options.clone()
+    };
+    create_docker_image(source, envs, plan_options, &build_options).await
+}
+
+#[cfg(feature = "docker")]
+/// Builds only the setup and install phases of an app, like warming the cache, and opens an interactive `shell` in the
+/// image with the app mounted in its directory, to run the build commands by hand. The image is removed once the shell
+/// exits, unless it was given a name.
+pub async fn open_shell(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    shell: &str,
+) -> Result<(), NixpacksError> {
+    let env = build_environment(path, envs, plan_options, build_options, false).await?;
+    let engine = build_options.engine.cli();
+    // The shell exits with the status of the last command run in it, which isn't an error of its own
+    let status = Command::new(engine.run_cli())
+        .args(engine.shell_args(&env.image, &env.source, &env.workdir, shell))
+        .status()
+        .with_context(|| format!("Running {}", engine.run_cli()));
+    env.remove(build_options)?;
+    status?;
+
+    Ok(())
+}
+
+#[cfg(feature = "docker")]
+/// Builds only the setup and install phases of an app like [`open_shell`], copies the app in, and runs `cmd` in the
+/// image, e.g. its tests or migrations in CI before building the final image. Returns the exit code of the command.
+pub async fn exec_command(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    cmd: &[String],
+) -> Result<i32, NixpacksError> {
+    let env = build_environment(path, envs, plan_options, build_options, true).await?;
+    let engine = build_options.engine.cli();
+    let status = Command::new(engine.run_cli())
+        .args(engine.exec_args(&env.image, &env.workdir, cmd, is_terminal()))
+        .status()
+        .with_context(|| format!("Running {}", engine.run_cli()));
+    env.remove(build_options)?;
+
+    // Commands killed by a signal have no exit code
+    Ok(status?.code().unwrap_or(1))
+}
+
+#[cfg(feature = "docker")]
+/// An image of the setup and install phases of an app, for running commands in.
+struct BuildEnvironment {
+    image: String,
+    /// The app's directory on this machine, for mounting in the image.
+    source: String,
+    /// The app's directory in the image.
+    workdir: String,
+}
+
+#[cfg(feature = "docker")]
+impl BuildEnvironment {
+    /// Removes the image, unless it was given a name to keep it by.
+    fn remove(&self, build_options: &DockerBuilderOptions) -> Result<()> {
+        if build_options.name.is_none() {
+            Command::new(build_options.engine.cli().run_cli())
+                .args(["rmi", &self.image])
+                .output()
+                .with_context(|| format!("Removing {}", self.image))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "docker")]
+/// Builds only the setup and install phases of an app, like warming the cache, then copies the app in if `with_source`.
+async fn build_environment(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    with_source: bool,
+) -> Result<BuildEnvironment, NixpacksError> {
+    let source = fs::canonicalize(path).with_context(|| format!("Reading {path}"))?;
+    let build_options = DockerBuilderOptions {
+        cache_only: true,
+        with_source,
+        ..build_options.clone()
+    };
+    let result = create_docker_image(path, envs, plan_options, &build_options).await?;
+    let image = match result.tags.first() {
+        Some(image) => image.clone(),
+        None => return Err(NixpacksError::Other(anyhow!("No image was built to open"))),
+    };
+
+    let workdir = match &plan_options.app_dir {
+        Some(dir) => format!("{APP_DIR}{}", dir.trim_matches('/')),
+        None => APP_DIR.to_string(),
+    };
+    Ok(BuildEnvironment {
+        image,
+        source: source.to_string_lossy().to_string(),
+        workdir,
+    })
+}
+
+#[cfg(feature = "docker")]
+/// Builds the images of the services Nixpacks builds in a compose file in the repo, generating the file from
+/// [`get_compose_services`] first if it doesn't exist, then starts the services with `compose up` and `args`. Returns the
+/// exit code of `compose up`.
+pub async fn compose_up(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    file: &str,
+    args: &[String],
+) -> Result<i32, NixpacksError> {
+    let compose_path = std::path::Path::new(path).join(file);
+    if !compose_path.exists() {
+        let services = get_compose_services(path, envs.clone(), plan_options)?;
+        fs::write(&compose_path, to_compose_yaml(&services)?)
+            .with_context(|| format!("Writing {}", compose_path.display()))?;
+        println!("Generated {}", compose_path.display());
+    }
+    let compose = fs::read_to_string(&compose_path)
+        .with_context(|| format!("Reading {}", compose_path.display()))?;
+
+    for (image, app) in get_built_services(&compose)? {
+        println!("\nBuilding {image} from {app}");
+        let plan_options = GeneratePlanOptions {
+            app_dir: if app == "." { None } else { Some(app) },
+            ..plan_options.clone()
+        };
+        let build_options = DockerBuilderOptions {
+            name: Some(image),
+            ..build_options.clone()
+        };
+        create_docker_image(path, envs.clone(), &plan_options, &build_options).await?;
+    }
+
+    let engine = build_options.engine.cli();
+    let status = Command::new(engine.run_cli())
+        .arg("compose")
+        .arg("--file")
+        .arg(fs::canonicalize(&compose_path).map_err(anyhow::Error::from)?)
+        .arg("up")
+        .args(args)
+        .current_dir(path)
+        .status()
+        .with_context(|| format!("Running {} compose", engine.run_cli()))?;
+
+    // Compose killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(feature = "docker")]
+/// Builds an app and rebuilds it whenever its files change, until the process is interrupted. The plan is only
+/// generated again when a change can affect it, and each build reuses the layers of the one before. Failed builds are
+/// reported without stopping the watch.
+pub async fn watch_app(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    build_options: &DockerBuilderOptions,
+    watch_options: &WatchOptions,
+) -> Result<(), NixpacksError> {
+    let source = fs::canonicalize(path).with_context(|| format!("Reading {path}"))?;
+    let app_name = source
+        .file_name()
+        .map_or_else(
+            || "app".to_string(),
+            |name| name.to_string_lossy().to_lowercase(),
+        )
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+    // Each build replaces the image of the one before, instead of leaving an image behind for every change
+    let build_options = DockerBuilderOptions {
+        name: Some(
+            build_options
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("nixpacks-watch-{app_name}")),
+        ),
+        ..build_options.clone()
+    };
+    let mut container = AppContainer::new(
+        build_options.engine.cli(),
+        format!("nixpacks-watch-{app_name}"),
+    );
+
+    let mut options = plan_options.clone();
+    let mut previous = snapshot_in_background(&source).await?;
+    loop {
+        match create_docker_image(path, envs.clone(), &options, &build_options).await {
+            Ok(result) => {
+                options.exact_plan = Some(result.plan);
+                if watch_options.run {
+                    if let Some(image) = result.tags.first() {
+                        container.restart(image, &watch_options.ports)?;
+                    }
+                }
+            }
+            Err(NixpacksError::Cancelled) => return Err(NixpacksError::Cancelled),
+            Err(error) => eprintln!("{}", format!("Build failed: {error}").red()),
+        }
+
+        println!("\nWatching {path} for changes");
+        let changes = loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            let current = snapshot_in_background(&source).await?;
+            let changes = diff(&previous, &current);
+            previous = current;
+            if !changes.is_empty() {
+                break changes;
+            }
+        };
+
+        if changes_plan(&changes, plan_options.config_file.as_deref()) {
+            println!("The app's config or dependencies changed, generating its plan again");
+            options.exact_plan = plan_options.exact_plan.clone();
+        }
+    }
+}
+
+#[cfg(feature = "docker")]
+/// Snapshots the app's files on the blocking thread pool, so that walking a large app doesn't stall the build and
+/// cancellation running on the runtime.
+async fn snapshot_in_background(source: &std::path::Path) -> Result<Snapshot> {
+    let source = source.to_path_buf();
+    tokio::task::spawn_blocking(move || snapshot(&source)).await?
+}
+
+#[cfg(feature = "docker")]
+/// Removes the cached directories saved by previous builds, in an incremental cache image or in object storage.
+pub fn clear_incremental_cache(
+    image: Option<&str>,
+    remote: Option<&str>,
+) -> Result<(), NixpacksError> {
+    if image.is_none() && remote.is_none() {
+        return Err(NixpacksError::Other(anyhow!(
+            "Pass --incremental-cache-image or --incremental-cache with the cache to clear"
+        )));
+    }
+
+    if let Some(image) = image {
+        IncrementalCache::remove_image(image)?;
+    }
+    if let Some(remote) = remote {
+        RemoteCache::from_url(remote)?.clear()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "docker")]
+/// Warns about the phases that copy the whole app, since their layers are only reused by builds of the same source.
+fn warn_uncacheable_phases(plan: &BuildPlan) {
+    for (name, phase) in plan.phases.clone().unwrap_or_default() {
+        if phase.runs_docker_commands() && phase.only_include_files.is_none() {
+            eprintln!(
+                "{}",
+                format!(
+                    " Warning: The {name} phase copies the whole app, so later builds only reuse it while the app's files stay the same"
+                )
+                .bright_yellow()
+            );
+        }
+    }
+}
+
+#[cfg(feature = "docker")]
+/// Fails before building if a Nix package of the plan can't be installed, and warns about the ones that have to be
+/// built from source. The warnings go to stderr, leaving stdout to the build's events.
+fn check_plan_packages(plan: &BuildPlan) -> Result<()> {
+    let checks = check_packages(plan)?;
+
+    for check in checks
+        .iter()
+        .filter(|check| check.status == PackageStatus::NotCached)
+    {
+        eprintln!(
+            "{}",
+            format!(
+                " Warning: {} from nixpkgs {} isn't in a binary cache, so it's built from source",
+                check.name, check.archive
+            )
+            .bright_yellow()
+        );
+    }
+
+    let unavailable = checks
+        .iter()
+        .filter_map(|check| match check.status {
+            PackageStatus::Missing => Some(format!(
+                "  {}: nixpkgs {} has no package of this name",
+                check.name, check.archive
+            )),
+            PackageStatus::Broken => Some(format!(
+                "  {}: can't be evaluated in nixpkgs {}, it may be marked broken or insecure",
+                check.name, check.archive
+            )),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !unavailable.is_empty() {
+        return Err(NixpacksError::plan_invalid(format!(
+            "These Nix packages can't be installed:\n{}",
+            unavailable.join("\n")
+        ))
+        .into());
+    }
 
     Ok(())
 }
 
+#[cfg(feature = "docker")]
+/// Builds the app's own Dockerfile instead of a build plan, with the same tags, labels, and cache options.
+async fn build_app_dockerfile(
+    app: &App,
+    environment: &Environment,
+    build_options: &DockerBuilderOptions,
+) -> Result<BuildResult> {
+    if build_options.dockerfile_template.is_some()
+        || build_options.squash
+        || build_options.parallel_phases
+        || build_options.uses_incremental_cache()
+    {
+        bail!("--dockerfile-template, --squash, --parallel-phases, --incremental-cache-image, and --incremental-cache change the generated Dockerfile, so can't be combined with --prefer-dockerfile");
+    }
+
+    let mut build_options = build_options.clone();
+    if !build_options.no_oci_labels {
+        let created = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let oci_labels = get_oci_labels(&app.source, &build_options.labels, &created);
+        build_options.labels.extend(oci_labels);
+    }
+    build_options.labels.extend([
+        format!("{BUILT_BY_LABEL}={NIX_PACKS_VERSION}"),
+        format!("{APP_LABEL}={}", app.source.display()),
+    ]);
+
+    if !build_options.print_dockerfile && !build_options.emits_events() {
+        println!("\nBuilding the Dockerfile of the app instead of a Nixpacks plan\n");
+    }
 
+    // The variables are only passed as build arguments, for the ARGs the Dockerfile declares
+    let plan = BuildPlan {
+        variables: Some(Environment::clone_variables(environment)),
+        ..Default::default()
+    };
+
+    let builder = DockerImageBuilder::new(Logger::new(), build_options);
+    builder
+        .create_image(app.source.to_str().unwrap(), &plan, environment)
+        .await
+}
+
+#[cfg(feature = "devenv")]
+/// Provisions remote development environments with the app source and the packages from its build plan.
+pub async fn provision_devenv(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    devenv_options: &DevenvOptions,
+) -> Result<(), NixpacksError> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let declared = DeclarativeProvider::find_providers(&app)?;
+    let plugins = PluginProvider::find_plugins();
+    let providers = with_custom_providers(&declared, &plugins);
+    let mut generator = NixpacksBuildPlanGenerator::new(&providers, plan_options.clone());
+    let (plan, app) = generator.generate_plan(&app, &environment)?;
+
+    let logger = Logger::new();
+    let provisioner = DevenvProvisioner::new(logger, devenv_options.clone());
+    Ok(provisioner
+        .provision(app.source.to_str().unwrap(), &plan)
+        .await?)
+}
@@ -1,36 +1,350 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use clap::{arg, Parser, Subcommand, ValueEnum};
+use colored::Colorize;
 use nixpacks::{
-    create_docker_image, generate_build_plan, get_plan_providers,
+    clear_incremental_cache, compose_up, create_docker_image, detect_providers, exec_command,
+    explain_build_plan, generate_build_plan, get_compose_services, get_plan_providers,
+    get_workspace_apps,
     nixpacks::{
-        builder::docker::DockerBuilderOptions,
-        nix::pkg::Pkg,
+        app::App,
+        builder::docker::{
+            built_images::{get_images_string, list_built_images},
+            clean::{clean, format_size, parse_age, parse_size, CleanOptions},
+            engine::{Engine, ImageOutput, ResourceLimits},
+            kaniko::Kaniko,
+            progress::Progress,
+            timings::Timings,
+            watch::WatchOptions,
+            DockerBuilderOptions, Runtime,
+        },
+        cache_key::get_lockfiles_cache_key,
+        compose::to_compose_yaml,
+        devenv::{
+            app_name,
+            bootstrap::NixInstaller,
+            cloud::{CloudProvider, CreateHostOptions},
+            flake::to_flake_nix,
+            forward::PortForward,
+            DevenvFormat, DevenvOptions, DevenvTarget,
+        },
+        doctor::{run_checks, CheckStatus, DoctorOptions},
+        nix::{pkg::Pkg, NixConfig},
         plan::{
+            diff::get_diff_string,
+            explain::get_explain_string,
             generator::GeneratePlanOptions,
-            phase::{Phase, StartPhase},
-            BuildPlan,
+            init::{to_init_config, InitOverrides},
+            phase::{validate_user, Healthcheck, Phase, StartPhase},
+            validate::validate_plan_file,
+            BuildPlan, DISABLED_PROVIDER_PREFIX,
         },
     },
+    open_shell, provision_devenv, watch_app,
 };
 use std::{
-    collections::hash_map::DefaultHasher,
-    env,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    env, fs,
     hash::{Hash, Hasher},
+    io::{self, Read, Write},
     ops::Deref,
-    string::ToString, io::Read,
+    path::Path,
+    string::ToString,
 };
 
-use ssh2::Session;
-use std::fs::File;
-use std::io::Write;
-use std::net::TcpStream;
-use std::path::Path;
-
 /// The build plan config file format to use.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum PlanFormat {
     Json,
     Toml,
+    Yaml,
+    /// The Dockerfile `nixpacks build` would build from the plan
+    Dockerfile,
+    /// A flake.nix with a devShell and package of the plan's Nix packages
+    Flake,
+}
+
+/// The output format of `nixpacks plan explain`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ExplainFormat {
+    Text,
+    Json,
+}
+
+/// The output format of `nixpacks detect`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum DetectFormat {
+    Text,
+    Json,
+}
+
+/// The output format of `nixpacks plan graph`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+/// The kind of configuration `nixpacks devenv` applies to the remote host.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum DevenvTargetArg {
+    HomeManager,
+    Nixos,
+}
+
+/// What `nixpacks devenv` produces.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum DevenvFormatArg {
+    Config,
+    Flake,
+}
+
+/// The cloud provider `nixpacks devenv` creates a host on.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum CloudProviderArg {
+    Aws,
+    Hetzner,
+}
+
+/// The CLI `nixpacks build` builds the image with.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum EngineArg {
+    Docker,
+    Podman,
+    Buildah,
+}
+
+/// How `nixpacks build` shows the output of the build.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ProgressArg {
+    Auto,
+    Plain,
+    Json,
+}
+
+/// How `nixpacks build --timings` reports where the build's time went.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum TimingsArg {
+    Text,
+    Json,
+}
+
+/// What the default cache key is derived from.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum CacheKeyFromArg {
+    /// The absolute path of the app
+    Path,
+    /// The contents of the app's lockfiles, falling back to its path when it has none
+    Lockfiles,
+}
+
+/// What the final image contains besides the app.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RuntimeArg {
+    Full,
+    Slim,
+}
+
+impl From<RuntimeArg> for Runtime {
+    fn from(runtime: RuntimeArg) -> Self {
+        match runtime {
+            RuntimeArg::Full => Runtime::Full,
+            RuntimeArg::Slim => Runtime::Slim,
+        }
+    }
+}
+
+impl From<EngineArg> for Engine {
+    fn from(engine: EngineArg) -> Self {
+        match engine {
+            EngineArg::Docker => Engine::Docker,
+            EngineArg::Podman => Engine::Podman,
+            EngineArg::Buildah => Engine::Buildah,
+        }
+    }
+}
+
+impl From<ProgressArg> for Progress {
+    fn from(progress: ProgressArg) -> Self {
+        match progress {
+            ProgressArg::Auto => Progress::Auto,
+            ProgressArg::Plain => Progress::Plain,
+            ProgressArg::Json => Progress::Json,
+        }
+    }
+}
+
+/// The installer `nixpacks devenv` uses on hosts without Nix.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum NixInstallerArg {
+    Determinate,
+    Official,
+}
+
+/// The subcommands of `nixpacks plan`, and their arguments.
+#[derive(Subcommand)]
+enum PlanCommands {
+    /// Show how the build plan of an app differs from a plan file or from another app's plan
+    Diff {
+        /// App source
+        path: String,
+
+        /// App source to compare with. Its plan is the new side of the diff
+        #[arg(required_unless_present = "against", conflicts_with = "against")]
+        other_path: Option<String>,
+
+        /// Plan file (JSON, TOML, or YAML) to compare with. It's the old side of the diff
+        #[arg(long)]
+        against: Option<String>,
+    },
+
+    /// Show where each package, variable, and command in the build plan of an app comes from
+    Explain {
+        /// App source
+        path: String,
+
+        /// Print the explanations as text or JSON
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: ExplainFormat,
+    },
+
+    /// Print the graph of phases and the phases they depend on
+    Graph {
+        /// App source
+        path: String,
+
+        /// Print the graph in the Graphviz DOT language or as a Mermaid flowchart
+        #[arg(short, long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+    },
+
+    /// Check a plan or config file for unknown fields, unknown phase dependencies, and dependency cycles
+    Validate {
+        /// Plan or config file (JSON, TOML, or YAML)
+        file: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Build only the setup and install phases of an app, without its source, so later builds start from those layers
+    Warm {
+        /// App source
+        path: String,
+
+        /// Name for the image of the installed packages, to push and pass to builds with --cache-from
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Set target platform for the image
+        #[arg(long)]
+        platform: Vec<String>,
+
+        /// Unique identifier to key cache by. Must match the one later builds use. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
+
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
+
+        /// Image to hold the cached directories between builds.
+        #[arg(long)]
+        incremental_cache_image: Option<String>,
+
+        /// Object storage to hold the cached directories between builds instead of an image, e.g. s3://bucket/prefix or gs://bucket/prefix
+        #[arg(long)]
+        incremental_cache: Option<String>,
+
+        /// Image to consider as cache sources
+        #[arg(long)]
+        cache_from: Option<String>,
+
+        /// Push the image to the registry after building
+        #[arg(long)]
+        push: bool,
+
+        /// Build argument to pass to install commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// BuildKit secret to mount in install commands, e.g. id=npmrc,src=$HOME/.npmrc
+        #[arg(long)]
+        secret: Vec<String>,
+
+        /// How to show the build output: as the engine picks, plain for CI logs, or JSON lines of build events
+        #[arg(long, value_enum, default_value = "auto")]
+        progress: ProgressArg,
+
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
+    },
+
+    /// Remove the cached directories saved by previous builds
+    Clear {
+        /// Incremental cache image to remove
+        #[arg(long)]
+        incremental_cache_image: Option<String>,
+
+        /// Object storage holding the incremental cache to empty, e.g. s3://bucket/prefix or gs://bucket/prefix
+        #[arg(long)]
+        incremental_cache: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ComposeCommands {
+    /// Write a docker-compose.yml with a service for each app, or for each service of the nixpacks.toml services table
+    Generate {
+        /// App source
+        path: String,
+
+        /// Compose file to write, relative to the app source
+        #[arg(long, default_value = "docker-compose.yml")]
+        file: String,
+
+        /// Replace the compose file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Build the services' images with Nixpacks and start them with compose up, generating the compose file if needed
+    Up {
+        /// App source
+        path: String,
+
+        /// Compose file to build and start, relative to the app source
+        #[arg(long, default_value = "docker-compose.yml")]
+        file: String,
+
+        /// Arguments to pass to compose up, after --, e.g. -- --detach
+        #[arg(last = true)]
+        args: Vec<String>,
+
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
+
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
+
+        /// Build argument to pass to install commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// BuildKit secret to mount in install commands, e.g. id=npmrc,src=$HOME/.npmrc
+        #[arg(long)]
+        secret: Vec<String>,
+
+        /// Container engine to build the images and run compose with
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
+
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
+    },
 }
 
 /// Arguments passed to `nixpacks`.
@@ -41,9 +355,13 @@ struct Args {
     #[command(subcommand)]
     command: Commands,
 
-    /// Specify an entire build plan in json format that should be used to configure the build
+    /// Specify an entire build plan in json, toml, or yaml format that should be used to configure the build, or - to read it from stdin
+    #[arg(long, alias = "json-plan", global = true)]
+    plan: Option<String>,
+
+    /// Build exactly this plan (JSON, TOML, or YAML) instead of detecting one, or - to read it from stdin
     #[arg(long, global = true)]
-    json_plan: Option<String>,
+    plan_file: Option<String>,
 
     /// Specify the install command to use
     #[arg(long, short, global = true)]
@@ -57,53 +375,508 @@ struct Args {
     #[arg(long, short, global = true)]
     start_cmd: Option<String>,
 
-    /// Provide additional nix packages to install in the environment
-    #[arg(long, short, global = true)]
-    pkgs: Vec<String>,
+    /// Provide additional nix packages to install in the environment
+    #[arg(long, short, global = true)]
+    pkgs: Vec<String>,
+
+    /// Provide additional apt packages to install in the environment
+    #[arg(long, short, global = true)]
+    apt: Vec<String>,
+
+    /// Provide additional nix libraries to install in the environment
+    #[arg(long, global = true)]
+    libs: Vec<String>,
+
+    /// Provide environment variables to your build
+    #[arg(long, short, global = true)]
+    env: Vec<String>,
+
+    /// Path to config file
+    #[arg(long, short, global = true)]
+    config: Option<String>,
+
+    /// Command that checks the app is healthy, run periodically in the container
+    #[arg(long, global = true)]
+    healthcheck_cmd: Option<String>,
+
+    /// Port the app listens on, exposed by the image instead of the detected ones, e.g. 8080 or 53/udp
+    #[arg(long, global = true)]
+    expose: Vec<String>,
+
+    /// Run the app as this user instead of root, creating it in the image if needed
+    #[arg(long, global = true)]
+    run_as_user: Option<String>,
+
+    /// Image to build on instead of the Nixpacks base image, e.g. an internal mirror. It needs Nix installed
+    #[arg(long, global = true)]
+    base_image: Option<String>,
+
+    /// Revision of nixpkgs to install every phase's Nix packages from, e.g. github:NixOS/nixpkgs/<rev>
+    #[arg(long, global = true)]
+    nixpkgs: Option<String>,
+
+    /// Binary cache to install Nix packages from besides cache.nixos.org, e.g. https://my-team.cachix.org. Can be
+    /// repeated
+    #[arg(long, global = true)]
+    nix_cache: Vec<String>,
+
+    /// Public key the Nix binary caches sign packages with, e.g. my-team.cachix.org-1:... Can be repeated
+    #[arg(long, global = true)]
+    nix_cache_key: Vec<String>,
+
+    /// Subdirectory of a monorepo to detect and build the app in, e.g. apps/api, with the whole repo as the context
+    #[arg(long, global = true)]
+    app: Option<String>,
+
+    /// Build a statically linked app that runs on an empty image, for providers that support it, e.g. Go
+    #[arg(long = "static", global = true)]
+    static_build: bool,
+
+    /// Provider to build the app with instead of the detected one, e.g. node. Can be repeated, with `...` for the
+    /// detected providers and `!name` to never use a provider
+    #[arg(long = "provider", global = true)]
+    providers: Vec<String>,
+}
+
+/// The valid subcommands passed to `nixpacks`, and their arguments.
+#[derive(Subcommand)]
+enum Commands {
+    /// Generate a build plan for an app
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Plan {
+        #[command(subcommand)]
+        command: Option<PlanCommands>,
+
+        /// App source
+        #[arg(required = true)]
+        path: Option<String>,
+
+        /// Specify the output format of the build plan.
+        #[arg(short, long, value_enum, default_value = "json")]
+        format: PlanFormat,
+    },
+
+    /// Provision a development environment for an app on a remote host over SSH
+    Devenv {
+        /// App source
+        path: String,
+
+        /// Hosts to provision. Aliases from ~/.ssh/config are resolved
+        hostnames: Vec<String>,
+
+        /// File listing additional hosts to provision, one per line
+        #[arg(long)]
+        hosts_file: Option<String>,
+
+        /// TOML inventory of hosts to provision, with per-host users, keys, ports, and workdirs
+        #[arg(long)]
+        inventory: Option<String>,
+
+        /// Maximum number of hosts to provision at once
+        #[arg(long, default_value_t = 4)]
+        parallel: usize,
+
+        /// User to log in as on the remote host
+        #[arg(long)]
+        ssh_user: Option<String>,
+
+        /// Private key to authenticate with instead of ssh-agent
+        #[arg(long)]
+        identity_file: Option<String>,
+
+        /// SSH port of the remote host
+        #[arg(long)]
+        port: Option<u16>,
+
+        /// Apply the packages with home-manager, or as a NixOS module with nixos-rebuild
+        #[arg(long, value_enum, default_value = "home-manager")]
+        target: DevenvTargetArg,
+
+        /// Directory on the remote host to sync the app to. Defaults to ~/<app directory name>
+        #[arg(long)]
+        workdir: Option<String>,
+
+        /// Print the generated config and remote commands without connecting to the host
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Installer to use on hosts that don't have Nix yet
+        #[arg(long, value_enum, default_value = "determinate")]
+        nix_installer: NixInstallerArg,
+
+        /// Apply Nix config to the hosts, or also write a flake.nix devShell into the app directory
+        #[arg(long, value_enum, default_value = "config")]
+        format: DevenvFormatArg,
+
+        /// Forward a local port to the provisioned host after provisioning, e.g. 3000 or 8080:3000
+        #[arg(long = "forward", value_name = "LOCAL:REMOTE")]
+        forwards: Vec<String>,
+
+        /// Upload and apply the config even if the host already has it
+        #[arg(long)]
+        force: bool,
+
+        /// Write a shell.nix and .envrc into the app directory for this machine instead of provisioning hosts
+        #[arg(long)]
+        local: bool,
+
+        /// Open a shell in the app directory on the host once it's provisioned
+        #[arg(long)]
+        attach: bool,
+
+        /// Account to set the environment up for on the remote host. Defaults to the SSH user
+        #[arg(long)]
+        remote_user: Option<String>,
+
+        /// Home directory of the remote user. Detected over SSH by default
+        #[arg(long)]
+        remote_home: Option<String>,
+
+        /// Ask for a password to log in with, for hosts that don't allow key authentication. Also read from SSH_PASSWORD
+        #[arg(long)]
+        password_prompt: bool,
+
+        /// Create a host with a cloud provider's CLI and provision it
+        #[arg(long, value_enum)]
+        create_host: Option<CloudProviderArg>,
+
+        /// Instance or server type for --create-host
+        #[arg(long)]
+        instance_type: Option<String>,
+
+        /// Name of an SSH key registered with the cloud provider to authorize on the created host
+        #[arg(long)]
+        ssh_key_name: Option<String>,
+
+        /// Keep running after provisioning and sync changed files to the host over SFTP
+        #[arg(long)]
+        watch: bool,
+    },
+
+    /// List all of the providers that will be used to build the app
+    Detect {
+        /// App source
+        path: String,
+
+        /// List the apps in the npm, Yarn, pnpm, or Nx workspaces of a monorepo and their providers instead
+        #[arg(long)]
+        all_apps: bool,
+
+        /// Print the names of the providers, or JSON with the files each one detected the app by, the version it
+        /// resolved, and the phases it adds
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: DetectFormat,
+    },
+
+    /// Generate the Dockerfile for an app, without building it
+    Dockerfile {
+        /// App source
+        path: String,
+
+        /// Write the Dockerfile and the files it copies into the image to this directory, instead of printing it
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Handlebars-style template laying out the Dockerfile, with {{phases}}, {{start}}, and the other sections
+        #[arg(long)]
+        dockerfile_template: Option<String>,
+
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
+
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
+
+        /// Disable building with the cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Run the install and build commands in a single layer
+        #[arg(long)]
+        squash: bool,
+
+        /// Run phases that don't depend on each other, like the installs of several providers, in parallel stages
+        #[arg(long)]
+        parallel_phases: bool,
+
+        /// What the final image contains: the full build environment, or only the app and its Nix packages
+        #[arg(long, value_enum, default_value = "full")]
+        runtime: RuntimeArg,
+    },
+
+    /// Build an app
+    Build {
+        /// App source
+        path: String,
+
+        /// Name for the built image
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Procfile process to start the image with, e.g. worker, instead of web
+        #[arg(long)]
+        process: Option<String>,
+
+        /// Save output directory instead of building it with Docker
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Print the generated Dockerfile to stdout
+        #[arg(short, long, hide = true)]
+        dockerfile: bool,
+
+        /// Additional tags to add to the output image
+        #[arg(short, long)]
+        tag: Vec<String>,
+
+        /// Additional labels to add to the output image
+        #[arg(short, long)]
+        label: Vec<String>,
+
+        /// Don't label the image with the git revision and remote it was built from
+        #[arg(long)]
+        no_oci_labels: bool,
+
+        /// Set target platform for your output image
+        #[arg(long)]
+        platform: Vec<String>,
+
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
+
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
+
+        /// Output Nixpacks related files to the current directory
+        #[arg(long)]
+        current_dir: bool,
+
+        /// Disable building with the cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Image to hold the cached directories between builds.
+        #[arg(long)]
+        incremental_cache_image: Option<String>,
+
+        /// Object storage to hold the cached directories between builds instead of an image, e.g. s3://bucket/prefix or gs://bucket/prefix
+        #[arg(long)]
+        incremental_cache: Option<String>,
+
+        /// Image to consider as cache sources
+        #[arg(long)]
+        cache_from: Option<String>,
+
+        /// Enable writing cache metadata into the output image
+        #[arg(long)]
+        inline_cache: bool,
+
+        /// Do not error when no start command can be found
+        #[arg(long)]
+        no_error_without_start: bool,
+
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
+
+        /// Build with Docker on this host over SSH. Can be user@host or an alias from ~/.ssh/config
+        #[arg(long)]
+        remote_host: Option<String>,
+
+        /// Push the image and its tags to the registry after building
+        #[arg(long)]
+        push: bool,
+
+        /// Build argument to pass to install and build commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// BuildKit secret to mount in install and build commands, e.g. id=npmrc,src=$HOME/.npmrc
+        #[arg(long)]
+        secret: Vec<String>,
+
+        /// SSH agent socket or key to expose to install and build commands, e.g. default
+        #[arg(long)]
+        ssh: Vec<String>,
+
+        /// Network for install and build commands: host, none, or the name of a Docker network
+        #[arg(long)]
+        network: Option<String>,
+
+        /// Memory limit for install and build commands, e.g. 2g
+        #[arg(long)]
+        memory: Option<String>,
+
+        /// Number of CPUs install and build commands can use, e.g. 1.5
+        #[arg(long)]
+        cpus: Option<f64>,
+
+        /// Run the install and build commands in a single layer
+        #[arg(long)]
+        squash: bool,
+
+        /// Run phases that don't depend on each other, like the installs of several providers, in parallel stages
+        #[arg(long)]
+        parallel_phases: bool,
+
+        /// Build the app's own Dockerfile, if it has one, instead of generating one
+        #[arg(long)]
+        prefer_dockerfile: bool,
+
+        /// What the final image contains: the full build environment, or only the app and its Nix packages
+        #[arg(long, value_enum, default_value = "full")]
+        runtime: RuntimeArg,
+
+        /// Container engine to build the image with
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
+
+        /// Write the image to a file, e.g. oci:./image.tar
+        #[arg(long)]
+        output: Option<String>,
+
+        /// Handlebars-style template laying out the Dockerfile, with {{phases}}, {{start}}, and the other sections
+        #[arg(long)]
+        dockerfile_template: Option<String>,
+
+        /// How to show the build output: as the engine picks, plain for CI logs, or JSON lines of build events
+        #[arg(long, value_enum, default_value = "auto")]
+        progress: ProgressArg,
+
+        /// Check that every Nix package exists, and warn about ones no binary cache has, before building. Needs Nix
+        #[arg(long)]
+        check_packages: bool,
+
+        /// Report the time each phase and step took, and which were cached, as a table or JSON after the build
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+        timings: Option<TimingsArg>,
+
+        /// Build with kaniko, directly when running in the kaniko image and otherwise in a local container
+        #[arg(long)]
+        kaniko: bool,
+
+        /// Write a Kubernetes Job spec that builds the --out directory with kaniko to this file, instead of building
+        #[arg(long)]
+        kaniko_job: Option<String>,
+
+        /// Where the kaniko Job downloads the build context from, e.g. s3://bucket/app.tar.gz
+        #[arg(long)]
+        kaniko_context: Option<String>,
+
+        /// Write the image ID, digest, tags, size, build time, and plan to this file as JSON after the build
+        #[arg(long)]
+        metadata_file: Option<String>,
+    },
+
+    /// Warm or clear the caches builds start from
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Build only the setup and install phases of an app and open a shell in them, with the app mounted, to debug its
+    /// build commands
+    Shell {
+        /// App source
+        path: String,
+
+        /// Name for the image, to keep it after the shell exits
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// Shell to run in the image
+        #[arg(long, default_value = "bash")]
+        shell: String,
+
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
+
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
+
+        /// Build argument to pass to install commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// BuildKit secret to mount in install commands, e.g. id=npmrc,src=$HOME/.npmrc
+        #[arg(long)]
+        secret: Vec<String>,
+
+        /// Container engine to build the image with
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
+
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
+    },
+
+    /// Build an app's setup and install phases and run a command in them with the app mounted, exiting with its code
+    Exec {
+        /// App source
+        path: String,
+
+        /// Command to run, after --, e.g. -- npm test
+        #[arg(last = true, required = true)]
+        cmd: Vec<String>,
+
+        /// Name for the image, to keep it after the command exits
+        #[arg(short, long)]
+        name: Option<String>,
 
-    /// Provide additional apt packages to install in the environment
-    #[arg(long, short, global = true)]
-    apt: Vec<String>,
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
 
-    /// Provide additional nix libraries to install in the environment
-    #[arg(long, global = true)]
-    libs: Vec<String>,
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
 
-    /// Provide environment variables to your build
-    #[arg(long, short, global = true)]
-    env: Vec<String>,
+        /// Build argument to pass to install commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
 
-    /// Path to config file
-    #[arg(long, short, global = true)]
-    config: Option<String>,
-}
+        /// BuildKit secret to mount in install commands, e.g. id=npmrc,src=$HOME/.npmrc
+        #[arg(long)]
+        secret: Vec<String>,
 
-/// The valid subcommands passed to `nixpacks`, and their arguments.
-#[derive(Subcommand)]
-enum Commands {
-    /// Generate a build plan for an app
-    Plan {
-        /// App source
-        path: String,
+        /// Container engine to build the image with
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
 
-        /// Specify the output format of the build plan.
-        #[arg(short, long, value_enum, default_value = "json")]
-        format: PlanFormat,
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
     },
-    Devenv {
-        /// App source
-        path: String,
-        hostname: String,
+
+    /// Generate a docker-compose.yml for the apps of a repo, and build and start its services
+    Compose {
+        #[command(subcommand)]
+        command: ComposeCommands,
     },
 
-    /// List all of the providers that will be used to build the app
-    Detect {
+    /// Detect an app's providers and write a commented nixpacks.toml with the overrides chosen interactively
+    Init {
         /// App source
         path: String,
+
+        /// Write the config without asking for any overrides
+        #[arg(long, short)]
+        yes: bool,
+
+        /// Replace the app's nixpacks.toml if it already has one
+        #[arg(long)]
+        force: bool,
     },
 
-    /// Build an app
-    Build {
+    /// Build an app and rebuild it when its files change, optionally running the latest image
+    Watch {
         /// App source
         path: String,
 
@@ -111,57 +884,89 @@ enum Commands {
         #[arg(short, long)]
         name: Option<String>,
 
-        /// Save output directory instead of building it with Docker
-        #[arg(short, long)]
-        out: Option<String>,
+        /// Run the image after each build, restarting it when the app is rebuilt
+        #[arg(long)]
+        run: bool,
 
-        /// Print the generated Dockerfile to stdout
-        #[arg(short, long, hide = true)]
-        dockerfile: bool,
+        /// Port to publish from the running image, e.g. 3000:3000. Can be repeated
+        #[arg(long, requires = "run")]
+        port: Vec<String>,
 
-        /// Additional tags to add to the output image
-        #[arg(short, long)]
-        tag: Vec<String>,
+        /// Unique identifier to key cache by. Defaults to the current directory
+        #[arg(long)]
+        cache_key: Option<String>,
 
-        /// Additional labels to add to the output image
-        #[arg(short, long)]
-        label: Vec<String>,
+        /// What the default cache key is derived from, when --cache-key isn't passed
+        #[arg(long, value_enum, default_value = "path")]
+        cache_key_from: CacheKeyFromArg,
 
-        /// Set target platform for your output image
+        /// Build argument to pass to install and build commands without setting it in the image, e.g. NPM_TOKEN=abc
+        #[arg(long = "build-arg", value_name = "KEY=VALUE")]
+        build_args: Vec<String>,
+
+        /// BuildKit secret to mount in install and build commands, e.g. id=npmrc,src=$HOME/.npmrc
         #[arg(long)]
-        platform: Vec<String>,
+        secret: Vec<String>,
 
-        /// Unique identifier to key cache by. Defaults to the current directory
+        /// Container engine to build the image with
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
+
+        /// Display more info during build
+        #[arg(long, short)]
+        verbose: bool,
+    },
+
+    /// Check the container engine, network access, and devenv hosts, and print how to fix what doesn't work
+    Doctor {
+        /// Container engine to check
+        #[arg(long, value_enum, default_value = "docker")]
+        engine: EngineArg,
+
+        /// Devenv hosts to check SSH access to. Aliases from ~/.ssh/config are resolved
+        hostnames: Vec<String>,
+
+        /// File listing additional hosts to check, one per line
         #[arg(long)]
-        cache_key: Option<String>,
+        hosts_file: Option<String>,
 
-        /// Output Nixpacks related files to the current directory
+        /// TOML inventory of hosts to check, with per-host users, keys, and ports
         #[arg(long)]
-        current_dir: bool,
+        inventory: Option<String>,
 
-        /// Disable building with the cache
+        /// SSH user to connect as. Defaults to the one in ~/.ssh/config, or `ubuntu`
         #[arg(long)]
-        no_cache: bool,
+        ssh_user: Option<String>,
 
-        /// Image to hold the cached directories between builds.
+        /// Private key for SSH authentication. Defaults to ssh-agent
         #[arg(long)]
-        incremental_cache_image: Option<String>,
+        identity_file: Option<String>,
 
-        /// Image to consider as cache sources
+        /// SSH port of the hosts
         #[arg(long)]
-        cache_from: Option<String>,
+        port: Option<u16>,
+    },
 
-        /// Enable writing cache metadata into the output image
+    /// List the images Nixpacks built, with the app, providers, and plan each was built from
+    Images {
+        /// Print the images as JSON
         #[arg(long)]
-        inline_cache: bool,
+        json: bool,
+    },
 
-        /// Do not error when no start command can be found
+    /// Remove the images Nixpacks built, unused incremental cache images, and leftover temp build directories
+    Clean {
+        /// List what would be removed without removing it
         #[arg(long)]
-        no_error_without_start: bool,
+        dry_run: bool,
 
-        /// Display more info during build
-        #[arg(long, short)]
-        verbose: bool,
+        /// Only remove what was created at least this long ago, e.g. 30m, 12h, 7d, or 2w
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Only remove what takes at least this much space, e.g. 500MB or 1.5GB
+        #[arg(long)]
+        larger_than: Option<String>,
     },
 }
 
@@ -194,115 +999,402 @@ async fn main() -> Result<()> {
         build.cmds = Some(vec![build_cmds]);
         cli_plan.add_phase(build);
     }
-    if let Some(start_cmd) = args.start_cmd {
-        let start = StartPhase::new(start_cmd);
+    if args.start_cmd.is_some()
+        || args.run_as_user.is_some()
+        || args.healthcheck_cmd.is_some()
+        || !args.expose.is_empty()
+    {
+        if let Some(user) = &args.run_as_user {
+            validate_user(user)?;
+        }
+        let start = StartPhase {
+            cmd: args.start_cmd,
+            user: args.run_as_user,
+            healthcheck: args.healthcheck_cmd.map(|cmd| Healthcheck {
+                cmd: Some(cmd),
+                ..Default::default()
+            }),
+            ports: if args.expose.is_empty() {
+                None
+            } else {
+                Some(args.expose)
+            },
+            ..Default::default()
+        };
         cli_plan.set_start_phase(start);
     }
+    cli_plan.build_image = args.base_image;
+    cli_plan.nixpkgs = args.nixpkgs;
+    if !args.nix_cache.is_empty() || !args.nix_cache_key.is_empty() {
+        // Keep the caches from the config file as well
+        let with_configured = |values: Vec<String>| {
+            (!values.is_empty()).then(|| {
+                let mut values = values;
+                values.insert(0, "...".to_string());
+                values
+            })
+        };
+        cli_plan.nix = Some(NixConfig {
+            substituters: with_configured(args.nix_cache),
+            trusted_public_keys: with_configured(args.nix_cache_key),
+        });
+    }
+    if !args.providers.is_empty() {
+        // Only disabling providers keeps the detected ones
+        let mut providers = args.providers;
+        if providers
+            .iter()
+            .all(|name| name.starts_with(DISABLED_PROVIDER_PREFIX))
+        {
+            providers.insert(0, "...".to_string());
+        }
+        cli_plan.providers = Some(providers);
+    }
 
-    let json_plan = args.json_plan.map(BuildPlan::from_json).transpose()?;
+    let plan = args
+        .plan
+        .map(|plan| {
+            if plan == "-" {
+                BuildPlan::parse(read_stdin()?)
+            } else {
+                BuildPlan::parse(plan)
+            }
+        })
+        .transpose()?;
+    let exact_plan = args
+        .plan_file
+        .map(|file| {
+            if file == "-" {
+                BuildPlan::parse(read_stdin()?)
+            } else {
+                BuildPlan::from_file(&file)
+            }
+        })
+        .transpose()?;
 
-    // Merge the CLI build plan with the json build plan
-    let cli_plan = if let Some(json_plan) = json_plan {
-        BuildPlan::merge_plans(&[json_plan, cli_plan])
+    // Merge the CLI build plan with the json or yaml build plan
+    let cli_plan = if let Some(plan) = plan {
+        BuildPlan::merge_plans(&[plan, cli_plan])
     } else {
         cli_plan
     };
 
-    let env: Vec<&str> = args.env.iter().map(|e| e.deref()).collect();
+    let mut env: Vec<&str> = args.env.iter().map(|e| e.deref()).collect();
+    if args.static_build {
+        env.push("NIXPACKS_STATIC=1");
+    }
     let options = GeneratePlanOptions {
         plan: Some(cli_plan),
         config_file: args.config,
+        exact_plan,
+        app_dir: args.app,
     };
 
     match args.command {
         // Produce a build plan for a project and print it to stdout.
-        Commands::Plan { path, format } => {
+        Commands::Plan {
+            command:
+                Some(PlanCommands::Diff {
+                    path,
+                    other_path,
+                    against,
+                }),
+            ..
+        } => {
+            let plan = generate_build_plan(&path, env.clone(), &options)?;
+            let (old, new) = match (other_path, against) {
+                (Some(other_path), _) => (plan, generate_build_plan(&other_path, env, &options)?),
+                (None, Some(against)) => (BuildPlan::from_file(&against)?, plan),
+                (None, None) => unreachable!(),
+            };
+
+            println!("{}", get_diff_string(&old.diff(&new)?));
+        }
+        Commands::Plan {
+            command: Some(PlanCommands::Explain { path, format }),
+            ..
+        } => {
+            let explanations = explain_build_plan(&path, env, &options)?;
+            match format {
+                ExplainFormat::Text => println!("{}", get_explain_string(&explanations)),
+                ExplainFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&explanations)?);
+                }
+            }
+        }
+        Commands::Plan {
+            command: Some(PlanCommands::Graph { path, format }),
+            ..
+        } => {
+            let plan = generate_build_plan(&path, env, &options)?;
+            match format {
+                GraphFormat::Dot => println!("{}", plan.to_dot()),
+                GraphFormat::Mermaid => println!("{}", plan.to_mermaid()),
+            }
+        }
+        Commands::Plan {
+            command: Some(PlanCommands::Validate { file }),
+            ..
+        } => {
+            let errors = validate_plan_file(&file)?;
+            if !errors.is_empty() {
+                for error in &errors {
+                    println!("{}", error.to_string().red());
+                }
+                bail!("Found {} problem(s) in {}", errors.len(), file);
+            }
+
+            println!("{file} is valid");
+        }
+        Commands::Plan { path, format, .. } => {
+            let path = path.expect("path is required without a subcommand");
+            if format == PlanFormat::Dockerfile {
+                // Generated the same way as by `nixpacks build`, including its default cache key
+                let build_options = &DockerBuilderOptions {
+                    print_dockerfile: true,
+                    cache_key: get_default_cache_key(&path, CacheKeyFromArg::Path)?,
+                    no_error_without_start: true,
+                    ..Default::default()
+                };
+                create_docker_image(&path, env, &options, build_options).await?;
+                return Ok(());
+            }
+
             let plan = generate_build_plan(&path, env, &options)?;
 
             let plan_s = match format {
                 PlanFormat::Json => plan.to_json()?,
                 PlanFormat::Toml => plan.to_toml()?,
+                PlanFormat::Yaml => plan.to_yaml()?,
+                PlanFormat::Flake => {
+                    to_flake_nix(&plan, &app_name(&Path::new(&path).canonicalize()?))
+                }
+                PlanFormat::Dockerfile => unreachable!(),
             };
 
             println!("{plan_s}");
         }
 
-        Commands::Devenv { path, hostname } => {
-            let plan = generate_build_plan(&path, env, &options)?;
-            // let plan_s = plan.to_json()?;
-            let packages = plan.get_packages();
-            let home_manager_config = to_home_manager_nix(packages);
-            // print home manager config
-            print!("{home_manager_config}");
-            // upload home_manager_config to remote host
-            print!("uploading home manager config to remote host");
-
-            let tcp = TcpStream::connect(hostname+":22").unwrap();
-            let mut sess = Session::new().unwrap();
-                // Use the TCP stream to start an SSH session
-            sess.set_tcp_stream(tcp);
-            sess.handshake().unwrap();
-
-            // Authenticate using a private key
-            let key_path = Path::new("/Users/robertwendt/.ssh/nixos");
-            // let mut private_key = File::open(&key_path).unwrap();
-            sess.userauth_pubkey_file("ubuntu", None, key_path, None).unwrap();
-            assert!(sess.authenticated());
-
-            let mut f = sess.scp_send(Path::new("/home/ubuntu/.config/home-manager/home.nix"), 0o644, home_manager_config.clone().as_bytes().len() as u64, None).unwrap();
-            
-            f.write_all(home_manager_config.clone().as_bytes()).unwrap();
-            
-            print!("uploaded home manager config to remote host");
-
-            print!("run home manager switch on remote host");
-            let mut channel = sess.channel_session().unwrap();
-            channel.exec("nix-shell '<home-manager>' -A install").unwrap();
-            let mut s = String::new();
-            channel.read_to_string(&mut s).unwrap();
-            print!("{}", s);
-            channel.wait_close().unwrap();
-            print!("home manager switch done");
+        // Sync an app to remote hosts and install its packages there.
+        Commands::Devenv {
+            path,
+            hostnames,
+            hosts_file,
+            inventory,
+            parallel,
+            ssh_user,
+            identity_file,
+            port,
+            target,
+            workdir,
+            dry_run,
+            nix_installer,
+            format,
+            forwards,
+            force,
+            local,
+            attach,
+            remote_user,
+            remote_home,
+            password_prompt,
+            create_host,
+            instance_type,
+            ssh_key_name,
+            watch,
+        } => {
+            let forwards = forwards
+                .iter()
+                .map(|forward| forward.parse::<PortForward>())
+                .collect::<Result<Vec<_>>>()?;
 
+            let devenv_options = &DevenvOptions {
+                hostnames,
+                hosts_file,
+                inventory,
+                parallelism: parallel,
+                ssh_user,
+                identity_file,
+                port,
+                target: match target {
+                    DevenvTargetArg::HomeManager => DevenvTarget::HomeManager,
+                    DevenvTargetArg::Nixos => DevenvTarget::Nixos,
+                },
+                workdir,
+                dry_run,
+                nix_installer: match nix_installer {
+                    NixInstallerArg::Determinate => NixInstaller::Determinate,
+                    NixInstallerArg::Official => NixInstaller::Official,
+                },
+                format: match format {
+                    DevenvFormatArg::Config => DevenvFormat::Config,
+                    DevenvFormatArg::Flake => DevenvFormat::Flake,
+                },
+                forwards,
+                force,
+                local,
+                attach,
+                remote_user,
+                remote_home,
+                password_prompt,
+                create_host: create_host.map(|provider| match provider {
+                    CloudProviderArg::Aws => CloudProvider::Aws,
+                    CloudProviderArg::Hetzner => CloudProvider::Hetzner,
+                }),
+                create_host_options: CreateHostOptions {
+                    instance_type,
+                    ssh_key_name,
+                },
+                watch,
+            };
+            provision_devenv(&path, env, &options, devenv_options).await?;
         }
 
-
         // Detect which providers should be used to build a project and print them to stdout.
-        Commands::Detect { path } => {
-            let providers = get_plan_providers(&path, env, &options)?;
-            println!("{}", providers.join(", "));
+        Commands::Detect {
+            path,
+            all_apps,
+            format,
+        } => {
+            if all_apps {
+                let apps = get_workspace_apps(&path, env.clone(), &options)?;
+                match format {
+                    DetectFormat::Text => {
+                        for (dir, providers) in apps {
+                            println!("{dir}: {}", providers.join(", "));
+                        }
+                    }
+                    DetectFormat::Json => {
+                        let mut detections = BTreeMap::new();
+                        for (dir, _) in apps {
+                            let options = GeneratePlanOptions {
+                                app_dir: Some(dir.clone()),
+                                ..options.clone()
+                            };
+                            detections.insert(dir, detect_providers(&path, env.clone(), &options)?);
+                        }
+                        println!("{}", serde_json::to_string_pretty(&detections)?);
+                    }
+                }
+                return Ok(());
+            }
+
+            match format {
+                DetectFormat::Text => {
+                    let providers = get_plan_providers(&path, env, &options)?;
+                    println!("{}", providers.join(", "));
+                }
+                DetectFormat::Json => {
+                    let detections = detect_providers(&path, env, &options)?;
+                    println!("{}", serde_json::to_string_pretty(&detections)?);
+                }
+            }
+        }
+        // Write the Dockerfile and its supporting files to a directory, or print the Dockerfile to stdout.
+        Commands::Dockerfile {
+            path,
+            out,
+            dockerfile_template,
+            cache_key,
+            cache_key_from,
+            no_cache,
+            squash,
+            parallel_phases,
+            runtime,
+        } => {
+            let cache_key = if !no_cache && cache_key.is_none() {
+                get_default_cache_key(&path, cache_key_from)?
+            } else {
+                cache_key
+            };
+
+            let build_options = &DockerBuilderOptions {
+                print_dockerfile: out.is_none(),
+                out_dir: out,
+                cache_key,
+                no_cache,
+                dockerfile_template,
+                squash,
+                parallel_phases,
+                runtime: runtime.into(),
+                ..Default::default()
+            };
+            create_docker_image(&path, env, &options, build_options).await?;
         }
         // Generate a Dockerfile and builds a container, using any specified build options.
         Commands::Build {
             path,
             name,
+            process,
             out,
             dockerfile,
             tag,
             label,
+            no_oci_labels,
             platform,
             cache_key,
+            cache_key_from,
             current_dir,
             no_cache,
             incremental_cache_image,
+            incremental_cache,
             cache_from,
             inline_cache,
             no_error_without_start,
             verbose,
+            remote_host,
+            push,
+            build_args,
+            secret,
+            ssh,
+            network,
+            memory,
+            cpus,
+            squash,
+            parallel_phases,
+            prefer_dockerfile,
+            runtime,
+            engine,
+            output,
+            dockerfile_template,
+            progress,
+            check_packages,
+            timings,
+            kaniko,
+            kaniko_job,
+            kaniko_context,
+            metadata_file,
         } => {
             let verbose = verbose || args.env.contains(&"NIXPACKS_VERBOSE=1".to_string());
 
+            let process_env = process.map(|process| format!("NIXPACKS_PROCESS={process}"));
+            if let Some(process_env) = &process_env {
+                env.push(process_env);
+            }
+
             // Default to absolute `path` of the source that is being built as the cache-key if not disabled
             let cache_key = if !no_cache && cache_key.is_none() {
-                get_default_cache_key(&path)?
+                get_default_cache_key(&path, cache_key_from)?
             } else {
                 cache_key
             };
 
+            let kaniko = match (kaniko_job, kaniko_context) {
+                (Some(path), Some(context)) => Some(Kaniko::Job { path, context }),
+                (Some(_), None) => {
+                    bail!("Pass --kaniko-context with where the Job can download the build context from")
+                }
+                (None, Some(_)) => {
+                    bail!("--kaniko-context is only used with --kaniko-job")
+                }
+                (None, None) if kaniko => Some(Kaniko::Run),
+                (None, None) => None,
+            };
+
             let build_options = &DockerBuilderOptions {
                 name,
                 tags: tag,
                 labels: label,
+                no_oci_labels,
                 out_dir: out,
                 quiet: false,
                 cache_key,
@@ -314,17 +1406,345 @@ async fn main() -> Result<()> {
                 cache_from,
                 no_error_without_start,
                 incremental_cache_image,
+                incremental_cache,
+                verbose,
+                remote_host,
+                push,
+                build_args,
+                secrets: secret,
+                ssh,
+                network,
+                limits: ResourceLimits { memory, cpus },
+                engine: engine.into(),
+                output: output
+                    .map(|output| output.parse::<ImageOutput>())
+                    .transpose()?,
+                dockerfile_template,
+                prefer_dockerfile,
+                squash,
+                parallel_phases,
+                runtime: runtime.into(),
+                progress: progress.into(),
+                check_packages,
+                timings: timings.map(|timings| match timings {
+                    TimingsArg::Text => Timings::Text,
+                    TimingsArg::Json => Timings::Json,
+                }),
+                cache_only: false,
+                with_source: false,
+                log_sink: None,
+                kaniko,
+                cancel: None,
+            };
+            let result = create_docker_image(&path, env, &options, build_options).await?;
+
+            if let Some(metadata_file) = metadata_file {
+                fs::write(&metadata_file, serde_json::to_string_pretty(&result)?)
+                    .with_context(|| format!("Writing build metadata to {metadata_file}"))?;
+            }
+        }
+        // Build the layers of an app that only change with its packages and lockfiles.
+        Commands::Cache {
+            command:
+                CacheCommands::Warm {
+                    path,
+                    name,
+                    platform,
+                    cache_key,
+                    cache_key_from,
+                    incremental_cache_image,
+                    incremental_cache,
+                    cache_from,
+                    push,
+                    build_args,
+                    secret,
+                    progress,
+                    verbose,
+                },
+        } => {
+            // The same default cache key as `nixpacks build`, so its cache mounts are the ones warmed
+            let cache_key = match cache_key {
+                Some(cache_key) => Some(cache_key),
+                None => get_default_cache_key(&path, cache_key_from)?,
+            };
+
+            let build_options = &DockerBuilderOptions {
+                name,
+                cache_key,
+                platform,
+                // Pushed images hold the metadata other hosts need to reuse their layers with --cache-from
+                inline_cache: true,
+                cache_from,
+                incremental_cache_image,
+                incremental_cache,
                 verbose,
+                push,
+                build_args,
+                secrets: secret,
+                progress: progress.into(),
+                cache_only: true,
+                ..Default::default()
             };
             create_docker_image(&path, env, &options, build_options).await?;
         }
+        Commands::Shell {
+            path,
+            name,
+            shell,
+            cache_key,
+            cache_key_from,
+            build_args,
+            secret,
+            engine,
+            verbose,
+        } => {
+            // The same default cache key as `nixpacks build`, so the shell reuses its cache mounts
+            let cache_key = match cache_key {
+                Some(cache_key) => Some(cache_key),
+                None => get_default_cache_key(&path, cache_key_from)?,
+            };
+
+            let build_options = &DockerBuilderOptions {
+                name,
+                cache_key,
+                verbose,
+                build_args,
+                secrets: secret,
+                engine: engine.into(),
+                ..Default::default()
+            };
+            open_shell(&path, env, &options, build_options, &shell).await?;
+        }
+        Commands::Exec {
+            path,
+            cmd,
+            name,
+            cache_key,
+            cache_key_from,
+            build_args,
+            secret,
+            engine,
+            verbose,
+        } => {
+            // The same default cache key as `nixpacks build`, so the command reuses its cache mounts
+            let cache_key = match cache_key {
+                Some(cache_key) => Some(cache_key),
+                None => get_default_cache_key(&path, cache_key_from)?,
+            };
+
+            let build_options = &DockerBuilderOptions {
+                name,
+                cache_key,
+                verbose,
+                build_args,
+                secrets: secret,
+                engine: engine.into(),
+                ..Default::default()
+            };
+            let code = exec_command(&path, env, &options, build_options, &cmd).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Compose {
+            command: ComposeCommands::Generate { path, file, force },
+        } => {
+            let compose_path = Path::new(&path).join(&file);
+            if compose_path.exists() && !force {
+                bail!(
+                    "{} already exists. Pass --force to replace it",
+                    compose_path.display()
+                );
+            }
+
+            let services = get_compose_services(&path, env, &options)?;
+            fs::write(&compose_path, to_compose_yaml(&services)?)
+                .with_context(|| format!("Writing {}", compose_path.display()))?;
+            println!(
+                "Wrote {} with {} services",
+                compose_path.display(),
+                services.len()
+            );
+        }
+        Commands::Compose {
+            command:
+                ComposeCommands::Up {
+                    path,
+                    file,
+                    args,
+                    cache_key,
+                    cache_key_from,
+                    build_args,
+                    secret,
+                    engine,
+                    verbose,
+                },
+        } => {
+            // The same default cache key as `nixpacks build`, so the services reuse its cache mounts
+            let cache_key = match cache_key {
+                Some(cache_key) => Some(cache_key),
+                None => get_default_cache_key(&path, cache_key_from)?,
+            };
+
+            let build_options = &DockerBuilderOptions {
+                cache_key,
+                verbose,
+                build_args,
+                secrets: secret,
+                engine: engine.into(),
+                ..Default::default()
+            };
+            let code = compose_up(&path, env, &options, build_options, &file, &args).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Init { path, yes, force } => {
+            let config_path = Path::new(&path).join("nixpacks.toml");
+            if config_path.exists() && !force {
+                bail!(
+                    "{} already exists. Pass --force to replace it",
+                    config_path.display()
+                );
+            }
+
+            let providers = get_plan_providers(&path, env.clone(), &options)?;
+            let plan = generate_build_plan(&path, env, &options)?;
+            println!("{}", plan.get_build_string()?);
+
+            let overrides = if yes {
+                InitOverrides::default()
+            } else {
+                prompt_init_overrides(&plan)?
+            };
+            fs::write(&config_path, to_init_config(&plan, &providers, &overrides)?)
+                .with_context(|| format!("Writing {}", config_path.display()))?;
+            println!("Wrote {}", config_path.display());
+        }
+        Commands::Watch {
+            path,
+            name,
+            run,
+            port,
+            cache_key,
+            cache_key_from,
+            build_args,
+            secret,
+            engine,
+            verbose,
+        } => {
+            let cache_key = match cache_key {
+                Some(cache_key) => Some(cache_key),
+                None => get_default_cache_key(&path, cache_key_from)?,
+            };
+
+            let build_options = &DockerBuilderOptions {
+                name,
+                cache_key,
+                verbose,
+                build_args,
+                secrets: secret,
+                engine: engine.into(),
+                ..Default::default()
+            };
+            let watch_options = &WatchOptions { run, ports: port };
+            watch_app(&path, env, &options, build_options, watch_options).await?;
+        }
+        Commands::Cache {
+            command:
+                CacheCommands::Clear {
+                    incremental_cache_image,
+                    incremental_cache,
+                },
+        } => {
+            clear_incremental_cache(
+                incremental_cache_image.as_deref(),
+                incremental_cache.as_deref(),
+            )?;
+        }
+        Commands::Doctor {
+            engine,
+            hostnames,
+            hosts_file,
+            inventory,
+            ssh_user,
+            identity_file,
+            port,
+        } => {
+            let doctor_options = DoctorOptions {
+                engine: engine.into(),
+                devenv: DevenvOptions {
+                    hostnames,
+                    hosts_file,
+                    inventory,
+                    ssh_user,
+                    identity_file,
+                    port,
+                    ..Default::default()
+                },
+            };
+
+            let checks = run_checks(&doctor_options)?;
+            for check in &checks {
+                let line = check.to_string();
+                match check.status {
+                    CheckStatus::Passed => println!("{}", line.green()),
+                    CheckStatus::Warning => println!("{}", line.yellow()),
+                    CheckStatus::Failed => println!("{}", line.red()),
+                }
+            }
+
+            let failed = checks
+                .iter()
+                .filter(|check| check.status == CheckStatus::Failed)
+                .count();
+            if failed > 0 {
+                bail!("{failed} of {} checks failed", checks.len());
+            }
+        }
+        Commands::Images { json } => {
+            let images = list_built_images()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&images)?);
+            } else {
+                println!("{}", get_images_string(&images));
+            }
+        }
+        Commands::Clean {
+            dry_run,
+            older_than,
+            larger_than,
+        } => {
+            let clean_options = CleanOptions {
+                dry_run,
+                older_than: older_than.map(|age| parse_age(&age)).transpose()?,
+                larger_than: larger_than.map(|size| parse_size(&size)).transpose()?,
+            };
+
+            let cleanups = clean(&clean_options)?;
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            for cleanup in &cleanups {
+                println!("{verb} {cleanup}");
+            }
+            println!(
+                "{verb} {} items, freeing {}",
+                cleanups.len(),
+                format_size(cleanups.iter().map(|cleanup| cleanup.size).sum())
+            );
+        }
     }
 
     Ok(())
 }
 
 /// Creates a key for storing image layers in the Docker cache.
-fn get_default_cache_key(path: &str) -> Result<Option<String>> {
+fn get_default_cache_key(path: &str, from: CacheKeyFromArg) -> Result<Option<String>> {
+    if from == CacheKeyFromArg::Lockfiles {
+        if let Some(cache_key) = get_lockfiles_cache_key(&App::new(path)?)? {
+            return Ok(Some(cache_key));
+        }
+    }
+
     let current_dir = env::current_dir()?;
     let source = current_dir.join(path).canonicalize();
     if let Ok(source) = source {
@@ -341,45 +1761,50 @@ fn get_default_cache_key(path: &str) -> Result<Option<String>> {
     }
 }
 
-fn to_home_manager_nix(packages: Vec<String>) -> String {
-    // filter npm from packages 
-    let packages = packages.into_iter().filter(|p| !p.starts_with("npm")).collect::<Vec<_>>();
-    // if yarn_* replace with yarn
-    let packages = packages.into_iter().map(
-        |p| {
-            if p.starts_with("yarn-") {
-                return "yarn".to_string();
-            }
-            return p;
-        }
-    ).collect::<Vec<_>>();
-    let mut text = "
-    { config, pkgs, lib, ... }:
+/// Asks for the overrides of `nixpacks init` on the terminal. Empty answers keep what was detected.
+fn prompt_init_overrides(plan: &BuildPlan) -> Result<InitOverrides> {
+    let detected_start = plan
+        .start_phase
+        .as_ref()
+        .and_then(|start| start.cmd.clone())
+        .unwrap_or_else(|| "none".to_string());
+    let start_cmd = prompt(&format!("Start command [{detected_start}]"))?;
+    let nix_pkgs = prompt("Extra Nix packages, separated by spaces")?;
+    let apt_pkgs = prompt("Extra apt packages, separated by spaces")?;
+    let variables = prompt("Environment variables, as NAME=value separated by spaces")?;
 
-    {
-      # Home Manager needs a bit of information about you and the paths it should
-      # manage.
-      home.username = \"ubuntu\";
-      home.homeDirectory = \"/home/ubuntu\";
-    
-      # This value determines the Home Manager release that your configuration is
-      # compatible with. This helps avoid breakage when a new Home Manager release
-      # introduces backwards incompatible changes.
-      #
-      # You should not change this value, even if you update Home Manager. If you do
-      # want to update the value, then make sure to first check the Home Manager
-      # release notes.
-      home.stateVersion = \"23.05\"; # Please read the comment before changing.
-    
-      # The home.packages option allows you to install Nix packages into your
-      # environment.
-      home.packages = with pkgs; [ 
-".to_string();
-    for package in &packages {
-        // append pkgs.package to text 
-        text = format!("{}        {} \n", text, package);
-    }
-    text.push_str("    ];\n}\n");
+    Ok(InitOverrides {
+        start_cmd: (!start_cmd.is_empty()).then(|| start_cmd),
+        nix_pkgs: nix_pkgs
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect(),
+        apt_pkgs: apt_pkgs
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect(),
+        variables: variables
+            .split_whitespace()
+            .map(|variable| match variable.split_once('=') {
+                Some((name, value)) => Ok((name.to_string(), value.to_string())),
+                None => bail!("Invalid variable `{variable}`, expected NAME=value"),
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+/// Asks a question on the terminal, returning the trimmed answer.
+fn prompt(question: &str) -> Result<String> {
+    print!("{question}: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
 
-    return text;
+/// Reads a plan piped to `--plan -` or `--plan-file -`.
+fn read_stdin() -> Result<String> {
+    let mut contents = String::new();
+    io::stdin().read_to_string(&mut contents)?;
+    Ok(contents)
 }
@@ -1,8 +1,9 @@
 use path_slash::PathBufExt;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
-use std::path::Path;
-use std::{env, fs, path::PathBuf};
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::{env, fs, io, path::PathBuf};
 
 use anyhow::{bail, Context, Result};
 use globset::Glob;
@@ -14,11 +15,23 @@ pub type StaticAssets = BTreeMap<String, String>;
 
 pub const ASSETS_DIR: &str = "/assets/";
 
+/// The path the files of an app in memory are under, which doesn't exist on disk.
+pub const MEMORY_SOURCE: &str = "/nixpacks-app";
+
+/// A file of an app that's in memory instead of a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppFile {
+    pub contents: Vec<u8>,
+    pub executable: bool,
+}
+
 /// Represents a project's file and directory paths.
 #[derive(Debug, Clone)]
 pub struct App {
     pub source: PathBuf,
     pub paths: Vec<PathBuf>,
+    /// The files of an app in memory, by their path under `source`
+    files: Option<Arc<BTreeMap<PathBuf, AppFile>>>,
 }
 
 impl App {
@@ -33,11 +46,94 @@ impl App {
         let dir = fs::read_dir(source.clone()).context("Failed to read app source directory")?;
         let paths: Vec<PathBuf> = dir.map(|path| path.unwrap().path()).collect();
 
-        Ok(App { source, paths })
+        Ok(App {
+            source,
+            paths,
+            files: None,
+        })
+    }
+
+    /// An app of files in memory, by their path relative to the app, e.g. from an uploaded archive. It's planned
+    /// without writing it to disk.
+    pub fn from_files<I: IntoIterator<Item = (String, AppFile)>>(files: I) -> App {
+        let source = PathBuf::from(MEMORY_SOURCE);
+        let files: BTreeMap<PathBuf, AppFile> = files
+            .into_iter()
+            .map(|(path, file)| (normalize_path(&source.join(path)), file))
+            .collect();
+
+        App {
+            paths: memory_children(&files, &source),
+            source,
+            files: Some(Arc::new(files)),
+        }
+    }
+
+    /// Whether the app's files are in memory instead of a directory.
+    pub fn is_in_memory(&self) -> bool {
+        self.files.is_some()
+    }
+
+    /// The app in a directory of this one.
+    pub fn subdirectory(&self, dir: &str) -> Result<App> {
+        let files = match &self.files {
+            Some(files) => files,
+            None => return App::new(&self.source.join(dir).to_string_lossy()),
+        };
+
+        if !self.includes_directory(dir) {
+            bail!("Failed to read app source directory");
+        }
+        let source = normalize_path(&self.source.join(dir));
+        Ok(App {
+            paths: memory_children(files, &source),
+            source,
+            files: Some(files.clone()),
+        })
+    }
+
+    /// Write the files of an app in memory to a directory, e.g. for the engine to build it from.
+    pub fn write_files(&self, dir: &Path) -> Result<()> {
+        for (path, file) in self.files.iter().flat_map(|files| files.iter()) {
+            let path = dir.join(path.strip_prefix(&self.source)?);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, &file.contents)
+                .with_context(|| format!("Writing {}", path.display()))?;
+
+            #[cfg(not(target_os = "windows"))]
+            if file.executable {
+                use std::os::unix::prelude::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The file of an app in memory at a path, which may be relative to the app.
+    fn memory_file(&self, name: &str) -> Option<&AppFile> {
+        self.files
+            .as_ref()
+            .and_then(|files| files.get(&normalize_path(&self.source.join(name))))
+    }
+
+    /// Whether a path, which may be relative to the app, is a directory of an app in memory.
+    fn is_memory_directory(&self, name: &str) -> bool {
+        let dir = normalize_path(&self.source.join(name));
+        self.files.as_ref().map_or(false, |files| {
+            files
+                .keys()
+                .any(|path| path != &dir && path.starts_with(&dir))
+        })
     }
 
     /// Check if a file exists
     pub fn includes_file(&self, name: &str) -> bool {
+        if self.is_in_memory() {
+            return self.memory_file(name).is_some();
+        }
         self.source.join(name).is_file()
     }
 
@@ -49,7 +145,7 @@ impl App {
         let directories = self
             .find_glob(pattern)?
             .into_iter()
-            .filter(|path| path.is_file())
+            .filter(|path| self.includes_file(&path.to_string_lossy()))
             .collect();
 
         Ok(directories)
@@ -63,7 +159,7 @@ impl App {
         let directories = self
             .find_glob(pattern)?
             .into_iter()
-            .filter(|path| path.is_dir())
+            .filter(|path| self.includes_directory(&path.to_string_lossy()))
             .collect();
 
         Ok(directories)
@@ -85,6 +181,22 @@ impl App {
             .build();
         let glob = Glob::new(pattern_str)?.compile_matcher();
 
+        if let Some(files) = &self.files {
+            let mut paths = BTreeSet::new();
+            for path in files.keys().filter(|path| path.starts_with(&self.source)) {
+                // The directories of the files, up to the app's
+                paths.extend(
+                    path.ancestors()
+                        .take_while(|ancestor| *ancestor != self.source)
+                        .map(Path::to_path_buf),
+                );
+            }
+            return Ok(paths
+                .into_iter()
+                .filter(|path| glob.is_match(path))
+                .collect());
+        }
+
         let relative_paths = walker
             .into_iter()
             .filter_map(Result::ok) // remove bad ones
@@ -108,6 +220,12 @@ impl App {
     /// # Errors
     /// This will error if the path doesn't exist, or if the contents isn't UTF-8
     pub fn read_file(&self, name: &str) -> Result<String> {
+        if self.is_in_memory() {
+            let data = String::from_utf8(self.read_file_bytes(name)?)
+                .with_context(|| format!("Error reading {name}"))?;
+            return Ok(data.replace("\r\n", "\n"));
+        }
+
         let data = fs::read_to_string(PathBuf::from_slash_lossy(
             self.source.join(name).as_os_str(),
         ))
@@ -119,6 +237,35 @@ impl App {
         Ok(data.replace("\r\n", "\n"))
     }
 
+    /// Read the contents of a file that may not be text, like `bun.lockb`
+    pub fn read_file_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        if self.is_in_memory() {
+            return self
+                .memory_file(name)
+                .map(|file| file.contents.clone())
+                .with_context(|| format!("Error reading {name}"));
+        }
+
+        fs::read(self.source.join(name)).with_context(|| format!("Error reading {name}"))
+    }
+
+    /// The names of the files and directories in a directory of the app
+    pub fn read_directory(&self, name: &str) -> io::Result<BTreeSet<String>> {
+        if self.is_in_memory() {
+            let dir = normalize_path(&self.source.join(name));
+            return Ok(self
+                .files
+                .iter()
+                .flat_map(|files| memory_children(files, &dir))
+                .filter_map(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+                .collect());
+        }
+
+        fs::read_dir(self.source.join(name))?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect()
+    }
+
     /// Check whether filenames matching a pattern exist in the project.
     pub fn find_match(&self, re: &Regex, pattern: &str) -> Result<bool> {
         let paths = match self.find_files(pattern) {
@@ -127,9 +274,7 @@ impl App {
         };
 
         for path in paths {
-            let path_buf = fs::canonicalize(path)?;
-
-            if let Some(p) = path_buf.to_str() {
+            if let Some(p) = path.to_str() {
                 let f = self.read_file(p)?;
                 if re.find(f.as_str()).is_some() {
                     return Ok(true);
@@ -142,9 +287,37 @@ impl App {
 
     /// Check if a directory exists
     pub fn includes_directory(&self, name: &str) -> bool {
+        if self.is_in_memory() {
+            return self.is_memory_directory(name);
+        }
         self.source.join(name).is_dir()
     }
 
+    /// The files and directories of the given names that exist, e.g. the ones a provider detects an app by.
+    pub fn find_existing_paths(&self, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .filter(|name| {
+                if self.is_in_memory() {
+                    return self.includes_file(name) || self.includes_directory(name);
+                }
+                self.source.join(name).exists()
+            })
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Returns a list of file paths matching a glob pattern, relative to the app source directory
+    ///
+    /// # Errors
+    /// Creating the Glob fails
+    pub fn find_relative_files(&self, pattern: &str) -> Result<Vec<String>> {
+        self.find_files(pattern)?
+            .iter()
+            .map(|path| Ok(self.strip_source_path(path)?.to_slash_lossy().to_string()))
+            .collect()
+    }
+
     #[cfg(target_os = "windows")]
     pub fn is_file_executable(&self, name: &str) -> bool {
         true
@@ -155,6 +328,10 @@ impl App {
     pub fn is_file_executable(&self, name: &str) -> bool {
         use std::os::unix::prelude::PermissionsExt;
 
+        if self.is_in_memory() {
+            return self.memory_file(name).map_or(false, |file| file.executable);
+        }
+
         let path = self.source.join(name);
         if path.is_file() {
             let metadata = path.metadata().unwrap();
@@ -223,6 +400,34 @@ impl App {
     }
 }
 
+/// The path without `.` and `..` components, which apps in memory don't resolve on disk.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The files and directories directly in a directory of an app in memory.
+fn memory_children(files: &BTreeMap<PathBuf, AppFile>, dir: &Path) -> Vec<PathBuf> {
+    files
+        .keys()
+        .filter_map(|path| {
+            let name = path.strip_prefix(dir).ok()?.components().next()?;
+            Some(dir.join(name))
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,6 +521,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_find_existing_paths() -> Result<()> {
+        let app = App::new("./examples/node")?;
+        assert_eq!(
+            app.find_existing_paths(&["package.json", "yarn.lock", "index.js"]),
+            vec!["package.json".to_string(), "index.js".to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_relative_files() -> Result<()> {
+        let app = App::new("./examples/node-monorepo")?;
+        assert_eq!(
+            app.find_relative_files("**/*.tsx")?,
+            vec![
+                "packages/client/pages/_app.tsx".to_string(),
+                "packages/client/pages/index.tsx".to_string()
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_find_match() -> Result<()> {
         let app = App::new("./examples/node-monorepo")?;
@@ -0,0 +1,298 @@
+use super::app::{App, AppFile};
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Component, Path},
+};
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Where the files of an app come from, for planning and building apps that aren't in a directory, like the archives
+/// uploaded to a hosting platform.
+///
+/// Paths are sources of the app in that directory, [`MemorySource`] of files in memory, and [`TarSource`] of a tar
+/// archive.
+pub trait AppSource {
+    /// Read the app, keeping its files in memory if they aren't in a directory.
+    fn into_app(self) -> Result<App>;
+}
+
+impl<P: AsRef<Path>> AppSource for P {
+    fn into_app(self) -> Result<App> {
+        App::new(&self.as_ref().to_string_lossy())
+    }
+}
+
+impl AppSource for App {
+    fn into_app(self) -> Result<App> {
+        Ok(self)
+    }
+}
+
+/// An app of files in memory, by their path relative to the app.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    files: BTreeMap<String, AppFile>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, replacing any at the same path.
+    #[must_use]
+    pub fn file<P: Into<String>, C: Into<Vec<u8>>>(mut self, path: P, contents: C) -> Self {
+        self.files.insert(
+            path.into(),
+            AppFile {
+                contents: contents.into(),
+                executable: false,
+            },
+        );
+        self
+    }
+
+    /// Add an executable file, like a `gradlew` script, replacing any at the same path.
+    #[must_use]
+    pub fn executable<P: Into<String>, C: Into<Vec<u8>>>(mut self, path: P, contents: C) -> Self {
+        self.files.insert(
+            path.into(),
+            AppFile {
+                contents: contents.into(),
+                executable: true,
+            },
+        );
+        self
+    }
+}
+
+impl AppSource for MemorySource {
+    fn into_app(self) -> Result<App> {
+        Ok(App::from_files(self.files))
+    }
+}
+
+/// An app in a tar archive, which may be gzipped, read into memory. Symlinks and other special files are left out.
+pub struct TarSource<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TarSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> AppSource for TarSource<R> {
+    fn into_app(mut self) -> Result<App> {
+        let mut data = Vec::new();
+        self.reader
+            .read_to_end(&mut data)
+            .context("Reading the app's archive")?;
+
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(data.as_slice())
+                .read_to_end(&mut decompressed)
+                .context("Decompressing the app's archive")?;
+            data = decompressed;
+        }
+
+        Ok(App::from_files(read_tar(&data)?))
+    }
+}
+
+/// The regular files of a tar archive, by their path in it.
+fn read_tar(data: &[u8]) -> Result<BTreeMap<String, AppFile>> {
+    let mut files = BTreeMap::new();
+    // The name of the next entry, from a GNU or PAX header before it, when it's too long for its own header
+    let mut long_name = None;
+
+    let mut offset = 0;
+    while offset + TAR_BLOCK_SIZE <= data.len() {
+        let header = &data[offset..offset + TAR_BLOCK_SIZE];
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+
+        let size = parse_octal(&header[124..136]).context("Reading the app's archive")?;
+        let start = offset + TAR_BLOCK_SIZE;
+        let contents = data
+            .get(start..start + size)
+            .context("The app's archive is truncated")?;
+        offset = start + (size + TAR_BLOCK_SIZE - 1) / TAR_BLOCK_SIZE * TAR_BLOCK_SIZE;
+
+        match header[156] {
+            b'0' | b'7' | 0 => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => header_name(header),
+                };
+                // Old archives mark directories with a trailing slash instead of their type
+                if name.ends_with('/') {
+                    continue;
+                }
+                let path = match archive_path(&name)? {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let mode = parse_octal(&header[100..108]).unwrap_or_default();
+                files.insert(
+                    path,
+                    AppFile {
+                        contents: contents.to_vec(),
+                        executable: mode & 0o111 != 0,
+                    },
+                );
+            }
+            b'L' => long_name = Some(field(contents)),
+            b'x' => long_name = pax_path(contents),
+            _ => long_name = None,
+        }
+    }
+
+    Ok(files)
+}
+
+/// The name of an entry in its header, with the prefix of a ustar header.
+fn header_name(header: &[u8]) -> String {
+    let name = field(&header[0..100]);
+    let prefix = if &header[257..262] == b"ustar" {
+        field(&header[345..500])
+    } else {
+        String::new()
+    };
+
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// The path of a file in an archive relative to the app, failing if it's outside of the app.
+fn archive_path(name: &str) -> Result<Option<String>> {
+    let mut parts = Vec::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir | Component::RootDir => {}
+            _ => bail!("The app's archive has a file outside of the app: {name}"),
+        }
+    }
+
+    Ok((!parts.is_empty()).then(|| parts.join("/")))
+}
+
+/// The `path` record of a PAX header.
+fn pax_path(contents: &[u8]) -> Option<String> {
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|byte| *byte == b' ')?;
+        let length: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..length)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(String::from_utf8_lossy(path.strip_suffix(b"\n")?).to_string());
+        }
+        rest = &rest[length..];
+    }
+    None
+}
+
+/// A NUL-terminated field of a header.
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+fn parse_octal(bytes: &[u8]) -> Result<usize> {
+    let digits = field(bytes);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    Ok(usize::from_str_radix(digits, 8)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    /// A ustar archive of the given files, with their modes.
+    fn tar(files: &[(&str, &str, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (name, contents, mode) in files {
+            let mut header = [0_u8; TAR_BLOCK_SIZE];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(format!("{mode:07o}").as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            data.extend_from_slice(&header);
+
+            data.extend_from_slice(contents.as_bytes());
+            let padding = (TAR_BLOCK_SIZE - contents.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+            data.extend(std::iter::repeat(0).take(padding));
+        }
+        data.extend_from_slice(&[0; TAR_BLOCK_SIZE * 2]);
+        data
+    }
+
+    #[test]
+    fn test_memory_source() -> Result<()> {
+        let app = MemorySource::new()
+            .file("package.json", r#"{"name": "app"}"#)
+            .executable("bin/start", "#!/bin/sh")
+            .into_app()?;
+
+        assert!(app.is_in_memory());
+        assert!(app.includes_file("package.json"));
+        assert!(app.includes_directory("bin"));
+        assert!(app.is_file_executable("bin/start"));
+        assert!(!app.is_file_executable("package.json"));
+        assert_eq!(
+            app.find_relative_files("**/*")?,
+            vec!["bin/start", "package.json"]
+        );
+        assert_eq!(app.paths.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_source() -> Result<()> {
+        let archive = tar(&[
+            ("./index.js", "console.log(1)", 0o644),
+            ("./gradlew", "#!/bin/sh", 0o755),
+        ]);
+        let app = TarSource::new(archive.as_slice()).into_app()?;
+
+        assert_eq!(app.read_file("index.js")?, "console.log(1)");
+        assert!(app.is_file_executable("gradlew"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzipped_tar_source() -> Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar(&[("main.go", "package main", 0o644)]))?;
+        let archive = encoder.finish()?;
+
+        let app = TarSource::new(archive.as_slice()).into_app()?;
+        assert_eq!(app.read_file("main.go")?, "package main");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_source_outside_of_app() {
+        let archive = tar(&[("../escape", "", 0o644)]);
+        assert!(TarSource::new(archive.as_slice()).into_app().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::nixpacks::{devenv::ssh_config::home_dir, plan::BuildPlan, NIX_PACKS_VERSION};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Label of every image Nixpacks builds, set to the version that built it.
+pub const BUILT_BY_LABEL: &str = "com.nixpacks.version";
+
+/// Label with the absolute path of the app an image was built from.
+pub const APP_LABEL: &str = "com.nixpacks.app";
+
+/// Label with the comma-separated providers an image was built with.
+pub const PROVIDERS_LABEL: &str = "com.nixpacks.providers";
+
+/// Label with the hash of the plan an image was built from, see [`get_plan_hash`].
+pub const PLAN_HASH_LABEL: &str = "com.nixpacks.plan-hash";
+
+/// An image built by Nixpacks, as listed by `nixpacks images`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltImage {
+    /// The name the image was built with, or its ID if it has no name anymore.
+    pub name: String,
+    pub image_id: Option<String>,
+    /// Every name the image can be run with.
+    pub tags: Vec<String>,
+    pub app: String,
+    pub providers: Vec<String>,
+    pub plan_hash: String,
+    /// When the build finished, in RFC 3339.
+    pub built_at: String,
+    pub nixpacks_version: String,
+    /// Whether the image is in the local engine's image store, rather than only recorded in the state file, e.g.
+    /// after it was built on a remote host or removed.
+    #[serde(default)]
+    pub local: bool,
+}
+
+/// A hash of the plan, for telling whether two images were built from the same plan.
+pub fn get_plan_hash(plan: &BuildPlan) -> Result<String> {
+    Ok(format!("{:x}", Sha1::digest(plan.to_json()?.as_bytes())))
+}
+
+/// The labels describing an image built from the app with the providers and plan.
+pub fn get_build_labels(app: &str, providers: &[String], plan: &BuildPlan) -> Result<Vec<String>> {
+    Ok(vec![
+        format!("{BUILT_BY_LABEL}={NIX_PACKS_VERSION}"),
+        format!("{APP_LABEL}={app}"),
+        format!("{PROVIDERS_LABEL}={}", providers.join(",")),
+        format!("{PLAN_HASH_LABEL}={}", get_plan_hash(plan)?),
+    ])
+}
+
+/// Where the images Nixpacks built are recorded, `$XDG_STATE_HOME/nixpacks/images.json`, which defaults to
+/// `~/.local/state/nixpacks/images.json`.
+pub fn get_state_file() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".local").join("state")))?;
+    Some(state_home.join("nixpacks").join("images.json"))
+}
+
+fn read_records(file: &Path) -> Result<Vec<BuiltImage>> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("Reading {}", file.display()))?;
+    serde_json::from_str(&contents).with_context(|| format!("Parsing {}", file.display()))
+}
+
+fn write_records(file: &Path, records: &[BuiltImage]) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+    }
+    fs::write(file, serde_json::to_string_pretty(records)?)
+        .with_context(|| format!("Writing {}", file.display()))
+}
+
+/// Record a build in the state file, replacing the record of an earlier build with the same name.
+pub fn record_build(image: BuiltImage) -> Result<()> {
+    let file = match get_state_file() {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    let mut records = read_records(&file)?;
+    records.retain(|record| record.name != image.name);
+    records.push(BuiltImage {
+        local: false,
+        ..image
+    });
+    write_records(&file, &records)
+}
+
+/// Remove the records of the images with these IDs from the state file, after the images were removed.
+pub fn forget_images(image_ids: &[String]) -> Result<()> {
+    let file = match get_state_file() {
+        Some(file) if file.exists() => file,
+        _ => return Ok(()),
+    };
+    let mut records = read_records(&file)?;
+    records.retain(|record| {
+        record
+            .image_id
+            .as_ref()
+            .map_or(true, |id| !image_ids.contains(id))
+    });
+    write_records(&file, &records)
+}
+
+/// The images Nixpacks built: the local images with its labels, then the builds recorded in the state file that
+/// aren't among them, most recent first. Without a Docker daemon only the recorded builds are listed.
+pub fn list_built_images() -> Result<Vec<BuiltImage>> {
+    let mut images = get_local_images().unwrap_or_default();
+    let local_ids = images
+        .iter()
+        .filter_map(|image| image.image_id.clone())
+        .collect::<BTreeSet<_>>();
+    let local_names = images
+        .iter()
+        .flat_map(|image| image.tags.clone())
+        .collect::<BTreeSet<_>>();
+
+    if let Some(file) = get_state_file() {
+        images.extend(read_records(&file)?.into_iter().filter(|record| {
+            !record
+                .image_id
+                .as_ref()
+                .map_or(false, |id| local_ids.contains(id))
+                && !local_names.contains(&record.name)
+        }));
+    }
+
+    images.sort_by(|a, b| b.built_at.cmp(&a.built_at));
+    Ok(images)
+}
+
+fn get_local_images() -> Result<Vec<BuiltImage>> {
+    let ids = docker_output(&[
+        "images".to_string(),
+        "--quiet".to_string(),
+        "--no-trunc".to_string(),
+        "--filter".to_string(),
+        format!("label={BUILT_BY_LABEL}"),
+    ])?
+    .lines()
+    .map(ToString::to_string)
+    .collect::<BTreeSet<_>>();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut args = vec![
+        "image".to_string(),
+        "inspect".to_string(),
+        "--format".to_string(),
+        "{{.Id}}\t{{.Created}}\t{{join .RepoTags \",\"}}\t{{json .Config.Labels}}".to_string(),
+    ];
+    args.extend(ids);
+    Ok(docker_output(&args)?
+        .lines()
+        .filter_map(parse_local_image)
+        .collect())
+}
+
+/// Parse a line of `docker image inspect` with the ID, creation time, tags, and labels of an image.
+fn parse_local_image(line: &str) -> Option<BuiltImage> {
+    let mut fields = line.splitn(4, '\t');
+    let image_id = fields.next()?.to_string();
+    let created = OffsetDateTime::parse(fields.next()?, &Rfc3339).ok()?;
+    let tags = fields
+        .next()?
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let labels = serde_json::from_str::<Option<BTreeMap<String, String>>>(fields.next()?)
+        .ok()?
+        .unwrap_or_default();
+    let label = |name: &str| labels.get(name).cloned().unwrap_or_default();
+
+    Some(BuiltImage {
+        name: tags.first().cloned().unwrap_or_else(|| {
+            image_id
+                .trim_start_matches("sha256:")
+                .chars()
+                .take(12)
+                .collect()
+        }),
+        image_id: Some(image_id),
+        tags,
+        app: label(APP_LABEL),
+        providers: label(PROVIDERS_LABEL)
+            .split(',')
+            .filter(|provider| !provider.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+        plan_hash: label(PLAN_HASH_LABEL),
+        built_at: created.format(&Rfc3339).ok()?,
+        nixpacks_version: label(BUILT_BY_LABEL),
+        local: true,
+    })
+}
+
+/// The images as a table, with a row per image of its name, app, providers, plan hash, and build time.
+pub fn get_images_string(images: &[BuiltImage]) -> String {
+    let rows = std::iter::once([
+        "NAME".to_string(),
+        "APP".to_string(),
+        "PROVIDERS".to_string(),
+        "PLAN".to_string(),
+        "BUILT".to_string(),
+    ])
+    .chain(images.iter().map(|image| {
+        [
+            if image.local {
+                image.name.clone()
+            } else {
+                format!("{} (not local)", image.name)
+            },
+            image.app.clone(),
+            image.providers.join(","),
+            image.plan_hash.chars().take(12).collect(),
+            image.built_at.clone(),
+        ]
+    }))
+    .collect::<Vec<_>>();
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Run `docker` with the arguments and return its output.
+pub fn docker_output(args: &[String]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .context("Running docker. Is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "Listing images failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_image() {
+        let image = parse_local_image(
+            "sha256:0123456789abcdef\t2023-01-01T12:00:00.5Z\tapp:latest,app:v2\t{\"com.nixpacks.app\":\"/src/app\",\"com.nixpacks.providers\":\"node,python\",\"com.nixpacks.plan-hash\":\"abc\",\"com.nixpacks.version\":\"1.0.0\"}",
+        )
+        .unwrap();
+
+        assert_eq!(image.name, "app:latest");
+        assert_eq!(image.tags, vec!["app:latest", "app:v2"]);
+        assert_eq!(image.app, "/src/app");
+        assert_eq!(image.providers, vec!["node", "python"]);
+        assert_eq!(image.plan_hash, "abc");
+        assert_eq!(image.built_at, "2023-01-01T12:00:00.5Z");
+        assert!(image.local);
+
+        // Images that were replaced by a later build have no tags
+        let image =
+            parse_local_image("sha256:0123456789abcdef\t2023-01-01T12:00:00Z\t\tnull").unwrap();
+        assert_eq!(image.name, "0123456789ab");
+        assert!(image.providers.is_empty());
+    }
+
+    #[test]
+    fn test_images_string() {
+        let images = [
+            BuiltImage {
+                name: "app".to_string(),
+                app: "/src/app".to_string(),
+                providers: vec!["node".to_string()],
+                plan_hash: "0123456789abcdef".to_string(),
+                built_at: "2023-01-01T12:00:00Z".to_string(),
+                local: true,
+                ..Default::default()
+            },
+            BuiltImage {
+                name: "api".to_string(),
+                app: "/src/api".to_string(),
+                built_at: "2022-12-31T12:00:00Z".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            get_images_string(&images),
+            "NAME             APP       PROVIDERS  PLAN          BUILT\n\
+             app              /src/app  node       0123456789ab  2023-01-01T12:00:00Z\n\
+             api (not local)  /src/api                           2022-12-31T12:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_plan_hash() -> Result<()> {
+        let plan = BuildPlan::from_toml("[start]\ncmd = 'npm start'")?;
+        assert_eq!(get_plan_hash(&plan)?, get_plan_hash(&plan.clone())?);
+        assert_ne!(get_plan_hash(&plan)?, get_plan_hash(&BuildPlan::default())?);
+
+        let labels = get_build_labels("/src/app", &["node".to_string()], &plan)?;
+        assert!(labels.contains(&"com.nixpacks.providers=node".to_string()));
+        Ok(())
+    }
+}
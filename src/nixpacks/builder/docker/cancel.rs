@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::{
+    fmt,
+    process::{Child, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often a running command checks whether its build was cancelled.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancels an in-flight build from another thread or task, e.g. when a user stops it on a server. Clones cancel the
+/// same build.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        CancelHandle::default()
+    }
+
+    /// Stop the build. The engine command it's running is killed, and the build fails with [`BuildCancelled`].
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The error of a build stopped with [`CancelHandle::cancel`], returned by the library as `NixpacksError::Cancelled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildCancelled;
+
+impl fmt::Display for BuildCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The build was cancelled")
+    }
+}
+
+impl std::error::Error for BuildCancelled {}
+
+/// Fails with [`BuildCancelled`] if the build was cancelled.
+pub fn check_cancelled(cancel: Option<&CancelHandle>) -> Result<()> {
+    if cancel.map_or(false, CancelHandle::is_cancelled) {
+        return Err(BuildCancelled.into());
+    }
+    Ok(())
+}
+
+/// Wait for the command to exit, killing it if the build is cancelled first.
+pub fn wait_or_kill(child: &mut Child, cancel: Option<&CancelHandle>) -> Result<ExitStatus> {
+    let cancel = match cancel {
+        Some(cancel) => cancel,
+        None => return Ok(child.wait()?),
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if cancel.is_cancelled() {
+            kill(child);
+            return Err(BuildCancelled.into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Kill the command and reap it, ignoring that it may have just exited.
+pub fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_wait_or_kill() {
+        let cancel = CancelHandle::new();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(POLL_INTERVAL);
+            canceller.cancel();
+        });
+
+        let error = wait_or_kill(&mut child, Some(&cancel)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BuildCancelled>(),
+            Some(&BuildCancelled)
+        );
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_wait_without_cancel() {
+        let mut child = Command::new("true").spawn().unwrap();
+        assert!(wait_or_kill(&mut child, None).unwrap().success());
+        assert!(check_cancelled(None).is_ok());
+    }
+}
@@ -0,0 +1,364 @@
+use super::built_images::{docker_output, forget_images, BUILT_BY_LABEL};
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    path::Path,
+    process::Command,
+    time::{Duration, SystemTime},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use walkdir::WalkDir;
+
+/// Label of the images holding the incremental cache between builds.
+pub const INCREMENTAL_CACHE_LABEL: &str = "com.nixpacks.incremental-cache";
+
+/// Prefixes of the temp directories builds write the Dockerfile, assets, and in-memory sources to.
+const TEMP_DIR_PREFIXES: &[&str] = &["nixpacks", "nixpacks-source"];
+
+/// Temp directories newer than this may belong to a build that's still running, so they're always kept.
+const MIN_TEMP_DIR_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Options of `nixpacks clean`. Only what matches every filter is removed.
+#[derive(Clone, Debug, Default)]
+pub struct CleanOptions {
+    /// List what would be removed without removing it.
+    pub dry_run: bool,
+    /// Only remove what was created at least this long ago.
+    pub older_than: Option<Duration>,
+    /// Only remove what takes at least this many bytes.
+    pub larger_than: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CleanupKind {
+    /// An image built by Nixpacks.
+    Image,
+    /// An incremental cache image that was replaced by a later build's.
+    IncrementalCache,
+    /// A temp build directory left behind by a build that didn't finish.
+    TempDir,
+}
+
+/// Something `nixpacks clean` removes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cleanup {
+    pub kind: CleanupKind,
+    /// The image ID or the directory's path.
+    pub id: String,
+    /// The image's tags or the directory's path, for display.
+    pub name: String,
+    pub size: u64,
+    pub age: Duration,
+}
+
+impl fmt::Display for Cleanup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            CleanupKind::Image => "image",
+            CleanupKind::IncrementalCache => "incremental cache image",
+            CleanupKind::TempDir => "temp directory",
+        };
+        write!(
+            f,
+            "{kind} {} ({}, {} old)",
+            self.name,
+            format_size(self.size),
+            format_age(self.age)
+        )
+    }
+}
+
+/// Remove the images Nixpacks built, the incremental cache images no build uses anymore, and the temp build
+/// directories left behind, returning what was removed. Images used by containers can't be removed and are skipped.
+pub fn clean(options: &CleanOptions) -> Result<Vec<Cleanup>> {
+    let mut cleanups = find_images(BUILT_BY_LABEL, false, CleanupKind::Image)?;
+    cleanups.extend(find_images(
+        INCREMENTAL_CACHE_LABEL,
+        true,
+        CleanupKind::IncrementalCache,
+    )?);
+    cleanups.extend(find_temp_dirs(&std::env::temp_dir()));
+    cleanups.retain(|cleanup| is_matched(cleanup, options));
+
+    if options.dry_run {
+        return Ok(cleanups);
+    }
+
+    let mut removed = Vec::new();
+    for cleanup in cleanups {
+        match remove(&cleanup) {
+            Ok(()) => removed.push(cleanup),
+            Err(error) => eprintln!("Skipping {cleanup}: {error:#}"),
+        }
+    }
+
+    let removed_images = removed
+        .iter()
+        .filter(|cleanup| cleanup.kind == CleanupKind::Image)
+        .map(|cleanup| cleanup.id.clone())
+        .collect::<Vec<_>>();
+    forget_images(&removed_images)?;
+    Ok(removed)
+}
+
+fn is_matched(cleanup: &Cleanup, options: &CleanOptions) -> bool {
+    options.older_than.map_or(true, |age| cleanup.age >= age)
+        && options
+            .larger_than
+            .map_or(true, |size| cleanup.size >= size)
+}
+
+fn remove(cleanup: &Cleanup) -> Result<()> {
+    if cleanup.kind == CleanupKind::TempDir {
+        return fs::remove_dir_all(&cleanup.id).with_context(|| format!("Removing {}", cleanup.id));
+    }
+
+    let output = Command::new("docker")
+        .args(["image", "rm", "--force", &cleanup.id])
+        .output()
+        .context("Removing image")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// The images with the label, only the untagged ones if `dangling`.
+fn find_images(label: &str, dangling: bool, kind: CleanupKind) -> Result<Vec<Cleanup>> {
+    let mut args = vec![
+        "images".to_string(),
+        "--quiet".to_string(),
+        "--no-trunc".to_string(),
+        "--filter".to_string(),
+        format!("label={label}"),
+    ];
+    if dangling {
+        args.extend(["--filter".to_string(), "dangling=true".to_string()]);
+    }
+    let ids = docker_output(&args)?
+        .lines()
+        .map(ToString::to_string)
+        .collect::<BTreeSet<_>>();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut args = vec![
+        "image".to_string(),
+        "inspect".to_string(),
+        "--format".to_string(),
+        "{{.Id}}\t{{.Created}}\t{{.Size}}\t{{join .RepoTags \",\"}}".to_string(),
+    ];
+    args.extend(ids);
+    let now = OffsetDateTime::now_utc();
+    Ok(docker_output(&args)?
+        .lines()
+        .filter_map(|line| parse_image(line, kind, now))
+        .collect())
+}
+
+/// Parse a line of `docker image inspect` with the ID, creation time, size, and tags of an image.
+fn parse_image(line: &str, kind: CleanupKind, now: OffsetDateTime) -> Option<Cleanup> {
+    let mut fields = line.split('\t');
+    let id = fields.next()?.to_string();
+    let created = OffsetDateTime::parse(fields.next()?, &Rfc3339).ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let name = match fields.next().unwrap_or_default() {
+        "" => id.trim_start_matches("sha256:").chars().take(12).collect(),
+        tags => tags.to_string(),
+    };
+
+    Some(Cleanup {
+        kind,
+        id,
+        name,
+        size,
+        age: Duration::try_from(now - created).unwrap_or_default(),
+    })
+}
+
+/// The temp build directories in `dir` old enough not to belong to a running build.
+fn find_temp_dirs(dir: &Path) -> Vec<Cleanup> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !is_temp_dir_name(&name) || !entry.file_type().ok()?.is_dir() {
+                return None;
+            }
+
+            let age = SystemTime::now()
+                .duration_since(entry.metadata().ok()?.modified().ok()?)
+                .unwrap_or_default();
+            if age < MIN_TEMP_DIR_AGE {
+                return None;
+            }
+
+            let path = entry.path();
+            Some(Cleanup {
+                kind: CleanupKind::TempDir,
+                id: path.to_string_lossy().to_string(),
+                name: path.to_string_lossy().to_string(),
+                size: dir_size(&path),
+                age,
+            })
+        })
+        .collect()
+}
+
+/// Whether the name is one the temp directory crate gives directories with our prefixes, a dot and 12 random
+/// characters, e.g. `nixpacks.AbC123dEf456`.
+fn is_temp_dir_name(name: &str) -> bool {
+    name.rsplit_once('.').map_or(false, |(prefix, suffix)| {
+        TEMP_DIR_PREFIXES.contains(&prefix)
+            && suffix.len() == 12
+            && suffix.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(fs::Metadata::is_file)
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Parse an age like `30m`, `12h`, `7d`, or `2w`.
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let unit_start = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (count, unit) = age.split_at(unit_start);
+    let count = count
+        .parse::<u64>()
+        .with_context(|| format!("Invalid age {age}. Use a number with m, h, d, or w, e.g. 7d"))?;
+    let seconds = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("Invalid age {age}. Use a number with m, h, d, or w, e.g. 7d"),
+    };
+    Ok(Duration::from_secs(count * seconds))
+}
+
+/// Parse a size like `500MB`, `1.5GB`, or `100k`, in decimal units like Docker shows sizes in.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (count, unit) = size.split_at(unit_start);
+    let count = count.parse::<f64>().with_context(|| {
+        format!("Invalid size {size}. Use a number with B, KB, MB, or GB, e.g. 500MB")
+    })?;
+    let multiplier = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1.0,
+        "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        _ => bail!("Invalid size {size}. Use a number with B, KB, MB, or GB, e.g. 500MB"),
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok((count * multiplier) as u64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    let units = [("TB", 1e12), ("GB", 1e9), ("MB", 1e6), ("kB", 1e3)];
+    units
+        .iter()
+        .find(|(_, size)| bytes as f64 >= *size)
+        .map_or_else(
+            || format!("{bytes} B"),
+            |(unit, size)| format!("{:.1} {unit}", bytes as f64 / size),
+        )
+}
+
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86400));
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("500MB").unwrap(), 500_000_000);
+        assert_eq!(parse_size("1.5gb").unwrap(), 1_500_000_000);
+        assert_eq!(parse_size("100k").unwrap(), 100_000);
+        assert_eq!(parse_size("42").unwrap(), 42);
+        assert!(parse_size("lots").is_err());
+        assert_eq!(format_size(1_500_000_000), "1.5 GB");
+        assert_eq!(format_size(42), "42 B");
+    }
+
+    #[test]
+    fn test_parse_image() {
+        let now = OffsetDateTime::parse("2023-01-03T12:00:00Z", &Rfc3339).unwrap();
+        let image = parse_image(
+            "sha256:0123456789abcdef\t2023-01-01T12:00:00.123456789Z\t1500000000\t",
+            CleanupKind::IncrementalCache,
+            now,
+        )
+        .unwrap();
+        assert_eq!(image.name, "0123456789ab");
+        assert_eq!(
+            image.age,
+            Duration::from_secs(2 * 86400) - Duration::from_nanos(123_456_789)
+        );
+        assert_eq!(image.size, 1_500_000_000);
+
+        let image = parse_image(
+            "sha256:0123456789abcdef\t2023-01-01T12:00:00Z\t100\tapp:latest,app:v2",
+            CleanupKind::Image,
+            now,
+        )
+        .unwrap();
+        assert_eq!(image.name, "app:latest,app:v2");
+        assert!(is_matched(
+            &image,
+            &CleanOptions {
+                older_than: Some(parse_age("1d").unwrap()),
+                ..Default::default()
+            }
+        ));
+        assert!(!is_matched(
+            &image,
+            &CleanOptions {
+                larger_than: Some(1000),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_is_temp_dir_name() {
+        assert!(is_temp_dir_name("nixpacks.AbC123dEf456"));
+        assert!(is_temp_dir_name("nixpacks-source.AbC123dEf456"));
+        assert!(!is_temp_dir_name("nixpacks-devenv.AbC123dEf456"));
+        assert!(!is_temp_dir_name("nixpacks.toml"));
+        assert!(!is_temp_dir_name("nixpacks"));
+    }
+}
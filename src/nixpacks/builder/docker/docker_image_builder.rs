@@ -1,11 +1,21 @@
 use super::{dockerfile_generation::DockerfileGenerator, DockerBuilderOptions, ImageBuilder};
 use crate::nixpacks::{
-    builder::docker::{
-        dockerfile_generation::OutputDir,
-        file_server::FileServer,
-        incremental_cache::{IncrementalCache, IncrementalCacheDirs},
+    builder::{
+        docker::{
+            cancel::{check_cancelled, wait_or_kill, CancelHandle},
+            dockerfile_generation::OutputDir,
+            engine::{platform_image, BuildCmds, ContainerEngine, Engine, ImageOutput},
+            file_server::FileServer,
+            incremental_cache::{IncrementalCache, IncrementalCacheDirs, RemoteCache},
+            kaniko::{Kaniko, KanikoBuild},
+            progress::{run_with_events, BuildEvent, Progress, ProgressParser},
+            remote_build::RemoteBuild,
+            timings::{Timings, TimingsRecorder, TimingsReport},
+        },
+        BuildResult,
     },
     environment::Environment,
+    error::NixpacksError,
     files,
     logger::Logger,
     plan::BuildPlan,
@@ -13,7 +23,10 @@ use crate::nixpacks::{
 use anyhow::{bail, Context, Ok, Result};
 use std::{
     fs::{self, remove_dir_all, File},
-    process::Command,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Instant,
 };
 use tempdir::TempDir;
 use uuid::Uuid;
@@ -41,8 +54,100 @@ use async_trait::async_trait;
 #[async_trait]
 impl ImageBuilder for DockerImageBuilder {
     /// Build a Docker image from a given BuildPlan and data from environment variables.
-    async fn create_image(&self, app_src: &str, plan: &BuildPlan, env: &Environment) -> Result<()> {
+    async fn create_image(
+        &self,
+        app_src: &str,
+        plan: &BuildPlan,
+        env: &Environment,
+    ) -> Result<BuildResult> {
         let id = Uuid::new_v4();
+        let build_started = Instant::now();
+        let mut result = BuildResult {
+            plan: plan.clone(),
+            ..Default::default()
+        };
+
+        if self.options.remote_host.is_some()
+            && (self.options.out_dir.is_some() || self.options.uses_incremental_cache())
+        {
+            bail!("Building on a remote host can't be combined with --out, --incremental-cache-image, or --incremental-cache");
+        }
+        if self.options.remote_host.is_some()
+            && (!self.options.secrets.is_empty() || !self.options.ssh.is_empty())
+        {
+            bail!("Secrets and SSH agents are read from this machine and can't be used with --remote-host");
+        }
+        if self.options.engine != Engine::Docker && self.options.uses_incremental_cache() {
+            bail!("--incremental-cache-image and --incremental-cache are only supported with the docker engine");
+        }
+        if self.options.incremental_cache_image.is_some()
+            && self.options.incremental_cache.is_some()
+        {
+            bail!("Pass either --incremental-cache-image or --incremental-cache");
+        }
+        let remote_cache = self
+            .options
+            .incremental_cache
+            .as_deref()
+            .map(RemoteCache::from_url)
+            .transpose()?;
+        if self.options.output.is_some()
+            && (self.options.push
+                || self.options.remote_host.is_some()
+                || self.options.out_dir.is_some())
+        {
+            bail!("--output can't be combined with --push, --remote-host, or --out");
+        }
+        if self.options.output.is_some() && self.is_multi_platform() {
+            bail!("--output can only write an image for a single --platform");
+        }
+        if self.options.engine == Engine::Docker
+            && !self.options.limits.is_empty()
+            && self.options.output.is_some()
+        {
+            bail!("--memory and --cpus can't be combined with --output when building with docker");
+        }
+        if self.options.squash && self.options.uses_incremental_cache() {
+            bail!(
+                "--squash can't be combined with --incremental-cache-image or --incremental-cache"
+            );
+        }
+        if self.options.kaniko.is_some()
+            && (!self.options.secrets.is_empty()
+                || !self.options.ssh.is_empty()
+                || self.options.network.is_some())
+        {
+            bail!("kaniko can't mount secrets or SSH agents, or choose the network");
+        }
+        if self.options.kaniko.is_some()
+            && (self.is_multi_platform()
+                || self.options.remote_host.is_some()
+                || self.options.output.is_some()
+                || self.options.uses_incremental_cache())
+        {
+            bail!("kaniko can't be combined with multiple --platform values, --remote-host, --output, --incremental-cache-image, or --incremental-cache");
+        }
+        if let Some(Kaniko::Job { .. }) = self.options.kaniko {
+            if self.options.out_dir.is_none() || self.options.name.is_none() {
+                bail!("Pass --out with the directory to write the build context to, and --name with the image the Job pushes");
+            }
+        }
+        if self.options.emits_events()
+            && (self.options.remote_host.is_some() || self.options.kaniko.is_some())
+        {
+            bail!("--progress json can't be combined with --remote-host or kaniko");
+        }
+        if self.options.timings.is_some()
+            && (self.options.remote_host.is_some() || self.options.kaniko.is_some())
+        {
+            bail!("--timings can't be combined with --remote-host or kaniko");
+        }
+        if self.options.push && self.options.name.is_none() {
+            bail!("Pass --name with the registry and repository to push the image to");
+        }
+
+        let cancel = self.options.cancel.as_ref();
+        check_cancelled(cancel)?;
 
         let output = get_output_dir(app_src, &self.options)?;
         let name = self.options.name.clone().unwrap_or_else(|| id.to_string());
@@ -50,9 +155,17 @@ impl ImageBuilder for DockerImageBuilder {
 
         let incremental_cache = IncrementalCache::default();
         let incremental_cache_dirs = IncrementalCacheDirs::new(&output);
+        let _cleanup = CancelCleanup {
+            temp_dir: output.is_temp.then(|| output.root.clone()),
+            incremental_cache_dirs: &incremental_cache_dirs,
+            cancel,
+        };
 
-        let file_server_config = if self.options.incremental_cache_image.is_some() {
+        let file_server_config = if self.options.uses_incremental_cache() {
             incremental_cache_dirs.create()?;
+            if let Some(remote_cache) = &remote_cache {
+                incremental_cache.restore_from_remote(remote_cache, &incremental_cache_dirs)?;
+            }
 
             let file_server = FileServer {};
             let config = file_server.start(&incremental_cache_dirs);
@@ -61,14 +174,29 @@ impl ImageBuilder for DockerImageBuilder {
             None
         };
 
-        let dockerfile = plan
-            .generate_dockerfile(&self.options, env, &output, file_server_config)
-            .context("Generating Dockerfile for plan")?;
+        // kaniko doesn't support BuildKit cache mounts
+        let dockerfile_options = if self.options.kaniko.is_some() {
+            DockerBuilderOptions {
+                cache_key: None,
+                ..self.options.clone()
+            }
+        } else {
+            self.options.clone()
+        };
+
+        let app_dockerfile = Path::new(app_src).join("Dockerfile");
+        let dockerfile = if self.options.prefer_dockerfile && app_dockerfile.is_file() {
+            fs::read_to_string(app_dockerfile).context("Reading the app's Dockerfile")?
+        } else {
+            plan.generate_dockerfile(&dockerfile_options, env, &output, file_server_config)
+                .context("Generating Dockerfile for plan")?
+        };
 
         // If printing the Dockerfile, don't write anything to disk
         if self.options.print_dockerfile {
             println!("{dockerfile}");
-            return Ok(());
+            result.duration = build_started.elapsed();
+            return Ok(result);
         }
 
         self.write_app(app_src, &output).context("Writing app")?;
@@ -77,26 +205,198 @@ impl ImageBuilder for DockerImageBuilder {
         plan.write_supporting_files(&self.options, env, &output)
             .context("Writing supporting files")?;
 
-        // Only build if the --out flag was not specified
-        if self.options.out_dir.is_none() {
-            let mut docker_build_cmd = self.get_docker_build_cmd(plan, name.as_str(), &output)?;
+        check_cancelled(cancel)?;
+        if let Some(kaniko) = &self.options.kaniko {
+            let kaniko_build = KanikoBuild::new(&self.options);
+            match kaniko {
+                Kaniko::Run => {
+                    kaniko_build.run(plan, &name, &output)?;
+                    if self.options.push {
+                        result.tags = self.get_built_images(&name);
+                    }
 
-            // Execute docker build
-            let build_result = docker_build_cmd.spawn()?.wait().context("Building image")?;
-            if !build_result.success() {
-                bail!("Docker build failed")
+                    self.logger.log_section("Successfully Built!");
+                    if !self.options.push {
+                        println!(
+                            "\nkaniko doesn't keep the image, pass --push to push it to a registry"
+                        );
+                    }
+
+                    if output.is_temp {
+                        remove_dir_all(output.root)?;
+                    }
+                }
+                Kaniko::Job { path, context } => {
+                    kaniko_build.write_job(plan, &name, path, context)?;
+
+                    println!("\nSaved output to:");
+                    println!("  {}", output.root.to_str().unwrap());
+                    println!("\nUpload it to {context}, then build it with:");
+                    println!("  kubectl apply -f {path}");
+                }
             }
+        } else if let Some(host) = &self.options.remote_host {
+            let remote_build = RemoteBuild::new(host, self.engine())?;
+            remote_build.build(
+                &output,
+                &self.get_build_cmds(
+                    plan,
+                    name.as_str(),
+                    ".",
+                    &output.get_relative_path("Dockerfile").to_string_lossy(),
+                ),
+            )?;
 
             self.logger.log_section("Successfully Built!");
             println!("\nRun:");
-            println!("  docker run -it {name}");
+            for image in self.get_run_images(&name) {
+                println!("  {}", remote_build.run_hint(&image));
+            }
+            result.tags = self.get_built_images(&name);
 
+            if output.is_temp {
+                remove_dir_all(output.root)?;
+            }
+        } else if self.options.out_dir.is_none() {
+            // Only build if the --out flag was not specified
+            self.check_engine_installed()?;
+            let build_cmds = self.get_build_cmds(
+                plan,
+                name.as_str(),
+                &output.root.to_string_lossy(),
+                &output.get_absolute_path("Dockerfile").to_string_lossy(),
+            );
+
+            if let Some(setup_args) = &build_cmds.setup {
+                // The builder is usually left over from an earlier build with the same limits
+                let _ = self
+                    .engine_command()
+                    .args(setup_args)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+
+            if let Some(remove_args) = &build_cmds.remove_manifest {
+                // There's usually no earlier manifest list to remove
+                let _ = self
+                    .engine_command()
+                    .args(remove_args)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+
+            // Execute docker build
+            let mut timings_reports = Vec::new();
+            for (platform, build_args) in &build_cmds.builds {
+                if let Some(platform) = platform {
+                    self.log_section(&format!("Building for {platform}"));
+                }
+                let mut build_cmd = self.engine().command();
+                build_cmd.args(build_args);
+                let started = Instant::now();
+                let mut timings = TimingsRecorder::default();
+                let json = self.options.emits_events();
+                let build_result = if json || self.options.timings.is_some() {
+                    run_with_events(
+                        &mut build_cmd,
+                        &mut ProgressParser::new(plan),
+                        !json,
+                        cancel,
+                        |event| {
+                            if json {
+                                self.emit(event.clone());
+                            }
+                            timings.record(event);
+                        },
+                    )?
+                } else {
+                    wait_or_kill(&mut build_cmd.spawn()?, cancel).context("Building image")?
+                };
+                if !build_result.success() {
+                    return Err(NixpacksError::BuildFailed {
+                        exit_code: build_result.code(),
+                        platform: platform.clone(),
+                    }
+                    .into());
+                }
+                timings_reports
+                    .push(timings.report(platform.clone(), started.elapsed().as_secs_f64()));
+            }
+
+            if let Some(create_args) = &build_cmds.create_manifest {
+                self.log_step(&format!("Creating manifest list {name}"));
+                let create_result = wait_or_kill(
+                    &mut self.engine_command().args(create_args).spawn()?,
+                    cancel,
+                )
+                .context("Creating manifest list")?;
+                if !create_result.success() {
+                    bail!("Failed to create manifest list {name}")
+                }
+            }
+
+            if let Some(image_output) = &self.options.output {
+                if let Some(export_args) = self.engine().export_args(&name, image_output) {
+                    let export_result = wait_or_kill(
+                        &mut self.engine_command().args(export_args).spawn()?,
+                        cancel,
+                    )
+                    .context("Exporting image")?;
+                    if !export_result.success() {
+                        bail!("Failed to export image")
+                    }
+                }
+            }
+
+            for (image, push_args) in &build_cmds.push {
+                self.log_step(&format!("Pushing {image}"));
+                let push_result =
+                    wait_or_kill(&mut self.engine_command().args(push_args).spawn()?, cancel)
+                        .context("Pushing image")?;
+                if !push_result.success() {
+                    bail!("Failed to push {image}")
+                }
+            }
+
+            result.tags = self.get_built_images(&name);
+            if self.options.output.is_none() && !self.is_multi_platform() {
+                self.inspect_image(&name, &mut result);
+            }
+
+            match &self.options.output {
+                _ if self.options.emits_events() => self.emit(BuildEvent::BuildFinished {
+                    images: self.get_run_images(&name),
+                }),
+                Some(ImageOutput::Oci(path)) => {
+                    self.logger.log_section("Successfully Built!");
+                    println!("\nSaved OCI image to:");
+                    println!("  {path}");
+                }
+                None => {
+                    self.logger.log_section("Successfully Built!");
+                    println!("\nRun:");
+                    for image in self.get_run_images(&name) {
+                        println!("  {}", self.engine().run_cmd(&image));
+                    }
+                }
+            }
+
+            if let Some(timings) = self.options.timings {
+                self.print_timings(timings, timings_reports)?;
+            }
+
+            check_cancelled(cancel)?;
             if self.options.incremental_cache_image.is_some() {
                 incremental_cache.create_image(
                     &incremental_cache_dirs,
                     &self.options.incremental_cache_image.clone().unwrap(),
                 )?;
             }
+            if let Some(remote_cache) = &remote_cache {
+                incremental_cache.save_to_remote(remote_cache, &incremental_cache_dirs)?;
+            }
 
             if output.is_temp {
                 remove_dir_all(output.root)?;
@@ -106,7 +406,44 @@ impl ImageBuilder for DockerImageBuilder {
             println!("  {}", output.root.to_str().unwrap());
         }
 
-        Ok(())
+        result.duration = build_started.elapsed();
+        Ok(result)
+    }
+}
+
+/// Read the ID, size, and first registry digest from the output of the engine's inspect command.
+fn parse_inspect_output(output: &str, result: &mut BuildResult) {
+    let mut fields = output.split_whitespace();
+    result.image_id = fields.next().map(ToString::to_string);
+    result.size_bytes = fields.next().and_then(|size| size.parse().ok());
+    result.digest = fields.next().and_then(|digests| {
+        digests
+            .split(',')
+            .find_map(|digest| digest.split_once('@'))
+            .map(|(_, digest)| digest.to_string())
+    });
+}
+
+/// Removes the temporary files of a cancelled build when it stops: the whole build context if it's temporary, and
+/// otherwise the partial incremental cache.
+struct CancelCleanup<'a> {
+    temp_dir: Option<PathBuf>,
+    incremental_cache_dirs: &'a IncrementalCacheDirs,
+    cancel: Option<&'a CancelHandle>,
+}
+
+impl Drop for CancelCleanup<'_> {
+    fn drop(&mut self) {
+        if !self.cancel.map_or(false, CancelHandle::is_cancelled) {
+            return;
+        }
+
+        // The build may have removed them already
+        if let Some(temp_dir) = &self.temp_dir {
+            let _ = remove_dir_all(temp_dir);
+        } else {
+            let _ = self.incremental_cache_dirs.remove();
+        }
     }
 }
 
@@ -115,71 +452,309 @@ impl DockerImageBuilder {
         DockerImageBuilder { logger, options }
     }
 
-    /// Generates the Docker command and arguments for building the project.
-    fn get_docker_build_cmd(
+    /// The CLI that builds and pushes the image.
+    fn engine(&self) -> &'static dyn ContainerEngine {
+        self.options.engine.cli()
+    }
+
+    /// A command invoking the engine's CLI for anything but the build. With `--progress json` its output goes to
+    /// stderr, leaving stdout to the events.
+    fn engine_command(&self) -> Command {
+        let mut cmd = self.engine().command();
+        if self.options.emits_events() {
+            cmd.stdout(io::stderr());
+        }
+        cmd
+    }
+
+    /// Send the event to the log sink, or print it as JSON.
+    fn emit(&self, event: BuildEvent) {
+        match &self.options.log_sink {
+            Some(sink) => sink.send(event),
+            None => event.emit(),
+        }
+    }
+
+    /// Log a section title, or emit it as an event with `--progress json`.
+    fn log_section(&self, msg: &str) {
+        if self.options.emits_events() {
+            self.emit(BuildEvent::Message {
+                message: msg.to_string(),
+            });
+        } else {
+            self.logger.log_section(msg);
+        }
+    }
+
+    /// Log a line, or emit it as an event with `--progress json`.
+    fn log_step(&self, msg: &str) {
+        if self.options.emits_events() {
+            self.emit(BuildEvent::Message {
+                message: msg.to_string(),
+            });
+        } else {
+            self.logger.log_step(msg);
+        }
+    }
+
+    /// Show where the time of each build went, as tables or JSON, or as events with `--progress json`.
+    fn print_timings(&self, timings: Timings, reports: Vec<TimingsReport>) -> Result<()> {
+        for report in reports {
+            match timings {
+                _ if self.options.emits_events() => self.emit(BuildEvent::Timings(report)),
+                Timings::Json => println!("{}", serde_json::to_string(&report)?),
+                Timings::Text => println!("\n{}", report.to_table()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with installation instructions if the engine's CLI can't be run.
+    fn check_engine_installed(&self) -> Result<()> {
+        let engine = self.engine();
+        if engine.command().output().is_err() {
+            return Err(NixpacksError::DockerUnavailable {
+                engine: engine.name().to_string(),
+                install_url: engine.install_url().to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// The engine commands that build the project from a context directory and push it.
+    ///
+    /// When several platforms are given, each is built into its own image, and the images are collected into a
+    /// manifest list named after the image.
+    fn get_build_cmds(
         &self,
         plan: &BuildPlan,
         name: &str,
-        output: &OutputDir,
-    ) -> Result<Command> {
-        let mut docker_build_cmd = Command::new("docker");
+        context: &str,
+        dockerfile: &str,
+    ) -> BuildCmds {
+        let engine = self.engine();
+        let setup = if self.options.limits.is_empty() {
+            None
+        } else {
+            engine.limits_setup_args(&self.options.limits)
+        };
+
+        if !self.is_multi_platform() {
+            let images = std::iter::once(name.to_string())
+                .chain(self.options.tags.clone())
+                .collect::<Vec<_>>();
+            return BuildCmds {
+                builds: vec![(
+                    None,
+                    self.get_docker_build_args(
+                        plan,
+                        &images,
+                        &self.options.platform,
+                        context,
+                        dockerfile,
+                    ),
+                )],
+                push: self.get_push_cmds(name, &[]),
+                setup,
+                ..Default::default()
+            };
+        }
+
+        let platform_images = self
+            .options
+            .platform
+            .iter()
+            .map(|platform| platform_image(name, platform))
+            .collect::<Vec<_>>();
 
-        if docker_build_cmd.output().is_err() {
-            bail!("Please install Docker to build the app https://docs.docker.com/engine/install/")
+        BuildCmds {
+            setup,
+            remove_manifest: engine.remove_manifest_args(name),
+            builds: self
+                .options
+                .platform
+                .iter()
+                .zip(&platform_images)
+                .map(|(platform, image)| {
+                    (
+                        Some(platform.clone()),
+                        self.get_docker_build_args(
+                            plan,
+                            std::slice::from_ref(image),
+                            std::slice::from_ref(platform),
+                            context,
+                            dockerfile,
+                        ),
+                    )
+                })
+                .collect(),
+            create_manifest: engine.manifest_create_args(name, &platform_images),
+            push: self.get_push_cmds(name, &platform_images),
         }
+    }
+
+    /// The arguments to the engine's CLI that build the project from a context directory.
+    fn get_docker_build_args(
+        &self,
+        plan: &BuildPlan,
+        images: &[String],
+        platforms: &[String],
+        context: &str,
+        dockerfile: &str,
+    ) -> Vec<String> {
+        let engine = self.engine();
+        let limited = !self.options.limits.is_empty();
+        let mut args = engine.build_subcommand(self.options.output.is_some() || limited);
+        args.extend([
+            context.to_string(),
+            "-f".to_string(),
+            dockerfile.to_string(),
+        ]);
 
-        // Enable BuildKit for all builds
-        docker_build_cmd.env("DOCKER_BUILDKIT", "1");
+        for image in images {
+            args.push("-t".to_string());
+            args.push(image.clone());
+        }
 
-        docker_build_cmd
-            .arg("build")
-            .arg(&output.root)
-            .arg("-f")
-            .arg(&output.get_absolute_path("Dockerfile"))
-            .arg("-t")
-            .arg(name);
+        // Timings are read from the plain output
+        if self.options.verbose
+            || self.options.progress != Progress::Auto
+            || self.options.log_sink.is_some()
+            || self.options.timings.is_some()
+        {
+            args.extend(engine.verbose_args());
+        }
 
-        if self.options.verbose {
-            docker_build_cmd.arg("--progress=plain");
+        let has_healthcheck = plan
+            .start_phase
+            .as_ref()
+            .and_then(|start| start.healthcheck.as_ref())
+            .map_or(false, |healthcheck| healthcheck.cmd.is_some());
+        if has_healthcheck {
+            args.extend(engine.healthcheck_args());
         }
 
         if self.options.quiet {
-            docker_build_cmd.arg("--quiet");
+            args.push("--quiet".to_string());
         }
 
         if self.options.no_cache {
-            docker_build_cmd.arg("--no-cache");
+            args.push("--no-cache".to_string());
+        }
+
+        if let Some(network) = &self.options.network {
+            args.push("--network".to_string());
+            args.push(network.clone());
+        }
+
+        if limited {
+            args.extend(engine.limits_args(&self.options.limits));
         }
 
         if let Some(value) = &self.options.cache_from {
-            docker_build_cmd.arg("--cache-from").arg(value);
+            args.push("--cache-from".to_string());
+            args.push(value.clone());
         }
 
         if self.options.inline_cache {
-            docker_build_cmd
-                .arg("--build-arg")
-                .arg("BUILDKIT_INLINE_CACHE=1");
+            args.push("--build-arg".to_string());
+            args.push("BUILDKIT_INLINE_CACHE=1".to_string());
         }
 
         // Add build environment variables
         for (name, value) in &plan.variables.clone().unwrap_or_default() {
-            docker_build_cmd
-                .arg("--build-arg")
-                .arg(format!("{name}={value}"));
+            args.push("--build-arg".to_string());
+            args.push(format!("{name}={value}"));
+        }
+        for build_arg in &self.options.build_args {
+            args.push("--build-arg".to_string());
+            args.push(build_arg.clone());
         }
 
-        // Add user defined tags and labels to the image
-        for t in self.options.tags.clone() {
-            docker_build_cmd.arg("-t").arg(t);
+        for secret in &self.options.secrets {
+            args.push("--secret".to_string());
+            args.push(secret.clone());
+        }
+        for agent in &self.options.ssh {
+            args.push("--ssh".to_string());
+            args.push(agent.clone());
         }
+
+        // Add user defined labels to the image
         for l in self.options.labels.clone() {
-            docker_build_cmd.arg("--label").arg(l);
+            args.push("--label".to_string());
+            args.push(l);
         }
-        for l in self.options.platform.clone() {
-            docker_build_cmd.arg("--platform").arg(l);
+        for l in platforms {
+            args.push("--platform".to_string());
+            args.push(l.clone());
         }
 
-        Ok(docker_build_cmd)
+        if let Some(output) = &self.options.output {
+            args.extend(engine.output_args(output));
+        }
+
+        args
+    }
+
+    /// Whether an image is built for each of several platforms.
+    fn is_multi_platform(&self) -> bool {
+        self.options.platform.len() > 1
+    }
+
+    /// The arguments of each command that pushes the image once the build succeeds, paired with the image it pushes.
+    fn get_push_cmds(&self, name: &str, platform_images: &[String]) -> Vec<(String, Vec<String>)> {
+        if !self.options.push {
+            return Vec::new();
+        }
+
+        self.engine()
+            .push_cmds(name, &self.options.tags, platform_images)
+    }
+
+    /// The images to suggest running once the build succeeds.
+    ///
+    /// Docker can only run a multi-platform image from a registry, so until it's pushed each platform's image is listed.
+    fn get_run_images(&self, name: &str) -> Vec<String> {
+        let engine = self.engine();
+        if !self.is_multi_platform()
+            || self.options.push
+            || engine.manifest_create_args(name, &[]).is_some()
+        {
+            return vec![name.to_string()];
+        }
+
+        self.options
+            .platform
+            .iter()
+            .map(|platform| platform_image(name, platform))
+            .collect()
+    }
+
+    /// The names of the built image: the ones it's run with, and its tags when it's a single image.
+    fn get_built_images(&self, name: &str) -> Vec<String> {
+        let mut images = self.get_run_images(name);
+        if images == [name] {
+            images.extend(self.options.tags.clone());
+        }
+        images
+    }
+
+    /// Fill in the ID, size, and digest of the built image from the engine's store, leaving them out if it can't be
+    /// inspected.
+    fn inspect_image(&self, image: &str, result: &mut BuildResult) {
+        let output = self.engine().inspect_args(image).and_then(|args| {
+            self.engine_command()
+                .args(args)
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+        });
+        if let Some(output) = output.filter(|output| output.status.success()) {
+            parse_inspect_output(&String::from_utf8_lossy(&output.stdout), result);
+        }
     }
 
     /// Copies project files to temporary output dir, if that option was used.
@@ -200,3 +775,256 @@ impl DockerImageBuilder {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        builder::docker::{engine::ResourceLimits, progress::LogSink},
+        plan::phase::{Healthcheck, StartPhase},
+    };
+
+    #[test]
+    fn test_push_multi_platform_builds_each_platform() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                push: true,
+                tags: vec!["ghcr.io/acme/app:v1".to_string()],
+                platform: vec!["linux/amd64".to_string(), "linux/arm64".to_string()],
+                ..Default::default()
+            },
+        );
+        let cmds =
+            builder.get_build_cmds(&BuildPlan::default(), "ghcr.io/acme/app", ".", "Dockerfile");
+
+        assert_eq!(cmds.remove_manifest, None);
+        assert_eq!(cmds.builds.len(), 2);
+        let (platform, args) = &cmds.builds[1];
+        assert_eq!(platform.as_deref(), Some("linux/arm64"));
+        assert_eq!(
+            args.join(" "),
+            "build . -f Dockerfile -t ghcr.io/acme/app:latest-linux-arm64 --platform linux/arm64"
+        );
+        assert_eq!(cmds.create_manifest, None);
+        assert_eq!(
+            cmds.push
+                .into_iter()
+                .map(|(_, args)| args[..2].join(" "))
+                .collect::<Vec<_>>(),
+            vec![
+                "push ghcr.io/acme/app:latest-linux-amd64",
+                "push ghcr.io/acme/app:latest-linux-arm64",
+                "buildx imagetools"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_single_platform_after_build() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                push: true,
+                tags: vec!["ghcr.io/acme/app:v1".to_string()],
+                ..Default::default()
+            },
+        );
+        let cmds =
+            builder.get_build_cmds(&BuildPlan::default(), "ghcr.io/acme/app", ".", "Dockerfile");
+
+        assert_eq!(cmds.builds.len(), 1);
+        let (platform, args) = &cmds.builds[0];
+        assert_eq!(platform, &None);
+        assert_eq!(
+            args.join(" "),
+            "build . -f Dockerfile -t ghcr.io/acme/app -t ghcr.io/acme/app:v1"
+        );
+        assert_eq!(
+            cmds.push
+                .into_iter()
+                .map(|(image, _)| image)
+                .collect::<Vec<_>>(),
+            vec!["ghcr.io/acme/app", "ghcr.io/acme/app:v1"]
+        );
+    }
+
+    #[test]
+    fn test_podman_multi_platform_uses_manifest() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                engine: Engine::Podman,
+                push: true,
+                platform: vec!["linux/amd64".to_string(), "linux/arm64".to_string()],
+                ..Default::default()
+            },
+        );
+        let cmds =
+            builder.get_build_cmds(&BuildPlan::default(), "ghcr.io/acme/app", ".", "Dockerfile");
+
+        assert_eq!(
+            cmds.remove_manifest.unwrap().join(" "),
+            "rmi ghcr.io/acme/app"
+        );
+        assert_eq!(cmds.builds.len(), 2);
+        assert_eq!(
+            cmds.create_manifest.unwrap().join(" "),
+            "manifest create ghcr.io/acme/app containers-storage:ghcr.io/acme/app:latest-linux-amd64 containers-storage:ghcr.io/acme/app:latest-linux-arm64"
+        );
+        assert_eq!(
+            cmds.push[0].1.join(" "),
+            "manifest push --all ghcr.io/acme/app docker://ghcr.io/acme/app"
+        );
+    }
+
+    #[test]
+    fn test_docker_multi_platform_run_images() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                platform: vec!["linux/amd64".to_string(), "linux/arm64".to_string()],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            builder.get_run_images("app"),
+            vec!["app:latest-linux-amd64", "app:latest-linux-arm64"]
+        );
+    }
+
+    #[test]
+    fn test_built_images() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                tags: vec!["app:v1".to_string()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(builder.get_built_images("app"), vec!["app", "app:v1"]);
+    }
+
+    #[test]
+    fn test_parse_inspect_output() {
+        let mut result = BuildResult::default();
+        parse_inspect_output(
+            "sha256:0123 52428800 ghcr.io/acme/app@sha256:4567\n",
+            &mut result,
+        );
+        assert_eq!(result.image_id, Some("sha256:0123".to_string()));
+        assert_eq!(result.size_bytes, Some(52_428_800));
+        assert_eq!(result.digest, Some("sha256:4567".to_string()));
+
+        // Images that weren't pushed have no digest
+        let mut result = BuildResult::default();
+        parse_inspect_output("sha256:0123 52428800 \n", &mut result);
+        assert_eq!(result.digest, None);
+    }
+
+    #[test]
+    fn test_build_network() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                network: Some("host".to_string()),
+                ..Default::default()
+            },
+        );
+        let cmds = builder.get_build_cmds(&BuildPlan::default(), "app", ".", "Dockerfile");
+
+        assert_eq!(
+            cmds.builds[0].1.join(" "),
+            "build . -f Dockerfile -t app --network host"
+        );
+    }
+
+    #[test]
+    fn test_log_sink_reads_plain_output() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                log_sink: Some(LogSink::new(|_| {})),
+                ..Default::default()
+            },
+        );
+        let cmds = builder.get_build_cmds(&BuildPlan::default(), "app", ".", "Dockerfile");
+
+        assert_eq!(
+            cmds.builds[0].1.join(" "),
+            "build . -f Dockerfile -t app --progress=plain"
+        );
+    }
+
+    #[test]
+    fn test_docker_limits_use_own_builder() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                limits: ResourceLimits {
+                    memory: Some("2g".to_string()),
+                    cpus: None,
+                },
+                ..Default::default()
+            },
+        );
+        let cmds = builder.get_build_cmds(&BuildPlan::default(), "app", ".", "Dockerfile");
+
+        assert_eq!(
+            cmds.setup.unwrap().join(" "),
+            "buildx create --name nixpacks-memory-2g-cpus-any --driver docker-container --driver-opt memory=2g"
+        );
+        assert_eq!(
+            cmds.builds[0].1.join(" "),
+            "buildx build . -f Dockerfile -t app --builder nixpacks-memory-2g-cpus-any --load"
+        );
+    }
+
+    #[test]
+    fn test_podman_limits() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                engine: Engine::Podman,
+                limits: ResourceLimits {
+                    memory: None,
+                    cpus: Some(2.0),
+                },
+                ..Default::default()
+            },
+        );
+        let cmds = builder.get_build_cmds(&BuildPlan::default(), "app", ".", "Dockerfile");
+
+        assert_eq!(cmds.setup, None);
+        assert_eq!(
+            cmds.builds[0].1.join(" "),
+            "build . -f Dockerfile -t app --cpu-period 100000 --cpu-quota 200000"
+        );
+    }
+
+    #[test]
+    fn test_podman_keeps_healthcheck() {
+        let builder = DockerImageBuilder::new(
+            Logger::new(),
+            DockerBuilderOptions {
+                engine: Engine::Podman,
+                ..Default::default()
+            },
+        );
+        let mut plan = BuildPlan::default();
+        plan.set_start_phase(StartPhase {
+            healthcheck: Some(Healthcheck {
+                cmd: Some("curl -f localhost:3000".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let cmds = builder.get_build_cmds(&plan, "app", ".", "Dockerfile");
+
+        assert_eq!(
+            cmds.builds[0].1.join(" "),
+            "build . -f Dockerfile -t app --format docker"
+        );
+    }
+}
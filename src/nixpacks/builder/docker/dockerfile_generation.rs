@@ -1,18 +1,26 @@
 use super::{
-    file_server::FileServerConfig, incremental_cache::IncrementalCache, utils, DockerBuilderOptions,
+    file_server::FileServerConfig, incremental_cache::IncrementalCache, utils,
+    DockerBuilderOptions, Runtime,
 };
+pub use crate::nixpacks::plan::APP_DIR;
 use crate::nixpacks::{
     app,
     environment::Environment,
-    images::DEFAULT_BASE_IMAGE,
-    nix::{create_nix_expressions_for_phases, nix_file_names_for_phases, setup_files_for_phases},
+    images::{
+        DEBIAN_BASE_IMAGE, DEFAULT_BASE_IMAGE, SCRATCH_IMAGE, STANDALONE_IMAGE, UBUNTU_BASE_IMAGE,
+    },
+    nix::{
+        create_nix_expressions_for_phases, nix_file_names_for_phases, setup_files_for_phases,
+        NixConfig,
+    },
     plan::{
-        phase::{Phase, StartPhase},
+        phase::{Healthcheck, Phase, StartPhase},
         BuildPlan,
     },
+    utils::shell_quote,
 };
-use anyhow::{Context, Ok, Result};
-use indoc::formatdoc;
+use anyhow::{bail, Context, Ok, Result};
+use indoc::{formatdoc, indoc};
 use path_slash::PathBufExt;
 use std::{
     fs::{self, File},
@@ -21,7 +29,39 @@ use std::{
 };
 
 const NIXPACKS_OUTPUT_DIR: &str = ".nixpacks";
-pub const APP_DIR: &str = "/app/";
+
+/// Written before a phase runs in its own stage, to find the files the phase changed.
+const BRANCH_MARKER: &str = "/tmp/.nixpacks-branch";
+
+/// The archive of the files a phase running in its own stage changed outside of the app.
+const BRANCH_CHANGES: &str = "/tmp/nixpacks-branch.tar";
+
+/// Where the build stage of a slim image collects the files the final stage copies to its root.
+const SLIM_RUNTIME_DIR: &str = "/nixpacks-runtime";
+
+/// The layout of the generated Dockerfile. Each `{{placeholder}}` is replaced with a section generated from the plan.
+pub const DEFAULT_DOCKERFILE_TEMPLATE: &str = indoc! {r#"
+    FROM {{base_image}}
+    {{base_image_check}}
+
+    ENTRYPOINT ["/bin/bash", "-l", "-c"]
+    WORKDIR {{app_dir}}
+
+    {{setup_copy_cmds}}
+    {{nix_install_cmds}}
+    {{apt_pkgs}}
+    {{assets_copy_cmd}}
+    {{variables}}
+    {{build_args}}
+
+    {{phases}}
+
+    {{start}}
+
+"#};
+
+/// The name of the first stage, when phases run in parallel stages.
+const BUILD_STAGE: &str = "nixpacks-build";
 
 /// Represents a directory into which project files and generated assets like Dockerfiles are written.
 #[derive(Debug, Clone)]
@@ -117,6 +157,19 @@ impl DockerfileGenerator for BuildPlan {
         let nix_file_names = nix_file_names_for_phases(&plan.phases.clone().unwrap_or_default());
 
         let mut nix_install_cmds: Vec<String> = Vec::new();
+        let nix_conf_lines = plan
+            .nix
+            .as_ref()
+            .map(NixConfig::get_nix_conf_lines)
+            .unwrap_or_default();
+        if !nix_conf_lines.is_empty() {
+            let lines = nix_conf_lines
+                .iter()
+                .map(|line| shell_quote(line))
+                .collect::<Vec<_>>()
+                .join(" ");
+            nix_install_cmds.push(format!("RUN printf '%s\\n' {lines} >> /etc/nix/nix.conf"));
+        }
         for name in nix_file_names {
             let nix_file = output.get_relative_path(name);
 
@@ -161,6 +214,9 @@ impl DockerfileGenerator for BuildPlan {
             )
         };
 
+        // Unlike plan variables, these aren't set in the image
+        let build_args_string = utils::get_build_arg_declaration(&options.build_args)?;
+
         let static_assets = plan.static_assets.clone().unwrap_or_default();
         let assets_copy_cmd = if static_assets.is_empty() {
             String::new()
@@ -172,78 +228,120 @@ impl DockerfileGenerator for BuildPlan {
             format!("COPY {rel_assets_slash_path} {}", app::ASSETS_DIR)
         };
 
-        let phases = plan.get_sorted_phases()?;
-
-        let mut dockerfile_phases = phases
-            .into_iter()
-            .map(|phase| {
-                let phase_dockerfile = phase
-                    .generate_dockerfile(options, env, output, file_server_config.clone())
-                    .context(format!(
-                        "Generating Dockerfile for phase {}",
-                        phase.get_name()
-                    ))?;
-
-                Ok(phase_dockerfile)
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        let profile_dockerfile = plan
-            .get_sorted_phases()?
-            .into_iter()
-            .map(|phase| {
-                // Ensure paths are available in the environment
-                let profile_dockerfile = if let Some(paths) = &phase.paths {
-                    let joined_paths = paths.join(":");
-                    format!("RUN printf '\\nPATH={joined_paths}:$PATH' >> /root/.profile")
-                } else {
-                    String::new()
-                };
-
-                Ok(profile_dockerfile)
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        dockerfile_phases.extend(profile_dockerfile.iter().cloned());
+        if options.squash && options.parallel_phases {
+            bail!("--squash can't be combined with --parallel-phases");
+        }
+        if options.squash && options.uses_incremental_cache() {
+            bail!(
+                "--squash can't be combined with --incremental-cache-image or --incremental-cache"
+            );
+        }
 
-        let dockerfile_phases_str = dockerfile_phases.join("\n");
+        let phases = plan.get_sorted_phases()?;
+        let levels = plan.get_phase_levels()?;
+        let runs_in_parallel = options.parallel_phases
+            && levels.iter().any(|level| {
+                level
+                    .iter()
+                    .filter(|phase| phase.runs_docker_commands())
+                    .count()
+                    > 1
+            });
+
+        // The stage the image's files are copied from, when the start runs in another image
+        let (dockerfile_phases_str, build_stage) = if options.squash {
+            (
+                get_squashed_phases_dockerfile(&phases, options, env)?,
+                "0".to_string(),
+            )
+        } else if runs_in_parallel {
+            get_parallel_phases_dockerfile(
+                &levels,
+                options,
+                env,
+                output,
+                file_server_config.as_ref(),
+            )?
+        } else {
+            (
+                get_phases_dockerfile(
+                    &phases,
+                    options,
+                    env,
+                    output,
+                    file_server_config.as_ref(),
+                    &assets_copy_cmd,
+                )?,
+                "0".to_string(),
+            )
+        };
+        // Phases in their own layers copy the assets right before they're needed instead
+        let assets_copy_cmd = if options.squash || runs_in_parallel {
+            assets_copy_cmd
+        } else {
+            String::new()
+        };
 
-        let start_phase_str = plan
-            .start_phase
-            .clone()
-            .unwrap_or_default()
-            .generate_dockerfile(options, env, output, file_server_config)?;
+        let start_phase = plan.start_phase.clone().unwrap_or_default();
+        // Warming the cache stops at the installed packages, before the app itself is copied in
+        let start_phase_str = if options.cache_only && options.with_source {
+            format!("COPY . {APP_DIR}")
+        } else if options.cache_only {
+            String::new()
+        } else if start_phase.runs_in_scratch_image() {
+            get_scratch_start_dockerfile(&start_phase, &args_string, &build_stage)?
+        } else if options.runtime == Runtime::Slim {
+            get_slim_start_dockerfile(&start_phase, &args_string, &build_stage)
+        } else {
+            get_start_dockerfile(&start_phase, &build_stage)
+        };
 
         let base_image = plan
             .build_image
             .clone()
             .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
 
-        let dockerfile = formatdoc! {"
-            FROM {base_image}
-
-            ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
-            WORKDIR {APP_DIR}
-
-            {setup_copy_cmds}
-            {nix_install_cmds}
-            {apt_pkgs_str}
-            {assets_copy_cmd}
-            {args_string}
+        // Other base images, like internal mirrors, have to provide Nix to install packages with
+        let base_image_check = if [DEBIAN_BASE_IMAGE, UBUNTU_BASE_IMAGE]
+            .contains(&base_image.as_str())
+        {
+            String::new()
+        } else {
+            format!(
+                "RUN command -v nix-env > /dev/null || (echo 'The base image {base_image} needs Nix installed, like {DEFAULT_BASE_IMAGE}' >&2 && exit 1)"
+            )
+        };
 
-            {dockerfile_phases_str}
+        let template = match &options.dockerfile_template {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Reading Dockerfile template {path}"))?,
+            None => DEFAULT_DOCKERFILE_TEMPLATE.to_string(),
+        };
 
-            {start_phase_str}
-        ", 
-        base_image=base_image,
-        APP_DIR=APP_DIR,
-        setup_copy_cmds=setup_copy_cmds,
-        nix_install_cmds=nix_install_cmds,
-        apt_pkgs_str=apt_pkgs_str,
-        assets_copy_cmd=assets_copy_cmd,
-        args_string=args_string,
-        dockerfile_phases_str=dockerfile_phases_str,
-        start_phase_str=start_phase_str};
+        let dockerfile = utils::render_template(
+            &template,
+            &[
+                // Phases running in parallel branch off of the first stage by its name
+                (
+                    "base_image",
+                    if runs_in_parallel {
+                        format!("{base_image} AS {BUILD_STAGE}")
+                    } else {
+                        base_image
+                    },
+                ),
+                ("base_image_check", base_image_check),
+                ("app_dir", APP_DIR.to_string()),
+                ("setup_copy_cmds", setup_copy_cmds),
+                ("nix_install_cmds", nix_install_cmds),
+                ("apt_pkgs", apt_pkgs_str),
+                ("assets_copy_cmd", assets_copy_cmd),
+                ("variables", args_string),
+                ("build_args", build_args_string),
+                ("phases", dockerfile_phases_str),
+                ("start", start_phase_str),
+            ],
+        )?;
 
         Ok(dockerfile)
     }
@@ -322,46 +420,7 @@ impl DockerfileGenerator for StartPhase {
         _output: &OutputDir,
         _file_server_config: Option<FileServerConfig>,
     ) -> Result<String> {
-        let start_cmd = match &self.cmd {
-            Some(cmd) => utils::get_exec_command(cmd),
-            None => String::new(),
-        };
-
-        let dockerfile: String = match &self.run_image {
-            Some(run_image) => {
-                let copy_cmds = utils::get_copy_from_commands(
-                    "0",
-                    &self.only_include_files.clone().unwrap_or_default(),
-                    APP_DIR,
-                );
-
-                // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
-                formatdoc! {"
-                  # start
-                  FROM {run_image}
-                  ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
-                  WORKDIR {APP_DIR}
-                  COPY --from=0 /etc/ssl/certs /etc/ssl/certs
-                  RUN true
-                  {copy_cmds}
-                  {start_cmd}
-                ",
-                run_image=run_image,
-                APP_DIR=APP_DIR,
-                copy_cmds=copy_cmds.join("\n"),
-                start_cmd=start_cmd,}
-            }
-            None => {
-                formatdoc! {"
-                  # start
-                  COPY . /app
-                  {}
-                ",
-                start_cmd}
-            }
-        };
-
-        Ok(dockerfile)
+        Ok(get_start_dockerfile(self, "0"))
     }
 }
 
@@ -371,7 +430,7 @@ impl DockerfileGenerator for Phase {
         &self,
         options: &DockerBuilderOptions,
         env: &Environment,
-        _output: &OutputDir,
+        output: &OutputDir,
         file_server_config: Option<FileServerConfig>,
     ) -> Result<String> {
         if !self.runs_docker_commands() {
@@ -380,11 +439,7 @@ impl DockerfileGenerator for Phase {
 
         let phase = self;
 
-        let cache_key = if !options.no_cache && !env.is_config_variable_truthy("NO_CACHE") {
-            options.cache_key.clone()
-        } else {
-            None
-        };
+        let cache_key = get_cache_key(options, env);
 
         // Ensure paths are available in the environment
         let build_path = if let Some(paths) = &phase.paths {
@@ -402,17 +457,30 @@ impl DockerfileGenerator for Phase {
         let phase_copy_cmds = utils::get_copy_commands(&phase_files, APP_DIR);
 
         let cache_mount = utils::get_cache_mount(&cache_key, &phase.cache_directories);
-        let cmds_str = if options.incremental_cache_image.is_some() {
-            let image = &options.incremental_cache_image.clone().unwrap();
-            let cache_copy_in_command = if IncrementalCache::is_image_exists(image)? {
-                IncrementalCache::get_copy_to_image_command(&phase.cache_directories, image)
-                    .join("\n")
-            } else {
-                String::new()
+        // Credentials are mounted for every command, but never written to a layer
+        let credential_mounts = get_credential_mounts(options)?;
+        let mounts = [cache_mount.as_str(), credential_mounts.as_str()]
+            .into_iter()
+            .filter(|mount| !mount.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let cmds_str = if options.uses_incremental_cache() {
+            let dirs = [
+                phase.cache_directories.clone().unwrap_or_default(),
+                phase.persisted_directories.clone().unwrap_or_default(),
+            ]
+            .concat();
+            let cache_copy_in_command = match &options.incremental_cache_image {
+                Some(image) if IncrementalCache::is_image_exists(image)? => {
+                    IncrementalCache::get_copy_to_image_command(&Some(dirs), image).join("\n")
+                }
+                Some(_) => String::new(),
+                None => IncrementalCache::get_copy_from_context_command(&dirs, output).join("\n"),
             };
 
             let cache_copy_out_command = IncrementalCache::get_copy_from_image_command(
                 &phase.cache_directories,
+                &phase.persisted_directories,
                 file_server_config,
             );
 
@@ -422,7 +490,13 @@ impl DockerfileGenerator for Phase {
             ]
             .concat()
             .iter()
-            .map(|s| format!("RUN {s}"))
+            .map(|s| {
+                if credential_mounts.is_empty() {
+                    format!("RUN {s}")
+                } else {
+                    format!("RUN {credential_mounts} {s}")
+                }
+            })
             .collect::<Vec<_>>()
             .join("\n");
 
@@ -433,7 +507,7 @@ impl DockerfileGenerator for Phase {
                 .clone()
                 .unwrap_or_default()
                 .iter()
-                .map(|s| format!("RUN {cache_mount} {s}"))
+                .map(|s| format!("RUN {mounts} {s}"))
                 .collect::<Vec<_>>()
                 .join("\n")
         };
@@ -465,9 +539,513 @@ impl DockerfileGenerator for Phase {
     }
 }
 
+/// The key cache mounts are shared under, unless caching is disabled.
+fn get_cache_key(options: &DockerBuilderOptions, env: &Environment) -> Option<String> {
+    if !options.no_cache && !env.is_config_variable_truthy("NO_CACHE") {
+        options.cache_key.clone()
+    } else {
+        None
+    }
+}
+
+/// Mounts for the secrets and SSH agents of every command. They're never written to a layer.
+fn get_credential_mounts(options: &DockerBuilderOptions) -> Result<String> {
+    Ok([
+        utils::get_secret_mounts(&options.secrets)?,
+        utils::get_ssh_mounts(&options.ssh),
+    ]
+    .into_iter()
+    .filter(|mount| !mount.is_empty())
+    .collect::<Vec<_>>()
+    .join(" "))
+}
+
+/// The command adding the phase's paths to the login shell's PATH, so they're available when the app starts.
+fn get_profile_cmd(phase: &Phase) -> Option<String> {
+    phase.paths.as_ref().map(|paths| {
+        let joined_paths = paths.join(":");
+        format!("printf '\\nPATH={joined_paths}:$PATH' >> /root/.profile")
+    })
+}
+
+/// Returns the Dockerfile instructions of every phase, each in its own layers. The static assets are copied right
+/// before the first phase using them, or after the last one, so changing them doesn't rerun the phases before.
+fn get_phases_dockerfile(
+    phases: &[Phase],
+    options: &DockerBuilderOptions,
+    env: &Environment,
+    output: &OutputDir,
+    file_server_config: Option<&FileServerConfig>,
+    assets_copy_cmd: &str,
+) -> Result<String> {
+    let mut dockerfile_phases = phases
+        .iter()
+        .map(|phase| {
+            let phase_dockerfile = phase
+                .generate_dockerfile(options, env, output, file_server_config.cloned())
+                .context(format!(
+                    "Generating Dockerfile for phase {}",
+                    phase.get_name()
+                ))?;
+
+            Ok(phase_dockerfile)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let profile_dockerfile = phases
+        .iter()
+        .map(|phase| {
+            // Ensure paths are available in the environment
+            let profile_dockerfile = get_profile_cmd(phase)
+                .map(|cmd| format!("RUN {cmd}"))
+                .unwrap_or_default();
+
+            Ok(profile_dockerfile)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if !assets_copy_cmd.is_empty() {
+        let first_using_assets = phases
+            .iter()
+            .position(uses_assets)
+            .unwrap_or(dockerfile_phases.len());
+        dockerfile_phases.insert(first_using_assets, assets_copy_cmd.to_string());
+    }
+
+    dockerfile_phases.extend(profile_dockerfile.iter().cloned());
+
+    Ok(dockerfile_phases.join("\n"))
+}
+
+/// Whether the phase reads the static assets, in its commands or files.
+fn uses_assets(phase: &Phase) -> bool {
+    [&phase.cmds, &phase.only_include_files]
+        .iter()
+        .filter_map(|values| values.as_ref())
+        .flatten()
+        .any(|value| value.contains(app::ASSETS_DIR))
+}
+
+/// The start of the image, in the build stage or in its run image, which the app is copied to from `build_stage`.
+fn get_start_dockerfile(start_phase: &StartPhase, build_stage: &str) -> String {
+    let start_cmd = match &start_phase.cmd {
+        Some(cmd) => utils::get_exec_command(cmd),
+        None => String::new(),
+    };
+    let start_cmd = format!(
+        "{}{}{}{start_cmd}",
+        get_expose_cmd(start_phase.ports.as_deref()),
+        get_healthcheck_cmd(start_phase.healthcheck.as_ref()),
+        get_user_cmds(start_phase.user.as_deref())
+    );
+
+    match &start_phase.run_image {
+        Some(run_image) => {
+            let copy_cmds = utils::get_copy_from_commands(
+                build_stage,
+                &start_phase.only_include_files.clone().unwrap_or_default(),
+                APP_DIR,
+            );
+
+            // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
+            formatdoc! {"
+              # start
+              FROM {run_image}
+              ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
+              WORKDIR {APP_DIR}
+              COPY --from={build_stage} /etc/ssl/certs /etc/ssl/certs
+              RUN true
+              {copy_cmds}
+              {start_cmd}
+            ",
+            copy_cmds=copy_cmds.join("\n"),}
+        }
+        None => {
+            formatdoc! {"
+              # start
+              COPY . /app
+              {}
+            ",
+            start_cmd}
+        }
+    }
+}
+
+/// The EXPOSE instruction documenting the ports the app listens on, if there are any.
+fn get_expose_cmd(ports: Option<&[String]>) -> String {
+    match ports {
+        Some(ports) if !ports.is_empty() => format!("EXPOSE {}\n", ports.join(" ")),
+        _ => String::new(),
+    }
+}
+
+/// The HEALTHCHECK instruction for the healthcheck, if it has a command. Like the start command, the check runs in a
+/// login shell so it finds the app's packages.
+fn get_healthcheck_cmd(healthcheck: Option<&Healthcheck>) -> String {
+    let (healthcheck, cmd) = match healthcheck {
+        Some(healthcheck @ Healthcheck { cmd: Some(cmd), .. }) => (healthcheck, cmd),
+        _ => return String::new(),
+    };
+
+    let mut options = Vec::new();
+    if let Some(interval) = &healthcheck.interval {
+        options.push(format!("--interval={interval}"));
+    }
+    if let Some(timeout) = &healthcheck.timeout {
+        options.push(format!("--timeout={timeout}"));
+    }
+    if let Some(retries) = healthcheck.retries {
+        options.push(format!("--retries={retries}"));
+    }
+    // Each argument is a JSON string, so quotes, backslashes, and control characters in the command are escaped
+    let args = ["/bin/bash", "-l", "-c", cmd]
+        .iter()
+        .map(|arg| serde_json::to_string(arg).expect("BUG: Strings always serialize"))
+        .collect::<Vec<_>>();
+    options.push(format!("CMD [{}]", args.join(", ")));
+
+    format!("HEALTHCHECK {}\n", options.join(" "))
+}
+
+/// Dockerfile lines that switch to the user the app runs as, creating it if needed and giving it the app directory.
+///
+/// The user gets root's login profile and Nix profile, so the start command finds the same packages.
+fn get_user_cmds(user: Option<&str>) -> String {
+    let user = match user {
+        Some(user) => user,
+        None => return String::new(),
+    };
+    let quoted = shell_quote(user);
+
+    formatdoc! {"
+      RUN (id -u {quoted} > /dev/null 2>&1 || useradd --create-home --user-group {quoted}) \\
+        && home=$(getent passwd {quoted} | cut -d: -f6) && mkdir -p $home \\
+        && if [ -f /root/.profile ]; then cp /root/.profile $home/.profile; fi \\
+        && if [ -e /root/.nix-profile ]; then ln -sfn $(readlink -f /root/.nix-profile) $home/.nix-profile; fi \\
+        && chown -R {quoted}: $home {APP_DIR}
+      USER {user}
+    "}
+}
+
+/// The final stage of a slim image: the app's files and the closure of its Nix packages, copied onto a minimal base
+/// image without the rest of the build environment. The plan's variables are declared again, since stages don't share
+/// them.
+fn get_slim_start_dockerfile(
+    start_phase: &StartPhase,
+    variables: &str,
+    build_stage: &str,
+) -> String {
+    let run_image = start_phase
+        .run_image
+        .clone()
+        .unwrap_or_else(|| STANDALONE_IMAGE.to_string());
+    let start_cmd = start_phase
+        .cmd
+        .as_ref()
+        .map(|cmd| utils::get_exec_command(cmd))
+        .unwrap_or_default();
+    let start_cmd = format!(
+        "{}{}{}{start_cmd}",
+        get_expose_cmd(start_phase.ports.as_deref()),
+        get_healthcheck_cmd(start_phase.healthcheck.as_ref()),
+        get_user_cmds(start_phase.user.as_deref())
+    );
+    let copy_cmds = utils::get_copy_from_commands(
+        build_stage,
+        &start_phase.only_include_files.clone().unwrap_or_default(),
+        APP_DIR,
+    );
+
+    // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
+    formatdoc! {"
+      # runtime closure
+      RUN mkdir -p {SLIM_RUNTIME_DIR}/root && cp /root/.profile {SLIM_RUNTIME_DIR}/root/.profile \\
+        && if [ -e /root/.nix-profile ]; then \\
+          cp -a --parents $(nix-store -qR $(readlink -f /root/.nix-profile)) {SLIM_RUNTIME_DIR} \\
+          && ln -s $(readlink -f /root/.nix-profile) {SLIM_RUNTIME_DIR}/root/.nix-profile; \\
+        fi
+
+      # start
+      FROM {run_image}
+      ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]
+      WORKDIR {APP_DIR}
+      COPY --from={build_stage} /etc/ssl/certs /etc/ssl/certs
+      RUN true
+      COPY --from={build_stage} {SLIM_RUNTIME_DIR} /
+      ENV PATH=/root/.nix-profile/bin:$PATH
+      {variables}
+      {copy_cmds}
+      {start_cmd}
+    ",
+    copy_cmds=copy_cmds.join("\n"),}
+}
+
+/// The final stage of an image for a statically linked app: only the app's files on an empty image. There is no shell,
+/// so the start command is run directly and can't use shell syntax, and a user to run as must already be known by ID.
+fn get_scratch_start_dockerfile(
+    start_phase: &StartPhase,
+    variables: &str,
+    build_stage: &str,
+) -> Result<String> {
+    let start_cmd = match &start_phase.cmd {
+        Some(cmd) => {
+            if cmd.contains(|c: char| "'\"\\$`;&|<>()*?~#\n".contains(c)) {
+                bail!(
+                    "The start command `{cmd}` needs a shell, which the scratch image doesn't have. Start the app with its binary and plain arguments, without quotes or shell operators"
+                );
+            }
+
+            let params = cmd
+                .split_whitespace()
+                .map(|param| format!("\"{param}\""))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("CMD [{params}]")
+        }
+        None => String::new(),
+    };
+    let user_cmd = start_phase
+        .user
+        .as_ref()
+        .map(|user| format!("USER {user}\n"))
+        .unwrap_or_default();
+    let copy_cmds = utils::get_copy_from_commands(
+        build_stage,
+        &start_phase.only_include_files.clone().unwrap_or_default(),
+        APP_DIR,
+    );
+
+    Ok(formatdoc! {"
+      # start
+      FROM {SCRATCH_IMAGE}
+      WORKDIR {APP_DIR}
+      COPY --from={build_stage} /etc/ssl/certs /etc/ssl/certs
+      {variables}
+      {copy_cmds}
+      {expose_cmd}{user_cmd}{start_cmd}
+    ",
+    copy_cmds=copy_cmds.join("\n"),
+    expose_cmd=get_expose_cmd(start_phase.ports.as_deref()),})
+}
+
+/// Returns the Dockerfile instructions of every phase, where the phases of a level that don't depend on each other
+/// each run in their own stage, so BuildKit runs them at the same time. The stages are then merged into a new stage,
+/// which the next level runs in. Returns the name of the last stage too.
+fn get_parallel_phases_dockerfile(
+    levels: &[Vec<Phase>],
+    options: &DockerBuilderOptions,
+    env: &Environment,
+    output: &OutputDir,
+    file_server_config: Option<&FileServerConfig>,
+) -> Result<(String, String)> {
+    // Build args are only declared in the stage they're declared in, so every stage declares them again
+    let build_args = utils::get_build_arg_declaration(&options.build_args)?;
+    let from = |image: &str, stage: &str| {
+        if build_args.is_empty() {
+            format!("FROM {image} AS {stage}")
+        } else {
+            format!("FROM {image} AS {stage}\n{build_args}")
+        }
+    };
+    let mut stage = BUILD_STAGE.to_string();
+    let mut dockerfile_phases = Vec::new();
+
+    for (index, level) in levels.iter().enumerate() {
+        let (branches, noops): (Vec<&Phase>, Vec<&Phase>) =
+            level.iter().partition(|phase| phase.runs_docker_commands());
+        let sequential = if branches.len() < 2 {
+            level.iter().collect()
+        } else {
+            noops
+        };
+
+        for phase in sequential {
+            dockerfile_phases.push(
+                phase
+                    .generate_dockerfile(options, env, output, file_server_config.cloned())
+                    .context(format!(
+                        "Generating Dockerfile for phase {}",
+                        phase.get_name()
+                    ))?,
+            );
+        }
+        if branches.len() < 2 {
+            continue;
+        }
+
+        let merged_stage = format!("{BUILD_STAGE}-{index}");
+        let mut merge_cmds = vec![from(&stage, &merged_stage)];
+        for phase in &branches {
+            let branch_stage = get_phase_stage(phase);
+            let phase_dockerfile = phase
+                .generate_dockerfile(options, env, output, file_server_config.cloned())
+                .context(format!(
+                    "Generating Dockerfile for phase {}",
+                    phase.get_name()
+                ))?;
+            dockerfile_phases.push(format!(
+                "{}\nRUN touch {BRANCH_MARKER}\n{phase_dockerfile}{}\n",
+                from(&stage, &branch_stage),
+                get_branch_changes_cmd()
+            ));
+            merge_cmds.extend(get_merge_cmds(phase, &branch_stage));
+        }
+
+        let names = branches
+            .iter()
+            .map(|phase| phase.get_name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        dockerfile_phases.push(format!(
+            "# merge {names} phases\n{}\n",
+            merge_cmds.join("\n")
+        ));
+        stage = merged_stage;
+    }
+
+    // Ensure paths are available in the environment
+    dockerfile_phases.extend(
+        levels
+            .iter()
+            .flatten()
+            .filter_map(get_profile_cmd)
+            .map(|cmd| format!("RUN {cmd}")),
+    );
+
+    Ok((dockerfile_phases.join("\n"), stage))
+}
+
+/// The name of the stage a phase runs in when it runs in parallel, e.g. `nixpacks-node-install` for `node:install`.
+fn get_phase_stage(phase: &Phase) -> String {
+    let name = phase
+        .get_name()
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+    format!("nixpacks-{name}")
+}
+
+/// Archives every file a phase created or changed outside of the app in its own stage, found by comparing their change
+/// times to the marker written before the phase ran, for merging them into the next stage.
+fn get_branch_changes_cmd() -> String {
+    format!(
+        "RUN find / -xdev -cnewer {BRANCH_MARKER} -not -path '/app' -not -path '/app/*' -not -path '/tmp' -not -path '/tmp/*' -print0 | tar --null --no-recursion -cf {BRANCH_CHANGES} -T -"
+    )
+}
+
+/// Copies what a phase wrote in its own stage into the merged stage: the app, and everything it wrote outside of it,
+/// like its persisted directories, the directories on its paths, and global installs. Its paths are added to
+/// `NIXPACKS_PATH` again since stages don't share it. Files the phase removed outside of the app stay removed only in
+/// its own stage.
+fn get_merge_cmds(phase: &Phase, branch_stage: &str) -> Vec<String> {
+    let mut cmds = vec![
+        format!("COPY --from={branch_stage} {APP_DIR} {APP_DIR}"),
+        format!(
+            "RUN --mount=type=bind,from={branch_stage},source={BRANCH_CHANGES},target={BRANCH_CHANGES} tar -xpf {BRANCH_CHANGES} -C /"
+        ),
+    ];
+    if let Some(paths) = &phase.paths {
+        cmds.push(format!(
+            "ENV NIXPACKS_PATH {}:$NIXPACKS_PATH",
+            paths.join(":")
+        ));
+    }
+
+    cmds
+}
+
+/// Collapse the phases into a single copy of the app and a single `RUN` of all their commands, so the image has one
+/// layer for them and files a later command removes never end up in it.
+fn get_squashed_phases_dockerfile(
+    phases: &[Phase],
+    options: &DockerBuilderOptions,
+    env: &Environment,
+) -> Result<String> {
+    let phases = phases
+        .iter()
+        .filter(|phase| phase.runs_docker_commands())
+        .collect::<Vec<_>>();
+    if phases.is_empty() {
+        return Ok(String::new());
+    }
+
+    // Later phases' paths come first, as if each phase had prepended its own
+    let paths = phases
+        .iter()
+        .rev()
+        .filter_map(|phase| phase.paths.as_ref())
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    let build_path = if paths.is_empty() {
+        String::new()
+    } else {
+        format!("ENV NIXPACKS_PATH {}:$NIXPACKS_PATH", paths.join(":"))
+    };
+
+    let mut files: Vec<String> = Vec::new();
+    for phase in &phases {
+        if let Some(phase_files) = &phase.only_include_files {
+            for file in phase_files {
+                if !files.contains(file) {
+                    files.push(file.clone());
+                }
+            }
+        } else {
+            files = vec![".".to_string()];
+            break;
+        }
+    }
+    let copy_cmds = utils::get_copy_commands(&files, APP_DIR).join("\n");
+
+    let cache_key = get_cache_key(options, env);
+    let mut mounts: Vec<String> = Vec::new();
+    for phase in &phases {
+        let cache_mount = utils::get_cache_mount(&cache_key, &phase.cache_directories);
+        for mount in cache_mount.split_whitespace() {
+            if !mounts.iter().any(|m| m == mount) {
+                mounts.push(mount.to_string());
+            }
+        }
+    }
+    let credential_mounts = get_credential_mounts(options)?;
+    if !credential_mounts.is_empty() {
+        mounts.push(credential_mounts);
+    }
+
+    // Each command runs in its own shell like it would in its own RUN, so operators, comments, and `cd`s in one
+    // command don't change how the commands after it run
+    let cmds = phases
+        .iter()
+        .flat_map(|phase| phase.cmds.clone().unwrap_or_default())
+        .map(|cmd| format!("sh -c {}", shell_quote(&cmd)))
+        .chain(phases.iter().filter_map(|phase| get_profile_cmd(phase)))
+        .collect::<Vec<_>>()
+        .join(" && ");
+    let run_cmd = if mounts.is_empty() {
+        format!("RUN {cmds}")
+    } else {
+        format!("RUN {} {cmds}", mounts.join(" "))
+    };
+
+    let names = phases
+        .iter()
+        .map(|phase| phase.get_name())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let dockerfile_stmts = [build_path, copy_cmds, run_cmd]
+        .into_iter()
+        .filter(|stmt| !stmt.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(format!("# {names} phases, squashed\n{dockerfile_stmts}\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
+    use tempdir::TempDir;
 
     #[test]
     fn test_phase_generation() {
@@ -487,6 +1065,47 @@ mod tests {
         assert!(dockerfile.contains("echo test"));
     }
 
+    #[test]
+    fn test_phase_generation_with_secrets() {
+        let mut phase = Phase::new("install");
+        phase.add_cmd("npm ci");
+
+        let dockerfile = phase
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    secrets: vec!["id=npmrc,src=/home/me/.npmrc".to_string()],
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("RUN --mount=type=secret,id=npmrc npm ci"));
+    }
+
+    #[test]
+    fn test_phase_generation_with_ssh() {
+        let mut phase = Phase::new("install");
+        phase.add_cmd("cargo fetch");
+
+        let dockerfile = phase
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    cache_key: Some("app".to_string()),
+                    ssh: vec!["default".to_string()],
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("RUN --mount=type=ssh,id=default cargo fetch"));
+    }
+
     #[test]
     fn test_plan_generation() {
         let mut plan = BuildPlan::default();
@@ -514,4 +1133,474 @@ mod tests {
         assert!(dockerfile.contains("apt-get update"));
         assert!(dockerfile.contains("wget"));
     }
+
+    #[test]
+    fn test_plan_generation_copies_assets_before_use() {
+        let mut plan = BuildPlan {
+            static_assets: Some(BTreeMap::from([(
+                "nginx.conf".to_string(),
+                "daemon off;".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let mut install = Phase::new("install");
+        install.add_cmd("npm ci");
+        plan.add_phase(install);
+        let mut build = Phase::new("build");
+        build.add_cmd("cp /assets/nginx.conf nginx.conf");
+        build.depends_on_phase("install");
+        plan.add_phase(build);
+
+        let generate = |plan: &BuildPlan| {
+            plan.generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap()
+        };
+
+        let dockerfile = generate(&plan);
+        let assets = dockerfile.find("COPY .nixpacks/assets /assets/").unwrap();
+        assert!(dockerfile.find("RUN  npm ci").unwrap() < assets);
+        assert!(assets < dockerfile.find("# build phase").unwrap());
+
+        plan.phases.as_mut().unwrap().remove("build");
+        let dockerfile = generate(&plan);
+        assert!(
+            dockerfile.find("RUN  npm ci").unwrap()
+                < dockerfile.find("COPY .nixpacks/assets /assets/").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_plan_generation_with_custom_base_image() {
+        let mut plan = BuildPlan {
+            build_image: Some("registry.acme.internal/nixpacks:ubuntu".to_string()),
+            ..Default::default()
+        };
+        plan.add_phase(Phase::new("test"));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.starts_with(
+            "FROM registry.acme.internal/nixpacks:ubuntu\nRUN command -v nix-env > /dev/null ||"
+        ));
+        assert!(!BuildPlan::default()
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap()
+            .contains("command -v nix-env"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_nix_caches() {
+        let mut plan = BuildPlan {
+            nix: Some(NixConfig {
+                substituters: Some(vec![
+                    "https://my-team.cachix.org".to_string(),
+                    "https://nix-community.cachix.org".to_string(),
+                ]),
+                trusted_public_keys: Some(vec!["my-team.cachix.org-1:abc=".to_string()]),
+            }),
+            ..Default::default()
+        };
+        plan.add_phase(Phase::new("test"));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains(
+            "RUN printf '%s\\n' 'extra-substituters = https://my-team.cachix.org https://nix-community.cachix.org' 'extra-trusted-public-keys = my-team.cachix.org-1:abc=' >> /etc/nix/nix.conf"
+        ));
+        assert!(!BuildPlan::default()
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap()
+            .contains("/etc/nix/nix.conf"));
+
+        let mut plan = BuildPlan {
+            nix: Some(NixConfig {
+                substituters: Some(vec!["https://example.com/it's".to_string()]),
+                trusted_public_keys: None,
+            }),
+            ..Default::default()
+        };
+        plan.add_phase(Phase::new("test"));
+        assert!(plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap()
+            .contains(
+                "RUN printf '%s\\n' 'extra-substituters = https://example.com/it'\\''s' >> "
+            ));
+    }
+
+    #[test]
+    fn test_plan_generation_with_template() {
+        let dir = TempDir::new("nixpacks-template").unwrap();
+        let template = dir.path().join("Dockerfile.hbs");
+        fs::write(
+            &template,
+            "FROM {{ base_image }}\nLABEL team=web\n{{phases}}\n{{start}}\n",
+        )
+        .unwrap();
+
+        let mut plan = BuildPlan::new(&[], Some(StartPhase::new("npm start".to_string())));
+        let mut build = Phase::new("build");
+        build.add_cmd("npm run build");
+        plan.add_phase(build);
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    dockerfile_template: Some(template.to_string_lossy().to_string()),
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.starts_with(&format!("FROM {DEFAULT_BASE_IMAGE}\nLABEL team=web\n")));
+        assert!(dockerfile.contains("npm run build"));
+        assert!(dockerfile.contains("CMD [\"npm start\"]"));
+    }
+
+    #[test]
+    fn test_plan_generation_squashed() {
+        let mut plan = BuildPlan::default();
+
+        let mut install = Phase::install(None);
+        install.add_cmd("npm ci");
+        install.add_cache_directory("/root/.npm");
+        install.add_path("/app/node_modules/.bin".to_string());
+        plan.add_phase(install);
+
+        let mut build = Phase::build(None);
+        build.add_cmd("npm run build");
+        build.add_cmd("rm -rf /app/.tmp");
+        plan.add_phase(build);
+
+        let mut release = Phase::new("release");
+        release.depends_on_phase("build");
+        release.add_cmd("cd dist || exit 1; ./release # tag it");
+        plan.add_phase(release);
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    cache_key: Some("app".to_string()),
+                    squash: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains(
+            "# install, build, release phases, squashed\nENV NIXPACKS_PATH /app/node_modules/.bin:$NIXPACKS_PATH\nCOPY . /app/.\nRUN --mount=type=cache,id=app-/root/npm,target=/root/.npm sh -c 'npm ci' && sh -c 'npm run build' && sh -c 'rm -rf /app/.tmp' && sh -c"
+        ));
+        assert_eq!(dockerfile.matches("RUN --mount").count(), 1);
+        assert!(dockerfile.contains("&& sh -c 'cd dist || exit 1; ./release # tag it' &&"));
+    }
+
+    #[test]
+    fn test_plan_generation_squashed_with_incremental_cache() {
+        let plan = BuildPlan::new(&[Phase::build(Some("npm run build".to_string()))], None);
+
+        let error = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    squash: true,
+                    incremental_cache_image: Some("app-cache".to_string()),
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("--incremental-cache-image"));
+    }
+
+    #[test]
+    fn test_plan_generation_parallel_phases() {
+        let mut node_install = Phase::new("node:install");
+        node_install.add_cmd("npm ci");
+        node_install.depends_on_phase("setup");
+
+        let mut python_install = Phase::new("python:install");
+        python_install.add_cmd("python -m venv /opt/venv && pip install -r requirements.txt");
+        python_install.add_persisted_directory("/opt/venv");
+        python_install.add_path("/opt/venv/bin".to_string());
+        python_install.depends_on_phase("setup");
+
+        let mut build = Phase::build(Some("npm run build".to_string()));
+        build.depends_on = Some(vec![
+            "node:install".to_string(),
+            "python:install".to_string(),
+        ]);
+
+        let mut start = StartPhase::new("./server");
+        start.run_in_slim_image();
+        let plan = BuildPlan::new(
+            &[Phase::setup(None), node_install, python_install, build],
+            Some(start),
+        );
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    parallel_phases: true,
+                    build_args: vec!["NPM_TOKEN".to_string()],
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.starts_with(&format!("FROM {DEFAULT_BASE_IMAGE} AS nixpacks-build\n")));
+        assert!(dockerfile.contains(
+            "FROM nixpacks-build AS nixpacks-node-install\nARG NPM_TOKEN\nRUN touch /tmp/.nixpacks-branch\n# node:install phase\n"
+        ));
+        assert!(dockerfile.contains(
+            "FROM nixpacks-build AS nixpacks-python-install\nARG NPM_TOKEN\nRUN touch /tmp/.nixpacks-branch\n# python:install phase\n"
+        ));
+        assert!(dockerfile.contains(
+            "RUN find / -xdev -cnewer /tmp/.nixpacks-branch -not -path '/app' -not -path '/app/*' -not -path '/tmp' -not -path '/tmp/*' -print0 | tar --null --no-recursion -cf /tmp/nixpacks-branch.tar -T -\n"
+        ));
+        assert!(dockerfile.contains(
+            "# merge node:install, python:install phases\nFROM nixpacks-build AS nixpacks-build-1\nARG NPM_TOKEN\nCOPY --from=nixpacks-node-install /app/ /app/\nRUN --mount=type=bind,from=nixpacks-node-install,source=/tmp/nixpacks-branch.tar,target=/tmp/nixpacks-branch.tar tar -xpf /tmp/nixpacks-branch.tar -C /\nCOPY --from=nixpacks-python-install /app/ /app/\nRUN --mount=type=bind,from=nixpacks-python-install,source=/tmp/nixpacks-branch.tar,target=/tmp/nixpacks-branch.tar tar -xpf /tmp/nixpacks-branch.tar -C /\nENV NIXPACKS_PATH /opt/venv/bin:$NIXPACKS_PATH\n"
+        ));
+        assert!(dockerfile.contains("# build phase\nCOPY . /app/.\nRUN  npm run build"));
+        assert!(dockerfile.contains("COPY --from=nixpacks-build-1 /app/ /app/"));
+
+        // Without independent phases, the Dockerfile is the same as without --parallel-phases
+        let mut plan = BuildPlan::default();
+        plan.add_phase(Phase::install(Some("npm ci".to_string())));
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    parallel_phases: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(!dockerfile.contains("nixpacks-build"));
+    }
+
+    #[test]
+    fn test_cache_only_dockerfile() {
+        let mut install = Phase::install(Some("npm ci".to_string()));
+        install.add_file_dependency("package.json".to_string());
+        install.add_file_dependency("package-lock.json".to_string());
+        let mut build = Phase::build(Some("npm run build".to_string()));
+        build.depends_on_phase("install");
+        let mut plan = BuildPlan::new(
+            &[Phase::setup(None), install, build],
+            Some(StartPhase::new("npm start")),
+        );
+        plan.keep_install_phases();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    cache_only: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("COPY package-lock.json /app/package-lock.json\nRUN  npm ci"));
+        assert!(!dockerfile.contains("npm run build"));
+        assert!(!dockerfile.contains("COPY . /app"));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    cache_only: true,
+                    with_source: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.trim_end().ends_with("COPY . /app/"));
+        assert!(!dockerfile.contains("npm run build"));
+    }
+
+    #[test]
+    fn test_plan_generation_slim_runtime() {
+        let mut start = StartPhase::new("./server".to_string());
+        start.add_file_dependency("./server");
+        let plan = BuildPlan::new(&[], Some(start));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    runtime: Runtime::Slim,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("nix-store -qR $(readlink -f /root/.nix-profile)"));
+        assert!(dockerfile.contains(&format!("FROM {STANDALONE_IMAGE}\n")));
+        assert!(dockerfile.contains("COPY --from=0 /nixpacks-runtime /\n"));
+        assert!(dockerfile.contains("COPY --from=0 /app/server /app/server\nCMD [\"./server\"]"));
+    }
+
+    #[test]
+    fn test_plan_generation_scratch_image() {
+        let mut start = StartPhase::new("./out --port 8080".to_string());
+        start.run_in_scratch_image();
+        start.add_file_dependency("./out");
+        let plan = BuildPlan::new(&[], Some(start));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    runtime: Runtime::Slim,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let (_, start_stage) = dockerfile.split_once("FROM scratch\n").unwrap();
+        assert!(!dockerfile.contains("nix-store -qR"));
+        assert!(!start_stage.contains("ENTRYPOINT"));
+        assert!(start_stage
+            .contains("COPY --from=0 /app/out /app/out\nCMD [\"./out\", \"--port\", \"8080\"]"));
+    }
+
+    #[test]
+    fn test_plan_generation_scratch_image_needs_shell() {
+        for cmd in ["./out --name \"a b\"", "cd 'apps/api' && ./out"] {
+            let mut start = StartPhase::new(cmd.to_string());
+            start.run_in_scratch_image();
+            let plan = BuildPlan::new(&[], Some(start));
+
+            let error = plan
+                .generate_dockerfile(
+                    &DockerBuilderOptions::default(),
+                    &Environment::default(),
+                    &OutputDir::default(),
+                    None,
+                )
+                .unwrap_err();
+            assert!(error.to_string().contains("needs a shell"));
+        }
+    }
+
+    #[test]
+    fn test_start_phase_generation_with_user() {
+        let start = StartPhase {
+            cmd: Some("npm start".to_string()),
+            user: Some("app".to_string()),
+            ..Default::default()
+        };
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("useradd --create-home --user-group 'app'"));
+        assert!(dockerfile.contains("chown -R 'app': $home /app/\nUSER app\nCMD [\"npm start\"]"));
+    }
+
+    #[test]
+    fn test_start_phase_generation_with_healthcheck() {
+        let start = StartPhase {
+            cmd: Some("npm start".to_string()),
+            healthcheck: Some(Healthcheck {
+                cmd: Some("curl -f \"localhost:3000/health\" | grep -qx 'ok\\s*'".to_string()),
+                interval: Some("10s".to_string()),
+                retries: Some(3),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains(
+            "HEALTHCHECK --interval=10s --retries=3 CMD [\"/bin/bash\", \"-l\", \"-c\", \"curl -f \\\"localhost:3000/health\\\" | grep -qx 'ok\\\\s*'\"]\nCMD [\"npm start\"]"
+        ));
+    }
+
+    #[test]
+    fn test_start_phase_generation_with_ports() {
+        let mut start = StartPhase::new("npm start");
+        start.add_port("3000");
+        start.add_port("9229/tcp");
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("EXPOSE 3000 9229/tcp\nCMD [\"npm start\"]"));
+    }
 }
@@ -0,0 +1,654 @@
+use crate::nixpacks::plan::APP_DIR;
+use anyhow::{bail, Error, Result};
+use std::{process::Command, str::FromStr};
+
+/// The CLI used to build and push images from the generated Dockerfile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Engine {
+    Docker,
+    /// Podman, for systems without a Docker daemon.
+    Podman,
+    /// Buildah, which builds images without any daemon. They can be run with Podman.
+    Buildah,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::Docker
+    }
+}
+
+impl Engine {
+    pub fn cli(self) -> &'static dyn ContainerEngine {
+        match self {
+            Engine::Docker => &DockerEngine,
+            Engine::Podman => &PodmanEngine,
+            Engine::Buildah => &BuildahEngine,
+        }
+    }
+}
+
+/// Where the built image is written, instead of only the engine's image store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageOutput {
+    /// A tarball of an OCI image layout.
+    Oci(String),
+}
+
+impl FromStr for ImageOutput {
+    type Err = Error;
+
+    /// Parses `oci:<path>`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("oci", path)) if !path.is_empty() => Ok(ImageOutput::Oci(path.to_string())),
+            _ => bail!("Invalid output `{s}`, expected oci:<path>, e.g. oci:./image.tar"),
+        }
+    }
+}
+
+/// Memory and CPU limits for the containers that run the install and build commands.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// A memory limit in the engine's format, e.g. `2g`.
+    pub memory: Option<String>,
+    /// How many CPUs the build can use, e.g. `1.5`.
+    pub cpus: Option<f64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpus.is_none()
+    }
+
+    /// The CPU quota per 100ms period that limits a container to `cpus`.
+    fn cpu_quota(&self) -> Option<String> {
+        self.cpus
+            .map(|cpus| ((cpus * CPU_PERIOD as f64) as u64).to_string())
+    }
+}
+
+/// The length of the period CPU quotas are given for, in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+
+/// The parts of a container engine's CLI used to build and push images. The defaults follow Podman and Buildah.
+pub trait ContainerEngine: Sync {
+    /// The name of the CLI binary.
+    fn name(&self) -> &'static str;
+
+    /// Where to find installation instructions.
+    fn install_url(&self) -> &'static str;
+
+    /// Environment variables the CLI is run with.
+    fn env(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// The subcommand that builds an image. `buildx` is set when the build needs an exporter or a builder of its own.
+    fn build_subcommand(&self, buildx: bool) -> Vec<String> {
+        let _ = buildx;
+        vec!["build".to_string()]
+    }
+
+    /// Flags that make the build print the full output of every step.
+    fn verbose_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Flags that keep the image's HEALTHCHECK, which the OCI image format has no place for.
+    fn healthcheck_args(&self) -> Vec<String> {
+        vec!["--format".to_string(), "docker".to_string()]
+    }
+
+    /// The arguments of a command that prepares the engine to build within the limits, if it needs one. It's fine for it
+    /// to fail when the engine was already prepared by an earlier build.
+    fn limits_setup_args(&self, limits: &ResourceLimits) -> Option<Vec<String>> {
+        let _ = limits;
+        None
+    }
+
+    /// Flags that keep the build within the limits.
+    fn limits_args(&self, limits: &ResourceLimits) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(memory) = &limits.memory {
+            args.extend(["--memory".to_string(), memory.clone()]);
+        }
+        if let Some(quota) = limits.cpu_quota() {
+            args.extend([
+                "--cpu-period".to_string(),
+                CPU_PERIOD.to_string(),
+                "--cpu-quota".to_string(),
+                quota,
+            ]);
+        }
+        args
+    }
+
+    /// The arguments of a command that removes an earlier image or manifest list with the given name, if the engine
+    /// keeps manifest lists locally. It's fine for it to fail when there's nothing to remove.
+    fn remove_manifest_args(&self, name: &str) -> Option<Vec<String>> {
+        Some(vec!["rmi".to_string(), name.to_string()])
+    }
+
+    /// The arguments of a command that collects the images built for each platform into a local manifest list named
+    /// after the image, if the engine supports them.
+    fn manifest_create_args(&self, name: &str, platform_images: &[String]) -> Option<Vec<String>> {
+        let mut args = vec![
+            "manifest".to_string(),
+            "create".to_string(),
+            name.to_string(),
+        ];
+        args.extend(
+            platform_images
+                .iter()
+                .map(|image| format!("containers-storage:{image}")),
+        );
+        Some(args)
+    }
+
+    /// The arguments of each command that pushes the built image, paired with the image it pushes.
+    ///
+    /// When several platforms were built, the manifest list is pushed to every tag.
+    fn push_cmds(
+        &self,
+        name: &str,
+        tags: &[String],
+        platform_images: &[String],
+    ) -> Vec<(String, Vec<String>)> {
+        images(name, tags)
+            .map(|image| {
+                let args = if platform_images.is_empty() {
+                    vec!["push".to_string(), image.clone()]
+                } else {
+                    vec![
+                        "manifest".to_string(),
+                        "push".to_string(),
+                        "--all".to_string(),
+                        name.to_string(),
+                        format!("docker://{image}"),
+                    ]
+                };
+                (image, args)
+            })
+            .collect()
+    }
+
+    /// Flags that make the build write the image to `output`.
+    fn output_args(&self, output: &ImageOutput) -> Vec<String> {
+        let _ = output;
+        Vec::new()
+    }
+
+    /// The arguments of a command that writes the built image to `output`, if the build doesn't.
+    fn export_args(&self, name: &str, output: &ImageOutput) -> Option<Vec<String>> {
+        let _ = (name, output);
+        None
+    }
+
+    /// The arguments of a command that prints the ID, size in bytes, and registry digests of an image in the engine's
+    /// store, separated by spaces, if the engine has one.
+    fn inspect_args(&self, image: &str) -> Option<Vec<String>> {
+        Some(vec![
+            "image".to_string(),
+            "inspect".to_string(),
+            "--format".to_string(),
+            "{{.Id}} {{.Size}} {{join .RepoDigests \",\"}}".to_string(),
+            image.to_string(),
+        ])
+    }
+
+    /// The CLI that runs the built images.
+    fn run_cli(&self) -> &'static str {
+        self.name()
+    }
+
+    /// The command that runs the built image.
+    fn run_cmd(&self, name: &str) -> String {
+        format!("{} run -it {name}", self.run_cli())
+    }
+
+    /// The arguments of the `run_cli` command that runs the image in the foreground as the container `name`, publishing
+    /// `ports`. The container is removed when it stops.
+    fn run_args(&self, image: &str, name: &str, ports: &[String]) -> Vec<String> {
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            name.to_string(),
+        ];
+        args.extend(
+            ports
+                .iter()
+                .flat_map(|port| ["-p".to_string(), port.clone()]),
+        );
+        args.push(image.to_string());
+        args
+    }
+
+    /// The arguments of the `run_cli` command that opens an interactive `shell` in the image, with the app's `source`
+    /// mounted at the app's directory and `workdir` as the working directory. The container is removed when it exits.
+    fn shell_args(&self, image: &str, source: &str, workdir: &str, shell: &str) -> Vec<String> {
+        vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            "-it".to_string(),
+            "-v".to_string(),
+            format!("{source}:{APP_DIR}"),
+            "-w".to_string(),
+            workdir.to_string(),
+            image.to_string(),
+            shell.to_string(),
+        ]
+    }
+
+    /// The arguments of the `run_cli` command that runs `cmd` in the image with `workdir` as the working directory,
+    /// passing stdin on, and allocating a terminal if `tty`. The container is removed when it exits.
+    fn exec_args(&self, image: &str, workdir: &str, cmd: &[String], tty: bool) -> Vec<String> {
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            if tty { "-it" } else { "-i" }.to_string(),
+            "-w".to_string(),
+            workdir.to_string(),
+            image.to_string(),
+        ];
+        args.extend(cmd.iter().cloned());
+        args
+    }
+
+    /// The arguments that print the version of the CLI, or of the daemon it talks to.
+    fn version_args(&self) -> Vec<String> {
+        vec!["--version".to_string()]
+    }
+
+    /// A command invoking the CLI.
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(self.name());
+        cmd.envs(self.env().iter().copied());
+        cmd
+    }
+}
+
+fn images(name: &str, tags: &[String]) -> impl Iterator<Item = String> {
+    std::iter::once(name.to_string()).chain(tags.to_vec())
+}
+
+/// The name of the image built for one of several platforms, e.g. `acme/app:latest-linux-arm64`.
+pub fn platform_image(name: &str, platform: &str) -> String {
+    let (repository, tag) = match name.rsplit_once(':') {
+        // A colon followed by a path is a registry port, not a tag
+        Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+        _ => (name, "latest"),
+    };
+    format!("{repository}:{tag}-{}", platform.replace('/', "-"))
+}
+
+/// The engine commands that build an image and push it, in the order they run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildCmds {
+    /// Prepares the engine for the build, like creating a builder. It's fine for this to fail.
+    pub setup: Option<Vec<String>>,
+    /// Removes an earlier manifest list with the same name. It's fine for this to fail.
+    pub remove_manifest: Option<Vec<String>>,
+    /// Each build, paired with its platform when several platforms are built one after another.
+    pub builds: Vec<(Option<String>, Vec<String>)>,
+    /// Collects the platform images into a manifest list.
+    pub create_manifest: Option<Vec<String>>,
+    /// Each push, paired with the image it pushes.
+    pub push: Vec<(String, Vec<String>)>,
+}
+
+/// The name of the buildx builder for the limits, shared by builds with the same limits.
+fn limits_builder_name(limits: &ResourceLimits) -> String {
+    format!(
+        "nixpacks-memory-{}-cpus-{}",
+        limits.memory.as_deref().unwrap_or("any"),
+        limits
+            .cpus
+            .map_or_else(|| "any".to_string(), |cpus| cpus.to_string())
+    )
+}
+
+pub struct DockerEngine;
+
+impl ContainerEngine for DockerEngine {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn install_url(&self) -> &'static str {
+        "https://docs.docker.com/engine/install/"
+    }
+
+    fn env(&self) -> &'static [(&'static str, &'static str)] {
+        // Enable BuildKit for all builds
+        &[("DOCKER_BUILDKIT", "1")]
+    }
+
+    /// Writing an OCI image needs one of buildx's exporters.
+    fn build_subcommand(&self, buildx: bool) -> Vec<String> {
+        if buildx {
+            vec!["buildx".to_string(), "build".to_string()]
+        } else {
+            vec!["build".to_string()]
+        }
+    }
+
+    fn verbose_args(&self) -> Vec<String> {
+        vec!["--progress=plain".to_string()]
+    }
+
+    /// The daemon's version, so this fails when the daemon isn't running or can't be accessed.
+    fn version_args(&self) -> Vec<String> {
+        vec![
+            "version".to_string(),
+            "--format".to_string(),
+            "{{.Server.Version}}".to_string(),
+        ]
+    }
+
+    /// Docker always builds images in its own format.
+    fn healthcheck_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// BuildKit ignores the resource flags of `docker build`, so the limits are set on a builder of their own instead.
+    fn limits_setup_args(&self, limits: &ResourceLimits) -> Option<Vec<String>> {
+        let mut args = vec![
+            "buildx".to_string(),
+            "create".to_string(),
+            "--name".to_string(),
+            limits_builder_name(limits),
+            "--driver".to_string(),
+            "docker-container".to_string(),
+        ];
+        if let Some(memory) = &limits.memory {
+            args.extend(["--driver-opt".to_string(), format!("memory={memory}")]);
+        }
+        if let Some(quota) = limits.cpu_quota() {
+            args.extend([
+                "--driver-opt".to_string(),
+                format!("cpu-period={CPU_PERIOD}"),
+                "--driver-opt".to_string(),
+                format!("cpu-quota={quota}"),
+            ]);
+        }
+        Some(args)
+    }
+
+    /// The builder runs in a container, so the image has to be loaded into Docker's image store.
+    fn limits_args(&self, limits: &ResourceLimits) -> Vec<String> {
+        vec![
+            "--builder".to_string(),
+            limits_builder_name(limits),
+            "--load".to_string(),
+        ]
+    }
+
+    /// Docker only keeps manifest lists in registries.
+    fn remove_manifest_args(&self, _name: &str) -> Option<Vec<String>> {
+        None
+    }
+
+    fn manifest_create_args(
+        &self,
+        _name: &str,
+        _platform_images: &[String],
+    ) -> Option<Vec<String>> {
+        None
+    }
+
+    fn output_args(&self, output: &ImageOutput) -> Vec<String> {
+        match output {
+            ImageOutput::Oci(path) => vec!["--output".to_string(), format!("type=oci,dest={path}")],
+        }
+    }
+
+    /// The platform images are pushed first, then buildx creates the manifest list in the registry from them.
+    fn push_cmds(
+        &self,
+        name: &str,
+        tags: &[String],
+        platform_images: &[String],
+    ) -> Vec<(String, Vec<String>)> {
+        if platform_images.is_empty() {
+            return images(name, tags)
+                .map(|image| (image.clone(), vec!["push".to_string(), image]))
+                .collect();
+        }
+
+        let mut create_args = vec![
+            "buildx".to_string(),
+            "imagetools".to_string(),
+            "create".to_string(),
+        ];
+        create_args.extend(images(name, tags).flat_map(|image| ["-t".to_string(), image]));
+        create_args.extend(platform_images.iter().cloned());
+
+        platform_images
+            .iter()
+            .map(|image| (image.clone(), vec!["push".to_string(), image.clone()]))
+            .chain(std::iter::once((name.to_string(), create_args)))
+            .collect()
+    }
+}
+
+pub struct PodmanEngine;
+
+impl ContainerEngine for PodmanEngine {
+    fn name(&self) -> &'static str {
+        "podman"
+    }
+
+    fn install_url(&self) -> &'static str {
+        "https://podman.io/docs/installation"
+    }
+
+    fn export_args(&self, name: &str, output: &ImageOutput) -> Option<Vec<String>> {
+        match output {
+            ImageOutput::Oci(path) => Some(vec![
+                "save".to_string(),
+                "--format".to_string(),
+                "oci-archive".to_string(),
+                "-o".to_string(),
+                path.clone(),
+                name.to_string(),
+            ]),
+        }
+    }
+}
+
+pub struct BuildahEngine;
+
+impl ContainerEngine for BuildahEngine {
+    fn name(&self) -> &'static str {
+        "buildah"
+    }
+
+    fn install_url(&self) -> &'static str {
+        "https://github.com/containers/buildah/blob/main/install.md"
+    }
+
+    fn export_args(&self, name: &str, output: &ImageOutput) -> Option<Vec<String>> {
+        match output {
+            ImageOutput::Oci(path) => Some(vec![
+                "push".to_string(),
+                name.to_string(),
+                format!("oci-archive:{path}"),
+            ]),
+        }
+    }
+
+    /// Buildah has no `image inspect` command, so the images it builds aren't inspected.
+    fn inspect_args(&self, _image: &str) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Buildah can't run containers, but Podman shares its image store.
+    fn run_cli(&self) -> &'static str {
+        "podman"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_cmds() {
+        let tags = vec!["acme/app:v1".to_string()];
+        let platform_images = vec!["acme/app:latest-linux-amd64".to_string()];
+
+        assert_eq!(
+            DockerEngine.push_cmds("acme/app", &tags, &[]),
+            vec![
+                (
+                    "acme/app".to_string(),
+                    vec!["push".to_string(), "acme/app".to_string()]
+                ),
+                (
+                    "acme/app:v1".to_string(),
+                    vec!["push".to_string(), "acme/app:v1".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            PodmanEngine.push_cmds("acme/app", &tags, &platform_images)[1]
+                .1
+                .join(" "),
+            "manifest push --all acme/app docker://acme/app:v1"
+        );
+    }
+
+    #[test]
+    fn test_docker_multi_platform_push_cmds() {
+        let tags = vec!["acme/app:v1".to_string()];
+        let platform_images = vec![
+            "acme/app:latest-linux-amd64".to_string(),
+            "acme/app:latest-linux-arm64".to_string(),
+        ];
+
+        assert_eq!(
+            DockerEngine
+                .push_cmds("acme/app", &tags, &platform_images)
+                .into_iter()
+                .map(|(_, args)| args.join(" "))
+                .collect::<Vec<_>>(),
+            vec![
+                "push acme/app:latest-linux-amd64",
+                "push acme/app:latest-linux-arm64",
+                "buildx imagetools create -t acme/app -t acme/app:v1 acme/app:latest-linux-amd64 acme/app:latest-linux-arm64",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_platform_image() {
+        assert_eq!(
+            platform_image("acme/app", "linux/arm64"),
+            "acme/app:latest-linux-arm64"
+        );
+        assert_eq!(
+            platform_image("acme/app:v1", "linux/arm/v7"),
+            "acme/app:v1-linux-arm-v7"
+        );
+        assert_eq!(
+            platform_image("localhost:5000/app", "linux/amd64"),
+            "localhost:5000/app:latest-linux-amd64"
+        );
+    }
+
+    #[test]
+    fn test_manifest_create_args() {
+        let platform_images = vec!["app:latest-linux-amd64".to_string()];
+        assert_eq!(
+            BuildahEngine
+                .manifest_create_args("app", &platform_images)
+                .unwrap()
+                .join(" "),
+            "manifest create app containers-storage:app:latest-linux-amd64"
+        );
+        assert_eq!(
+            DockerEngine.manifest_create_args("app", &platform_images),
+            None
+        );
+    }
+
+    #[test]
+    fn test_oci_output() {
+        let output = "oci:./image.tar".parse::<ImageOutput>().unwrap();
+        assert_eq!(output, ImageOutput::Oci("./image.tar".to_string()));
+        assert!("docker:app".parse::<ImageOutput>().is_err());
+        assert!("oci:".parse::<ImageOutput>().is_err());
+
+        assert_eq!(
+            DockerEngine.output_args(&output),
+            vec!["--output", "type=oci,dest=./image.tar"]
+        );
+        assert_eq!(DockerEngine.export_args("app", &output), None);
+        assert_eq!(
+            BuildahEngine.export_args("app", &output).unwrap().join(" "),
+            "push app oci-archive:./image.tar"
+        );
+    }
+
+    #[test]
+    fn test_run_cmd() {
+        assert_eq!(Engine::Docker.cli().run_cmd("app"), "docker run -it app");
+        assert_eq!(Engine::Buildah.cli().run_cmd("app"), "podman run -it app");
+    }
+
+    #[test]
+    fn test_shell_args() {
+        assert_eq!(
+            DockerEngine
+                .shell_args("app", "/src/app", "/app/api", "bash")
+                .join(" "),
+            "run --rm -it -v /src/app:/app/ -w /app/api app bash"
+        );
+        assert_eq!(BuildahEngine.run_cli(), "podman");
+    }
+
+    #[test]
+    fn test_exec_args() {
+        let cmd = ["npm".to_string(), "test".to_string()];
+        assert_eq!(
+            DockerEngine
+                .exec_args("app", "/app/", &cmd, false)
+                .join(" "),
+            "run --rm -i -w /app/ app npm test"
+        );
+    }
+
+    #[test]
+    fn test_run_args() {
+        assert_eq!(
+            PodmanEngine
+                .run_args("app", "app-watch", &["3000:3000".to_string()])
+                .join(" "),
+            "run --rm --name app-watch -p 3000:3000 app"
+        );
+    }
+
+    #[test]
+    fn test_limits_args() {
+        let limits = ResourceLimits {
+            memory: Some("2g".to_string()),
+            cpus: Some(1.5),
+        };
+
+        assert_eq!(
+            PodmanEngine.limits_args(&limits).join(" "),
+            "--memory 2g --cpu-period 100000 --cpu-quota 150000"
+        );
+        assert_eq!(PodmanEngine.limits_setup_args(&limits), None);
+        assert_eq!(
+            DockerEngine.limits_setup_args(&limits).unwrap().join(" "),
+            "buildx create --name nixpacks-memory-2g-cpus-1.5 --driver docker-container --driver-opt memory=2g --driver-opt cpu-period=100000 --driver-opt cpu-quota=150000"
+        );
+        assert_eq!(
+            DockerEngine.limits_args(&limits).join(" "),
+            "--builder nixpacks-memory-2g-cpus-1.5 --load"
+        );
+    }
+}
@@ -1,26 +1,143 @@
 use std::{
     fs::{self},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Command,
 };
 
-use super::{dockerfile_generation::OutputDir, file_server::FileServerConfig};
+use super::{
+    clean::INCREMENTAL_CACHE_LABEL, dockerfile_generation::OutputDir, file_server::FileServerConfig,
+};
+use crate::nixpacks::NIX_PACKS_VERSION;
 use anyhow::{bail, Context, Result};
 use std::process::Stdio;
 
 const INCREMENTAL_CACHE_DIR: &str = "incremental-cache";
 const INCREMENTAL_CACHE_UPLOADS_DIR: &str = "uploads";
 const INCREMENTAL_CACHE_IMAGE_DIR: &str = "image";
+const INCREMENTAL_CACHE_DOWNLOADS_DIR: &str = "downloads";
+const INCREMENTAL_CACHE_RESTORE_DIR: &str = "restore";
 
 #[derive(Default)]
 pub struct IncrementalCache {}
 
+/// Object storage holding the cached directories between builds, instead of an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCache {
+    /// An S3 URL, e.g. `s3://bucket/prefix`, synced with the `aws` CLI.
+    S3(String),
+    /// A Google Cloud Storage URL, e.g. `gs://bucket/prefix`, synced with `gsutil`.
+    Gcs(String),
+}
+
+impl RemoteCache {
+    pub fn from_url(url: &str) -> Result<RemoteCache> {
+        let url = url.trim_end_matches('/').to_string();
+        if url.starts_with("s3://") {
+            Ok(RemoteCache::S3(url))
+        } else if url.starts_with("gs://") {
+            Ok(RemoteCache::Gcs(url))
+        } else {
+            bail!("The incremental cache must be an s3:// or gs:// URL, not {url}")
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        match self {
+            RemoteCache::S3(url) | RemoteCache::Gcs(url) => url,
+        }
+    }
+
+    /// The command copying the files in `from` that are missing or changed in `to`, where either is a local
+    /// directory or a URL in the bucket. With `delete`, files in `to` that aren't in `from` are removed.
+    fn sync_cmd(&self, from: &str, to: &str, delete: bool) -> Command {
+        let mut cmd = match self {
+            RemoteCache::S3(_) => {
+                let mut cmd = Command::new("aws");
+                cmd.args(["s3", "sync", from, to, "--only-show-errors"]);
+                if delete {
+                    cmd.arg("--delete");
+                }
+                cmd
+            }
+            RemoteCache::Gcs(_) => {
+                let mut cmd = Command::new("gsutil");
+                cmd.args(["-m", "-q", "rsync", "-r"]);
+                if delete {
+                    cmd.arg("-d");
+                }
+                cmd.args([from, to]);
+                cmd
+            }
+        };
+        cmd.stdout(Stdio::null());
+        cmd
+    }
+
+    /// Download the archives of the previous build into `dir`, returning whether there were any.
+    pub fn download(&self, dir: &Path) -> Result<bool> {
+        let result = self
+            .sync_cmd(self.url(), &dir.to_string_lossy(), false)
+            .spawn()
+            .with_context(|| format!("Downloading the incremental cache from {}", self.url()))?
+            .wait()?;
+
+        // gsutil fails when nothing has been uploaded yet, which only means there's nothing to restore
+        Ok(result.success() && fs::read_dir(dir)?.next().is_some())
+    }
+
+    /// Upload the archives in `dir` for the next build, removing the archives of directories this build doesn't cache.
+    pub fn upload(&self, dir: &Path) -> Result<()> {
+        let result = self
+            .sync_cmd(&dir.to_string_lossy(), self.url(), true)
+            .spawn()
+            .with_context(|| format!("Uploading the incremental cache to {}", self.url()))?
+            .wait()?;
+
+        if !result.success() {
+            bail!("Uploading the incremental cache to {} failed", self.url())
+        }
+
+        Ok(())
+    }
+
+    /// Delete the archives of every previous build, so the next build starts without them.
+    pub fn clear(&self) -> Result<()> {
+        let mut cmd = match self {
+            RemoteCache::S3(url) => {
+                let mut cmd = Command::new("aws");
+                cmd.args(["s3", "rm", url, "--recursive", "--only-show-errors"]);
+                cmd
+            }
+            RemoteCache::Gcs(url) => {
+                let mut cmd = Command::new("gsutil");
+                cmd.args(["-m", "-q", "rm", "-r", url]);
+                cmd
+            }
+        };
+        let result = cmd
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Clearing the incremental cache at {}", self.url()))?
+            .wait()?;
+
+        if !result.success() {
+            bail!("Clearing the incremental cache at {} failed", self.url())
+        }
+
+        println!("Incremental cache cleared: {}", self.url());
+        Ok(())
+    }
+}
+
 /// Directories in which to cache Docker image layers.
 #[derive(Default)]
 pub struct IncrementalCacheDirs {
     out_dir: OutputDir,
     pub uploads_dir: PathBuf,
     pub image_dir: PathBuf,
+    pub downloads_dir: PathBuf,
+    /// Where the directories downloaded from a remote cache are extracted, in the build context.
+    pub restore_dir: PathBuf,
 }
 
 impl IncrementalCacheDirs {
@@ -28,11 +145,16 @@ impl IncrementalCacheDirs {
         let incremental_cache_root = out_dir.get_absolute_path(INCREMENTAL_CACHE_DIR);
         let image_dir = incremental_cache_root.join(PathBuf::from(INCREMENTAL_CACHE_IMAGE_DIR));
         let uploads_dir = incremental_cache_root.join(PathBuf::from(INCREMENTAL_CACHE_UPLOADS_DIR));
+        let downloads_dir =
+            incremental_cache_root.join(PathBuf::from(INCREMENTAL_CACHE_DOWNLOADS_DIR));
+        let restore_dir = incremental_cache_root.join(PathBuf::from(INCREMENTAL_CACHE_RESTORE_DIR));
 
         IncrementalCacheDirs {
             out_dir: out_dir.clone(),
             uploads_dir,
             image_dir,
+            downloads_dir,
+            restore_dir,
         }
     }
 
@@ -64,7 +186,23 @@ impl IncrementalCacheDirs {
         fs::create_dir_all(&self.image_dir).context("Create incremental cache image dir")?;
         fs::create_dir_all(&self.uploads_dir)
             .context("Creating incremental-cache uploads directory")?;
+        fs::create_dir_all(&self.downloads_dir)
+            .context("Creating incremental-cache downloads directory")?;
+        fs::create_dir_all(&self.restore_dir)
+            .context("Creating incremental-cache restore directory")?;
+
+        Ok(())
+    }
+}
 
+impl IncrementalCacheDirs {
+    /// Removes the incremental cache directories, with what a build left in them.
+    pub fn remove(&self) -> Result<()> {
+        let incremental_cache_root = self.out_dir.get_absolute_path(INCREMENTAL_CACHE_DIR);
+        if incremental_cache_root.exists() {
+            fs::remove_dir_all(&incremental_cache_root)
+                .context("Removing incremental cache directories")?;
+        }
         Ok(())
     }
 }
@@ -84,7 +222,14 @@ impl IncrementalCache {
         // #3 Use Docker import: Provide 3 seconds in a sample test
         for f in files {
             let mut docker_import_cmd = Command::new("docker");
-            docker_import_cmd.arg("import").arg(&f?.path()).arg(tag);
+            docker_import_cmd
+                .arg("import")
+                .arg("--change")
+                .arg(format!(
+                    "LABEL {INCREMENTAL_CACHE_LABEL}={NIX_PACKS_VERSION}"
+                ))
+                .arg(&f?.path())
+                .arg(tag);
 
             let result = docker_import_cmd
                 .spawn()?
@@ -100,6 +245,79 @@ impl IncrementalCache {
         Ok(())
     }
 
+    /// Download the cached directories of the previous build from object storage and extract them into the build
+    /// context, for the Dockerfile to copy into the image.
+    pub fn restore_from_remote(
+        &self,
+        remote: &RemoteCache,
+        incremental_cache_dirs: &IncrementalCacheDirs,
+    ) -> Result<()> {
+        if !remote.download(&incremental_cache_dirs.downloads_dir)? {
+            println!("No incremental cache found at {}", remote.url());
+            return Ok(());
+        }
+
+        for f in fs::read_dir(&incremental_cache_dirs.downloads_dir)? {
+            let path = f?.path();
+            if path.extension().map_or(true, |ext| ext != "tar") {
+                continue;
+            }
+
+            let result = Command::new("tar")
+                .arg("-xf")
+                .arg(&path)
+                .arg("-C")
+                .arg(&incremental_cache_dirs.restore_dir)
+                .spawn()?
+                .wait()
+                .context("Extract incremental cache archive")?;
+            if !result.success() {
+                bail!("Extracting {} failed", path.display())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Upload the cached directories of this build to object storage.
+    pub fn save_to_remote(
+        &self,
+        remote: &RemoteCache,
+        incremental_cache_dirs: &IncrementalCacheDirs,
+    ) -> Result<()> {
+        remote.upload(&incremental_cache_dirs.uploads_dir)?;
+        println!("Incremental cache uploaded: {}", remote.url());
+        Ok(())
+    }
+
+    /// Remove the incremental cache image created by previous builds, so the next build starts without it.
+    pub fn remove_image(tag: &str) -> Result<()> {
+        let exists = Command::new("docker")
+            .args(["image", "inspect", tag])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Check incremental cache image exists")?
+            .success();
+        if !exists {
+            println!("No incremental cache image found: {tag}");
+            return Ok(());
+        }
+
+        let result = Command::new("docker")
+            .args(["image", "rm", tag])
+            .stdout(Stdio::null())
+            .spawn()?
+            .wait()
+            .context("Remove incremental cache image")?;
+        if !result.success() {
+            bail!("Removing incremental cache image {tag} failed")
+        }
+
+        println!("Incremental cache image removed: {tag}");
+        Ok(())
+    }
+
     /// Check if the provided image_tag matches a tag in the incremental Docker image cache.
     pub fn is_image_exists(image_tag: &str) -> Result<bool> {
         let mut docker_inspect_cmd = Command::new("docker");
@@ -145,32 +363,78 @@ impl IncrementalCache {
             .collect::<Vec<String>>()
     }
 
+    /// Produce Dockerfile line(s) copying the cached directories restored from a remote cache from the build context.
+    /// Directories the previous build didn't save are skipped.
+    pub fn get_copy_from_context_command(
+        cache_directories: &[String],
+        output: &OutputDir,
+    ) -> Vec<String> {
+        let restore_dir = output
+            .get_relative_path(INCREMENTAL_CACHE_DIR)
+            .join(INCREMENTAL_CACHE_RESTORE_DIR);
+
+        cache_directories
+            .iter()
+            .filter_map(|dir| {
+                let target_cache_dir = dir.replace('~', "/root");
+                // tar stores paths without a leading `/` or `./`
+                let archived_dir = target_cache_dir
+                    .trim_start_matches("./")
+                    .trim_start_matches('/');
+                let source = restore_dir.join(archived_dir);
+
+                output.root.join(&source).is_dir().then(|| {
+                    format!(
+                        "COPY {} {target_cache_dir}",
+                        source.to_string_lossy().replace('\\', "/")
+                    )
+                })
+            })
+            .collect()
+    }
+
     /// Produce Dockerfile line(s) copying files from the build image into the incremental cache.
+    ///
+    /// Cache directories are removed from the image afterwards, while persisted directories are kept.
     pub fn get_copy_from_image_command(
         cache_directories: &Option<Vec<String>>,
+        persisted_directories: &Option<Vec<String>>,
         file_server_config: Option<FileServerConfig>,
     ) -> Vec<String> {
-        let container_dirs = cache_directories.clone().unwrap_or_default();
-        if container_dirs.is_empty() || file_server_config.is_none() {
-            return vec![];
-        }
+        let cache_dirs = cache_directories.clone().unwrap_or_default();
+        let persisted_dirs = persisted_directories.clone().unwrap_or_default();
+        let server_config = match file_server_config {
+            Some(server_config) if !cache_dirs.is_empty() || !persisted_dirs.is_empty() => {
+                server_config
+            }
+            _ => return vec![],
+        };
 
-        let server_config = file_server_config.unwrap();
-        container_dirs
-            .iter()
-            .flat_map(|dir| {
-                let sanitized_dir = dir.replace('~', "/root");
-                let compressed_file_name = format!("{}.tar", sanitized_dir.replace('/', "%2f"));
-                vec![
-                    format!("if [ -d \"{sanitized_dir}\" ]; then tar -cf {compressed_file_name} {sanitized_dir}; fi;"),
-                    format!(
-                        "if [ -d \"{sanitized_dir}\" ]; then curl -v -T {} {} --header \"t:{}\" --retry 3 --retry-all-errors; fi;",
-                        compressed_file_name, server_config.upload_url, server_config.access_token,
-                    ),
-                    format!("if [ -d \"{sanitized_dir}\" ]; then rm -rf {sanitized_dir}; fi"),
-                ]
-            })
-            .collect::<Vec<String>>()
+        let cache_cmds = cache_dirs.iter().flat_map(|dir| {
+            let sanitized_dir = dir.replace('~', "/root");
+            let mut cmds = IncrementalCache::get_upload_commands(&sanitized_dir, &server_config);
+            cmds.push(format!(
+                "if [ -d \"{sanitized_dir}\" ]; then rm -rf {sanitized_dir}; fi"
+            ));
+            cmds
+        });
+        let persisted_cmds = persisted_dirs.iter().flat_map(|dir| {
+            IncrementalCache::get_upload_commands(&dir.replace('~', "/root"), &server_config)
+        });
+
+        cache_cmds.chain(persisted_cmds).collect::<Vec<String>>()
+    }
+
+    /// Archive a directory and upload it to the file server the incremental cache image is created from.
+    fn get_upload_commands(dir: &str, server_config: &FileServerConfig) -> Vec<String> {
+        let compressed_file_name = format!("{}.tar", dir.replace('/', "%2f"));
+        vec![
+            format!("if [ -d \"{dir}\" ]; then tar -cf {compressed_file_name} {dir}; fi;"),
+            format!(
+                "if [ -d \"{dir}\" ]; then curl -v -T {} {} --header \"t:{}\" --retry 3 --retry-all-errors; fi;",
+                compressed_file_name, server_config.upload_url, server_config.access_token,
+            ),
+        ]
     }
 }
 
@@ -178,6 +442,7 @@ impl IncrementalCache {
 fn test_get_copy_from_image_command() {
     let cmds = IncrementalCache::get_copy_from_image_command(
         &Some(vec!["./parent_dir/child_dir".to_string()]),
+        &None,
         Some(FileServerConfig {
             listen_to_ip: "0.0.0.0".to_string(),
             port: 1234,
@@ -210,3 +475,102 @@ fn test_get_copy_to_image_command() {
             .to_string()
     );
 }
+
+#[test]
+fn test_get_copy_from_image_command_keeps_persisted_directories() {
+    let cmds = IncrementalCache::get_copy_from_image_command(
+        &None,
+        &Some(vec!["/opt/venv".to_string()]),
+        Some(FileServerConfig {
+            listen_to_ip: "0.0.0.0".to_string(),
+            port: 1234,
+            access_token: "test_access_token".to_string(),
+            upload_url: "http://test.com/upload".to_string(),
+            files_dir: PathBuf::from("./source_dir".to_string()),
+        }),
+    );
+
+    assert_eq!(cmds.len(), 2);
+    assert_eq!(
+        cmds[0],
+        "if [ -d \"/opt/venv\" ]; then tar -cf %2fopt%2fvenv.tar /opt/venv; fi;".to_string()
+    );
+    assert!(!cmds.iter().any(|cmd| cmd.contains("rm -rf")));
+}
+
+#[test]
+fn test_remote_cache_from_url() {
+    assert_eq!(
+        RemoteCache::from_url("s3://bucket/prefix/").unwrap(),
+        RemoteCache::S3("s3://bucket/prefix".to_string())
+    );
+    assert_eq!(
+        RemoteCache::from_url("gs://bucket").unwrap(),
+        RemoteCache::Gcs("gs://bucket".to_string())
+    );
+    assert!(RemoteCache::from_url("https://example.com/cache").is_err());
+}
+
+#[test]
+fn test_remote_cache_upload_deletes_stale_archives() {
+    let args = |remote: &str, delete: bool| {
+        RemoteCache::from_url(remote)
+            .unwrap()
+            .sync_cmd("uploads", remote, delete)
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        args("s3://bucket/cache", true),
+        vec![
+            "s3",
+            "sync",
+            "uploads",
+            "s3://bucket/cache",
+            "--only-show-errors",
+            "--delete"
+        ]
+    );
+    assert_eq!(
+        args("gs://bucket/cache", true),
+        vec![
+            "-m",
+            "-q",
+            "rsync",
+            "-r",
+            "-d",
+            "uploads",
+            "gs://bucket/cache"
+        ]
+    );
+    assert!(!args("s3://bucket/cache", false).contains(&"--delete".to_string()));
+}
+
+#[test]
+fn test_get_copy_from_context_command() {
+    let tmp = tempdir::TempDir::new("nixpacks").unwrap();
+    let output = OutputDir::new(tmp.path().to_path_buf(), true).unwrap();
+    let dirs = IncrementalCacheDirs::new(&output);
+    dirs.create().unwrap();
+    fs::create_dir_all(dirs.restore_dir.join("opt/venv")).unwrap();
+    fs::create_dir_all(dirs.restore_dir.join("target")).unwrap();
+
+    let cmds = IncrementalCache::get_copy_from_context_command(
+        &[
+            "/opt/venv".to_string(),
+            "./target".to_string(),
+            "~/.cache/pip".to_string(),
+        ],
+        &output,
+    );
+
+    assert_eq!(
+        cmds,
+        vec![
+            "COPY .nixpacks/incremental-cache/restore/opt/venv /opt/venv".to_string(),
+            "COPY .nixpacks/incremental-cache/restore/target ./target".to_string(),
+        ]
+    );
+}
@@ -0,0 +1,271 @@
+use super::{dockerfile_generation::OutputDir, DockerBuilderOptions};
+use crate::nixpacks::{devenv::ssh_config::home_dir, plan::BuildPlan};
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::{fs, path::Path, process::Command};
+
+/// The kaniko executor image, run as a local container or in a Kubernetes Job.
+pub const KANIKO_IMAGE: &str = "gcr.io/kaniko-project/executor:v1.23.2";
+
+/// Where the executor is in the kaniko image, e.g. when nixpacks runs in a CI job that uses it.
+const KANIKO_EXECUTOR: &str = "/kaniko/executor";
+
+/// Where the build context is mounted in the local kaniko container.
+const WORKSPACE: &str = "/workspace";
+
+/// The Kubernetes secret the Job reads registry credentials from, as created by `kubectl create secret docker-registry`.
+const REGISTRY_SECRET: &str = "regcred";
+
+/// How the image is built with kaniko, which needs neither a Docker daemon nor root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kaniko {
+    /// Run the executor on this machine: directly inside the kaniko image, or otherwise in a local container.
+    Run,
+    /// Write a Kubernetes Job spec to `path` that builds the context downloaded from `context`,
+    /// e.g. `s3://bucket/app.tar.gz`.
+    Job { path: String, context: String },
+}
+
+/// Builds the generated Dockerfile with kaniko.
+pub struct KanikoBuild<'a> {
+    options: &'a DockerBuilderOptions,
+}
+
+impl<'a> KanikoBuild<'a> {
+    pub fn new(options: &'a DockerBuilderOptions) -> KanikoBuild<'a> {
+        KanikoBuild { options }
+    }
+
+    /// Run the executor on the build context, streaming its output.
+    pub fn run(&self, plan: &BuildPlan, name: &str, output: &OutputDir) -> Result<()> {
+        let mut cmd = if Path::new(KANIKO_EXECUTOR).exists() {
+            let mut cmd = Command::new(KANIKO_EXECUTOR);
+            cmd.args(self.executor_args(
+                plan,
+                name,
+                &format!("dir://{}", output.root.display()),
+                &output.get_absolute_path("Dockerfile").to_string_lossy(),
+            ));
+            cmd
+        } else {
+            self.container_cmd(plan, name, output)?
+        };
+
+        let result = cmd.spawn()?.wait().context("Building image with kaniko")?;
+        if !result.success() {
+            bail!("kaniko build failed")
+        }
+
+        Ok(())
+    }
+
+    /// A `docker run` command running the executor on the build context in a local container.
+    fn container_cmd(&self, plan: &BuildPlan, name: &str, output: &OutputDir) -> Result<Command> {
+        let mut cmd = Command::new("docker");
+        if cmd.output().is_err() {
+            bail!(
+                "Please install docker to run kaniko in a container, or run nixpacks in the {KANIKO_IMAGE} image"
+            )
+        }
+
+        cmd.args(["run", "--rm", "-v"])
+            .arg(format!("{}:{WORKSPACE}", output.root.display()));
+        if let Some(memory) = &self.options.limits.memory {
+            cmd.arg("--memory").arg(memory);
+        }
+        if let Some(cpus) = self.options.limits.cpus {
+            cmd.arg("--cpus").arg(cpus.to_string());
+        }
+
+        // Push with the registry credentials from `docker login`
+        let docker_config = home_dir().map(|home| home.join(".docker/config.json"));
+        if let Some(docker_config) = docker_config.filter(|path| path.exists()) {
+            cmd.arg("-v").arg(format!(
+                "{}:/kaniko/.docker/config.json:ro",
+                docker_config.display()
+            ));
+        }
+
+        cmd.arg(KANIKO_IMAGE).args(self.executor_args(
+            plan,
+            name,
+            &format!("dir://{WORKSPACE}"),
+            &format!("{WORKSPACE}/Dockerfile"),
+        ));
+
+        Ok(cmd)
+    }
+
+    /// Write a Kubernetes Job spec that builds the context with kaniko and pushes the image.
+    pub fn write_job(&self, plan: &BuildPlan, name: &str, path: &str, context: &str) -> Result<()> {
+        fs::write(path, self.job_spec(plan, name, context)?)
+            .with_context(|| format!("Writing kaniko Job to {path}"))
+    }
+
+    /// A Kubernetes Job spec that builds the context with kaniko and pushes the image.
+    fn job_spec(&self, plan: &BuildPlan, name: &str, context: &str) -> Result<String> {
+        let job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": job_name(name) },
+            "spec": {
+                "backoffLimit": 0,
+                "template": {
+                    "spec": {
+                        "restartPolicy": "Never",
+                        "containers": [{
+                            "name": "kaniko",
+                            "image": KANIKO_IMAGE,
+                            "args": self.executor_args(plan, name, context, "Dockerfile"),
+                            "volumeMounts": [{
+                                "name": "docker-config",
+                                "mountPath": "/kaniko/.docker",
+                            }],
+                        }],
+                        "volumes": [{
+                            "name": "docker-config",
+                            "secret": {
+                                "secretName": REGISTRY_SECRET,
+                                "items": [{ "key": ".dockerconfigjson", "path": "config.json" }],
+                            },
+                        }],
+                    },
+                },
+            },
+        });
+
+        Ok(serde_yaml::to_string(&job)?)
+    }
+
+    /// The executor's arguments for building the context. The image is only pushed with `--push` or from a Job.
+    fn executor_args(
+        &self,
+        plan: &BuildPlan,
+        name: &str,
+        context: &str,
+        dockerfile: &str,
+    ) -> Vec<String> {
+        let options = self.options;
+        let mut args = vec![
+            format!("--context={context}"),
+            format!("--dockerfile={dockerfile}"),
+        ];
+
+        let push = options.push || matches!(options.kaniko, Some(Kaniko::Job { .. }));
+        if push {
+            for image in std::iter::once(name.to_string()).chain(options.tags.clone()) {
+                args.push(format!("--destination={image}"));
+            }
+        } else {
+            args.push("--no-push".to_string());
+        }
+
+        if options.verbose {
+            args.push("--verbosity=debug".to_string());
+        }
+
+        for (name, value) in &plan.variables.clone().unwrap_or_default() {
+            args.push(format!("--build-arg={name}={value}"));
+        }
+        for build_arg in &options.build_args {
+            args.push(format!("--build-arg={build_arg}"));
+        }
+        for label in &options.labels {
+            args.push(format!("--label={label}"));
+        }
+        if let Some(platform) = options.platform.first() {
+            args.push(format!("--custom-platform={platform}"));
+        }
+
+        args
+    }
+}
+
+/// A Kubernetes object name for the Job building the image, e.g. `nixpacks-app` for `ghcr.io/acme/app:v1`.
+fn job_name(name: &str) -> String {
+    let repository = name.rsplit('/').next().unwrap_or(name);
+    let repository = repository.split(':').next().unwrap_or(repository);
+    let sanitized = repository
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+
+    format!("nixpacks-{}", sanitized.trim_matches('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_executor_args() {
+        let options = DockerBuilderOptions {
+            push: true,
+            tags: vec!["ghcr.io/acme/app:v1".to_string()],
+            build_args: vec!["GIT_SHA=abc".to_string()],
+            platform: vec!["linux/arm64".to_string()],
+            kaniko: Some(Kaniko::Run),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            KanikoBuild::new(&options)
+                .executor_args(
+                    &BuildPlan::default(),
+                    "ghcr.io/acme/app",
+                    "dir:///workspace",
+                    "/workspace/Dockerfile"
+                )
+                .join(" "),
+            "--context=dir:///workspace --dockerfile=/workspace/Dockerfile --destination=ghcr.io/acme/app --destination=ghcr.io/acme/app:v1 --build-arg=GIT_SHA=abc --custom-platform=linux/arm64"
+        );
+    }
+
+    #[test]
+    fn test_executor_args_without_push() {
+        let options = DockerBuilderOptions {
+            kaniko: Some(Kaniko::Run),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            KanikoBuild::new(&options).executor_args(
+                &BuildPlan::default(),
+                "app",
+                "dir:///workspace",
+                "/workspace/Dockerfile"
+            )[2],
+            "--no-push"
+        );
+    }
+
+    #[test]
+    fn test_job_spec() {
+        let options = DockerBuilderOptions {
+            kaniko: Some(Kaniko::Job {
+                path: "job.yaml".to_string(),
+                context: "s3://builds/app.tar.gz".to_string(),
+            }),
+            ..Default::default()
+        };
+        let job = KanikoBuild::new(&options)
+            .job_spec(
+                &BuildPlan::default(),
+                "ghcr.io/acme/app",
+                "s3://builds/app.tar.gz",
+            )
+            .unwrap();
+
+        assert!(job.contains("name: nixpacks-app\n"));
+        assert!(job.contains("- --context=s3://builds/app.tar.gz\n"));
+        assert!(job.contains("- --destination=ghcr.io/acme/app\n"));
+        assert!(job.contains("secretName: regcred\n"));
+    }
+
+    #[test]
+    fn test_job_name() {
+        assert_eq!(job_name("ghcr.io/acme/my_app:v1"), "nixpacks-my-app");
+        assert_eq!(job_name("app"), "nixpacks-app");
+    }
+}
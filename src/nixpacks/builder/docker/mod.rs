@@ -1,5 +1,27 @@
+use self::{
+    cancel::CancelHandle,
+    engine::{Engine, ImageOutput, ResourceLimits},
+    kaniko::Kaniko,
+    progress::{LogSink, Progress},
+    timings::Timings,
+};
 use super::ImageBuilder;
 
+/// What the final image contains besides the app.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Runtime {
+    /// The whole build environment.
+    Full,
+    /// Only the Nix packages the app needs, on a minimal base image.
+    Slim,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime::Full
+    }
+}
+
 /// Holds options for generating a Docker image.
 #[derive(Clone, Default, Debug)]
 #[allow(clippy::struct_excessive_bools)]
@@ -9,6 +31,8 @@ pub struct DockerBuilderOptions {
     pub print_dockerfile: bool,
     pub tags: Vec<String>,
     pub labels: Vec<String>,
+    /// Don't label the image with the git revision and remote it was built from.
+    pub no_oci_labels: bool,
     pub quiet: bool,
     pub cache_key: Option<String>,
     pub no_cache: bool,
@@ -18,12 +42,82 @@ pub struct DockerBuilderOptions {
     pub current_dir: bool,
     pub no_error_without_start: bool,
     pub incremental_cache_image: Option<String>,
+    /// Object storage holding the cached directories between builds instead of an image, e.g. `s3://bucket/prefix`.
+    pub incremental_cache: Option<String>,
     pub verbose: bool,
+    /// Build on this host over SSH instead of with the local Docker daemon.
+    pub remote_host: Option<String>,
+    /// Push the image and its tags to their registry after building.
+    pub push: bool,
+    /// Build arguments declared for install and build commands but not set in the image, in the form accepted by
+    /// `docker build --build-arg`.
+    pub build_args: Vec<String>,
+    /// BuildKit secrets available to install and build commands, in the form accepted by `docker build --secret`.
+    pub secrets: Vec<String>,
+    /// SSH agent sockets or keys available to install and build commands, in the form accepted by `docker build --ssh`.
+    pub ssh: Vec<String>,
+    /// Network the install and build commands run in, e.g. `host` or `none`, as accepted by `docker build --network`.
+    pub network: Option<String>,
+    /// Memory and CPU limits for the install and build commands, for builds on shared hosts.
+    pub limits: ResourceLimits,
+    /// The CLI that builds and pushes the image.
+    pub engine: Engine,
+    /// Also write the image here, e.g. as an OCI tarball for environments without a Docker daemon.
+    pub output: Option<ImageOutput>,
+    /// Handlebars-style template file laying out the generated Dockerfile, instead of the default layout.
+    pub dockerfile_template: Option<String>,
+    /// Build the app's own Dockerfile, if it has one, instead of generating one from a build plan.
+    pub prefer_dockerfile: bool,
+    /// Run the install and build commands in a single layer, so files they leave behind and later remove aren't kept.
+    pub squash: bool,
+    /// Run phases that don't depend on each other in parallel stages, merging what they write to the app afterwards.
+    pub parallel_phases: bool,
+    /// What the final image contains besides the app.
+    pub runtime: Runtime,
+    /// How the output of the build is shown.
+    pub progress: Progress,
+    /// Where the events of the build go instead of stdout. Setting it builds as with `Progress::Json`.
+    pub log_sink: Option<LogSink>,
+    /// Check that every Nix package of the plan exists, and which are in a binary cache, before building.
+    pub check_packages: bool,
+    /// Report how long each phase and step of the build took, once it's built.
+    pub timings: Option<Timings>,
+    /// Only build the setup and install phases, without copying the app in for the start, to warm the cache of the
+    /// builds after.
+    pub cache_only: bool,
+    /// With `cache_only`, copy the whole app in after the install phases, to run commands on it in the image.
+    pub with_source: bool,
+    /// Build with kaniko instead of the engine, for rootless builds in Kubernetes.
+    pub kaniko: Option<Kaniko>,
+    /// Stops the build when cancelled, killing the engine command it's running and removing its temporary files.
+    /// kaniko and remote builds are only stopped before they start.
+    pub cancel: Option<CancelHandle>,
+}
+
+impl DockerBuilderOptions {
+    /// Whether the cached directories are saved and restored between builds, in an image or object storage.
+    pub fn uses_incremental_cache(&self) -> bool {
+        self.incremental_cache_image.is_some() || self.incremental_cache.is_some()
+    }
+
+    /// Whether the build's output is turned into events, printed as JSON or sent to the log sink.
+    pub fn emits_events(&self) -> bool {
+        self.progress == Progress::Json || self.log_sink.is_some()
+    }
 }
 
+pub mod built_images;
 mod cache;
+pub mod cancel;
+pub mod clean;
 pub mod docker_image_builder;
-mod dockerfile_generation;
+pub(crate) mod dockerfile_generation;
+pub mod engine;
 pub mod file_server;
 pub mod incremental_cache;
+pub mod kaniko;
+pub mod progress;
+mod remote_build;
+pub mod timings;
 pub mod utils;
+pub mod watch;
@@ -0,0 +1,428 @@
+use super::{
+    cancel::{kill, wait_or_kill, BuildCancelled, CancelHandle, POLL_INTERVAL},
+    timings::TimingsReport,
+};
+use crate::nixpacks::plan::BuildPlan;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read},
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+};
+
+/// How the output of the image build is shown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Pass the engine's output through, letting it pick a format for the terminal.
+    Auto,
+    /// The full output of every step, for CI logs.
+    Plain,
+    /// One JSON event per line on stdout, for other tools to consume.
+    Json,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::Auto
+    }
+}
+
+/// A structured event emitted with `--progress json`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BuildEvent {
+    /// A message from nixpacks itself, like a section heading.
+    Message {
+        message: String,
+    },
+    PhaseStarted {
+        phase: String,
+    },
+    PhaseFinished {
+        phase: String,
+    },
+    /// A Dockerfile instruction started. `phase` is the plan phase it's part of, if it runs one of its commands.
+    StepStarted {
+        step: String,
+        name: String,
+        phase: Option<String>,
+    },
+    /// The step's layer was reused from the cache.
+    StepCached {
+        step: String,
+    },
+    StepFinished {
+        step: String,
+        seconds: Option<f64>,
+    },
+    StepFailed {
+        step: String,
+        error: String,
+    },
+    /// A line of output, from a step if it could be told which.
+    Log {
+        step: Option<String>,
+        line: String,
+    },
+    /// The image was built, and can be run or pushed with these names.
+    BuildFinished {
+        images: Vec<String>,
+    },
+    /// Where the time of the build went, with `--timings json`.
+    Timings(TimingsReport),
+}
+
+impl BuildEvent {
+    /// Print the event as a line of JSON on stdout.
+    pub fn emit(&self) {
+        if let Ok(json) = serde_json::to_string(self) {
+            println!("{json}");
+        }
+    }
+}
+
+/// Receives the events of a build instead of stdout, for services embedding nixpacks to stream the build to their own
+/// UIs. Events are sent as they happen, from the thread running the build.
+#[derive(Clone)]
+pub struct LogSink(Arc<dyn Fn(BuildEvent) + Send + Sync>);
+
+impl LogSink {
+    pub fn new<F: Fn(BuildEvent) + Send + Sync + 'static>(callback: F) -> LogSink {
+        LogSink(Arc::new(callback))
+    }
+
+    pub fn send(&self, event: BuildEvent) {
+        (self.0)(event);
+    }
+}
+
+impl fmt::Debug for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogSink")
+    }
+}
+
+/// Sends the events to a channel, for an async task to receive. Events sent after the receiver is dropped are lost.
+impl From<tokio::sync::mpsc::UnboundedSender<BuildEvent>> for LogSink {
+    fn from(sender: tokio::sync::mpsc::UnboundedSender<BuildEvent>) -> Self {
+        LogSink::new(move |event| {
+            let _ = sender.send(event);
+        })
+    }
+}
+
+/// Turns lines of build output into events. Understands BuildKit's plain progress output and the `STEP` output of
+/// Podman and Buildah.
+pub struct ProgressParser {
+    /// The commands of each phase, to tell which phase a step belongs to.
+    phase_cmds: Vec<(String, Vec<String>)>,
+    current_phase: Option<String>,
+    /// The Podman or Buildah step that's running, which is only ended by the next one.
+    current_step: Option<String>,
+    buildkit_step: Regex,
+    buildkit_line: Regex,
+    containers_step: Regex,
+}
+
+impl ProgressParser {
+    pub fn new(plan: &BuildPlan) -> ProgressParser {
+        let phase_cmds = plan
+            .get_sorted_phases()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|phase| (phase.get_name(), phase.cmds.clone().unwrap_or_default()))
+            .collect();
+
+        ProgressParser {
+            phase_cmds,
+            current_phase: None,
+            current_step: None,
+            buildkit_step: Regex::new(r"^#(\d+) (\[.+)$").unwrap(),
+            buildkit_line: Regex::new(r"^#(\d+) (.*)$").unwrap(),
+            containers_step: Regex::new(r"^STEP (\d+)/\d+: (.+)$").unwrap(),
+        }
+    }
+
+    /// The events a line of output stands for.
+    pub fn parse_line(&mut self, line: &str) -> Vec<BuildEvent> {
+        if let Some(caps) = self.buildkit_step.captures(line) {
+            return self.start_step(&caps[1], &caps[2]);
+        }
+
+        if let Some(caps) = self.buildkit_line.captures(line) {
+            let step = caps[1].to_string();
+            let rest = &caps[2];
+            let event = if rest == "CACHED" {
+                BuildEvent::StepCached { step }
+            } else if let Some(seconds) = rest.strip_prefix("DONE ") {
+                BuildEvent::StepFinished {
+                    step,
+                    seconds: seconds.trim_end_matches('s').parse().ok(),
+                }
+            } else if let Some(error) = rest.strip_prefix("ERROR: ") {
+                BuildEvent::StepFailed {
+                    step,
+                    error: error.to_string(),
+                }
+            } else {
+                // Output lines are prefixed with the time since the step started
+                let line = rest.split_once(' ').map_or(rest, |(_, line)| line);
+                BuildEvent::Log {
+                    step: Some(step),
+                    line: line.to_string(),
+                }
+            };
+            return vec![event];
+        }
+
+        if let Some(caps) = self.containers_step.captures(line) {
+            let (step, name) = (caps[1].to_string(), caps[2].to_string());
+            let mut events = self.finish_containers_step();
+            events.extend(self.start_step(&step, &name));
+            self.current_step = Some(step);
+            return events;
+        }
+
+        match (&self.current_step, line.strip_prefix("--> ")) {
+            (Some(step), Some(rest)) if rest.starts_with("Using cache") => {
+                vec![BuildEvent::StepCached { step: step.clone() }]
+            }
+            (Some(_), Some(_)) => self.finish_containers_step(),
+            (step, _) => vec![BuildEvent::Log {
+                step: step.clone(),
+                line: line.to_string(),
+            }],
+        }
+    }
+
+    /// The events closing the last step and phase, once the output ends.
+    pub fn finish(&mut self) -> Vec<BuildEvent> {
+        let mut events = self.finish_containers_step();
+        if let Some(phase) = self.current_phase.take() {
+            events.push(BuildEvent::PhaseFinished { phase });
+        }
+        events
+    }
+
+    fn start_step(&mut self, step: &str, name: &str) -> Vec<BuildEvent> {
+        let phase = self
+            .phase_cmds
+            .iter()
+            .find(|(_, cmds)| cmds.iter().any(|cmd| name.contains(cmd.as_str())))
+            .map(|(phase, _)| phase.clone());
+
+        let mut events = Vec::new();
+        if phase.is_some() && phase != self.current_phase {
+            if let Some(current) = self.current_phase.take() {
+                events.push(BuildEvent::PhaseFinished { phase: current });
+            }
+            events.push(BuildEvent::PhaseStarted {
+                phase: phase.clone().unwrap_or_default(),
+            });
+            self.current_phase.clone_from(&phase);
+        }
+
+        events.push(BuildEvent::StepStarted {
+            step: step.to_string(),
+            name: name.to_string(),
+            phase,
+        });
+        events
+    }
+
+    fn finish_containers_step(&mut self) -> Vec<BuildEvent> {
+        self.current_step
+            .take()
+            .map(|step| BuildEvent::StepFinished {
+                step,
+                seconds: None,
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Run a build command, passing the events its stdout and stderr stand for to `on_event`. The output itself is
+/// printed to stderr with `echo_output`, and dropped otherwise. The command is killed if the build is cancelled.
+pub fn run_with_events<F: FnMut(&BuildEvent)>(
+    cmd: &mut Command,
+    parser: &mut ProgressParser,
+    echo_output: bool,
+    cancel: Option<&CancelHandle>,
+    mut on_event: F,
+) -> Result<ExitStatus> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let readers = [
+        forward_lines(child.stdout.take(), sender.clone()),
+        forward_lines(child.stderr.take(), sender),
+    ];
+
+    loop {
+        if cancel.map_or(false, CancelHandle::is_cancelled) {
+            kill(&mut child);
+            return Err(BuildCancelled.into());
+        }
+        let line = match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if echo_output {
+            eprintln!("{line}");
+        }
+        for event in parser.parse_line(&line) {
+            on_event(&event);
+        }
+    }
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
+    for event in parser.finish() {
+        on_event(&event);
+    }
+
+    wait_or_kill(&mut child, cancel).context("Building image")
+}
+
+/// Send each line read from the stream to the channel, on another thread.
+fn forward_lines<R: Read + Send + 'static>(
+    stream: Option<R>,
+    sender: mpsc::Sender<String>,
+) -> Option<thread::JoinHandle<()>> {
+    stream.map(|stream| {
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::plan::phase::Phase;
+
+    fn parser() -> ProgressParser {
+        let mut install = Phase::install(None);
+        install.add_cmd("npm ci");
+        ProgressParser::new(&BuildPlan::new(&[install], None))
+    }
+
+    #[test]
+    fn test_parse_buildkit_output() {
+        let mut parser = parser();
+
+        assert_eq!(
+            parser.parse_line("#7 [4/6] RUN --mount=type=cache,id=app,target=/root/.npm npm ci"),
+            vec![
+                BuildEvent::PhaseStarted {
+                    phase: "install".to_string()
+                },
+                BuildEvent::StepStarted {
+                    step: "7".to_string(),
+                    name: "[4/6] RUN --mount=type=cache,id=app,target=/root/.npm npm ci"
+                        .to_string(),
+                    phase: Some("install".to_string())
+                }
+            ]
+        );
+        assert_eq!(
+            parser.parse_line("#7 0.412 added 120 packages"),
+            vec![BuildEvent::Log {
+                step: Some("7".to_string()),
+                line: "added 120 packages".to_string()
+            }]
+        );
+        assert_eq!(
+            parser.parse_line("#7 DONE 3.2s"),
+            vec![BuildEvent::StepFinished {
+                step: "7".to_string(),
+                seconds: Some(3.2)
+            }]
+        );
+        assert_eq!(
+            parser.parse_line("#3 CACHED"),
+            vec![BuildEvent::StepCached {
+                step: "3".to_string()
+            }]
+        );
+        assert_eq!(
+            parser.finish(),
+            vec![BuildEvent::PhaseFinished {
+                phase: "install".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_podman_output() {
+        let mut parser = parser();
+
+        assert_eq!(parser.parse_line("STEP 1/6: FROM ubuntu").len(), 1);
+        assert_eq!(
+            parser.parse_line("--> Using cache 1f2e3d"),
+            vec![BuildEvent::StepCached {
+                step: "1".to_string()
+            }]
+        );
+        assert_eq!(
+            parser.parse_line("STEP 2/6: RUN npm ci"),
+            vec![
+                BuildEvent::StepFinished {
+                    step: "1".to_string(),
+                    seconds: None
+                },
+                BuildEvent::PhaseStarted {
+                    phase: "install".to_string()
+                },
+                BuildEvent::StepStarted {
+                    step: "2".to_string(),
+                    name: "RUN npm ci".to_string(),
+                    phase: Some("install".to_string())
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_sink_channel() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let sink = LogSink::from(sender);
+        sink.send(BuildEvent::PhaseStarted {
+            phase: "install".to_string(),
+        });
+        drop(sink);
+
+        assert_eq!(
+            receiver.blocking_recv(),
+            Some(BuildEvent::PhaseStarted {
+                phase: "install".to_string()
+            })
+        );
+        assert_eq!(receiver.blocking_recv(), None);
+    }
+
+    #[test]
+    fn test_event_json() {
+        assert_eq!(
+            serde_json::to_string(&BuildEvent::StepCached {
+                step: "3".to_string()
+            })
+            .unwrap(),
+            r#"{"event":"step_cached","step":"3"}"#
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use super::{
+    dockerfile_generation::OutputDir,
+    engine::{BuildCmds, ContainerEngine},
+};
+use crate::nixpacks::{
+    devenv::{
+        inventory::HostSettings,
+        ssh::{RemoteHost, SshTarget},
+        ssh_config::SshConfig,
+        DevenvOptions,
+    },
+    logger::Logger,
+    utils::shell_quote,
+};
+use anyhow::{bail, Context, Result};
+use std::process::{Command, Stdio};
+
+/// Builds the image with a container engine on another machine, reached over SSH.
+///
+/// The generated build context is uploaded to a temporary directory on the host and removed once the build is done.
+/// The image stays on the host.
+pub struct RemoteBuild {
+    target: SshTarget,
+    engine: &'static dyn ContainerEngine,
+}
+
+impl RemoteBuild {
+    /// Resolve the host the same way `nixpacks devenv` does. It can be `user@host` or an alias from `~/.ssh/config`.
+    pub fn new(host: &str, engine: &'static dyn ContainerEngine) -> Result<RemoteBuild> {
+        let (user, host) = match host.split_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, host),
+        };
+        let options = DevenvOptions {
+            ssh_user: user,
+            ..Default::default()
+        };
+        let target = SshTarget::resolve(
+            host,
+            &HostSettings::default(),
+            &options,
+            &SshConfig::load()?,
+        )?;
+
+        Ok(RemoteBuild { target, engine })
+    }
+
+    /// Upload the build context and run the engine's build commands in it, streaming the output.
+    ///
+    /// The push commands are then run on the host, with its registry credentials.
+    pub fn build(&self, output: &OutputDir, cmds: &BuildCmds) -> Result<()> {
+        let logger = Logger::new();
+        logger.log_section(&format!(
+            "Building on {}@{}:{}",
+            self.target.user, self.target.hostname, self.target.port
+        ));
+
+        let remote = RemoteHost::connect(&self.target)?;
+        let engine = self.engine;
+        if remote
+            .exec(&format!("command -v {}", engine.name()))
+            .is_err()
+        {
+            bail!(
+                "Please install {} on {} to build the app there {}",
+                engine.name(),
+                self.target.host,
+                engine.install_url()
+            );
+        }
+
+        let context = remote
+            .exec("mktemp -d -t nixpacks-XXXXXX")
+            .context("Creating a build directory on the remote host")?
+            .trim()
+            .to_string();
+
+        let result = self.upload_and_build(&remote, output, &context, cmds);
+        remote
+            .exec(&format!("rm -rf {}", shell_quote(&context)))
+            .context("Removing the build directory from the remote host")?;
+
+        result
+    }
+
+    fn upload_and_build(
+        &self,
+        remote: &RemoteHost,
+        output: &OutputDir,
+        context: &str,
+        cmds: &BuildCmds,
+    ) -> Result<()> {
+        let bundle = bundle_dir(output)?;
+        println!("Uploading {} KB of build context", bundle.len() / 1024);
+        remote
+            .exec_with_input(&format!("tar -xzf - -C {}", shell_quote(context)), &bundle)
+            .context("Uploading build context")?;
+
+        if let Some(setup_args) = &cmds.setup {
+            // The builder is usually left over from an earlier build with the same limits
+            let _ = remote.exec(&remote_cmd(self.engine, setup_args));
+        }
+
+        if let Some(remove_args) = &cmds.remove_manifest {
+            // There's usually no earlier manifest list to remove
+            let _ = remote.exec(&remote_cmd(self.engine, remove_args));
+        }
+
+        for (platform, build_args) in &cmds.builds {
+            let failure = match platform {
+                Some(platform) => {
+                    println!("Building for {platform}");
+                    format!("Docker build for {platform} failed")
+                }
+                None => "Docker build failed".to_string(),
+            };
+            remote
+                .exec_streaming(&format!(
+                    "cd {} && {}",
+                    shell_quote(context),
+                    remote_cmd(self.engine, build_args)
+                ))
+                .context(failure)?;
+        }
+
+        if let Some(create_args) = &cmds.create_manifest {
+            remote
+                .exec_streaming(&remote_cmd(self.engine, create_args))
+                .context("Failed to create manifest list")?;
+        }
+
+        for (image, push_args) in &cmds.push {
+            println!("Pushing {image}");
+            remote
+                .exec_streaming(&remote_cmd(self.engine, push_args))
+                .with_context(|| format!("Failed to push {image}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// How to run the built image.
+    pub fn run_hint(&self, name: &str) -> String {
+        format!("ssh {} {}", self.target.host, self.engine.run_cmd(name))
+    }
+}
+
+/// A shell command running the engine's CLI with its environment and the given arguments.
+fn remote_cmd(engine: &dyn ContainerEngine, args: &[String]) -> String {
+    engine
+        .env()
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .chain(std::iter::once(engine.name().to_string()))
+        .chain(args.iter().map(|arg| shell_quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Bundle the whole build context into a gzipped tarball with the local `tar` binary.
+fn bundle_dir(output: &OutputDir) -> Result<Vec<u8>> {
+    let tar = Command::new("tar")
+        .arg("-czf")
+        .arg("-")
+        .arg("-C")
+        .arg(&output.root)
+        .arg(".")
+        .stderr(Stdio::piped())
+        .output()
+        .context("Please install tar to upload the build context")?;
+
+    if !tar.status.success() {
+        bail!(
+            "Failed to bundle build context\n{}",
+            String::from_utf8_lossy(&tar.stderr)
+        );
+    }
+
+    Ok(tar.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::builder::docker::engine::Engine;
+
+    #[test]
+    fn test_remote_cmd() {
+        let args = vec![
+            "build".to_string(),
+            ".".to_string(),
+            "-t".to_string(),
+            "my app".to_string(),
+        ];
+        assert_eq!(
+            remote_cmd(Engine::Docker.cli(), &args),
+            "DOCKER_BUILDKIT=1 docker 'build' '.' '-t' 'my app'"
+        );
+        assert_eq!(
+            remote_cmd(Engine::Podman.cli(), &args),
+            "podman 'build' '.' '-t' 'my app'"
+        );
+    }
+}
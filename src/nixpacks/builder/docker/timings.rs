@@ -0,0 +1,264 @@
+use super::progress::BuildEvent;
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write, time::Instant};
+
+/// How the time each step of the build took is reported once it's built.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timings {
+    /// A table of the phases and steps, after the build output.
+    Text,
+    /// The report as JSON on stdout, or as a `timings` event with `--progress json`.
+    Json,
+}
+
+/// How long a step of the build took, and whether its layer was reused from the cache.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StepTiming {
+    pub step: String,
+    pub name: String,
+    pub phase: Option<String>,
+    pub cached: bool,
+    pub seconds: f64,
+}
+
+/// The time the steps running a phase's commands took together.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub seconds: f64,
+    pub steps: usize,
+    pub cached_steps: usize,
+}
+
+/// Where the time of a build went, by phase and by Dockerfile step.
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TimingsReport {
+    /// The platform the image was built for, when building for several.
+    pub platform: Option<String>,
+    /// Wall time of the whole build.
+    pub seconds: f64,
+    pub phases: Vec<PhaseTiming>,
+    pub steps: Vec<StepTiming>,
+}
+
+/// Collects the timings of the steps from the events of a build.
+#[derive(Default)]
+pub struct TimingsRecorder {
+    steps: Vec<StepTiming>,
+    started: HashMap<String, Instant>,
+}
+
+impl TimingsRecorder {
+    pub fn record(&mut self, event: &BuildEvent) {
+        match event {
+            BuildEvent::StepStarted { step, name, phase } => {
+                // BuildKit repeats a step's heading when its output is interleaved with other steps
+                if self.started.contains_key(step) {
+                    return;
+                }
+                self.started.insert(step.clone(), Instant::now());
+                self.steps.push(StepTiming {
+                    step: step.clone(),
+                    name: name.clone(),
+                    phase: phase.clone(),
+                    cached: false,
+                    seconds: 0.0,
+                });
+            }
+            BuildEvent::StepCached { step } => {
+                if let Some(timing) = self.get_step(step) {
+                    timing.cached = true;
+                }
+            }
+            BuildEvent::StepFinished { step, seconds } => {
+                // Podman and Buildah don't say how long a step took, so it's the time until the next one started
+                let elapsed = self
+                    .started
+                    .get(step)
+                    .map(|started| started.elapsed().as_secs_f64());
+                if let Some(timing) = self.get_step(step) {
+                    timing.seconds = seconds.or(elapsed).unwrap_or_default();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The report of the build, which took `seconds` in total.
+    pub fn report(self, platform: Option<String>, seconds: f64) -> TimingsReport {
+        let mut phases: Vec<PhaseTiming> = Vec::new();
+        for step in &self.steps {
+            let phase = match &step.phase {
+                Some(phase) => phase,
+                None => continue,
+            };
+            if !phases.iter().any(|timing| &timing.phase == phase) {
+                phases.push(PhaseTiming {
+                    phase: phase.clone(),
+                    seconds: 0.0,
+                    steps: 0,
+                    cached_steps: 0,
+                });
+            }
+            let timing = phases
+                .iter_mut()
+                .find(|timing| &timing.phase == phase)
+                .expect("BUG: Phase timing was just added");
+            timing.seconds += step.seconds;
+            timing.steps += 1;
+            if step.cached {
+                timing.cached_steps += 1;
+            }
+        }
+
+        TimingsReport {
+            platform,
+            seconds,
+            phases,
+            steps: self.steps,
+        }
+    }
+
+    fn get_step(&mut self, step: &str) -> Option<&mut StepTiming> {
+        self.steps.iter_mut().find(|timing| timing.step == step)
+    }
+}
+
+impl TimingsReport {
+    /// The report as tables of the phases and steps, for the terminal.
+    pub fn to_table(&self) -> String {
+        let mut table = match &self.platform {
+            Some(platform) => format!("Build timings for {platform}: {:.1}s\n", self.seconds),
+            None => format!("Build timings: {:.1}s\n", self.seconds),
+        };
+
+        if !self.phases.is_empty() {
+            let width = self
+                .phases
+                .iter()
+                .map(|timing| timing.phase.len())
+                .max()
+                .unwrap_or_default()
+                .max("Phase".len());
+            let _ = writeln!(table, "\n  {:width$}  {:>8}  Cached", "Phase", "Time");
+            for timing in &self.phases {
+                let _ = writeln!(
+                    table,
+                    "  {:width$}  {:>7.1}s  {}/{}",
+                    timing.phase, timing.seconds, timing.cached_steps, timing.steps
+                );
+            }
+        }
+
+        let _ = writeln!(table, "\n  {:>8}  {:6}  Step", "Time", "Status");
+        for step in &self.steps {
+            let status = if step.cached { "cached" } else { "built" };
+            let _ = writeln!(
+                table,
+                "  {:>7.1}s  {status:6}  {}",
+                step.seconds,
+                truncate(&step.name, 80)
+            );
+        }
+
+        table
+    }
+}
+
+/// The first `max` characters of a step's name, to keep long commands on one line.
+fn truncate(name: &str, max: usize) -> String {
+    if name.chars().count() <= max {
+        name.to_string()
+    } else {
+        format!("{}...", name.chars().take(max - 3).collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(events: &[BuildEvent]) -> TimingsReport {
+        let mut recorder = TimingsRecorder::default();
+        for event in events {
+            recorder.record(event);
+        }
+        recorder.report(None, 4.5)
+    }
+
+    fn started(step: &str, name: &str, phase: Option<&str>) -> BuildEvent {
+        BuildEvent::StepStarted {
+            step: step.to_string(),
+            name: name.to_string(),
+            phase: phase.map(ToString::to_string),
+        }
+    }
+
+    fn finished(step: &str, seconds: f64) -> BuildEvent {
+        BuildEvent::StepFinished {
+            step: step.to_string(),
+            seconds: Some(seconds),
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let report = record(&[
+            started("5", "[2/5] COPY . /app/.", None),
+            finished("5", 0.2),
+            started("6", "[3/5] RUN npm ci", Some("install")),
+            BuildEvent::StepCached {
+                step: "6".to_string(),
+            },
+            started("7", "[4/5] RUN npm run build", Some("build")),
+            started("7", "[4/5] RUN npm run build", Some("build")),
+            finished("7", 3.0),
+            started("8", "[5/5] RUN npm run lint", Some("build")),
+            finished("8", 1.5),
+        ]);
+
+        assert_eq!(report.steps.len(), 4);
+        assert!(report.steps[1].cached);
+        assert_eq!(
+            report.phases,
+            vec![
+                PhaseTiming {
+                    phase: "install".to_string(),
+                    seconds: 0.0,
+                    steps: 1,
+                    cached_steps: 1,
+                },
+                PhaseTiming {
+                    phase: "build".to_string(),
+                    seconds: 4.5,
+                    steps: 2,
+                    cached_steps: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_table() {
+        let table = record(&[
+            started("6", "[3/4] RUN npm ci", Some("install")),
+            finished("6", 12.04),
+        ])
+        .to_table();
+
+        assert_eq!(
+            table,
+            "Build timings: 4.5s\n\n  Phase        Time  Cached\n  install     12.0s  0/1\n\n      Time  Status  Step\n     12.0s  built   [3/4] RUN npm ci\n"
+        );
+    }
+
+    #[test]
+    fn test_report_json() {
+        let json = serde_json::to_value(record(&[])).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "seconds": 4.5, "phases": [], "steps": [] })
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use crate::nixpacks::git::{get_git_remote_url, get_git_revision};
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
 use std::path::Path;
 
 use super::cache::sanitize_cache_key;
@@ -24,6 +27,97 @@ pub fn get_cache_mount(
     }
 }
 
+/// Produce a string of Docker command flags mounting each BuildKit secret at `/run/secrets/<id>`.
+///
+/// Secrets are given the same way as to `docker build --secret`, e.g. `id=npmrc,src=$HOME/.npmrc`.
+pub fn get_secret_mounts(secrets: &[String]) -> Result<String> {
+    let mounts = secrets
+        .iter()
+        .map(|secret| {
+            let id = secret
+                .split(',')
+                .find_map(|field| field.strip_prefix("id="))
+                .unwrap_or_default();
+            if id.is_empty() {
+                bail!("Secret `{secret}` needs an id, e.g. id=npmrc,src=$HOME/.npmrc");
+            }
+
+            Ok(format!("--mount=type=secret,id={id}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(mounts.join(" "))
+}
+
+/// Produce a string of Docker command flags exposing each SSH agent socket or key to the command.
+///
+/// Agents are given the same way as to `docker build --ssh`, e.g. `default` or `github=$HOME/.ssh/id_ed25519`.
+pub fn get_ssh_mounts(ssh: &[String]) -> String {
+    ssh.iter()
+        .map(|agent| {
+            let id = agent.split('=').next().unwrap_or_default();
+            format!("--mount=type=ssh,id={id}")
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Produce the Dockerfile line declaring each build argument, so install and build commands can read it.
+///
+/// Build arguments are given the same way as to `docker build --build-arg`, e.g. `NPM_TOKEN=abc` or `NPM_TOKEN`.
+pub fn get_build_arg_declaration(build_args: &[String]) -> Result<String> {
+    if build_args.is_empty() {
+        return Ok(String::new());
+    }
+
+    let names = build_args
+        .iter()
+        .map(|build_arg| {
+            let name = build_arg.split('=').next().unwrap_or_default();
+            if name.is_empty() {
+                bail!("Build argument `{build_arg}` needs a name, e.g. NPM_TOKEN=abc");
+            }
+
+            Ok(name)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(format!("ARG {}", names.join(" ")))
+}
+
+/// Produce the standard `org.opencontainers.image.*` labels describing where the image was built from, if the app is
+/// in a git repository. Labels already given in `labels` are left as they are.
+pub fn get_oci_labels(app_src: &Path, labels: &[String], created: &str) -> Vec<String> {
+    let revision = match get_git_revision(app_src) {
+        Some(revision) => revision,
+        None => return Vec::new(),
+    };
+
+    let oci_labels = [
+        ("org.opencontainers.image.revision", Some(revision)),
+        (
+            "org.opencontainers.image.source",
+            get_git_remote_url(app_src),
+        ),
+        (
+            "org.opencontainers.image.created",
+            Some(created.to_string()),
+        ),
+    ];
+
+    oci_labels
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let is_set = labels
+                .iter()
+                .any(|label| label.split('=').next() == Some(key));
+            value
+                .filter(|_| !is_set)
+                .map(|value| format!("{key}={value}"))
+        })
+        .collect()
+}
+
 /// Produce Dockerfile line(s) copying files into the build image.
 pub fn get_copy_commands(files: &[String], app_dir: &str) -> Vec<String> {
     if files.is_empty() {
@@ -46,7 +140,7 @@ pub fn get_copy_commands(files: &[String], app_dir: &str) -> Vec<String> {
 /// Produce Dockerfile line(s) copying files into the build image from a given Docker image layer.
 pub fn get_copy_from_commands(from: &str, files: &[String], app_dir: &str) -> Vec<String> {
     if files.is_empty() {
-        vec![format!("COPY --from=0 {app_dir} {app_dir}")]
+        vec![format!("COPY --from={from} {app_dir} {app_dir}")]
     } else {
         files
             .iter()
@@ -69,6 +163,38 @@ pub fn get_exec_command(command: &str) -> String {
     format!("CMD [\"{params}\"]")
 }
 
+/// Replace each Handlebars-style `{{name}}` placeholder in a template with its value.
+///
+/// Unknown placeholders are an error, so that typos in a custom template don't silently drop a section.
+pub fn render_template(template: &str, values: &[(&str, String)]) -> Result<String> {
+    let placeholder = Regex::new(r"\{\{\s*([A-Za-z_]+)\s*\}\}")?;
+
+    let mut unknown = Vec::new();
+    let rendered = placeholder.replace_all(template, |caps: &Captures| {
+        let name = &caps[1];
+        if let Some((_, value)) = values.iter().find(|(key, _)| *key == name) {
+            value.clone()
+        } else {
+            unknown.push(name.to_string());
+            String::new()
+        }
+    });
+
+    if !unknown.is_empty() {
+        bail!(
+            "Unknown placeholder(s) {} in template, expected one of {}",
+            unknown.join(", "),
+            values
+                .iter()
+                .map(|(key, _)| *key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(rendered.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,6 +221,47 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_get_secret_mounts() {
+        let secrets = vec![
+            "id=npmrc,src=/home/me/.npmrc".to_string(),
+            "src=pip.conf,id=pip".to_string(),
+        ];
+
+        assert_eq!(
+            "--mount=type=secret,id=npmrc --mount=type=secret,id=pip",
+            get_secret_mounts(&secrets).unwrap()
+        );
+        assert_eq!("", get_secret_mounts(&[]).unwrap());
+        assert!(get_secret_mounts(&["src=/home/me/.npmrc".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_get_ssh_mounts() {
+        let ssh = vec![
+            "default".to_string(),
+            "github=/home/me/.ssh/id_ed25519".to_string(),
+        ];
+
+        assert_eq!(
+            "--mount=type=ssh,id=default --mount=type=ssh,id=github",
+            get_ssh_mounts(&ssh)
+        );
+        assert_eq!("", get_ssh_mounts(&[]));
+    }
+
+    #[test]
+    fn test_get_build_arg_declaration() {
+        let build_args = vec!["NPM_TOKEN=abc=".to_string(), "GIT_SHA".to_string()];
+
+        assert_eq!(
+            "ARG NPM_TOKEN GIT_SHA",
+            get_build_arg_declaration(&build_args).unwrap()
+        );
+        assert_eq!("", get_build_arg_declaration(&[]).unwrap());
+        assert!(get_build_arg_declaration(&["=abc".to_string()]).is_err());
+    }
+
     #[cfg(not(target_os = "windows"))]
     #[test]
     fn test_get_copy_commands() {
@@ -163,4 +330,40 @@ mod tests {
             get_exec_command("command1 command2 -l \"asdf\"")
         );
     }
+
+    #[test]
+    fn test_render_template() {
+        let values = [
+            ("base_image", "ubuntu".to_string()),
+            ("start", "CMD [\"npm start\"]".to_string()),
+        ];
+
+        assert_eq!(
+            "FROM ubuntu\nCMD [\"npm start\"]\n",
+            render_template("FROM {{base_image}}\n{{ start }}\n", &values).unwrap()
+        );
+        assert!(render_template("FROM {{image}}", &values).is_err());
+    }
+
+    #[test]
+    fn test_get_oci_labels() {
+        let app_src = Path::new(env!("CARGO_MANIFEST_DIR"));
+        if get_git_revision(app_src).is_none() {
+            // Not built from a git checkout
+            return;
+        }
+
+        let labels = get_oci_labels(
+            app_src,
+            &["org.opencontainers.image.source=https://example.com/app".to_string()],
+            "2024-01-01T00:00:00Z",
+        );
+
+        assert!(labels[0].starts_with("org.opencontainers.image.revision="));
+        assert_eq!(
+            labels[1],
+            "org.opencontainers.image.created=2024-01-01T00:00:00Z"
+        );
+        assert_eq!(labels.len(), 2);
+    }
 }
@@ -0,0 +1,114 @@
+use super::engine::ContainerEngine;
+use crate::nixpacks::{cache_key::LOCKFILES, snapshot::Changes};
+use anyhow::{Context, Result};
+use std::{
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+/// Options of `nixpacks watch`.
+#[derive(Clone, Debug, Default)]
+pub struct WatchOptions {
+    /// Run the image after each build, replacing the container of the build before.
+    pub run: bool,
+    /// Ports to publish from the container, in the engine's `-p` format, e.g. `3000:3000`.
+    pub ports: Vec<String>,
+}
+
+/// Whether the changes can change the build plan, so it has to be generated again before rebuilding.
+///
+/// Providers read the files at the root of the app, like manifests, version files, and the Procfile, so changes there
+/// and to lockfiles and the config file do. Changes to other files, like the app's sources, are rebuilt with the same
+/// plan.
+pub fn changes_plan(changes: &Changes, config_file: Option<&str>) -> bool {
+    changes
+        .changed
+        .iter()
+        .chain(&changes.removed)
+        .any(|file| is_plan_input(file, config_file))
+}
+
+fn is_plan_input(file: &Path, config_file: Option<&str>) -> bool {
+    if file.components().count() == 1
+        || config_file.map_or(false, |config| file == Path::new(config))
+    {
+        return true;
+    }
+
+    file.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| LOCKFILES.contains(&name))
+}
+
+/// The container running the app's latest image while it's watched.
+pub struct AppContainer {
+    engine: &'static dyn ContainerEngine,
+    name: String,
+    process: Option<Child>,
+}
+
+impl AppContainer {
+    pub fn new(engine: &'static dyn ContainerEngine, name: String) -> Self {
+        Self {
+            engine,
+            name,
+            process: None,
+        }
+    }
+
+    /// Run `image`, stopping the container of the image before. Its output is shown along with the builds'.
+    pub fn restart(&mut self, image: &str, ports: &[String]) -> Result<()> {
+        self.stop()?;
+
+        let process = Command::new(self.engine.run_cli())
+            .args(self.engine.run_args(image, &self.name, ports))
+            .stdin(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Running {image}"))?;
+        self.process = Some(process);
+        Ok(())
+    }
+
+    /// Stop and remove the container, if it's running.
+    pub fn stop(&mut self) -> Result<()> {
+        if let Some(mut process) = self.process.take() {
+            Command::new(self.engine.run_cli())
+                .args(["rm", "-f", &self.name])
+                .output()
+                .with_context(|| format!("Stopping {}", self.name))?;
+            process.wait()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AppContainer {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn changes(files: &[&str]) -> Changes {
+        Changes {
+            changed: files.iter().map(PathBuf::from).collect(),
+            removed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_changes_plan() {
+        assert!(changes_plan(&changes(&["package.json"]), None));
+        assert!(changes_plan(&changes(&["api/yarn.lock"]), None));
+        assert!(changes_plan(
+            &changes(&["config/nixpacks.toml"]),
+            Some("config/nixpacks.toml")
+        ));
+        assert!(!changes_plan(&changes(&["src/index.js"]), None));
+        assert!(!changes_plan(&Changes::default(), None));
+    }
+}
@@ -1,9 +1,32 @@
 use super::{environment::Environment, plan::BuildPlan};
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Serialize, Serializer};
+use std::time::Duration;
 
 pub mod docker;
 
+/// What a build produced, e.g. for CI to deploy the image by its digest. The details the engine doesn't report, like
+/// the digest of an image that wasn't pushed, are `None`, and nothing is built with `--out` or `--print-dockerfile`.
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BuildResult {
+    pub image_id: Option<String>,
+    /// The registry digest of the pushed image, e.g. `sha256:…`.
+    pub digest: Option<String>,
+    /// The names the image can be run or pulled with.
+    pub tags: Vec<String>,
+    pub size_bytes: Option<u64>,
+    /// Wall time of the build, in seconds in JSON.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub duration: Duration,
+    pub plan: BuildPlan,
+}
+
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
 /// Types that impl this trait can produce Docker images.
 #[async_trait]
 pub trait ImageBuilder {
@@ -12,5 +35,24 @@ pub trait ImageBuilder {
         app_source: &str,
         plan: &BuildPlan,
         env: &Environment,
-    ) -> Result<()>;
+    ) -> Result<BuildResult>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_result_json() {
+        let result = BuildResult {
+            tags: vec!["app".to_string()],
+            duration: Duration::from_millis(1500),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+
+        assert_eq!(json["duration"], 1.5);
+        assert_eq!(json["tags"], serde_json::json!(["app"]));
+        assert!(json.get("digest").is_none());
+    }
 }
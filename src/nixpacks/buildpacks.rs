@@ -0,0 +1,229 @@
+use super::{
+    app::App,
+    environment::EnvironmentVariables,
+    plan::{phase::StartPhase, BuildPlan},
+};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Heroku's app descriptor.
+pub const APP_JSON: &str = "app.json";
+
+/// The project descriptor of Cloud Native Buildpacks.
+pub const PROJECT_TOML: &str = "project.toml";
+
+/// The variable Google Cloud's buildpacks read the start command from.
+const ENTRYPOINT_VARIABLE: &str = "GOOGLE_ENTRYPOINT";
+
+/// Buildpack names, without their registry or `heroku-buildpack-` prefix, and the providers that replace them.
+const BUILDPACK_PROVIDERS: &[(&[&str], &str)] = &[
+    (&["nodejs", "node", "nodejs-engine", "npm", "yarn"], "node"),
+    (&["python", "pip"], "python"),
+    (&["ruby"], "ruby"),
+    (&["go", "golang"], "go"),
+    (
+        &["java", "gradle", "maven", "java-maven", "java-gradle"],
+        "java",
+    ),
+    (&["php"], "php"),
+    (&["scala"], "scala"),
+    (&["clojure"], "clojure"),
+    (&["rust"], "rust"),
+    (&["elixir"], "elixir"),
+    (&["dotnet", "dotnet-core", "dotnet-core-sdk"], "csharp"),
+    (&["static", "nginx", "httpd"], "staticfile"),
+];
+
+/// A plan seeded from Heroku's `app.json` and the `project.toml` of Cloud Native Buildpacks, so apps set up for them
+/// build the same way: their variables are set, the providers replacing their buildpacks are used in the same order,
+/// and `GOOGLE_ENTRYPOINT` is the start command. `project.toml` takes precedence over `app.json`.
+pub fn get_buildpacks_plan(app: &App) -> Result<BuildPlan> {
+    let mut buildpacks = Vec::new();
+    let mut variables = EnvironmentVariables::new();
+
+    // Other tools use `app.json` too, like Expo, so fields that aren't in Heroku's format are ignored
+    if app.includes_file(APP_JSON) {
+        let heroku_app: Value = app.read_json(APP_JSON)?;
+        if let Some(heroku_buildpacks) = heroku_app.get("buildpacks").and_then(Value::as_array) {
+            buildpacks.extend(heroku_buildpacks.iter().filter_map(|buildpack| {
+                buildpack
+                    .get("url")
+                    .and_then(Value::as_str)
+                    .map(ToString::to_string)
+            }));
+        }
+        if let Some(env) = heroku_app.get("env").and_then(Value::as_object) {
+            variables.extend(env.iter().filter_map(|(name, value)| {
+                get_heroku_value(value).map(|value| (name.clone(), value))
+            }));
+        }
+    }
+
+    if app.includes_file(PROJECT_TOML) {
+        let project: toml::Value = toml::from_str(&app.read_file(PROJECT_TOML)?)
+            .with_context(|| format!("Error reading {PROJECT_TOML}"))?;
+
+        let project_buildpacks = get_project_buildpacks(&project);
+        if !project_buildpacks.is_empty() {
+            buildpacks = project_buildpacks;
+        }
+        variables.extend(get_project_env(&project));
+    }
+
+    let mut providers: Vec<String> = Vec::new();
+    for provider in buildpacks
+        .iter()
+        .filter_map(|id| get_buildpack_provider(id))
+    {
+        if !providers.iter().any(|name| name == provider) {
+            providers.push(provider.to_string());
+        }
+    }
+
+    Ok(BuildPlan {
+        providers: (!providers.is_empty()).then(|| providers),
+        start_phase: variables
+            .get(ENTRYPOINT_VARIABLE)
+            .map(|cmd| StartPhase::new(cmd.clone())),
+        variables: (!variables.is_empty()).then(|| variables),
+        ..Default::default()
+    })
+}
+
+/// The value of a variable in `app.json`, which is a string or an object with a `value`. Variables without a value,
+/// like required ones or generated secrets, are set when deploying instead.
+fn get_heroku_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Object(options) => options
+            .get("value")
+            .and_then(Value::as_str)
+            .map(ToString::to_string),
+        _ => None,
+    }
+}
+
+/// The tables of an array at a dotted path of `project.toml`, e.g. `io.buildpacks.group`.
+fn get_tables<'a>(project: &'a toml::Value, path: &str) -> Vec<&'a toml::Value> {
+    path.split('.')
+        .try_fold(project, |value, key| value.get(key))
+        .and_then(toml::Value::as_array)
+        .map(|values| values.iter().collect())
+        .unwrap_or_default()
+}
+
+/// The buildpacks of `project.toml`, in the format of schema 0.2, or of 0.1 under `build`.
+fn get_project_buildpacks(project: &toml::Value) -> Vec<String> {
+    ["io.buildpacks.group", "build.buildpacks"]
+        .iter()
+        .flat_map(|path| get_tables(project, path))
+        .filter_map(|buildpack| {
+            buildpack
+                .get("id")
+                .or_else(|| buildpack.get("uri"))
+                .and_then(toml::Value::as_str)
+                .map(ToString::to_string)
+        })
+        .collect()
+}
+
+/// The build variables of `project.toml`, in the format of schema 0.2, or of 0.1 under `build`.
+fn get_project_env(project: &toml::Value) -> EnvironmentVariables {
+    ["build.env", "io.buildpacks.build.env"]
+        .iter()
+        .flat_map(|path| get_tables(project, path))
+        .filter_map(|env| {
+            let name = env.get("name")?.as_str()?;
+            let value = env.get("value")?.as_str()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The provider replacing a buildpack, from its id or URL, e.g. `heroku/nodejs`,
+/// `https://github.com/heroku/heroku-buildpack-python`, or `docker://paketobuildpacks/go:1.0`.
+fn get_buildpack_provider(id: &str) -> Option<&'static str> {
+    let name = id
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()?
+        .split(['@', '#', ':'])
+        .next()?
+        .trim_start_matches("heroku-buildpack-")
+        .trim_end_matches(".git");
+
+    BUILDPACK_PROVIDERS
+        .iter()
+        .find(|(names, _)| names.contains(&name))
+        .map(|(_, provider)| *provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buildpack_provider() {
+        assert_eq!(get_buildpack_provider("heroku/nodejs"), Some("node"));
+        assert_eq!(
+            get_buildpack_provider("https://github.com/heroku/heroku-buildpack-python.git"),
+            Some("python")
+        );
+        assert_eq!(
+            get_buildpack_provider("docker://paketobuildpacks/go:1.0"),
+            Some("go")
+        );
+        assert_eq!(get_buildpack_provider("heroku/procfile"), None);
+    }
+
+    #[test]
+    fn test_project_toml() -> Result<()> {
+        let project: toml::Value = toml::from_str(
+            r#"
+            [_]
+            schema-version = "0.2"
+
+            [[io.buildpacks.group]]
+            uri = "heroku/python"
+
+            [[io.buildpacks.group]]
+            id = "heroku/nodejs"
+
+            [[io.buildpacks.build.env]]
+            name = "GOOGLE_ENTRYPOINT"
+            value = "gunicorn app:app"
+            "#,
+        )?;
+
+        assert_eq!(
+            get_project_buildpacks(&project),
+            vec!["heroku/python".to_string(), "heroku/nodejs".to_string()]
+        );
+        assert_eq!(
+            get_project_env(&project),
+            EnvironmentVariables::from([(
+                "GOOGLE_ENTRYPOINT".to_string(),
+                "gunicorn app:app".to_string()
+            )])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buildpacks_plan() -> Result<()> {
+        let plan = get_buildpacks_plan(&App::new("./examples/heroku-app-json")?)?;
+
+        assert_eq!(plan.providers, Some(vec!["node".to_string()]));
+        assert_eq!(
+            plan.variables,
+            Some(EnvironmentVariables::from([
+                ("GREETING".to_string(), "Hello from app.json".to_string()),
+                ("NODE_ENV".to_string(), "production".to_string()),
+            ]))
+        );
+        assert_eq!(plan.start_phase, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use super::app::App;
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+
+/// Lockfiles of the package managers the providers install dependencies with.
+pub const LOCKFILES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "deno.lock",
+    "Cargo.lock",
+    "go.sum",
+    "requirements.txt",
+    "poetry.lock",
+    "Pipfile.lock",
+    "pdm.lock",
+    "uv.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "mix.lock",
+    "pubspec.lock",
+    "gradle.lockfile",
+    "packages.lock.json",
+    "Package.resolved",
+    "flake.lock",
+];
+
+/// A cache key from the contents of the lockfiles at the root of the app, so builds of the same dependencies share
+/// a cache wherever the app is checked out. `None` when the app has no lockfiles.
+pub fn get_lockfiles_cache_key(app: &App) -> Result<Option<String>> {
+    let lockfiles = LOCKFILES
+        .iter()
+        .filter(|file| app.includes_file(file))
+        .collect::<Vec<_>>();
+    if lockfiles.is_empty() {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha1::new();
+    for file in lockfiles {
+        // Some lockfiles, like bun.lockb, aren't text
+        let contents = app.read_file_bytes(file)?;
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+        hasher.update(&contents);
+        hasher.update([0]);
+    }
+
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockfiles_cache_key() -> Result<()> {
+        let key = get_lockfiles_cache_key(&App::new("./examples/node-npm")?)?;
+        assert!(key.is_some());
+        assert_eq!(
+            key,
+            get_lockfiles_cache_key(&App::new("./examples/node-npm")?)?
+        );
+        assert_ne!(
+            key,
+            get_lockfiles_cache_key(&App::new("./examples/node-yarn-berry")?)?
+        );
+
+        assert_eq!(
+            get_lockfiles_cache_key(&App::new("./examples/shell-hello")?)?,
+            None
+        );
+
+        Ok(())
+    }
+}
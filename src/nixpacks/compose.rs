@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The field of a compose service with the directory of the app Nixpacks builds its image from.
+pub const APP_FIELD: &str = "x-nixpacks-app";
+
+/// A service in the `[services]` table of `nixpacks.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ServiceConfig {
+    /// The directory of the app to build, relative to the repo.
+    pub app: Option<String>,
+    /// An image to run instead of building an app, e.g. `postgres:15`.
+    pub image: Option<String>,
+    /// Ports to publish, as container ports like `3000` or `host:container` mappings. Defaults to the ports of the
+    /// app's plan.
+    pub ports: Option<Vec<String>>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct ServicesFile {
+    #[serde(default)]
+    services: BTreeMap<String, ServiceConfig>,
+}
+
+/// Read the `[services]` table of a `nixpacks.toml`, checking each service has an app or an image.
+pub fn parse_services_config(contents: &str) -> Result<BTreeMap<String, ServiceConfig>> {
+    let file: ServicesFile = toml::from_str(contents).context("Reading the services table")?;
+    for (name, service) in &file.services {
+        if service.app.is_some() == service.image.is_some() {
+            bail!("Service {name} needs either an app to build or an image to run");
+        }
+    }
+    Ok(file.services)
+}
+
+/// A service of the generated compose file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ComposeService {
+    pub name: String,
+    /// The directory of the app Nixpacks builds the image from, relative to the repo. `None` for services that run
+    /// an image as it is.
+    pub app: Option<String>,
+    pub image: String,
+    /// Container ports, which are published on the same port of the host, or the next free one, or `host:container`
+    /// mappings.
+    pub ports: Vec<String>,
+    pub variables: BTreeMap<String, String>,
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ComposeFile {
+    services: BTreeMap<String, ComposeFileService>,
+}
+
+#[derive(Serialize)]
+struct ComposeFileService {
+    image: String,
+    #[serde(rename = "x-nixpacks-app", skip_serializing_if = "Option::is_none")]
+    app: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+}
+
+/// Write a `docker-compose.yml` running the services. Services built by Nixpacks are marked with [`APP_FIELD`], for
+/// `nixpacks compose up` to build them first.
+///
+/// Container ports are published on the same host port unless an earlier service already publishes it, in which case
+/// the next free one is used. Apps are told their port with `PORT`, unless their variables set it.
+pub fn to_compose_yaml(services: &[ComposeService]) -> Result<String> {
+    let names = services
+        .iter()
+        .map(|service| service.name.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut used_ports = BTreeSet::new();
+    // Explicit mappings keep their host ports
+    for service in services {
+        for port in &service.ports {
+            if let Some((host, _)) = port.split_once(':') {
+                used_ports.insert(host.parse::<u16>().unwrap_or_default());
+            }
+        }
+    }
+
+    let mut file = ComposeFile {
+        services: BTreeMap::new(),
+    };
+    for service in services {
+        for dependency in &service.depends_on {
+            if !names.contains(dependency.as_str()) {
+                bail!(
+                    "Service {} depends on {dependency}, which isn't a service",
+                    service.name
+                );
+            }
+        }
+
+        let mut environment = service.variables.clone();
+        let mut ports = Vec::new();
+        for port in &service.ports {
+            if port.contains(':') {
+                ports.push(port.clone());
+                continue;
+            }
+
+            let (number, protocol) = port.split_once('/').unwrap_or((port, ""));
+            let container = number
+                .parse::<u16>()
+                .with_context(|| format!("Invalid port {port} of service {}", service.name))?;
+            let mut host = container;
+            while !used_ports.insert(host) {
+                host = host.checked_add(1).context("No free host port")?;
+            }
+            ports.push(if protocol.is_empty() {
+                format!("{host}:{container}")
+            } else {
+                format!("{host}:{container}/{protocol}")
+            });
+
+            if service.app.is_some() {
+                environment
+                    .entry("PORT".to_string())
+                    .or_insert_with(|| container.to_string());
+            }
+        }
+
+        file.services.insert(
+            service.name.clone(),
+            ComposeFileService {
+                image: service.image.clone(),
+                app: service.app.clone(),
+                ports,
+                environment,
+                depends_on: service.depends_on.clone(),
+            },
+        );
+    }
+
+    Ok(format!(
+        "# Generated by nixpacks compose. Build the images of the services with x-nixpacks-app and start everything\n\
+         # with `nixpacks compose up`.\n{}",
+        serde_yaml::to_string(&file)?
+    ))
+}
+
+/// The services of a compose file that Nixpacks builds, as their image and the directory of their app.
+pub fn get_built_services(compose: &str) -> Result<Vec<(String, String)>> {
+    let file: Value = serde_yaml::from_str(compose).context("Reading the compose file")?;
+    let services = match file.get("services").and_then(Value::as_mapping) {
+        Some(services) => services,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut built = Vec::new();
+    for (name, service) in services {
+        let app = match service.get(APP_FIELD).and_then(Value::as_str) {
+            Some(app) => app,
+            None => continue,
+        };
+        let image = match service.get("image").and_then(Value::as_str) {
+            Some(image) => image,
+            None => bail!(
+                "Service {} has {APP_FIELD} but no image to build",
+                name.as_str().unwrap_or_default()
+            ),
+        };
+        built.push((image.to_string(), app.to_string()));
+    }
+    Ok(built)
+}
+
+/// A name usable for compose services and images, with lowercase letters, digits, and dashes.
+pub fn service_name(name: &str) -> String {
+    let name = name
+        .to_lowercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+    match name.trim_matches('-') {
+        "" => "app".to_string(),
+        name => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, ports: &[&str]) -> ComposeService {
+        ComposeService {
+            name: name.to_string(),
+            app: Some(format!("apps/{name}")),
+            image: format!("repo-{name}"),
+            ports: ports.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_services_config() -> Result<()> {
+        let services = parse_services_config(
+            r#"
+            [phases.setup]
+            nixPkgs = ["..."]
+
+            [services.api]
+            app = "apps/api"
+            variables = { DATABASE_URL = "postgres://postgres@db/postgres" }
+            dependsOn = ["db"]
+
+            [services.db]
+            image = "postgres:15"
+            ports = ["5432"]
+            "#,
+        )?;
+
+        assert_eq!(services["api"].app, Some("apps/api".to_string()));
+        assert_eq!(services["api"].depends_on, vec!["db"]);
+        assert_eq!(services["db"].ports, Some(vec!["5432".to_string()]));
+        assert!(parse_services_config("[services.web]\nports = [\"80\"]").is_err());
+        assert!(parse_services_config("").unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose_yaml() -> Result<()> {
+        let mut api = service("api", &["3000"]);
+        api.depends_on = vec!["db".to_string()];
+        let db = ComposeService {
+            name: "db".to_string(),
+            image: "postgres:15".to_string(),
+            ports: vec!["5432".to_string()],
+            ..Default::default()
+        };
+        let yaml = to_compose_yaml(&[api, service("web", &["3000"]), db])?;
+
+        assert_eq!(
+            get_built_services(&yaml)?,
+            vec![
+                ("repo-api".to_string(), "apps/api".to_string()),
+                ("repo-web".to_string(), "apps/web".to_string())
+            ]
+        );
+        let file: Value = serde_yaml::from_str(&yaml)?;
+        let services = &file["services"];
+        assert_eq!(services["api"]["ports"][0], "3000:3000");
+        assert_eq!(services["api"]["environment"]["PORT"], "3000");
+        assert_eq!(services["api"]["depends_on"][0], "db");
+        // The host port of the second app on the same port is the next free one
+        assert_eq!(services["web"]["ports"][0], "3001:3000");
+        assert_eq!(services["web"]["environment"]["PORT"], "3000");
+        assert_eq!(services["db"]["ports"][0], "5432:5432");
+        assert!(services["db"].get("environment").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose_yaml_unknown_dependency() {
+        let mut api = service("api", &[]);
+        api.depends_on = vec!["db".to_string()];
+        assert!(to_compose_yaml(&[api]).is_err());
+    }
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name("My_App"), "my-app");
+        assert_eq!(service_name("@acme/web"), "acme-web");
+        assert_eq!(service_name("."), "app");
+    }
+}
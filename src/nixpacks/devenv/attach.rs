@@ -0,0 +1,96 @@
+use super::{
+    ssh::RemoteHost,
+    terminal::{terminal_size, TerminalGuard},
+};
+use crate::nixpacks::utils::shell_quote;
+use anyhow::{Context, Result};
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// Write all of `data` to a non-blocking writer, waiting whenever it isn't ready.
+fn write_all_nonblocking<W: Write>(writer: &mut W, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Open an interactive login shell in `workdir` over an existing connection, returning once it exits.
+pub fn attach(remote: &RemoteHost, workdir: &str) -> Result<()> {
+    let session = remote.session();
+    let mut channel = session.channel_session()?;
+
+    let term = env::var("TERM").unwrap_or_else(|_| "xterm".to_string());
+    let (cols, rows) = terminal_size();
+    channel
+        .request_pty(&term, None, Some((cols, rows, 0, 0)))
+        .context("Requesting a terminal on the remote host")?;
+    channel
+        .exec(&format!(
+            "cd {} && exec \"${{SHELL:-sh}}\" -l",
+            shell_quote(workdir)
+        ))
+        .context("Starting remote shell")?;
+
+    let _raw_mode = TerminalGuard::raw()?;
+
+    // Reading stdin blocks, so it happens on its own thread
+    let (input_tx, input_rx) = mpsc::channel::<Vec<u8>>();
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+        loop {
+            match io::stdin().read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if input_tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    session.set_blocking(false);
+    let mut stdout = io::stdout();
+    let mut buf = vec![0; 32 * 1024];
+
+    loop {
+        let mut progress = false;
+
+        while let Ok(input) = input_rx.try_recv() {
+            write_all_nonblocking(&mut channel, &input)?;
+            progress = true;
+        }
+
+        match channel.read(&mut buf) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(n) => {
+                stdout.write_all(&buf[..n])?;
+                stdout.flush()?;
+                progress = true;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if !progress {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    session.set_blocking(true);
+    channel.wait_close()?;
+
+    Ok(())
+}
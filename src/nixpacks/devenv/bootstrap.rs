@@ -0,0 +1,73 @@
+/// Sets up the environment for a multi-user Nix install.
+const NIX_DAEMON_PROFILE: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+
+/// Sets up the environment for a single-user Nix install.
+const NIX_USER_PROFILE: &str = "$HOME/.nix-profile/etc/profile.d/nix.sh";
+
+pub const HOME_MANAGER_CHANNEL: &str =
+    "https://github.com/nix-community/home-manager/archive/master.tar.gz";
+
+/// The installer used when Nix is missing on the remote host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NixInstaller {
+    /// https://github.com/DeterminateSystems/nix-installer
+    Determinate,
+    /// The installer from nixos.org, in multi-user mode.
+    Official,
+}
+
+impl Default for NixInstaller {
+    fn default() -> Self {
+        NixInstaller::Determinate
+    }
+}
+
+impl NixInstaller {
+    /// The shell command that installs Nix non-interactively.
+    pub fn install_cmd(self) -> &'static str {
+        match self {
+            NixInstaller::Determinate => "curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix | sh -s -- install --no-confirm",
+            NixInstaller::Official => "curl -L https://nixos.org/nix/install | sh -s -- --daemon --yes",
+        }
+    }
+}
+
+/// Run a command with Nix on the PATH.
+///
+/// SSH commands run in a non-login shell, which doesn't source the profile scripts that Nix installs.
+pub fn with_nix_env(cmd: &str) -> String {
+    format!(
+        "for f in {NIX_DAEMON_PROFILE} {NIX_USER_PROFILE}; do if [ -e \"$f\" ]; then . \"$f\"; fi; done; {cmd}"
+    )
+}
+
+/// Install Nix unless it is already available.
+pub fn install_nix_cmd(installer: NixInstaller) -> String {
+    let check = with_nix_env("command -v nix-env > /dev/null");
+    format!("if ! ({check}); then {}; fi", installer.install_cmd())
+}
+
+/// Subscribe to the home-manager channel unless it is already present.
+pub fn add_home_manager_channel_cmd() -> String {
+    with_nix_env(&format!(
+        "nix-channel --list | grep -q '^home-manager ' || (nix-channel --add {HOME_MANAGER_CHANNEL} home-manager && nix-channel --update)"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_nix_cmd() {
+        let cmd = install_nix_cmd(NixInstaller::Official);
+        assert!(cmd.starts_with("if ! (for f in"));
+        assert!(cmd
+            .ends_with("then curl -L https://nixos.org/nix/install | sh -s -- --daemon --yes; fi"));
+    }
+
+    #[test]
+    fn test_with_nix_env() {
+        assert!(with_nix_env("nix --version").ends_with("; done; nix --version"));
+    }
+}
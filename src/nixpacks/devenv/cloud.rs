@@ -0,0 +1,340 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::{
+    io::Read,
+    net::{TcpStream, ToSocketAddrs},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// SSM parameter that AWS keeps pointed at the latest Ubuntu 22.04 AMI in each region.
+const AWS_UBUNTU_AMI_PARAMETER: &str =
+    "/aws/service/canonical/ubuntu/server/22.04/stable/current/amd64/hvm/ebs-gp2/ami-id";
+
+const HETZNER_IMAGE: &str = "ubuntu-22.04";
+
+/// How long to wait for a new host to accept SSH connections.
+pub const SSH_READY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A cloud provider that hosts can be created on, using its CLI and whatever credentials it is configured with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CloudProvider {
+    /// EC2, through the `aws` CLI.
+    Aws,
+    /// Hetzner Cloud, through the `hcloud` CLI.
+    Hetzner,
+}
+
+/// Settings for the instance to create.
+#[derive(Clone, Debug, Default)]
+pub struct CreateHostOptions {
+    pub instance_type: Option<String>,
+    /// A key pair or SSH key already registered with the provider, to be authorized on the instance.
+    pub ssh_key_name: Option<String>,
+}
+
+/// A newly created instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatedHost {
+    pub name: String,
+    pub id: String,
+    pub address: String,
+}
+
+impl CloudProvider {
+    pub fn display_name(self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "AWS",
+            CloudProvider::Hetzner => "Hetzner",
+        }
+    }
+
+    /// The user the provider's Ubuntu image lets you log in as.
+    pub fn default_user(self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "ubuntu",
+            CloudProvider::Hetzner => "root",
+        }
+    }
+
+    fn default_instance_type(self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "t3.medium",
+            CloudProvider::Hetzner => "cx22",
+        }
+    }
+
+    /// The command that creates the instance, given the image to boot it from.
+    pub fn create_cmd(self, name: &str, image: &str, options: &CreateHostOptions) -> Vec<String> {
+        let instance_type = options
+            .instance_type
+            .clone()
+            .unwrap_or_else(|| self.default_instance_type().to_string());
+
+        let mut cmd = match self {
+            CloudProvider::Aws => vec![
+                "aws".to_string(),
+                "ec2".to_string(),
+                "run-instances".to_string(),
+                "--image-id".to_string(),
+                image.to_string(),
+                "--instance-type".to_string(),
+                instance_type,
+                "--tag-specifications".to_string(),
+                format!("ResourceType=instance,Tags=[{{Key=Name,Value={name}}}]"),
+                "--output".to_string(),
+                "json".to_string(),
+            ],
+            CloudProvider::Hetzner => vec![
+                "hcloud".to_string(),
+                "server".to_string(),
+                "create".to_string(),
+                "--name".to_string(),
+                name.to_string(),
+                "--type".to_string(),
+                instance_type,
+                "--image".to_string(),
+                image.to_string(),
+                "-o".to_string(),
+                "json".to_string(),
+            ],
+        };
+
+        if let Some(key) = &options.ssh_key_name {
+            cmd.push(match self {
+                CloudProvider::Aws => "--key-name".to_string(),
+                CloudProvider::Hetzner => "--ssh-key".to_string(),
+            });
+            cmd.push(key.clone());
+        }
+
+        cmd
+    }
+
+    /// The image new instances boot from. For AWS this is looked up, so it is only a description until then.
+    pub fn image(self, resolve: bool) -> Result<String> {
+        match self {
+            CloudProvider::Aws if resolve => run_cli(&[
+                "aws",
+                "ssm",
+                "get-parameters",
+                "--names",
+                AWS_UBUNTU_AMI_PARAMETER,
+                "--query",
+                "Parameters[0].Value",
+                "--output",
+                "text",
+            ]),
+            CloudProvider::Aws => Ok("<latest Ubuntu 22.04 AMI>".to_string()),
+            CloudProvider::Hetzner => Ok(HETZNER_IMAGE.to_string()),
+        }
+    }
+
+    /// The error context of failures after an instance was created, so that it isn't left running unnoticed.
+    pub fn created_context(self, name: &str, id: Option<&str>) -> String {
+        let delete_cmd = match (self, id) {
+            (CloudProvider::Aws, Some(id)) => format!("aws ec2 terminate-instances --instance-ids {id}"),
+            (CloudProvider::Aws, None) => format!(
+                "aws ec2 terminate-instances --instance-ids $(aws ec2 describe-instances --filters Name=tag:Name,Values={name} --query 'Reservations[].Instances[].InstanceId' --output text)"
+            ),
+            (CloudProvider::Hetzner, _) => format!("hcloud server delete {name}"),
+        };
+        let instance = match id {
+            Some(id) => format!("{name} ({id})"),
+            None => name.to_string(),
+        };
+
+        format!(
+            "{instance} was created on {} and is still running. Delete it with `{delete_cmd}` if it isn't needed",
+            self.display_name()
+        )
+    }
+
+    /// Create an instance and wait until it has a public address.
+    ///
+    /// Errors after the instance was created name it, along with the command that deletes it.
+    pub fn create_host(self, name: &str, options: &CreateHostOptions) -> Result<CreatedHost> {
+        let image = self.image(true)?;
+        let cmd = self.create_cmd(name, &image, options);
+        let output = run_cli(&cmd.iter().map(String::as_str).collect::<Vec<_>>())?;
+        let output: Value = serde_json::from_str(&output)
+            .with_context(|| format!("Parsing output of `{}`", cmd.join(" ")))
+            .with_context(|| self.created_context(name, None))?;
+
+        match self {
+            CloudProvider::Aws => {
+                let id = parse_aws_instance_id(&output)
+                    .with_context(|| self.created_context(name, None))?;
+                wait_for_aws_address(&id)
+                    .with_context(|| self.created_context(name, Some(&id)))
+                    .map(|address| CreatedHost {
+                        name: name.to_string(),
+                        id,
+                        address,
+                    })
+            }
+            CloudProvider::Hetzner => {
+                let (id, address) = parse_hetzner_server(&output)
+                    .with_context(|| self.created_context(name, None))?;
+                Ok(CreatedHost {
+                    name: name.to_string(),
+                    id,
+                    address,
+                })
+            }
+        }
+    }
+}
+
+/// Wait for an EC2 instance to be running, returning its public address.
+fn wait_for_aws_address(id: &str) -> Result<String> {
+    run_cli(&[
+        "aws",
+        "ec2",
+        "wait",
+        "instance-running",
+        "--instance-ids",
+        id,
+    ])?;
+    let address = run_cli(&[
+        "aws",
+        "ec2",
+        "describe-instances",
+        "--instance-ids",
+        id,
+        "--query",
+        "Reservations[0].Instances[0].PublicIpAddress",
+        "--output",
+        "text",
+    ])?;
+    if address.is_empty() || address == "None" {
+        bail!("Instance {id} has no public IP address. Is it in a public subnet?");
+    }
+
+    Ok(address)
+}
+
+/// Run a provider CLI, returning its trimmed stdout.
+fn run_cli(args: &[&str]) -> Result<String> {
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Please install the `{}` CLI to create hosts", args[0]))?;
+
+    if !output.status.success() {
+        bail!(
+            "`{}` failed\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn parse_aws_instance_id(output: &Value) -> Result<String> {
+    output["Instances"][0]["InstanceId"]
+        .as_str()
+        .map(ToString::to_string)
+        .context("No instance ID in the output of `aws ec2 run-instances`")
+}
+
+fn parse_hetzner_server(output: &Value) -> Result<(String, String)> {
+    let server = &output["server"];
+    let id = server["id"]
+        .as_u64()
+        .context("No server ID in the output of `hcloud server create`")?;
+    let address = server["public_net"]["ipv4"]["ip"]
+        .as_str()
+        .context("No public IPv4 address in the output of `hcloud server create`")?;
+
+    Ok((id.to_string(), address.to_string()))
+}
+
+/// Wait until the host accepts connections and sends an SSH banner.
+pub fn wait_for_ssh(address: &str, port: u16, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    while !is_ssh_ready(address, port) {
+        if Instant::now() >= deadline {
+            bail!("Timed out waiting for SSH on {address}:{port}");
+        }
+        thread::sleep(Duration::from_secs(5));
+    }
+
+    Ok(())
+}
+
+fn is_ssh_ready(address: &str, port: u16) -> bool {
+    let addr = match (address, port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut a| a.next())
+    {
+        Some(addr) => addr,
+        None => return false,
+    };
+
+    let mut banner = [0; 4];
+    TcpStream::connect_timeout(&addr, Duration::from_secs(5))
+        .and_then(|mut stream| {
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            stream.read_exact(&mut banner)
+        })
+        .map_or(false, |()| &banner == b"SSH-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_cmd() {
+        let options = CreateHostOptions {
+            instance_type: None,
+            ssh_key_name: Some("laptop".to_string()),
+        };
+        assert_eq!(
+            CloudProvider::Hetzner
+                .create_cmd("nixpacks-node", HETZNER_IMAGE, &options)
+                .join(" "),
+            "hcloud server create --name nixpacks-node --type cx22 --image ubuntu-22.04 -o json --ssh-key laptop"
+        );
+        assert_eq!(
+            CloudProvider::Aws
+                .create_cmd("nixpacks-node", "ami-123", &options)
+                .join(" "),
+            "aws ec2 run-instances --image-id ami-123 --instance-type t3.medium --tag-specifications ResourceType=instance,Tags=[{Key=Name,Value=nixpacks-node}] --output json --key-name laptop"
+        );
+    }
+
+    #[test]
+    fn test_parse_provider_output() {
+        let aws: Value =
+            serde_json::from_str(r#"{"Instances": [{"InstanceId": "i-0abc"}]}"#).unwrap();
+        assert_eq!(parse_aws_instance_id(&aws).unwrap(), "i-0abc");
+
+        let hetzner: Value = serde_json::from_str(
+            r#"{"server": {"id": 42, "public_net": {"ipv4": {"ip": "203.0.113.7"}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_hetzner_server(&hetzner).unwrap(),
+            ("42".to_string(), "203.0.113.7".to_string())
+        );
+
+        assert!(parse_aws_instance_id(&hetzner).is_err());
+    }
+
+    #[test]
+    fn test_created_context() {
+        assert_eq!(
+            CloudProvider::Aws.created_context("nixpacks-node", Some("i-0abc")),
+            "nixpacks-node (i-0abc) was created on AWS and is still running. Delete it with `aws ec2 terminate-instances --instance-ids i-0abc` if it isn't needed"
+        );
+        assert!(CloudProvider::Hetzner
+            .created_context("nixpacks-node", None)
+            .contains("`hcloud server delete nixpacks-node`"));
+    }
+}
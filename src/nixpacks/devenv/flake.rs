@@ -0,0 +1,97 @@
+use super::{
+    nix_syntax::{quote_string, store_name, variables_to_attrs},
+    normalize_packages, plan_nixpkgs_archive, GENERATED_HEADER,
+};
+use crate::nixpacks::plan::BuildPlan;
+use indoc::formatdoc;
+
+/// Render the packages and variables of a build plan as a `flake.nix` with a default devShell, and a default package
+/// with the same packages for `nix profile install` or `nix build`.
+pub fn to_flake_nix(plan: &BuildPlan, name: &str) -> String {
+    let archive = plan_nixpkgs_archive(plan);
+    let packages = normalize_packages(plan.get_packages())
+        .iter()
+        .map(|p| format!("              {p}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let variables = variables_to_attrs(&plan.variables.clone().unwrap_or_default(), 12);
+    let description = quote_string(&format!("Development environment for {name}"));
+    let env_name = quote_string(&format!("{}-env", store_name(name)));
+
+    formatdoc! {"
+        {GENERATED_HEADER}
+        {{
+          description = {description};
+
+          inputs.nixpkgs.url = \"github:NixOS/nixpkgs/{archive}\";
+
+          outputs = {{ self, nixpkgs }}:
+            let
+              systems = [ \"x86_64-linux\" \"aarch64-linux\" \"x86_64-darwin\" \"aarch64-darwin\" ];
+              forAllSystems = nixpkgs.lib.genAttrs systems;
+            in
+            {{
+              devShells = forAllSystems (system:
+                let pkgs = nixpkgs.legacyPackages.${{system}};
+                in {{
+                  default = pkgs.mkShell {{
+                    packages = with pkgs; [
+        {packages}
+                    ];
+
+        {variables}
+                  }};
+                }});
+
+              packages = forAllSystems (system:
+                let pkgs = nixpkgs.legacyPackages.${{system}};
+                in {{
+                  default = pkgs.buildEnv {{
+                    name = {env_name};
+                    paths = with pkgs; [
+        {packages}
+                    ];
+                  }};
+                }});
+            }};
+        }}
+    "}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{environment::EnvironmentVariables, nix::pkg::Pkg, plan::phase::Phase};
+
+    #[test]
+    fn test_to_flake_nix() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs-16_x"), Pkg::new("yarn-1_x")]));
+        setup.set_nix_archive("abc123".to_string());
+        let mut plan = BuildPlan::new(&[setup], None);
+        plan.add_variables(EnvironmentVariables::from([(
+            "NODE_ENV".to_string(),
+            "production".to_string(),
+        )]));
+
+        let flake = to_flake_nix(&plan, "my-app");
+        assert!(flake.starts_with(GENERATED_HEADER));
+        assert!(flake.contains("description = \"Development environment for my-app\";"));
+        assert!(flake.contains("inputs.nixpkgs.url = \"github:NixOS/nixpkgs/abc123\";"));
+        assert!(flake.contains("              nodejs-16_x\n              yarn\n"));
+        assert!(flake.contains("            NODE_ENV = \"production\";"));
+        assert!(flake.contains(
+            "name = \"my-app-env\";\n            paths = with pkgs; [\n              nodejs-16_x\n"
+        ));
+    }
+
+    #[test]
+    fn test_to_flake_nix_quotes_name() {
+        let plan = BuildPlan::new(&[Phase::setup(Some(vec![Pkg::new("nodejs")]))], None);
+
+        let flake = to_flake_nix(&plan, "my \"app\" ${x}");
+        assert!(
+            flake.contains("description = \"Development environment for my \\\"app\\\" \\${x}\";")
+        );
+        assert!(flake.contains("name = \"my--app----x--env\";"));
+    }
+}
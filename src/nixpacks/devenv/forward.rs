@@ -0,0 +1,223 @@
+use super::ssh::RemoteHost;
+use anyhow::{bail, Context, Result};
+use ssh2::Channel;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often to send a keepalive so idle tunnels aren't dropped by the server.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A port on localhost that is forwarded to a port on the remote host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PortForward {
+    pub local_port: u16,
+    pub remote_port: u16,
+}
+
+impl FromStr for PortForward {
+    type Err = anyhow::Error;
+
+    /// Parse `LOCAL:REMOTE`, or a single port that is used for both.
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_port = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .with_context(|| format!("Invalid port `{port}` in --forward {s}"))
+        };
+
+        let (local_port, remote_port) = match s.split_once(':') {
+            Some((local, remote)) => (parse_port(local)?, parse_port(remote)?),
+            None => (parse_port(s)?, parse_port(s)?),
+        };
+
+        Ok(PortForward {
+            local_port,
+            remote_port,
+        })
+    }
+}
+
+/// A single forwarded connection, with any bytes that couldn't be written yet.
+struct Tunnel {
+    stream: TcpStream,
+    channel: Channel,
+    to_remote: Vec<u8>,
+    to_local: Vec<u8>,
+    local_closed: bool,
+}
+
+impl Tunnel {
+    /// Move whatever data is ready in either direction. Returns whether anything was moved.
+    fn pump(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut progress = false;
+
+        if self.to_remote.is_empty() && !self.local_closed {
+            match self.stream.read(buf) {
+                Ok(0) => {
+                    self.local_closed = true;
+                    progress = true;
+                }
+                Ok(n) => {
+                    self.to_remote.extend_from_slice(&buf[..n]);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !self.to_remote.is_empty() {
+            match self.channel.write(&self.to_remote) {
+                Ok(n) => {
+                    self.to_remote.drain(..n);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if self.to_local.is_empty() {
+            match self.channel.read(buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.to_local.extend_from_slice(&buf[..n]);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !self.to_local.is_empty() {
+            match self.stream.write(&self.to_local) {
+                Ok(n) => {
+                    self.to_local.drain(..n);
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// Whether either side has hung up and everything it sent has been delivered.
+    fn is_done(&self) -> bool {
+        (self.local_closed && self.to_remote.is_empty())
+            || (self.channel.eof() && self.to_local.is_empty())
+    }
+}
+
+/// Listen on each local port and tunnel connections to the matching port on the remote host.
+///
+/// Runs until the process is interrupted.
+pub fn forward_ports(remote: &RemoteHost, forwards: &[PortForward]) -> Result<()> {
+    let mut listeners = Vec::new();
+    for forward in forwards {
+        let listener = TcpListener::bind(("127.0.0.1", forward.local_port))
+            .with_context(|| format!("Listening on localhost:{}", forward.local_port))?;
+        listener.set_nonblocking(true)?;
+        listeners.push((listener, forward.remote_port));
+    }
+
+    let session = remote.session();
+    session.set_keepalive(true, KEEPALIVE_INTERVAL.as_secs() as u32);
+    session.set_blocking(false);
+
+    let mut tunnels: Vec<Tunnel> = Vec::new();
+    let mut buf = vec![0; 32 * 1024];
+    let mut last_keepalive = Instant::now();
+
+    loop {
+        let mut progress = false;
+
+        for (listener, remote_port) in &listeners {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    // Opening a channel is a round trip, it's simpler to wait for it
+                    session.set_blocking(true);
+                    let channel = session.channel_direct_tcpip("localhost", *remote_port, None);
+                    session.set_blocking(false);
+
+                    match channel {
+                        Ok(channel) => {
+                            stream.set_nonblocking(true)?;
+                            tunnels.push(Tunnel {
+                                stream,
+                                channel,
+                                to_remote: Vec::new(),
+                                to_local: Vec::new(),
+                                local_closed: false,
+                            });
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to reach port {remote_port} on the remote host: {e}");
+                        }
+                    }
+                    progress = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => bail!("Accepting connection: {e}"),
+            }
+        }
+
+        let mut i = 0;
+        while i < tunnels.len() {
+            let done = match tunnels[i].pump(&mut buf) {
+                Ok(moved) => {
+                    progress |= moved;
+                    tunnels[i].is_done()
+                }
+                Err(_) => true,
+            };
+
+            if done {
+                let mut tunnel = tunnels.swap_remove(i);
+                let _ = tunnel.channel.close();
+            } else {
+                i += 1;
+            }
+        }
+
+        if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+            let _ = session.keepalive_send();
+            last_keepalive = Instant::now();
+        }
+
+        if !progress {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_forward() {
+        assert_eq!(
+            "8080:3000".parse::<PortForward>().unwrap(),
+            PortForward {
+                local_port: 8080,
+                remote_port: 3000
+            }
+        );
+        assert_eq!(
+            "3000".parse::<PortForward>().unwrap(),
+            PortForward {
+                local_port: 3000,
+                remote_port: 3000
+            }
+        );
+        assert!("3000:web".parse::<PortForward>().is_err());
+        assert!("70000".parse::<PortForward>().is_err());
+    }
+}
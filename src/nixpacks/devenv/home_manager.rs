@@ -0,0 +1,193 @@
+use super::{
+    nix_syntax::{attr_name, quote_string, variables_to_attrs},
+    normalize_packages,
+};
+use crate::nixpacks::environment::EnvironmentVariables;
+use indoc::formatdoc;
+
+/// A systemd user service that runs the plan's start command in the synced app directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserService {
+    pub name: String,
+    pub workdir: String,
+    pub cmd: String,
+}
+
+impl UserService {
+    /// Create a service for an app, naming the unit after it.
+    pub fn new(app_name: &str, workdir: String, cmd: String) -> UserService {
+        let app_name = app_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect::<String>();
+
+        UserService {
+            name: format!("nixpacks-{app_name}"),
+            workdir,
+            cmd,
+        }
+    }
+
+    /// Render the service as a home-manager `systemd.user.services` entry, indented for the top level of `home.nix`.
+    fn to_nix(&self, variables: &EnvironmentVariables) -> String {
+        // systemd expands `%` specifiers and `$` variables itself, leave both to the shell
+        let cmd = self.cmd.replace('%', "%%").replace('$', "$$");
+        let mut environment = vec![
+            "\"PATH=${config.home.profileDirectory}/bin:/run/current-system/sw/bin:/usr/local/bin:/usr/bin:/bin\"".to_string(),
+        ];
+        environment.extend(variables.iter().map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            quote_string(&format!("\"{name}={value}\""))
+        }));
+        let environment = environment.join("\n      ");
+
+        let name = attr_name(&self.name);
+        let description = quote_string(&format!("Start command for {}", self.name));
+        let workdir = quote_string(&self.workdir);
+        let cmd = quote_string(&cmd);
+
+        let service = formatdoc! {"
+              systemd.user.services.{name} = {{
+                Unit.Description = {description};
+                Service = {{
+                  WorkingDirectory = {workdir};
+                  ExecStart = \"${{pkgs.bash}}/bin/bash -c \" + lib.escapeShellArg {cmd};
+                  Restart = \"on-failure\";
+                  Environment = [
+                    {environment}
+                  ];
+                }};
+                Install.WantedBy = [ \"default.target\" ];
+              }};
+
+              # Start or restart services whose configuration changed on switch.
+              systemd.user.startServices = \"sd-switch\";
+        "};
+
+        // Indented to sit at the top level of `home.nix`
+        let service = service
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("  {line}\n")
+                }
+            })
+            .collect::<String>();
+        format!("\n{service}")
+    }
+}
+
+/// Render the packages and variables of a build plan as a home-manager `home.nix` configuration for the given user.
+///
+/// If a service is given, it is added as a systemd user service that runs with the plan's variables.
+pub fn to_home_manager_nix(
+    username: &str,
+    home_directory: &str,
+    packages: Vec<String>,
+    variables: &EnvironmentVariables,
+    service: Option<&UserService>,
+) -> String {
+    let service = service
+        .map(|service| service.to_nix(variables))
+        .unwrap_or_default();
+    let packages = normalize_packages(packages)
+        .iter()
+        .map(|p| format!("    {p}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let variables = variables_to_attrs(variables, 4);
+    let username = quote_string(username);
+    let home_directory = quote_string(home_directory);
+
+    formatdoc! {"
+        {{ config, pkgs, lib, ... }}:
+
+        {{
+          # Home Manager needs a bit of information about you and the paths it should
+          # manage.
+          home.username = {username};
+          home.homeDirectory = {home_directory};
+
+          # This value determines the Home Manager release that your configuration is
+          # compatible with. This helps avoid breakage when a new Home Manager release
+          # introduces backwards incompatible changes.
+          #
+          # You should not change this value, even if you update Home Manager. If you do
+          # want to update the value, then make sure to first check the Home Manager
+          # release notes.
+          home.stateVersion = \"23.05\"; # Please read the comment before changing.
+
+          # The home.packages option allows you to install Nix packages into your
+          # environment.
+          home.packages = with pkgs; [
+        {packages}
+          ];
+
+          # Environment variables from the build plan, so login shells match the build environment.
+          home.sessionVariables = {{
+        {variables}
+          }};
+        {service}}}
+    "}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_home_manager_nix() {
+        let nix = to_home_manager_nix(
+            "dev",
+            "/Users/dev",
+            vec![
+                "nodejs-16_x".to_string(),
+                "npm-8_x".to_string(),
+                "yarn-1_x".to_string(),
+            ],
+            &EnvironmentVariables::from([("NODE_ENV".to_string(), "production".to_string())]),
+            None,
+        );
+
+        assert!(nix.contains("home.username = \"dev\";\n  home.homeDirectory = \"/Users/dev\";"));
+        assert!(nix.contains("home.packages = with pkgs; [\n    nodejs-16_x\n    yarn\n  ];"));
+        assert!(!nix.contains("npm"));
+        assert!(nix.contains("home.sessionVariables = {\n    NODE_ENV = \"production\";\n  };"));
+        assert!(!nix.contains("systemd.user.services"));
+    }
+
+    #[test]
+    fn test_user_service() {
+        let service = UserService::new(
+            "my app",
+            "/home/ubuntu/my app".to_string(),
+            "node index.js --port $PORT".to_string(),
+        );
+        assert_eq!(service.name, "nixpacks-my-app");
+
+        let nix = to_home_manager_nix(
+            "ubuntu",
+            "/home/ubuntu",
+            vec!["nodejs".to_string()],
+            &EnvironmentVariables::from([("PORT".to_string(), "3000".to_string())]),
+            Some(&service),
+        );
+
+        assert!(nix.contains("systemd.user.services.nixpacks-my-app = {"));
+        assert!(nix.contains("WorkingDirectory = \"/home/ubuntu/my app\";"));
+        assert!(nix.contains(
+            "ExecStart = \"${pkgs.bash}/bin/bash -c \" + lib.escapeShellArg \"node index.js --port $$PORT\";"
+        ));
+        assert!(nix.contains("        \"\\\"PORT=3000\\\"\""));
+        assert!(nix.contains("Install.WantedBy = [ \"default.target\" ];"));
+        assert!(nix.contains("systemd.user.startServices = \"sd-switch\";"));
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+
+/// Connection and sync settings for a host. Anything left unset falls back to the CLI options and `~/.ssh/config`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HostSettings {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub identity_file: Option<String>,
+    pub port: Option<u16>,
+    pub workdir: Option<String>,
+}
+
+impl HostSettings {
+    /// Fill in any settings that aren't set from `defaults`.
+    fn with_defaults(self, defaults: &HostSettings) -> HostSettings {
+        HostSettings {
+            hostname: self.hostname.or_else(|| defaults.hostname.clone()),
+            user: self.user.or_else(|| defaults.user.clone()),
+            identity_file: self
+                .identity_file
+                .or_else(|| defaults.identity_file.clone()),
+            port: self.port.or(defaults.port),
+            workdir: self.workdir.or_else(|| defaults.workdir.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct InventoryHost {
+    name: String,
+    #[serde(flatten)]
+    settings: HostSettings,
+}
+
+/// A TOML file describing a fleet of hosts to provision.
+///
+/// ```toml
+/// [defaults]
+/// user = "dev"
+/// identity_file = "~/.ssh/devboxes"
+///
+/// [[hosts]]
+/// name = "alice"
+/// hostname = "10.0.0.5"
+///
+/// [[hosts]]
+/// name = "bob"
+/// hostname = "10.0.0.6"
+/// port = 2222
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Inventory {
+    #[serde(default)]
+    defaults: HostSettings,
+    #[serde(default)]
+    hosts: Vec<InventoryHost>,
+}
+
+impl Inventory {
+    pub fn parse(contents: &str) -> Result<Inventory> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    pub fn from_file(path: &str) -> Result<Inventory> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Failed to read inventory {path}"))?;
+        Inventory::parse(&contents).with_context(|| format!("Failed to parse inventory {path}"))
+    }
+
+    /// Each host with its own settings, falling back to the inventory defaults.
+    pub fn hosts(&self) -> Vec<(String, HostSettings)> {
+        self.hosts
+            .iter()
+            .map(|host| {
+                (
+                    host.name.clone(),
+                    host.settings.clone().with_defaults(&self.defaults),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inventory() {
+        let inventory = Inventory::parse(
+            r#"
+            [defaults]
+            user = "dev"
+            identity_file = "~/.ssh/devboxes"
+
+            [[hosts]]
+            name = "alice"
+            hostname = "10.0.0.5"
+
+            [[hosts]]
+            name = "bob"
+            user = "root"
+            port = 2222
+            workdir = "/srv/app"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            inventory.hosts(),
+            vec![
+                (
+                    "alice".to_string(),
+                    HostSettings {
+                        hostname: Some("10.0.0.5".to_string()),
+                        user: Some("dev".to_string()),
+                        identity_file: Some("~/.ssh/devboxes".to_string()),
+                        port: None,
+                        workdir: None,
+                    }
+                ),
+                (
+                    "bob".to_string(),
+                    HostSettings {
+                        hostname: None,
+                        user: Some("root".to_string()),
+                        identity_file: Some("~/.ssh/devboxes".to_string()),
+                        port: Some(2222),
+                        workdir: Some("/srv/app".to_string()),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_host_requires_name() {
+        assert!(Inventory::parse("[[hosts]]\nhostname = \"10.0.0.5\"\n").is_err());
+    }
+}
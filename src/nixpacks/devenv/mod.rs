@@ -0,0 +1,956 @@
+use self::{
+    attach::attach,
+    bootstrap::{add_home_manager_channel_cmd, install_nix_cmd, with_nix_env, NixInstaller},
+    cloud::{wait_for_ssh, CloudProvider, CreateHostOptions, SSH_READY_TIMEOUT},
+    flake::to_flake_nix,
+    forward::{forward_ports, PortForward},
+    home_manager::{to_home_manager_nix, UserService},
+    inventory::{HostSettings, Inventory},
+    nixos::{to_nixos_configuration, to_nixos_module, NIXOS_CONFIGURATION_PATH, NIXOS_MODULE_PATH},
+    shell::{to_envrc, to_shell_nix},
+    ssh::{RemoteHost, SshAuth, SshTarget, DEFAULT_SSH_PORT},
+    ssh_config::SshConfig,
+    step::{content_hash, RemoteStep},
+    terminal::prompt_password,
+    watch::watch,
+};
+use super::{logger::Logger, nix::NIXPKGS_ARCHIVE, plan::BuildPlan, utils::shell_quote};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use futures::{stream, StreamExt};
+use std::{collections::HashSet, env, fs, path::Path, sync::Arc};
+use uuid::Uuid;
+
+pub mod attach;
+pub mod bootstrap;
+pub mod cloud;
+pub mod flake;
+pub mod forward;
+pub mod home_manager;
+pub mod inventory;
+pub mod nix_syntax;
+pub mod nixos;
+pub mod shell;
+pub mod ssh;
+pub mod ssh_config;
+pub mod step;
+pub mod terminal;
+pub mod upload;
+pub mod watch;
+
+/// Marks files written into the app directory as generated, so that Nixpacks only ever overwrites its own output.
+pub const GENERATED_HEADER: &str = "# Generated by Nixpacks";
+
+/// Where files for another account than the SSH user are staged before they're installed as root. The path is
+/// relative, so both scp and the remote shell resolve it in the SSH user's home, which other users can't write to.
+const STAGING_DIR: &str = ".nixpacks-staging";
+
+/// The kind of configuration generated for the remote host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DevenvTarget {
+    /// A per-user home-manager `home.nix`.
+    HomeManager,
+    /// A system-wide NixOS module, applied with `nixos-rebuild switch`.
+    Nixos,
+}
+
+impl Default for DevenvTarget {
+    fn default() -> Self {
+        DevenvTarget::HomeManager
+    }
+}
+
+/// What `nixpacks devenv` produces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DevenvFormat {
+    /// Nix configuration applied to remote hosts, as chosen by [`DevenvTarget`].
+    Config,
+    /// A `flake.nix` with a devShell, written into the app directory. Any hosts given are still provisioned.
+    Flake,
+}
+
+impl Default for DevenvFormat {
+    fn default() -> Self {
+        DevenvFormat::Config
+    }
+}
+
+/// Holds options for provisioning a development environment on a remote host.
+#[derive(Clone, Default, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DevenvOptions {
+    pub hostnames: Vec<String>,
+    pub hosts_file: Option<String>,
+    pub inventory: Option<String>,
+    pub parallelism: usize,
+    pub ssh_user: Option<String>,
+    pub identity_file: Option<String>,
+    pub port: Option<u16>,
+    pub target: DevenvTarget,
+    pub workdir: Option<String>,
+    pub dry_run: bool,
+    pub nix_installer: NixInstaller,
+    pub format: DevenvFormat,
+    pub forwards: Vec<PortForward>,
+    pub force: bool,
+    pub local: bool,
+    pub attach: bool,
+    pub remote_user: Option<String>,
+    pub remote_home: Option<String>,
+    pub password_prompt: bool,
+    pub create_host: Option<CloudProvider>,
+    pub create_host_options: CreateHostOptions,
+    pub watch: bool,
+}
+
+/// What provisioning did to a host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HostStatus {
+    /// The generated configuration was uploaded and applied.
+    Updated,
+    /// The host already had the generated configuration, so it was left alone.
+    Unchanged,
+}
+
+/// Installs the packages from a build plan on a remote host.
+pub struct DevenvProvisioner {
+    logger: Logger,
+    options: DevenvOptions,
+}
+
+impl DevenvProvisioner {
+    pub fn new(logger: Logger, options: DevenvOptions) -> DevenvProvisioner {
+        DevenvProvisioner { logger, options }
+    }
+
+    /// Sync the app to each host, then render the plan as Nix configuration, upload it, and switch to it.
+    ///
+    /// Up to `parallelism` hosts are provisioned at once. A failure on one host doesn't stop the others.
+    /// If any ports are forwarded or `watch` is set, this keeps running until interrupted. With `attach`, it ends when the remote shell exits.
+    pub async fn provision(&self, app_src: &str, plan: &BuildPlan) -> Result<()> {
+        let source = Path::new(app_src);
+        let has_hosts = !self.options.hostnames.is_empty()
+            || self.options.hosts_file.is_some()
+            || self.options.inventory.is_some()
+            || self.options.create_host.is_some();
+
+        if self.options.local {
+            if has_hosts {
+                bail!("--local sets up this machine and can't be combined with hosts");
+            }
+
+            self.write_generated(&source.join("shell.nix"), &to_shell_nix(plan))?;
+            return self.write_generated(&source.join(".envrc"), &to_envrc());
+        }
+
+        if self.options.format == DevenvFormat::Flake {
+            let flake = to_flake_nix(plan, &app_name(source));
+            self.write_generated(&source.join("flake.nix"), &flake)?;
+
+            if !has_hosts {
+                return Ok(());
+            }
+        }
+
+        let ssh_config = SshConfig::load()?;
+        let password = self.password()?;
+
+        let created = match self.options.create_host {
+            Some(provider) => Some(self.create_host(provider, source).await?),
+            None => None,
+        };
+
+        let mut hosts = Vec::new();
+        for (host, settings) in self.get_hosts(created.clone())? {
+            let mut target = SshTarget::resolve(&host, &settings, &self.options, &ssh_config)?;
+            if let Some(password) = &password {
+                target.auth = SshAuth::Password(password.clone());
+            }
+            hosts.push((target, settings));
+        }
+
+        if !self.options.forwards.is_empty() && hosts.len() > 1 {
+            bail!("--forward can only be used with a single host");
+        }
+        if self.options.attach {
+            if hosts.len() > 1 {
+                bail!("--attach can only be used with a single host");
+            }
+            if !self.options.forwards.is_empty() {
+                bail!("--attach can't be combined with --forward");
+            }
+        }
+        if self.options.watch {
+            if hosts.len() > 1 {
+                bail!("--watch can only be used with a single host");
+            }
+            if self.options.attach || !self.options.forwards.is_empty() {
+                bail!("--watch can't be combined with --attach or --forward");
+            }
+            // Changes are copied over SFTP as the SSH user, who can't write to another user's workdir
+            if hosts
+                .iter()
+                .any(|(target, _)| self.remote_user(target) != target.user)
+            {
+                bail!("--watch can't be combined with a --remote-user other than the SSH user");
+            }
+        }
+
+        if self.options.dry_run {
+            for (target, settings) in &hosts {
+                let workdir = self.workdir(source, target, settings);
+                let steps = self.get_steps(source, plan, target, &workdir);
+                self.print_steps(target, &steps)?;
+                for forward in &self.options.forwards {
+                    self.logger.log_step(&format!(
+                        "Would forward localhost:{} to {}:{}",
+                        forward.local_port, target.host, forward.remote_port
+                    ));
+                }
+                if self.options.attach {
+                    self.logger
+                        .log_step(&format!("Would open a shell in {workdir}"));
+                }
+                if self.options.watch {
+                    self.logger.log_step(&format!(
+                        "Would watch {} and sync changes to {workdir}",
+                        source.display()
+                    ));
+                }
+            }
+            return Ok(());
+        }
+
+        // Steps depend on the home directory, which is only known once connected to the host
+        let provisioner = Arc::new(DevenvProvisioner::new(Logger::new(), self.options.clone()));
+        let plan = Arc::new(plan.clone());
+        let prefix_output = hosts.len() > 1;
+        let results = stream::iter(hosts.into_iter().map(|(mut target, settings)| {
+            let provisioner = Arc::clone(&provisioner);
+            let plan = Arc::clone(&plan);
+            let source = source.to_path_buf();
+            async move {
+                let host = target.host.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let remote = connect_host(&target, prefix_output)?;
+                    if target.home.is_none() {
+                        let user = provisioner.remote_user(&target);
+                        target.home = Some(if user == target.user {
+                            remote.home_directory()?
+                        } else {
+                            remote.user_home_directory(&user)?
+                        });
+                    }
+
+                    let workdir = provisioner.workdir(&source, &target, &settings);
+                    let steps = provisioner.get_steps(&source, &plan, &target, &workdir);
+                    let status = provision_host(&remote, &target, &steps, prefix_output)?;
+                    Ok((status, remote, workdir))
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+                (host, result)
+            }
+        }))
+        .buffered(self.options.parallelism.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+        // Keep the connections open in case the session is used after provisioning
+        let mut sessions = Vec::new();
+        let results = results
+            .into_iter()
+            .map(|(host, result)| {
+                let status = result.map(|(status, remote, workdir)| {
+                    sessions.push((host.clone(), remote, workdir));
+                    status
+                });
+                (host, status)
+            })
+            .collect();
+        self.report(results)?;
+
+        if let Some((name, settings)) = &created {
+            self.logger.log_step(&format!(
+                "{name} is at {}",
+                settings.hostname.clone().unwrap_or_default()
+            ));
+        }
+
+        match sessions.pop() {
+            Some((host, remote, _)) if !self.options.forwards.is_empty() => {
+                self.forward(&host, remote).await
+            }
+            Some((_, remote, workdir)) if self.options.attach => {
+                self.logger
+                    .log_section(&format!("Opening a shell in {workdir}"));
+                tokio::task::spawn_blocking(move || attach(&remote, &workdir)).await?
+            }
+            Some((host, remote, workdir)) if self.options.watch => {
+                self.logger.log_section(&format!(
+                    "Watching {} for changes to sync to {host}:{workdir}",
+                    source.display()
+                ));
+                println!("Press Ctrl-C to stop");
+                let source = source.to_path_buf();
+                tokio::task::spawn_blocking(move || watch(&remote, &source, &workdir)).await?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The password to log in to every host with, from `SSH_PASSWORD` or asked for once with `--password-prompt`.
+    fn password(&self) -> Result<Option<String>> {
+        if let Ok(password) = env::var("SSH_PASSWORD") {
+            return Ok(Some(password));
+        }
+
+        if self.options.password_prompt && !self.options.dry_run {
+            return prompt_password("SSH password: ").map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Tunnel the forwarded ports to the host until the process is interrupted.
+    async fn forward(&self, host: &str, remote: RemoteHost) -> Result<()> {
+        self.logger.log_section("Forwarding ports");
+        for forward in &self.options.forwards {
+            self.logger.log_step(&format!(
+                "localhost:{} -> {host}:{}",
+                forward.local_port, forward.remote_port
+            ));
+        }
+        println!("Press Ctrl-C to stop");
+
+        let forwards = self.options.forwards.clone();
+        tokio::task::spawn_blocking(move || forward_ports(&remote, &forwards)).await?
+    }
+
+    /// Write a generated file into the app directory, refusing to replace one that Nixpacks didn't write.
+    fn write_generated(&self, path: &Path, contents: &str) -> Result<()> {
+        if self.options.dry_run {
+            self.logger
+                .log_step(&format!("Would write {}", path.display()));
+            println!("{contents}");
+            return Ok(());
+        }
+
+        if let Ok(existing) = fs::read_to_string(path) {
+            if !existing.starts_with(GENERATED_HEADER) {
+                bail!(
+                    "{} already exists and was not generated by Nixpacks",
+                    path.display()
+                );
+            }
+        }
+
+        fs::write(path, contents).with_context(|| format!("Writing {}", path.display()))?;
+        self.logger.log_step(&format!("Wrote {}", path.display()));
+
+        Ok(())
+    }
+
+    /// Summarize the outcome on each host, failing if any of them could not be provisioned.
+    fn report(&self, results: Vec<(String, Result<HostStatus>)>) -> Result<()> {
+        let total = results.len();
+        let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+
+        if total == 1 && failed == 1 {
+            if let Some((_, Err(e))) = results.into_iter().next() {
+                return Err(e);
+            }
+        } else {
+            self.logger.log_section("Summary");
+            for (host, result) in &results {
+                match result {
+                    Ok(HostStatus::Updated) => {
+                        self.logger
+                            .log_step(&format!("{host}: {}", "updated".green()));
+                    }
+                    Ok(HostStatus::Unchanged) => {
+                        self.logger
+                            .log_step(&format!("{host}: {}", "unchanged".dimmed()));
+                    }
+                    Err(e) => self
+                        .logger
+                        .log_step(&format!("{host}: {} {e:#}", "failed".red())),
+                }
+            }
+
+            if failed > 0 {
+                bail!("Failed to provision {failed} of {total} hosts");
+            }
+        }
+
+        self.logger.log_section("Successfully Provisioned!");
+
+        Ok(())
+    }
+
+    /// Create a host with the cloud provider and wait for it to accept SSH connections.
+    ///
+    /// In a dry run nothing is created, and the host's address is a placeholder.
+    async fn create_host(
+        &self,
+        provider: CloudProvider,
+        source: &Path,
+    ) -> Result<(String, HostSettings)> {
+        let name = format!(
+            "nixpacks-{}-{}",
+            app_name(source)
+                .to_lowercase()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "-"),
+            &Uuid::new_v4().simple().to_string()[..8]
+        );
+        let options = self.options.create_host_options.clone();
+        let mut settings = HostSettings {
+            user: match self.options.ssh_user {
+                Some(_) => None,
+                None => Some(provider.default_user().to_string()),
+            },
+            ..Default::default()
+        };
+
+        if self.options.dry_run {
+            let cmd = provider.create_cmd(&name, &provider.image(false)?, &options);
+            self.logger.log_step(&format!(
+                "Would create {name} on {}\n$ {}",
+                provider.display_name(),
+                cmd.join(" ")
+            ));
+            settings.hostname = Some(format!("<{name} address>"));
+            return Ok((name, settings));
+        }
+
+        self.logger
+            .log_section(&format!("Creating {name} on {}", provider.display_name()));
+        let port = self.options.port.unwrap_or(DEFAULT_SSH_PORT);
+        let created = tokio::task::spawn_blocking(move || {
+            let created = provider.create_host(&name, &options)?;
+            wait_for_ssh(&created.address, port, SSH_READY_TIMEOUT)
+                .with_context(|| provider.created_context(&created.name, Some(&created.id)))?;
+            Ok::<_, anyhow::Error>(created)
+        })
+        .await??;
+        self.logger.log_step(&format!(
+            "Created {} ({}) at {}",
+            created.name, created.id, created.address
+        ));
+
+        settings.hostname = Some(created.address);
+        Ok((created.name, settings))
+    }
+
+    /// All hosts to provision with their settings, from a created host, the inventory, the command line, and the hosts file.
+    ///
+    /// If a host is listed more than once, its first entry is used.
+    fn get_hosts(
+        &self,
+        created: Option<(String, HostSettings)>,
+    ) -> Result<Vec<(String, HostSettings)>> {
+        let mut hosts = created.into_iter().collect::<Vec<_>>();
+        hosts.extend(get_listed_hosts(&self.options)?);
+
+        let mut seen = HashSet::new();
+        hosts.retain(|(host, _)| seen.insert(host.clone()));
+
+        if hosts.is_empty() {
+            bail!(
+                "No hosts to provision. Pass a hostname, --hosts-file, --inventory, or --create-host"
+            );
+        }
+
+        Ok(hosts)
+    }
+
+    /// Print every step that would run on the host, including the contents of generated files.
+    fn print_steps(&self, target: &SshTarget, steps: &[RemoteStep]) -> Result<()> {
+        self.logger.log_section(&format!(
+            "Dry run for {}@{}:{}",
+            target.user, target.hostname, target.port
+        ));
+
+        for step in steps {
+            self.logger.log_step(&step.description());
+            println!("{}\n", step.to_dry_run_string()?);
+        }
+
+        Ok(())
+    }
+
+    /// Everything that needs to happen on the host to provision it.
+    fn get_steps(
+        &self,
+        source: &Path,
+        plan: &BuildPlan,
+        target: &SshTarget,
+        workdir: &str,
+    ) -> Vec<RemoteStep> {
+        let user = self.remote_user(target);
+        let mut steps = vec![RemoteStep::SyncApp {
+            source: source.to_path_buf(),
+            workdir: workdir.to_string(),
+            user: if user == target.user {
+                None
+            } else {
+                Some(user)
+            },
+        }];
+
+        steps.extend(match self.options.target {
+            DevenvTarget::HomeManager => self.home_manager_steps(source, plan, target, workdir),
+            DevenvTarget::Nixos => self.nixos_steps(plan, target),
+        });
+
+        steps
+    }
+
+    /// The directory on the remote host the app is synced to. Defaults to a directory named after the app in the user's home.
+    fn workdir(&self, source: &Path, target: &SshTarget, settings: &HostSettings) -> String {
+        if let Some(workdir) = settings.workdir.as_ref().or(self.options.workdir.as_ref()) {
+            target.expand_home(workdir)
+        } else {
+            format!("{}/{}", target.home_directory(), app_name(source))
+        }
+    }
+
+    /// The account the environment is set up for. Defaults to the SSH user.
+    fn remote_user(&self, target: &SshTarget) -> String {
+        self.options
+            .remote_user
+            .clone()
+            .unwrap_or_else(|| target.user.clone())
+    }
+
+    /// Write a file owned by the remote user. Files for another account than the SSH user are staged in a private
+    /// directory of the SSH user, installed as root, and then removed.
+    fn upload_as_user(
+        &self,
+        target: &SshTarget,
+        path: String,
+        contents: String,
+    ) -> Vec<RemoteStep> {
+        let user = self.remote_user(target);
+        if user == target.user {
+            return vec![RemoteStep::upload(path, contents)];
+        }
+
+        let file_name = Path::new(&path).file_name().unwrap().to_string_lossy();
+        let staged_path = format!("{STAGING_DIR}/{user}-{file_name}");
+        vec![
+            RemoteStep::exec(
+                "Creating staging directory",
+                format!("mkdir -p {STAGING_DIR} && chmod 700 {STAGING_DIR}"),
+            ),
+            // The file may hold secrets from the plan's variables, so only the SSH user can read it
+            RemoteStep::Upload {
+                path: staged_path.clone(),
+                contents,
+                mode: 0o600,
+            },
+            RemoteStep::exec(
+                format!("Installing {path}"),
+                format!(
+                    "{} && rm -f {}",
+                    target.privileged(&format!(
+                        "install -o {} -m 644 {} {}",
+                        shell_quote(&user),
+                        shell_quote(&staged_path),
+                        shell_quote(&path)
+                    )),
+                    shell_quote(&staged_path)
+                ),
+            ),
+        ]
+    }
+
+    /// Install the plan's packages for the remote user with home-manager.
+    ///
+    /// If the plan has a start command, it runs as a systemd user service that stays up after the SSH session ends.
+    fn home_manager_steps(
+        &self,
+        source: &Path,
+        plan: &BuildPlan,
+        target: &SshTarget,
+        workdir: &str,
+    ) -> Vec<RemoteStep> {
+        let user = self.remote_user(target);
+        let config_dir = format!("{}/.config/home-manager", target.home_directory());
+        let variables = plan.variables.clone().unwrap_or_default();
+        let service = plan
+            .start_phase
+            .as_ref()
+            .and_then(|start| start.cmd.clone())
+            .map(|cmd| UserService::new(&app_name(source), workdir.to_string(), cmd));
+
+        let mut steps = vec![
+            RemoteStep::exec(
+                "Installing Nix",
+                install_nix_cmd(self.options.nix_installer),
+            ),
+            // Channels are per user, so the channel is added for the account home-manager runs as
+            RemoteStep::exec(
+                "Adding the home-manager channel",
+                target.as_user(&user, &add_home_manager_channel_cmd()),
+            ),
+            RemoteStep::exec(
+                "Creating home-manager config directory",
+                target.as_user(
+                    &user,
+                    &format!(
+                        "mkdir -p {} {}",
+                        shell_quote(&config_dir),
+                        shell_quote(&format!("{}/.config/nixpacks", target.home_directory()))
+                    ),
+                ),
+            ),
+        ];
+
+        let home_nix_path = format!("{config_dir}/home.nix");
+        let home_nix = to_home_manager_nix(
+            &user,
+            &target.home_directory(),
+            plan.get_packages(),
+            &variables,
+            service.as_ref(),
+        );
+        let (check, stamp) = self.change_detection(target, &home_nix_path, &home_nix);
+        steps.extend(check);
+        steps.extend(self.upload_as_user(target, home_nix_path, home_nix));
+
+        if service.is_some() {
+            // Keep the user's services running when they aren't logged in
+            steps.push(RemoteStep::exec(
+                "Enabling linger",
+                target.privileged(&format!("loginctl enable-linger {}", shell_quote(&user))),
+            ));
+        }
+
+        steps.push(RemoteStep::exec(
+            "Running home-manager switch",
+            target.as_user(
+                &user,
+                &with_nix_env("nix-shell '<home-manager>' -A install"),
+            ),
+        ));
+
+        if let Some(service) = &service {
+            steps.push(RemoteStep::exec(
+                format!("Starting {}", service.name),
+                target.as_user(
+                    &user,
+                    &format!(
+                        "export XDG_RUNTIME_DIR=/run/user/$(id -u) && systemctl --user daemon-reload && systemctl --user restart {}.service",
+                        service.name
+                    ),
+                ),
+            ));
+        }
+
+        steps.extend(stamp);
+
+        steps
+    }
+
+    /// The steps that skip applying `contents` when the host already has it, and record that it was applied.
+    ///
+    /// Both are empty when `--force` is passed.
+    fn change_detection(
+        &self,
+        target: &SshTarget,
+        path: &str,
+        contents: &str,
+    ) -> (Option<RemoteStep>, Vec<RemoteStep>) {
+        if self.options.force {
+            return (None, Vec::new());
+        }
+
+        let file_name = Path::new(path).file_name().unwrap().to_string_lossy();
+        let stamp = format!(
+            "{}/.config/nixpacks/{file_name}.sha1",
+            target.home_directory()
+        );
+        let hash = content_hash(contents);
+
+        (
+            Some(RemoteStep::SkipIfUnchanged {
+                path: path.to_string(),
+                stamp: stamp.clone(),
+                hash: hash.clone(),
+            }),
+            self.upload_as_user(target, stamp, format!("{hash}\n")),
+        )
+    }
+
+    /// Install the plan's packages system-wide on a NixOS host.
+    fn nixos_steps(&self, plan: &BuildPlan, target: &SshTarget) -> Vec<RemoteStep> {
+        // Files are staged in the user's home and moved into place as root
+        let staging_dir = format!("{}/.config/nixpacks", target.home_directory());
+        let variables = plan.variables.clone().unwrap_or_default();
+        let mut steps = vec![RemoteStep::exec(
+            "Creating staging directory",
+            target.as_user(
+                &self.remote_user(target),
+                &format!("mkdir -p {}", shell_quote(&staging_dir)),
+            ),
+        )];
+
+        let module = to_nixos_module(plan.get_packages(), &variables, &self.remote_user(target));
+        let (check, stamp) = self.change_detection(target, NIXOS_MODULE_PATH, &module);
+        steps.extend(check);
+
+        for (path, contents) in [
+            (NIXOS_MODULE_PATH, module),
+            (NIXOS_CONFIGURATION_PATH, to_nixos_configuration()),
+        ] {
+            let file_name = Path::new(path).file_name().unwrap().to_string_lossy();
+            let staged_path = format!("{staging_dir}/{file_name}");
+            steps.extend(self.upload_as_user(target, staged_path.clone(), contents));
+            steps.push(RemoteStep::exec(
+                format!("Installing {path}"),
+                target.privileged(&format!(
+                    "install -m 644 {} {}",
+                    shell_quote(&staged_path),
+                    shell_quote(path)
+                )),
+            ));
+        }
+
+        steps.push(RemoteStep::exec(
+            "Running nixos-rebuild switch",
+            target.privileged(&format!(
+                "nixos-rebuild switch -I nixos-config={NIXOS_CONFIGURATION_PATH}"
+            )),
+        ));
+        steps.extend(stamp);
+
+        steps
+    }
+}
+
+/// When several hosts are provisioned at once, every line of output is prefixed with the host it came from.
+fn output_prefix(target: &SshTarget, prefix_output: bool) -> String {
+    if prefix_output {
+        format!("[{}] ", target.host)
+    } else {
+        String::new()
+    }
+}
+
+/// Open a connection to a single host.
+fn connect_host(target: &SshTarget, prefix_output: bool) -> Result<RemoteHost> {
+    Logger::new().log_section(&format!(
+        "{}Connecting to {}@{}:{}",
+        output_prefix(target, prefix_output),
+        target.user,
+        target.hostname,
+        target.port
+    ));
+
+    RemoteHost::connect(target)
+}
+
+/// Run each step on a host, stopping early if the host is already up to date.
+fn provision_host(
+    remote: &RemoteHost,
+    target: &SshTarget,
+    steps: &[RemoteStep],
+    prefix_output: bool,
+) -> Result<HostStatus> {
+    let logger = Logger::new();
+    let prefix = output_prefix(target, prefix_output);
+
+    for step in steps {
+        logger.log_step(&format!("{prefix}{}", step.description()));
+        if step.is_unchanged(remote)? {
+            println!("{prefix}Already up to date, pass --force to apply anyway");
+            return Ok(HostStatus::Unchanged);
+        }
+
+        let output = step.run(remote)?;
+        for line in output.lines() {
+            println!("{prefix}{line}");
+        }
+    }
+
+    Ok(HostStatus::Updated)
+}
+
+/// The name of the app, taken from its source directory.
+pub fn app_name(source: &Path) -> String {
+    source
+        .file_name()
+        .map_or_else(|| "app".to_string(), |n| n.to_string_lossy().to_string())
+}
+
+/// The hosts listed in the inventory, on the command line, and in the hosts file, with their settings.
+pub fn get_listed_hosts(options: &DevenvOptions) -> Result<Vec<(String, HostSettings)>> {
+    let mut hosts = Vec::new();
+    if let Some(inventory) = &options.inventory {
+        hosts.extend(Inventory::from_file(inventory)?.hosts());
+    }
+
+    let mut hostnames = options.hostnames.clone();
+    if let Some(hosts_file) = &options.hosts_file {
+        let contents = fs::read_to_string(hosts_file)
+            .with_context(|| format!("Failed to read hosts file {hosts_file}"))?;
+        hostnames.extend(parse_hosts_file(&contents));
+    }
+    hosts.extend(
+        hostnames
+            .into_iter()
+            .map(|host| (host, HostSettings::default())),
+    );
+
+    Ok(hosts)
+}
+
+/// Parse a hosts file with one host per line. Blank lines and `#` comments are ignored.
+pub fn parse_hosts_file(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// The nixpkgs revision the plan's packages are pinned to.
+pub fn plan_nixpkgs_archive(plan: &BuildPlan) -> String {
+    plan.phases
+        .clone()
+        .unwrap_or_default()
+        .values()
+        .find_map(|phase| phase.nixpkgs_archive.clone())
+        .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string())
+}
+
+/// Rename or drop packages that don't exist under the same name in nixpkgs.
+pub fn normalize_packages(packages: Vec<String>) -> Vec<String> {
+    packages
+        .into_iter()
+        // npm ships with nodejs
+        .filter(|p| !p.starts_with("npm"))
+        // Providers pin yarn to a node version, e.g. `yarn-1_x`
+        .map(|p| {
+            if p.starts_with("yarn-") {
+                "yarn".to_string()
+            } else {
+                p
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{nix::pkg::Pkg, plan::phase::Phase};
+
+    #[test]
+    fn test_parse_hosts_file() {
+        let hosts = parse_hosts_file(
+            "# staging\nweb-1.internal\n\n  web-2.internal  # second web node\ndevbox\n",
+        );
+        assert_eq!(hosts, vec!["web-1.internal", "web-2.internal", "devbox"]);
+    }
+
+    #[test]
+    fn test_get_hosts_deduplicates() {
+        let provisioner = DevenvProvisioner::new(
+            Logger::new(),
+            DevenvOptions {
+                hostnames: vec!["a".to_string(), "b".to_string(), "a".to_string()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            provisioner.get_hosts(None).unwrap(),
+            vec![
+                ("a".to_string(), HostSettings::default()),
+                ("b".to_string(), HostSettings::default())
+            ]
+        );
+
+        let provisioner = DevenvProvisioner::new(Logger::new(), DevenvOptions::default());
+        assert!(provisioner.get_hosts(None).is_err());
+    }
+
+    #[test]
+    fn test_default_archive() {
+        let plan = BuildPlan::new(&[Phase::setup(Some(vec![Pkg::new("go")]))], None);
+        assert_eq!(plan_nixpkgs_archive(&plan), NIXPKGS_ARCHIVE);
+    }
+
+    #[test]
+    fn test_force_skips_change_detection() {
+        let target = SshTarget::resolve(
+            "devbox",
+            &HostSettings::default(),
+            &DevenvOptions::default(),
+            &SshConfig::default(),
+        )
+        .unwrap();
+        let has_check = |force| {
+            let provisioner = DevenvProvisioner::new(
+                Logger::new(),
+                DevenvOptions {
+                    force,
+                    ..Default::default()
+                },
+            );
+            provisioner
+                .get_steps(Path::new("app"), &BuildPlan::default(), &target, "/srv/app")
+                .iter()
+                .any(|step| matches!(step, RemoteStep::SkipIfUnchanged { .. }))
+        };
+
+        assert!(has_check(false));
+        assert!(!has_check(true));
+    }
+
+    #[test]
+    fn test_remote_user_steps() {
+        let mut target = SshTarget::resolve(
+            "devbox",
+            &HostSettings::default(),
+            &DevenvOptions::default(),
+            &SshConfig::default(),
+        )
+        .unwrap();
+        target.home = Some("/home/dev".to_string());
+        let provisioner = DevenvProvisioner::new(
+            Logger::new(),
+            DevenvOptions {
+                remote_user: Some("dev".to_string()),
+                force: true,
+                ..Default::default()
+            },
+        );
+        let steps =
+            provisioner.get_steps(Path::new("app"), &BuildPlan::default(), &target, "/srv/app");
+
+        assert!(steps.contains(&RemoteStep::Upload {
+            path: ".nixpacks-staging/dev-home.nix".to_string(),
+            contents: to_home_manager_nix(
+                "dev",
+                "/home/dev",
+                Vec::new(),
+                &std::collections::BTreeMap::new(),
+                None
+            ),
+            mode: 0o600,
+        }));
+        assert!(steps.contains(&RemoteStep::exec(
+            "Installing /home/dev/.config/home-manager/home.nix",
+            "sudo install -o 'dev' -m 644 '.nixpacks-staging/dev-home.nix' '/home/dev/.config/home-manager/home.nix' && rm -f '.nixpacks-staging/dev-home.nix'"
+        )));
+        assert!(steps.iter().any(|step| matches!(
+            step,
+            RemoteStep::Exec { description, cmd }
+                if description == "Running home-manager switch" && cmd.starts_with("sudo -u 'dev' -H sh -c ")
+        )));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::nixpacks::environment::EnvironmentVariables;
+
+/// Quote a value as a Nix string literal.
+pub fn quote_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Render an attribute name, quoting it if it is not a plain Nix identifier.
+pub fn attr_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+
+    if is_identifier {
+        name.to_string()
+    } else {
+        quote_string(name)
+    }
+}
+
+/// Reduce a name to the characters Nix allows in a store path name, replacing the rest with `-`.
+pub fn store_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_' | '?' | '=') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    // Store path names can't start with a dot
+    let name = name.trim_start_matches('.');
+
+    if name.is_empty() {
+        "app".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Render environment variables as the lines of a Nix attribute set, each indented by `indent` spaces.
+pub fn variables_to_attrs(variables: &EnvironmentVariables, indent: usize) -> String {
+    variables
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}{} = {};",
+                " ".repeat(indent),
+                attr_name(name),
+                quote_string(value)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_string() {
+        assert_eq!(quote_string("hello"), "\"hello\"");
+        assert_eq!(
+            quote_string("say \"hi\" to ${USER}\\n"),
+            "\"say \\\"hi\\\" to \\${USER}\\\\n\""
+        );
+    }
+
+    #[test]
+    fn test_attr_name() {
+        assert_eq!(attr_name("NODE_ENV"), "NODE_ENV");
+        assert_eq!(attr_name("1PASSWORD"), "\"1PASSWORD\"");
+        assert_eq!(attr_name("MY.VAR"), "\"MY.VAR\"");
+    }
+
+    #[test]
+    fn test_store_name() {
+        assert_eq!(store_name("my-app"), "my-app");
+        assert_eq!(store_name("my app\"; ${x}"), "my-app-----x-");
+        assert_eq!(store_name(".hidden"), "hidden");
+        assert_eq!(store_name("..."), "app");
+    }
+
+    #[test]
+    fn test_variables_to_attrs() {
+        let variables = EnvironmentVariables::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("PORT".to_string(), "3000".to_string()),
+        ]);
+        assert_eq!(
+            variables_to_attrs(&variables, 4),
+            "    NODE_ENV = \"production\";\n    PORT = \"3000\";"
+        );
+    }
+}
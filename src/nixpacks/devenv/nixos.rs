@@ -0,0 +1,97 @@
+use super::{
+    nix_syntax::{attr_name, variables_to_attrs},
+    normalize_packages,
+};
+use crate::nixpacks::environment::EnvironmentVariables;
+use indoc::formatdoc;
+
+/// Where the generated module is written on the remote host.
+pub const NIXOS_MODULE_PATH: &str = "/etc/nixos/nixpacks.nix";
+
+/// A top-level configuration importing both the host's existing `configuration.nix` and the generated module.
+///
+/// Passed to `nixos-rebuild` with `-I nixos-config=...` so the host's own configuration is never edited.
+pub const NIXOS_CONFIGURATION_PATH: &str = "/etc/nixos/nixpacks-configuration.nix";
+
+/// Render the packages and variables of a build plan as a NixOS module.
+pub fn to_nixos_module(
+    packages: Vec<String>,
+    variables: &EnvironmentVariables,
+    user: &str,
+) -> String {
+    let packages = normalize_packages(packages)
+        .iter()
+        .map(|p| format!("    {p}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let variables = variables_to_attrs(variables, 4);
+
+    let user_config = if user == "root" {
+        String::new()
+    } else {
+        format!(
+            "\n  users.users.{} = {{\n    isNormalUser = true;\n  }};\n",
+            attr_name(user)
+        )
+    };
+
+    formatdoc! {"
+        {{ config, pkgs, lib, ... }}:
+
+        {{
+          environment.systemPackages = with pkgs; [
+        {packages}
+          ];
+
+          environment.variables = {{
+        {variables}
+          }};
+        {user_config}
+          # Nixpacks applies this configuration over SSH, so make sure it stays reachable.
+          services.openssh.enable = true;
+        }}
+    "}
+}
+
+/// Render the configuration that combines the host's `configuration.nix` with the generated module.
+pub fn to_nixos_configuration() -> String {
+    formatdoc! {"
+        {{
+          imports = [
+            ./configuration.nix
+            ./nixpacks.nix
+          ];
+        }}
+    "}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_nixos_module() {
+        let nix = to_nixos_module(
+            vec!["nodejs-16_x".to_string(), "npm-8_x".to_string()],
+            &EnvironmentVariables::from([("PORT".to_string(), "3000".to_string())]),
+            "ubuntu",
+        );
+
+        assert!(nix.contains("environment.systemPackages = with pkgs; [\n    nodejs-16_x\n  ];"));
+        assert!(nix.contains("environment.variables = {\n    PORT = \"3000\";\n  };"));
+        assert!(nix.contains("users.users.ubuntu = {\n    isNormalUser = true;\n  };"));
+        assert!(nix.contains("services.openssh.enable = true;"));
+    }
+
+    #[test]
+    fn test_to_nixos_module_quotes_user() {
+        let nix = to_nixos_module(Vec::new(), &EnvironmentVariables::new(), "first.last");
+        assert!(nix.contains("users.users.\"first.last\" = {"));
+    }
+
+    #[test]
+    fn test_to_nixos_module_as_root() {
+        let nix = to_nixos_module(vec!["go".to_string()], &EnvironmentVariables::new(), "root");
+        assert!(!nix.contains("users.users"));
+    }
+}
@@ -0,0 +1,59 @@
+use super::{
+    nix_syntax::variables_to_attrs, normalize_packages, plan_nixpkgs_archive, GENERATED_HEADER,
+};
+use crate::nixpacks::plan::BuildPlan;
+use indoc::formatdoc;
+
+/// Render the packages and variables of a build plan as a `shell.nix` for `nix-shell`.
+pub fn to_shell_nix(plan: &BuildPlan) -> String {
+    let archive = plan_nixpkgs_archive(plan);
+    let packages = normalize_packages(plan.get_packages())
+        .iter()
+        .map(|p| format!("    {p}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let variables = variables_to_attrs(&plan.variables.clone().unwrap_or_default(), 2);
+
+    formatdoc! {"
+        {GENERATED_HEADER}
+        {{ pkgs ? import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz\") {{ }} }}:
+
+        pkgs.mkShell {{
+          packages = with pkgs; [
+        {packages}
+          ];
+
+        {variables}
+        }}
+    "}
+}
+
+/// Render a direnv `.envrc` that loads `shell.nix` when entering the directory.
+pub fn to_envrc() -> String {
+    format!("{GENERATED_HEADER}\nuse nix\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{environment::EnvironmentVariables, nix::pkg::Pkg, plan::phase::Phase};
+
+    #[test]
+    fn test_to_shell_nix() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("python38"), Pkg::new("gcc")]));
+        setup.set_nix_archive("abc123".to_string());
+        let mut plan = BuildPlan::new(&[setup], None);
+        plan.add_variables(EnvironmentVariables::from([(
+            "PYTHONUNBUFFERED".to_string(),
+            "1".to_string(),
+        )]));
+
+        let shell = to_shell_nix(&plan);
+        assert!(shell.starts_with(GENERATED_HEADER));
+        assert!(shell.contains(
+            "import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/abc123.tar.gz\")"
+        ));
+        assert!(shell.contains("  packages = with pkgs; [\n    python38\n    gcc\n  ];"));
+        assert!(shell.contains("\n  PYTHONUNBUFFERED = \"1\";\n}"));
+    }
+}
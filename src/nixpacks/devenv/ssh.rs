@@ -0,0 +1,522 @@
+use super::{
+    inventory::HostSettings,
+    ssh_config::{expand_tilde, SshConfig},
+    DevenvOptions,
+};
+use crate::nixpacks::utils::shell_quote;
+use anyhow::{bail, Context, Result};
+use ssh2::{ExtendedData, KeyboardInteractivePrompt, Prompt, Session};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub const DEFAULT_SSH_PORT: u16 = 22;
+pub const DEFAULT_SSH_USER: &str = "ubuntu";
+
+/// How to authenticate with the remote host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SshAuth {
+    /// Try each identity loaded in the running ssh-agent.
+    Agent,
+    /// Use a private key file on disk.
+    IdentityFile(PathBuf),
+    /// Log in with a password, for hosts that don't allow public key authentication.
+    Password(String),
+}
+
+/// Answers every keyboard-interactive challenge with the password.
+struct PasswordPrompter<'a>(&'a str);
+
+impl KeyboardInteractivePrompt for PasswordPrompter<'_> {
+    fn prompt(
+        &mut self,
+        _username: &str,
+        _instructions: &str,
+        prompts: &[Prompt<'_>],
+    ) -> Vec<String> {
+        prompts.iter().map(|_| self.0.to_string()).collect()
+    }
+}
+
+/// Connection details for a remote host, resolved from CLI options and `~/.ssh/config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshTarget {
+    /// The host as it was given, which may be an alias from `~/.ssh/config`.
+    pub host: String,
+    pub hostname: String,
+    pub port: u16,
+    pub user: String,
+    pub auth: SshAuth,
+    /// The user's home directory, if it was passed or has been detected over SSH.
+    pub home: Option<String>,
+}
+
+impl SshTarget {
+    /// Combine the host's inventory settings, the CLI options, and the user's SSH config, in that order of precedence.
+    ///
+    /// A private key file is only used when one is passed or configured for the host, otherwise keys are taken from ssh-agent.
+    pub fn resolve(
+        host: &str,
+        settings: &HostSettings,
+        options: &DevenvOptions,
+        config: &SshConfig,
+    ) -> Result<SshTarget> {
+        let host_config = config.host(host);
+
+        let auth = match settings
+            .identity_file
+            .as_deref()
+            .map(expand_tilde)
+            .or_else(|| options.identity_file.as_ref().map(PathBuf::from))
+            .or_else(|| host_config.identity_files.first().cloned())
+        {
+            Some(identity_file) => SshAuth::IdentityFile(identity_file),
+            None => SshAuth::Agent,
+        };
+
+        Ok(SshTarget {
+            host: host.to_string(),
+            hostname: settings
+                .hostname
+                .clone()
+                .or(host_config.hostname)
+                .unwrap_or_else(|| host.to_string()),
+            port: settings
+                .port
+                .or(options.port)
+                .or(host_config.port)
+                .unwrap_or(DEFAULT_SSH_PORT),
+            user: settings
+                .user
+                .clone()
+                .or_else(|| options.ssh_user.clone())
+                .or(host_config.user)
+                .unwrap_or_else(|| DEFAULT_SSH_USER.to_string()),
+            auth,
+            home: options.remote_home.clone(),
+        })
+    }
+
+    /// The home directory of the SSH user on the remote host. Until it is known, it is assumed to be under `/home`.
+    pub fn home_directory(&self) -> String {
+        if let Some(home) = &self.home {
+            home.clone()
+        } else if self.user == "root" {
+            "/root".to_string()
+        } else {
+            format!("/home/{}", self.user)
+        }
+    }
+
+    /// Expand a leading `~` in a path to the SSH user's home directory on the remote host.
+    pub fn expand_home(&self, path: &str) -> String {
+        match path.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                format!("{}{rest}", self.home_directory())
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    /// Wrap a command so that it runs as root on the remote host.
+    pub fn privileged(&self, cmd: &str) -> String {
+        if self.user == "root" {
+            cmd.to_string()
+        } else {
+            format!("sudo {cmd}")
+        }
+    }
+
+    /// Wrap a command so that it runs as `user` on the remote host, with their home directory, unless they're the SSH
+    /// user.
+    pub fn as_user(&self, user: &str, cmd: &str) -> String {
+        if user == self.user {
+            cmd.to_string()
+        } else {
+            format!(
+                "sudo -u {} -H sh -c {}",
+                shell_quote(user),
+                shell_quote(cmd)
+            )
+        }
+    }
+}
+
+/// An authenticated SSH session to a remote host.
+pub struct RemoteHost {
+    session: Session,
+}
+
+impl RemoteHost {
+    /// Open a connection to the target and authenticate as its user.
+    pub fn connect(target: &SshTarget) -> Result<RemoteHost> {
+        let tcp = TcpStream::connect((target.hostname.as_str(), target.port))
+            .with_context(|| format!("Connecting to {}:{}", target.hostname, target.port))?;
+
+        RemoteHost::authenticate(target, tcp, None)
+    }
+
+    /// Like `connect`, but give up when connecting, or any later operation of the session, takes longer than
+    /// `timeout`.
+    pub fn connect_timeout(target: &SshTarget, timeout: Duration) -> Result<RemoteHost> {
+        let connect = || -> io::Result<TcpStream> {
+            let mut last_error = None;
+            for address in (target.hostname.as_str(), target.port).to_socket_addrs()? {
+                match TcpStream::connect_timeout(&address, timeout) {
+                    Ok(tcp) => return Ok(tcp),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+        };
+        let tcp = connect()
+            .with_context(|| format!("Connecting to {}:{}", target.hostname, target.port))?;
+
+        RemoteHost::authenticate(target, tcp, Some(timeout))
+    }
+
+    fn authenticate(
+        target: &SshTarget,
+        tcp: TcpStream,
+        timeout: Option<Duration>,
+    ) -> Result<RemoteHost> {
+        let mut session = Session::new().context("Creating SSH session")?;
+        if let Some(timeout) = timeout {
+            session.set_timeout(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX));
+        }
+        session.set_tcp_stream(tcp);
+        session.handshake().context("Performing SSH handshake")?;
+
+        match &target.auth {
+            SshAuth::Agent => session.userauth_agent(&target.user).with_context(|| {
+                format!(
+                    "Authenticating as {} with ssh-agent. Is it running with a key loaded? Otherwise pass --identity-file",
+                    target.user
+                )
+            })?,
+            SshAuth::IdentityFile(identity_file) => session
+                .userauth_pubkey_file(&target.user, None, identity_file, None)
+                .with_context(|| {
+                    format!(
+                        "Authenticating as {} with {}",
+                        target.user,
+                        identity_file.display()
+                    )
+                })?,
+            SshAuth::Password(password) => {
+                let methods = session
+                    .auth_methods(&target.user)
+                    .unwrap_or_default()
+                    .to_string();
+                let methods = methods.split(',').collect::<Vec<_>>();
+
+                if methods.contains(&"password") {
+                    session
+                        .userauth_password(&target.user, password)
+                        .with_context(|| {
+                            format!("Authenticating as {} with a password", target.user)
+                        })?;
+                } else if methods.contains(&"keyboard-interactive") {
+                    session
+                        .userauth_keyboard_interactive(
+                            &target.user,
+                            &mut PasswordPrompter(password),
+                        )
+                        .with_context(|| {
+                            format!(
+                                "Authenticating as {} with keyboard-interactive",
+                                target.user
+                            )
+                        })?;
+                } else {
+                    bail!(
+                        "{} doesn't accept password authentication, only {}",
+                        target.hostname,
+                        methods.join(", ")
+                    );
+                }
+            }
+        }
+        if !session.authenticated() {
+            bail!("Failed to authenticate as {}", target.user);
+        }
+
+        Ok(RemoteHost { session })
+    }
+
+    /// The underlying SSH session.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Write a file to the remote host over SCP.
+    pub fn upload(&self, path: &Path, contents: &[u8], mode: i32) -> Result<()> {
+        let mut remote_file = self
+            .session
+            .scp_send(path, mode, contents.len() as u64, None)
+            .with_context(|| format!("Opening remote file {}", path.display()))?;
+
+        remote_file
+            .write_all(contents)
+            .with_context(|| format!("Writing remote file {}", path.display()))?;
+        remote_file.send_eof()?;
+        remote_file.wait_eof()?;
+        remote_file.close()?;
+        remote_file.wait_close()?;
+
+        Ok(())
+    }
+
+    /// Ask the remote shell for the user's home directory.
+    pub fn home_directory(&self) -> Result<String> {
+        let home = self.exec("echo $HOME")?.trim().to_string();
+        if home.is_empty() {
+            bail!("Could not detect the home directory on the remote host. Pass --remote-home");
+        }
+
+        Ok(home)
+    }
+
+    /// Detect the home directory of another account on the remote host from its passwd entry.
+    pub fn user_home_directory(&self, user: &str) -> Result<String> {
+        let home = self
+            .exec(&format!(
+                "getent passwd {} | cut -d: -f6",
+                shell_quote(user)
+            ))?
+            .trim()
+            .to_string();
+        if home.is_empty() {
+            bail!("Could not find the home directory of {user} on the remote host. Pass --remote-home");
+        }
+
+        Ok(home)
+    }
+
+    /// Run a command on the remote host, returning its output. Errors if the command exits unsuccessfully.
+    pub fn exec(&self, cmd: &str) -> Result<String> {
+        self.exec_with_input(cmd, &[])
+    }
+
+    /// Run a command on the remote host with the given bytes written to its stdin.
+    pub fn exec_with_input(&self, cmd: &str, input: &[u8]) -> Result<String> {
+        let mut channel = self.session.channel_session()?;
+        channel.handle_extended_data(ExtendedData::Merge)?;
+        channel
+            .exec(cmd)
+            .with_context(|| format!("Running `{cmd}` on remote host"))?;
+
+        if !input.is_empty() {
+            channel
+                .write_all(input)
+                .with_context(|| format!("Writing input to `{cmd}`"))?;
+        }
+        channel.send_eof()?;
+
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+
+        let status = channel.exit_status()?;
+        if status != 0 {
+            bail!("Remote command `{cmd}` exited with status {status}\n{output}");
+        }
+
+        Ok(output)
+    }
+
+    /// Run a command on the remote host, printing its output as it arrives. Errors if the command exits unsuccessfully.
+    pub fn exec_streaming(&self, cmd: &str) -> Result<()> {
+        let mut channel = self.session.channel_session()?;
+        channel.handle_extended_data(ExtendedData::Merge)?;
+        channel
+            .exec(cmd)
+            .with_context(|| format!("Running `{cmd}` on remote host"))?;
+        channel.send_eof()?;
+
+        let mut stdout = io::stdout();
+        let mut buf = [0; 8 * 1024];
+        loop {
+            let n = channel.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            stdout.write_all(&buf[..n])?;
+            stdout.flush()?;
+        }
+        channel.wait_close()?;
+
+        let status = channel.exit_status()?;
+        if status != 0 {
+            bail!("Remote command `{cmd}` exited with status {status}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_defaults() {
+        let target = SshTarget::resolve(
+            "example.com",
+            &HostSettings::default(),
+            &DevenvOptions::default(),
+            &SshConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            target,
+            SshTarget {
+                host: "example.com".to_string(),
+                hostname: "example.com".to_string(),
+                port: DEFAULT_SSH_PORT,
+                user: DEFAULT_SSH_USER.to_string(),
+                auth: SshAuth::Agent,
+                home: None,
+            }
+        );
+        assert_eq!(target.home_directory(), "/home/ubuntu");
+        assert_eq!(target.privileged("whoami"), "sudo whoami");
+        assert_eq!(target.as_user("ubuntu", "whoami"), "whoami");
+        assert_eq!(
+            target.as_user("dev", "echo $HOME"),
+            "sudo -u 'dev' -H sh -c 'echo $HOME'"
+        );
+        assert_eq!(target.expand_home("~/app"), "/home/ubuntu/app");
+        assert_eq!(target.expand_home("/srv/app"), "/srv/app");
+    }
+
+    #[test]
+    fn test_remote_home() {
+        let options = DevenvOptions {
+            remote_home: Some("/Users/dev".to_string()),
+            ..Default::default()
+        };
+        let target = SshTarget::resolve(
+            "example.com",
+            &HostSettings::default(),
+            &options,
+            &SshConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(target.home_directory(), "/Users/dev");
+        assert_eq!(target.expand_home("~/app"), "/Users/dev/app");
+    }
+
+    #[test]
+    fn test_resolve_from_ssh_config() {
+        let config =
+            SshConfig::parse("Host devbox\n  HostName 10.0.0.5\n  User root\n  Port 2222\n");
+        let target = SshTarget::resolve(
+            "devbox",
+            &HostSettings::default(),
+            &DevenvOptions::default(),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(target.hostname, "10.0.0.5");
+        assert_eq!(target.port, 2222);
+        assert_eq!(target.user, "root");
+        assert_eq!(target.home_directory(), "/root");
+        assert_eq!(target.privileged("whoami"), "whoami");
+    }
+
+    #[test]
+    fn test_resolve_identity_file() {
+        let config = SshConfig::parse("Host devbox\n  IdentityFile /keys/config\n");
+        let target = SshTarget::resolve(
+            "devbox",
+            &HostSettings::default(),
+            &DevenvOptions::default(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            target.auth,
+            SshAuth::IdentityFile(PathBuf::from("/keys/config"))
+        );
+
+        let options = DevenvOptions {
+            identity_file: Some("/keys/cli".to_string()),
+            ..Default::default()
+        };
+        let target =
+            SshTarget::resolve("devbox", &HostSettings::default(), &options, &config).unwrap();
+        assert_eq!(
+            target.auth,
+            SshAuth::IdentityFile(PathBuf::from("/keys/cli"))
+        );
+    }
+
+    #[test]
+    fn test_cli_options_take_precedence() {
+        let config = SshConfig::parse("Host devbox\n  User root\n  Port 2222\n");
+        let options = DevenvOptions {
+            ssh_user: Some("dev".to_string()),
+            port: Some(22022),
+            ..Default::default()
+        };
+
+        let target =
+            SshTarget::resolve("devbox", &HostSettings::default(), &options, &config).unwrap();
+        assert_eq!(target.user, "dev");
+        assert_eq!(target.port, 22022);
+    }
+
+    #[test]
+    fn test_inventory_settings_take_precedence() {
+        let config = SshConfig::parse(
+            "Host devbox
+  HostName 10.0.0.5
+  User root
+",
+        );
+        let settings = HostSettings {
+            hostname: Some("10.0.0.6".to_string()),
+            user: Some("alice".to_string()),
+            identity_file: Some("/keys/inventory".to_string()),
+            ..Default::default()
+        };
+        let options = DevenvOptions {
+            ssh_user: Some("dev".to_string()),
+            identity_file: Some("/keys/cli".to_string()),
+            port: Some(22022),
+            ..Default::default()
+        };
+
+        let target = SshTarget::resolve("devbox", &settings, &options, &config).unwrap();
+        assert_eq!(target.hostname, "10.0.0.6");
+        assert_eq!(target.user, "alice");
+        assert_eq!(target.port, 22022);
+        assert_eq!(
+            target.auth,
+            SshAuth::IdentityFile(PathBuf::from("/keys/inventory"))
+        );
+    }
+
+    #[test]
+    fn test_password_prompter_answers_every_prompt() {
+        let prompts = [
+            Prompt {
+                text: "Password: ".into(),
+                echo: false,
+            },
+            Prompt {
+                text: "Password again: ".into(),
+                echo: false,
+            },
+        ];
+        assert_eq!(
+            PasswordPrompter("hunter2").prompt("dev", "", &prompts),
+            vec!["hunter2", "hunter2"]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use globset::Glob;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// A single `Host` block from an OpenSSH client config file.
+#[derive(Debug, Clone, Default)]
+struct HostBlock {
+    patterns: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl HostBlock {
+    /// Whether this block applies to the given host alias.
+    ///
+    /// Follows `ssh_config(5)`: any negated pattern that matches excludes the host, otherwise at least one positive pattern must match.
+    fn matches(&self, host: &str) -> bool {
+        let mut matched = false;
+        for pattern in &self.patterns {
+            let (negated, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+
+            let is_match =
+                Glob::new(pattern).map_or(false, |glob| glob.compile_matcher().is_match(host));
+
+            if is_match && negated {
+                return false;
+            }
+            matched |= is_match;
+        }
+
+        matched
+    }
+}
+
+/// Connection settings that `~/.ssh/config` specifies for a host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SshHostConfig {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<PathBuf>,
+}
+
+/// The subset of an OpenSSH client config that Nixpacks understands.
+#[derive(Debug, Clone, Default)]
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+impl SshConfig {
+    /// Parse the contents of an OpenSSH client config file.
+    ///
+    /// Options before the first `Host` line apply to every host. `Match` blocks are not supported and are skipped.
+    pub fn parse(contents: &str) -> SshConfig {
+        let mut blocks = vec![HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        }];
+        let mut skipping = false;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+                Some((keyword, value)) => (
+                    keyword.to_lowercase(),
+                    value
+                        .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+                        .trim()
+                        .trim_matches('"')
+                        .to_string(),
+                ),
+                None => continue,
+            };
+
+            match keyword.as_str() {
+                "host" => {
+                    skipping = false;
+                    blocks.push(HostBlock {
+                        patterns: value.split_whitespace().map(ToString::to_string).collect(),
+                        options: Vec::new(),
+                    });
+                }
+                "match" => skipping = true,
+                _ if !skipping => {
+                    if let Some(block) = blocks.last_mut() {
+                        block.options.push((keyword, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        SshConfig { blocks }
+    }
+
+    /// Read `~/.ssh/config`, returning an empty config if it does not exist.
+    pub fn load() -> Result<SshConfig> {
+        match home_dir() {
+            Some(home) => SshConfig::from_file(home.join(".ssh").join("config")),
+            None => Ok(SshConfig::default()),
+        }
+    }
+
+    /// Read the config file at the given path, returning an empty config if it does not exist.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SshConfig> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(SshConfig::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read SSH config {}", path.display()))?;
+        Ok(SshConfig::parse(&contents))
+    }
+
+    /// Resolve the settings for a host alias. As with `ssh`, the first value found for each option wins.
+    pub fn host(&self, host: &str) -> SshHostConfig {
+        let mut config = SshHostConfig::default();
+
+        for block in self.blocks.iter().filter(|block| block.matches(host)) {
+            for (keyword, value) in &block.options {
+                match keyword.as_str() {
+                    "hostname" if config.hostname.is_none() => {
+                        config.hostname = Some(value.replace("%h", host));
+                    }
+                    "user" if config.user.is_none() => config.user = Some(value.clone()),
+                    "port" if config.port.is_none() => config.port = value.parse().ok(),
+                    "identityfile" => config.identity_files.push(expand_tilde(value)),
+                    _ => {}
+                }
+            }
+        }
+
+        config
+    }
+}
+
+/// The home directory of the user running Nixpacks.
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Expand a leading `~` to the home directory of the user running Nixpacks.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => home_dir().unwrap_or_else(|| PathBuf::from(path)),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        # Global defaults
+        User fallback
+
+        Host devbox
+            HostName 10.0.0.5
+            User robert
+            Port 2222
+            IdentityFile /keys/devbox
+
+        Host *.internal !bastion.internal
+            User=ops
+            IdentityFile "/keys/internal"
+
+        Match host other
+            User ignored
+
+        Host *
+            Port 22
+            IdentityFile /keys/default
+    "#;
+
+    #[test]
+    fn test_resolve_alias() {
+        let config = SshConfig::parse(CONFIG);
+        assert_eq!(
+            config.host("devbox"),
+            SshHostConfig {
+                hostname: Some("10.0.0.5".to_string()),
+                user: Some("fallback".to_string()),
+                port: Some(2222),
+                identity_files: vec![
+                    PathBuf::from("/keys/devbox"),
+                    PathBuf::from("/keys/default")
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_wildcard_and_negated_patterns() {
+        let config = SshConfig::parse(CONFIG);
+
+        let web = config.host("web.internal");
+        assert_eq!(web.hostname, None);
+        assert_eq!(web.port, Some(22));
+        assert_eq!(
+            web.identity_files,
+            vec![
+                PathBuf::from("/keys/internal"),
+                PathBuf::from("/keys/default")
+            ]
+        );
+
+        let bastion = config.host("bastion.internal");
+        assert_eq!(bastion.identity_files, vec![PathBuf::from("/keys/default")]);
+    }
+
+    #[test]
+    fn test_match_blocks_are_skipped() {
+        let config = SshConfig::parse(CONFIG);
+        assert_eq!(config.host("other").user, Some("fallback".to_string()));
+    }
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let config = SshConfig::from_file("/this/does/not/exist").unwrap();
+        assert_eq!(config.host("devbox"), SshHostConfig::default());
+    }
+}
@@ -0,0 +1,176 @@
+use super::{ssh::RemoteHost, upload::bundle_app};
+use crate::nixpacks::{snapshot::app_files, utils::shell_quote};
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+
+/// A single action performed on the remote host while provisioning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteStep {
+    /// Run a shell command.
+    Exec { description: String, cmd: String },
+    /// Write a file.
+    Upload {
+        path: String,
+        contents: String,
+        mode: i32,
+    },
+    /// Copy the app source into a directory, as `user` if it isn't the SSH user.
+    SyncApp {
+        source: PathBuf,
+        workdir: String,
+        user: Option<String>,
+    },
+    /// Stop provisioning the host if the file at `path` already has the given hash and was applied successfully,
+    /// which is recorded by writing the hash to `stamp` as the last step.
+    SkipIfUnchanged {
+        path: String,
+        stamp: String,
+        hash: String,
+    },
+}
+
+impl RemoteStep {
+    pub fn exec<S: Into<String>, C: Into<String>>(description: S, cmd: C) -> RemoteStep {
+        RemoteStep::Exec {
+            description: description.into(),
+            cmd: cmd.into(),
+        }
+    }
+
+    pub fn upload<P: Into<String>, C: Into<String>>(path: P, contents: C) -> RemoteStep {
+        RemoteStep::Upload {
+            path: path.into(),
+            contents: contents.into(),
+            mode: 0o644,
+        }
+    }
+
+    /// A one-line summary of the step, used for logging.
+    pub fn description(&self) -> String {
+        match self {
+            RemoteStep::Exec { description, .. } => description.clone(),
+            RemoteStep::Upload { path, .. } => format!("Uploading {path}"),
+            RemoteStep::SyncApp { workdir, .. } => format!("Uploading app source to {workdir}"),
+            RemoteStep::SkipIfUnchanged { path, .. } => format!("Checking {path} for changes"),
+        }
+    }
+
+    /// The remote command that extracts the app source into the working directory.
+    fn sync_cmd(workdir: &str, user: Option<&str>) -> String {
+        let workdir = shell_quote(workdir);
+        let cmd = format!("mkdir -p {workdir} && tar -xzf - -C {workdir}");
+        match user {
+            Some(user) => format!(
+                "sudo -u {} -H sh -c {}",
+                shell_quote(user),
+                shell_quote(&cmd)
+            ),
+            None => cmd,
+        }
+    }
+
+    /// The remote command that prints the recorded hash, followed by the hash of the file as it is now.
+    fn hash_cmd(path: &str, stamp: &str) -> String {
+        format!(
+            "echo \"$(cat {} 2>/dev/null) $(sha1sum {} 2>/dev/null | cut -d' ' -f1)\"",
+            shell_quote(stamp),
+            shell_quote(path)
+        )
+    }
+
+    /// Whether the host already has this configuration applied. Always false for steps that aren't checks.
+    pub fn is_unchanged(&self, remote: &RemoteHost) -> Result<bool> {
+        match self {
+            RemoteStep::SkipIfUnchanged { path, stamp, hash } => {
+                let output = remote.exec(&RemoteStep::hash_cmd(path, stamp))?;
+                Ok(output.trim() == format!("{hash} {hash}"))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Perform the step on the remote host, returning any output.
+    pub fn run(&self, remote: &RemoteHost) -> Result<String> {
+        match self {
+            RemoteStep::Exec { cmd, .. } => remote.exec(cmd),
+            RemoteStep::Upload {
+                path,
+                contents,
+                mode,
+            } => {
+                remote
+                    .upload(Path::new(path), contents.as_bytes(), *mode)
+                    .with_context(|| format!("Uploading {path}"))?;
+                Ok(String::new())
+            }
+            RemoteStep::SyncApp {
+                source,
+                workdir,
+                user,
+            } => {
+                let bundle = bundle_app(source)?;
+                remote
+                    .exec_with_input(&RemoteStep::sync_cmd(workdir, user.as_deref()), &bundle)
+                    .context("Uploading app source")
+            }
+            RemoteStep::SkipIfUnchanged { .. } => Ok(String::new()),
+        }
+    }
+
+    /// Render what the step would do, without connecting to the host.
+    pub fn to_dry_run_string(&self) -> Result<String> {
+        let details = match self {
+            RemoteStep::Exec { cmd, .. } => format!("$ {cmd}"),
+            RemoteStep::Upload { contents, .. } => contents.trim_end().to_string(),
+            RemoteStep::SyncApp {
+                source,
+                workdir,
+                user,
+            } => {
+                let file_count = app_files(source)?.len();
+                format!(
+                    "$ {}\n({file_count} files from {})",
+                    RemoteStep::sync_cmd(workdir, user.as_deref()),
+                    source.display()
+                )
+            }
+            RemoteStep::SkipIfUnchanged { path, stamp, hash } => format!(
+                "$ {}\n(skips the remaining steps if both are {hash})",
+                RemoteStep::hash_cmd(path, stamp)
+            ),
+        };
+
+        Ok(details)
+    }
+}
+
+/// The SHA-1 of some generated config, as printed by `sha1sum`.
+pub fn content_hash(contents: &str) -> String {
+    format!("{:x}", Sha1::digest(contents.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dry_run_string() {
+        let exec = RemoteStep::exec("Running home-manager switch", "home-manager switch");
+        assert_eq!(exec.description(), "Running home-manager switch");
+        assert_eq!(exec.to_dry_run_string().unwrap(), "$ home-manager switch");
+
+        let upload = RemoteStep::upload("/home/ubuntu/home.nix", "{ }\n");
+        assert_eq!(upload.description(), "Uploading /home/ubuntu/home.nix");
+        assert_eq!(upload.to_dry_run_string().unwrap(), "{ }");
+    }
+
+    #[test]
+    fn test_content_hash() {
+        // echo -n 'hello' | sha1sum
+        assert_eq!(
+            content_hash("hello"),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        );
+    }
+}
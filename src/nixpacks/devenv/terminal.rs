@@ -0,0 +1,108 @@
+use anyhow::{bail, Context, Result};
+use std::io::{self, BufRead, Write};
+
+const DEFAULT_TERMINAL_SIZE: (u32, u32) = (80, 24);
+
+/// Changes the settings of the local terminal, restoring them when dropped.
+pub struct TerminalGuard {
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+}
+
+impl TerminalGuard {
+    /// Put the terminal into raw mode, so keystrokes like Ctrl-C are passed through instead of handled locally.
+    pub fn raw() -> Result<TerminalGuard> {
+        #[cfg(unix)]
+        return TerminalGuard::modify(|settings| unsafe { libc::cfmakeraw(settings) });
+
+        #[cfg(not(unix))]
+        Ok(TerminalGuard {})
+    }
+
+    /// Stop the terminal from echoing what is typed.
+    pub fn no_echo() -> Result<TerminalGuard> {
+        #[cfg(unix)]
+        return TerminalGuard::modify(|settings| settings.c_lflag &= !libc::ECHO);
+
+        #[cfg(not(unix))]
+        Ok(TerminalGuard {})
+    }
+
+    #[cfg(unix)]
+    fn modify<F: FnOnce(&mut libc::termios)>(f: F) -> Result<TerminalGuard> {
+        // Input is piped, there's no terminal to configure
+        if !is_terminal() {
+            return Ok(TerminalGuard { original: None });
+        }
+
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error()).context("Reading terminal settings");
+        }
+
+        let mut settings = original;
+        f(&mut settings);
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) } != 0 {
+            return Err(io::Error::last_os_error()).context("Changing terminal settings");
+        }
+
+        Ok(TerminalGuard {
+            original: Some(original),
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(original) = &self.original {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+    }
+}
+
+/// Whether stdin is an interactive terminal.
+pub fn is_terminal() -> bool {
+    #[cfg(unix)]
+    return unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+
+    #[cfg(not(unix))]
+    true
+}
+
+/// The columns and rows of the local terminal.
+pub fn terminal_size() -> (u32, u32) {
+    #[cfg(unix)]
+    {
+        let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return (u32::from(size.ws_col), u32::from(size.ws_row));
+        }
+    }
+
+    DEFAULT_TERMINAL_SIZE
+}
+
+/// Ask for a password on the terminal without echoing it.
+pub fn prompt_password(prompt: &str) -> Result<String> {
+    if !is_terminal() {
+        bail!("Can't prompt for a password without a terminal. Set SSH_PASSWORD instead");
+    }
+
+    eprint!("{prompt}");
+    io::stderr().flush()?;
+
+    let mut password = String::new();
+    {
+        let _no_echo = TerminalGuard::no_echo()?;
+        io::stdin()
+            .lock()
+            .read_line(&mut password)
+            .context("Reading password")?;
+    }
+    eprintln!();
+
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
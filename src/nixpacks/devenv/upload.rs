@@ -0,0 +1,79 @@
+use crate::nixpacks::snapshot::app_files;
+use anyhow::{bail, Context, Result};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Bundle an app's files into a gzipped tarball with the local `tar` binary.
+pub fn bundle_app(source: &Path) -> Result<Vec<u8>> {
+    let files = app_files(source)?;
+
+    let mut tar = Command::new("tar")
+        .arg("-czf")
+        .arg("-")
+        .arg("-C")
+        .arg(source)
+        .arg("--null")
+        .arg("-T")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Please install tar to upload the app source")?;
+
+    let mut file_list = Vec::new();
+    for file in &files {
+        file_list.extend_from_slice(file.to_string_lossy().as_bytes());
+        file_list.push(0);
+    }
+    // The list is written while the archive is read, so that tar doesn't block on a full stdout pipe while we block
+    // on a full stdin pipe
+    let mut stdin = tar.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&file_list));
+
+    let output = tar.wait_with_output().context("Bundling app source")?;
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Writing file list to tar"))?
+        .context("Writing file list to tar")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to bundle app source\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_bundle_large_app() {
+        let dir = TempDir::new("nixpacks-bundle").unwrap();
+        // Both the file list and the archive are larger than a pipe buffer
+        for i in 0..2000 {
+            fs::write(
+                dir.path()
+                    .join(format!("a-file-with-a-long-name-{i:04}.txt")),
+                "",
+            )
+            .unwrap();
+        }
+        let mut contents = vec![0; 512 * 1024];
+        rand::thread_rng().fill_bytes(&mut contents);
+        fs::write(dir.path().join("random.bin"), contents).unwrap();
+
+        let archive = bundle_app(dir.path()).unwrap();
+        assert!(archive.len() > 512 * 1024);
+    }
+}
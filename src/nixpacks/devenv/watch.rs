@@ -0,0 +1,89 @@
+use super::ssh::RemoteHost;
+use crate::nixpacks::snapshot::{diff, snapshot, WATCH_INTERVAL};
+use anyhow::{Context, Result};
+use ssh2::{OpenFlags, OpenType, Sftp};
+use std::{fs, io::Write, path::Path, thread};
+
+/// Keep the app directory on the host in sync with the local one until the process is interrupted.
+///
+/// The app is expected to have been uploaded already, so only files changed after this is called are copied.
+pub fn watch(remote: &RemoteHost, source: &Path, workdir: &str) -> Result<()> {
+    let sftp = remote.session().sftp().context("Starting SFTP session")?;
+    let workdir = Path::new(workdir);
+    let mut previous = snapshot(source)?;
+
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+
+        let mut current = snapshot(source)?;
+        let changes = diff(&previous, &current);
+        if changes.is_empty() {
+            continue;
+        }
+
+        for file in &changes.changed {
+            match push_file(&sftp, &source.join(file), &workdir.join(file)) {
+                Ok(()) => println!("Synced {}", file.display()),
+                // Files like editors' temporary files can be gone by the time they're read. Forgetting the file
+                // retries it on the next check if it's still there.
+                Err(e) => {
+                    eprintln!("Failed to sync {}: {e:#}", file.display());
+                    current.remove(file);
+                }
+            }
+        }
+        for file in &changes.removed {
+            // It may never have made it to the host
+            if sftp.unlink(&workdir.join(file)).is_ok() {
+                println!("Removed {}", file.display());
+            }
+        }
+
+        previous = current;
+    }
+}
+
+/// Copy a local file to the host, creating any missing parent directories.
+fn push_file(sftp: &Sftp, local: &Path, remote: &Path) -> Result<()> {
+    let contents = fs::read(local)?;
+    let metadata = fs::metadata(local)?;
+
+    if let Some(parent) = remote.parent() {
+        create_dir_all(sftp, parent)?;
+    }
+
+    let mut file = sftp.open_mode(
+        remote,
+        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+        file_mode(&metadata),
+        OpenType::File,
+    )?;
+    file.write_all(&contents)?;
+
+    Ok(())
+}
+
+fn create_dir_all(sftp: &Sftp, dir: &Path) -> Result<()> {
+    if sftp.stat(dir).is_ok() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        create_dir_all(sftp, parent)?;
+    }
+
+    sftp.mkdir(dir, 0o755)
+        .with_context(|| format!("Creating remote directory {}", dir.display()))
+}
+
+/// The permissions to give the remote copy of a file, keeping it executable if it is locally.
+fn file_mode(metadata: &fs::Metadata) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 != 0 {
+            return 0o755;
+        }
+    }
+
+    0o644
+}
@@ -0,0 +1,303 @@
+use super::{
+    builder::docker::engine::Engine,
+    devenv::{
+        get_listed_hosts,
+        ssh::{RemoteHost, SshTarget},
+        ssh_config::SshConfig,
+        DevenvOptions,
+    },
+    images::DEFAULT_BASE_IMAGE,
+};
+use anyhow::Result;
+use semver::Version;
+use std::{
+    fmt,
+    net::{TcpStream, ToSocketAddrs},
+    process::Command,
+    time::Duration,
+};
+
+/// The oldest Docker that supports every BuildKit feature the generated Dockerfiles use, like cache and secret mounts.
+const MIN_DOCKER_VERSION: Version = Version::new(20, 10, 0);
+
+/// How long to wait for a connection to a host before reporting it unreachable.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where Nix expressions download nixpkgs archives from, and where the installed packages are substituted from.
+const NIX_HOSTS: &[&str] = &["github.com", "cache.nixos.org"];
+
+/// The outcome of a check, from best to worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Passed,
+    /// Builds work, but some options don't.
+    Warning,
+    Failed,
+}
+
+/// A check of the environment Nixpacks builds and provisions in, with how to fix it when it doesn't pass.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn passed<N: Into<String>, D: Into<String>>(name: N, detail: D) -> Check {
+        Check {
+            name: name.into(),
+            status: CheckStatus::Passed,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem<N: Into<String>, D: Into<String>, F: Into<String>>(
+        status: CheckStatus,
+        name: N,
+        detail: D,
+        fix: F,
+    ) -> Check {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self.status {
+            CheckStatus::Passed => "✓",
+            CheckStatus::Warning => "!",
+            CheckStatus::Failed => "✗",
+        };
+        write!(f, "{symbol} {}: {}", self.name, self.detail)?;
+        if let Some(fix) = &self.fix {
+            write!(f, "\n    {fix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of `nixpacks doctor`. The devenv hosts to check are taken from the hostnames, hosts file, and inventory of
+/// `devenv`, and connected to with its SSH options.
+#[derive(Clone, Debug, Default)]
+pub struct DoctorOptions {
+    pub engine: Engine,
+    pub devenv: DevenvOptions,
+}
+
+/// Check the container engine, the network access builds need, and the devenv hosts.
+pub fn run_checks(options: &DoctorOptions) -> Result<Vec<Check>> {
+    let mut checks = vec![check_engine(options.engine)];
+    if options.engine == Engine::Docker {
+        checks.push(check_buildx());
+    }
+
+    for host in NIX_HOSTS {
+        checks.push(check_reachable(host, 443));
+    }
+    checks.push(check_reachable(&image_registry(DEFAULT_BASE_IMAGE), 443));
+
+    let hosts = get_listed_hosts(&options.devenv)?;
+    if !hosts.is_empty() {
+        let ssh_config = SshConfig::load()?;
+        for (host, settings) in hosts {
+            let target = SshTarget::resolve(&host, &settings, &options.devenv, &ssh_config)?;
+            checks.push(check_ssh(&target));
+        }
+    }
+
+    Ok(checks)
+}
+
+fn check_engine(engine: Engine) -> Check {
+    let cli = engine.cli();
+    let name = cli.name();
+    let output = match cli.command().args(cli.version_args()).output() {
+        Ok(output) => output,
+        Err(_) => {
+            return Check::problem(
+                CheckStatus::Failed,
+                name,
+                "not installed",
+                format!("Install it from {}", cli.install_url()),
+            )
+        }
+    };
+
+    if !output.status.success() {
+        let fix = if engine == Engine::Docker {
+            "Start the Docker daemon, and add your user to the docker group if it needs sudo"
+                .to_string()
+        } else {
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        };
+        return Check::problem(CheckStatus::Failed, name, "not working", fix);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match parse_version(&stdout) {
+        Some(version) if engine == Engine::Docker && version < MIN_DOCKER_VERSION => {
+            Check::problem(
+                CheckStatus::Warning,
+                name,
+                format!("version {version}"),
+                format!("Upgrade to Docker {MIN_DOCKER_VERSION} or newer for BuildKit cache and secret mounts"),
+            )
+        }
+        Some(version) => Check::passed(name, format!("version {version}")),
+        None => Check::passed(name, stdout.trim().to_string()),
+    }
+}
+
+fn check_buildx() -> Check {
+    match Command::new("docker").args(["buildx", "version"]).output() {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = parse_version(&stdout)
+                .map_or_else(|| stdout.trim().to_string(), |version| format!("version {version}"));
+            Check::passed("docker buildx", version)
+        }
+        _ => Check::problem(
+            CheckStatus::Warning,
+            "docker buildx",
+            "not installed",
+            "Install the docker-buildx plugin to build for several platforms, or with --output, --memory, or --cpus",
+        ),
+    }
+}
+
+/// Whether a TCP connection can be opened to the host, like builds open to download from it.
+fn check_reachable(host: &str, port: u16) -> Check {
+    let addresses = match (host, port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect::<Vec<_>>(),
+        Err(_) => {
+            return Check::problem(
+                CheckStatus::Failed,
+                host,
+                "can't be resolved",
+                "Check your DNS settings, or the proxy builds go through",
+            )
+        }
+    };
+
+    if addresses
+        .iter()
+        .any(|address| TcpStream::connect_timeout(address, CONNECT_TIMEOUT).is_ok())
+    {
+        Check::passed(host, "reachable")
+    } else {
+        Check::problem(
+            CheckStatus::Failed,
+            host,
+            format!("can't connect on port {port}"),
+            "Check your network and firewall. Builds with --network none need the packages in the base image",
+        )
+    }
+}
+
+fn check_ssh(target: &SshTarget) -> Check {
+    let name = format!("{}@{}:{}", target.user, target.hostname, target.port);
+    match RemoteHost::connect_timeout(target, CONNECT_TIMEOUT) {
+        Ok(_) => Check::passed(name, "connected"),
+        Err(error) => Check::problem(
+            CheckStatus::Failed,
+            name,
+            format!("{error:#}"),
+            "Check the host is up and accepts SSH, and the user, port, and key with --ssh-user, --port, and --identity-file",
+        ),
+    }
+}
+
+/// The registry an image is pulled from, Docker Hub for images without one.
+fn image_registry(image: &str) -> String {
+    match image.split_once('/') {
+        Some((registry, _)) if registry.contains('.') || registry.contains(':') => {
+            registry.split(':').next().unwrap_or(registry).to_string()
+        }
+        _ => "registry-1.docker.io".to_string(),
+    }
+}
+
+/// The first version number in a CLI's version output, e.g. `Docker version 20.10.21, build baeda1f`.
+fn parse_version(output: &str) -> Option<Version> {
+    output.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches('v').trim_end_matches(',');
+        let mut parts = word.split(['.', '-', '+']).map(str::parse::<u64>);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), patch) => Some(Version::new(
+                major,
+                minor,
+                patch.and_then(Result::ok).unwrap_or_default(),
+            )),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("20.10.21\n"), Some(Version::new(20, 10, 21)));
+        assert_eq!(
+            parse_version("github.com/docker/buildx v0.9.1 ed00243"),
+            Some(Version::new(0, 9, 1))
+        );
+        assert_eq!(
+            parse_version("buildah version 1.28.0 (image-spec 1.0.2-dev)"),
+            Some(Version::new(1, 28, 0))
+        );
+        assert_eq!(
+            parse_version("podman version 4.3"),
+            Some(Version::new(4, 3, 0))
+        );
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_image_registry() {
+        assert_eq!(image_registry(DEFAULT_BASE_IMAGE), "ghcr.io");
+        assert_eq!(image_registry("localhost:5000/app"), "localhost");
+        assert_eq!(image_registry("ubuntu:jammy"), "registry-1.docker.io");
+        assert_eq!(image_registry("library/ubuntu"), "registry-1.docker.io");
+    }
+
+    #[test]
+    fn test_check_reachable() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert_eq!(
+            check_reachable("127.0.0.1", port).status,
+            CheckStatus::Passed
+        );
+
+        drop(listener);
+        assert_eq!(
+            check_reachable("127.0.0.1", port).status,
+            CheckStatus::Failed
+        );
+    }
+
+    #[test]
+    fn test_check_display() {
+        let check = Check::problem(
+            CheckStatus::Warning,
+            "docker buildx",
+            "not installed",
+            "Install it",
+        );
+        assert_eq!(
+            check.to_string(),
+            "! docker buildx: not installed\n    Install it"
+        );
+    }
+}
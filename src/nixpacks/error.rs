@@ -0,0 +1,137 @@
+#[cfg(feature = "docker")]
+use super::builder::docker::cancel::BuildCancelled;
+use std::{error::Error, fmt};
+
+/// Why generating a plan or building an image failed, returned by the library's functions so programs embedding
+/// nixpacks can handle each kind of failure.
+///
+/// Inside nixpacks, errors are `anyhow` errors. The ones of a known kind are created as a `NixpacksError` where they
+/// happen, and taken back out of the `anyhow` error, through any context added to it, when it's returned.
+#[derive(Debug)]
+pub enum NixpacksError {
+    /// A provider failed to detect the app or to generate its plan.
+    ProviderDetection {
+        provider: String,
+        source: anyhow::Error,
+    },
+    /// A provider was chosen by a name no provider has.
+    UnknownProvider { provider: String },
+    /// No provider detected the app, so there's nothing to build.
+    NoBuildPlan,
+    /// The config file or plan can't be read or parsed, or the plan can't be built as it is, e.g. without a start
+    /// command.
+    PlanInvalid(anyhow::Error),
+    /// The container engine isn't installed, or can't be run.
+    DockerUnavailable { engine: String, install_url: String },
+    /// The engine failed to build the image. `exit_code` is `None` if it was killed by a signal.
+    BuildFailed {
+        exit_code: Option<i32>,
+        platform: Option<String>,
+    },
+    /// The build was stopped with a `CancelHandle`.
+    Cancelled,
+    /// Anything else, like failing to write the build context or to push the image.
+    Other(anyhow::Error),
+}
+
+impl NixpacksError {
+    pub fn plan_invalid<S: Into<String>>(message: S) -> NixpacksError {
+        NixpacksError::PlanInvalid(anyhow::anyhow!(message.into()))
+    }
+}
+
+impl fmt::Display for NixpacksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NixpacksError::ProviderDetection { provider, .. } => {
+                write!(f, "Provider {provider} failed")
+            }
+            NixpacksError::UnknownProvider { provider } => {
+                write!(f, "Provider {provider} not found")
+            }
+            NixpacksError::NoBuildPlan => {
+                f.write_str("Nixpacks was unable to generate a build plan for this app")
+            }
+            NixpacksError::PlanInvalid(error) | NixpacksError::Other(error) => {
+                write!(f, "{error}")
+            }
+            NixpacksError::DockerUnavailable {
+                engine,
+                install_url,
+            } => write!(f, "Please install {engine} to build the app {install_url}"),
+            NixpacksError::BuildFailed {
+                platform: Some(platform),
+                ..
+            } => write!(f, "Docker build for {platform} failed"),
+            NixpacksError::BuildFailed { platform: None, .. } => f.write_str("Docker build failed"),
+            NixpacksError::Cancelled => f.write_str("The build was cancelled"),
+        }
+    }
+}
+
+impl Error for NixpacksError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NixpacksError::ProviderDetection { source, .. } => Some(source.as_ref()),
+            // Displayed as the error itself, so its causes come next
+            NixpacksError::PlanInvalid(error) | NixpacksError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for NixpacksError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<NixpacksError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        #[cfg(feature = "docker")]
+        if error.is::<BuildCancelled>() {
+            return NixpacksError::Cancelled;
+        }
+        NixpacksError::Other(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow_through_context() {
+        let error = Err::<(), _>(NixpacksError::BuildFailed {
+            exit_code: Some(1),
+            platform: None,
+        })
+        .context("Building image")
+        .unwrap_err();
+
+        assert!(matches!(
+            NixpacksError::from(error),
+            NixpacksError::BuildFailed {
+                exit_code: Some(1),
+                platform: None
+            }
+        ));
+    }
+
+    #[cfg(feature = "docker")]
+    #[test]
+    fn test_from_anyhow_cancelled() {
+        let error = anyhow::Error::from(BuildCancelled).context("Pushing image");
+        assert!(matches!(
+            NixpacksError::from(error),
+            NixpacksError::Cancelled
+        ));
+    }
+
+    #[test]
+    fn test_from_anyhow_other() {
+        let error = NixpacksError::from(anyhow::anyhow!("Disk full").context("Writing app"));
+        assert!(matches!(error, NixpacksError::Other(_)));
+        assert_eq!(error.to_string(), "Writing app");
+        assert_eq!(error.source().unwrap().to_string(), "Disk full");
+    }
+}
@@ -3,3 +3,6 @@ pub const UBUNTU_BASE_IMAGE: &str = "ghcr.io/railwayapp/nixpacks:ubuntu-16896386
 pub const DEFAULT_BASE_IMAGE: &str = UBUNTU_BASE_IMAGE;
 
 pub const STANDALONE_IMAGE: &str = "ubuntu:jammy";
+
+/// An empty image, for statically linked apps that need nothing but their own files.
+pub const SCRATCH_IMAGE: &str = "scratch";
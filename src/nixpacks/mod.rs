@@ -1,12 +1,28 @@
 pub mod app;
+pub mod app_source;
+#[cfg(feature = "docker")]
 pub mod builder;
+pub mod buildpacks;
+pub mod cache_key;
+pub mod compose;
+#[cfg(feature = "devenv")]
+pub mod devenv;
+#[cfg(feature = "docker")]
+pub mod doctor;
 pub mod environment;
+pub mod error;
+#[cfg(feature = "docker")]
 mod files;
+#[cfg(feature = "docker")]
+pub mod git;
 pub mod images;
 pub mod logger;
 pub mod nix;
 pub mod plan;
+pub mod snapshot;
 #[macro_use]
 pub mod static_assets;
+pub mod tool_versions;
+pub mod utils;
 
 pub const NIX_PACKS_VERSION: &str = env!("CARGO_PKG_VERSION");
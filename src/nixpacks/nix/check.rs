@@ -0,0 +1,225 @@
+use super::{group_nix_packages_by_archive, NIXPKGS_ARCHIVE};
+use crate::nixpacks::plan::BuildPlan;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// The binary cache every build can substitute packages from.
+pub const DEFAULT_SUBSTITUTER: &str = "https://cache.nixos.org";
+
+/// Whether a Nix package of the plan can be installed, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackageStatus {
+    /// nixpkgs has no package of this name.
+    Missing,
+    /// The package can't be evaluated, e.g. because it's marked broken or insecure.
+    Broken,
+    /// No binary cache has the package, so it's built from source during the build.
+    NotCached,
+    Cached,
+}
+
+/// The status of a Nix package in the nixpkgs revision a phase installs it from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageCheck {
+    pub name: String,
+    pub archive: String,
+    pub status: PackageStatus,
+}
+
+/// Resolve every Nix package of the plan with the host's `nix`, and look its store path up in the binary caches,
+/// each revision and package at the same time. Packages given as expressions, like overrides, are left out.
+pub fn check_packages(plan: &BuildPlan) -> Result<Vec<PackageCheck>> {
+    let nix_installed = Command::new("nix")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |status| status.success());
+    if !nix_installed {
+        bail!("Checking the Nix packages needs Nix installed: https://nixos.org/download");
+    }
+
+    let substituters = [
+        vec![DEFAULT_SUBSTITUTER.to_string()],
+        plan.nix
+            .as_ref()
+            .and_then(|nix| nix.substituters.clone())
+            .unwrap_or_default(),
+    ]
+    .concat();
+
+    let phases = plan
+        .phases
+        .clone()
+        .unwrap_or_default()
+        .into_values()
+        .collect::<Vec<_>>();
+    let evaluations = group_nix_packages_by_archive(&phases)
+        .into_iter()
+        .map(|group| {
+            let archive = group
+                .archive
+                .clone()
+                .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+            let names = get_attr_path_names(&[group.pkgs, group.libs].concat());
+            let expression = get_check_expression(&archive, &group.overlays, &names);
+            thread::spawn(move || -> Result<Vec<(String, String, Value)>> {
+                let output = nix_command()
+                    .args(["eval", "--json", "--impure", "--expr", &expression])
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .context("Evaluating the Nix packages")?;
+                if !output.status.success() {
+                    bail!("Failed to evaluate the Nix packages from nixpkgs {archive}");
+                }
+                let paths: BTreeMap<String, Value> = serde_json::from_slice(&output.stdout)?;
+                Ok(paths
+                    .into_iter()
+                    .map(|(name, path)| (archive.clone(), name, path))
+                    .collect())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut evaluated = Vec::new();
+    for evaluation in evaluations {
+        let mut paths = evaluation
+            .join()
+            .map_err(|_| anyhow::anyhow!("Evaluating the Nix packages panicked"))??;
+        evaluated.append(&mut paths);
+    }
+
+    let lookups = evaluated
+        .into_iter()
+        .map(|(archive, name, path)| {
+            let substituters = substituters.clone();
+            thread::spawn(move || {
+                let status = match path {
+                    Value::String(path) => {
+                        if substituters.iter().any(|store| is_cached(store, &path)) {
+                            PackageStatus::Cached
+                        } else {
+                            PackageStatus::NotCached
+                        }
+                    }
+                    Value::Bool(false) => PackageStatus::Broken,
+                    _ => PackageStatus::Missing,
+                };
+                PackageCheck {
+                    name,
+                    archive,
+                    status,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    lookups
+        .into_iter()
+        .map(|lookup| {
+            lookup
+                .join()
+                .map_err(|_| anyhow::anyhow!("Looking up the Nix packages panicked"))
+        })
+        .collect()
+}
+
+/// The packages named by an attribute path, like `nodejs` or `python3Packages.pip`, which can be checked without
+/// evaluating arbitrary expressions.
+fn get_attr_path_names(pkgs: &[String]) -> Vec<String> {
+    let re_attr_path =
+        Regex::new(r"^[A-Za-z_][\w'-]*(\.[A-Za-z_][\w'-]*)*$").expect("BUG: Broken regex");
+    let mut names = pkgs
+        .iter()
+        .filter(|pkg| re_attr_path.is_match(pkg))
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// An expression evaluating to the store path of each package, `false` if it can't be evaluated, or `null` if
+/// nixpkgs doesn't have it.
+fn get_check_expression(archive: &str, overlays: &[String], names: &[String]) -> String {
+    let overlays = overlays
+        .iter()
+        .map(|url| format!("(import (builtins.fetchTarball \"{url}\"))"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let checks = names
+        .iter()
+        .map(|name| {
+            let path = name
+                .split('.')
+                .map(|attr| format!("\"{attr}\""))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("\"{name}\" = check [ {path} ];")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "let pkgs = import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/{archive}.tar.gz\") {{ overlays = [ {overlays} ]; }}; \
+         check = path: if pkgs.lib.hasAttrByPath path pkgs then \
+         (let result = builtins.tryEval (pkgs.lib.getAttrFromPath path pkgs).outPath; in if result.success then result.value else false) \
+         else null; \
+         in {{ {checks} }}"
+    )
+}
+
+/// Whether the binary cache has the store path.
+fn is_cached(store: &str, path: &str) -> bool {
+    nix_command()
+        .args(["path-info", "--store", store, path])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |status| status.success())
+}
+
+fn nix_command() -> Command {
+    let mut cmd = Command::new("nix");
+    cmd.args(["--extra-experimental-features", "nix-command"]);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attr_path_names() {
+        assert_eq!(
+            get_attr_path_names(&[
+                "nodejs-18_x".to_string(),
+                "python3Packages.pip".to_string(),
+                "(php.withExtensions (pe: pe.enabled))".to_string(),
+                "nodejs-18_x".to_string(),
+            ]),
+            vec!["nodejs-18_x".to_string(), "python3Packages.pip".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_check_expression() {
+        let expression = get_check_expression(
+            "abc123",
+            &["https://example.com/overlay.tar.gz".to_string()],
+            &["python3Packages.pip".to_string()],
+        );
+
+        assert!(expression.starts_with(
+            "let pkgs = import (fetchTarball \"https://github.com/NixOS/nixpkgs/archive/abc123.tar.gz\") { overlays = [ (import (builtins.fetchTarball \"https://example.com/overlay.tar.gz\")) ]; };"
+        ));
+        assert!(expression
+            .ends_with("in { \"python3Packages.pip\" = check [ \"python3Packages\" \"pip\" ]; }"));
+    }
+}
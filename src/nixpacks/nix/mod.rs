@@ -1,8 +1,11 @@
+use anyhow::{bail, Result};
 use indoc::formatdoc;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
 use crate::nixpacks::plan::phase::{Phase, Phases};
 
+pub mod check;
 pub mod pkg;
 
 // This line is automatically updated.
@@ -13,6 +16,56 @@ pub const NIXPKGS_ARCHIVE: &str = "293a28df6d7ff3dec1e61e37cc4ee6e6c0fb0847";
 // Version of the Nix archive that uses OpenSSL 1.1
 pub const NIXPACKS_ARCHIVE_LEGACY_OPENSSL: &str = "a0b7e70db7a55088d3de0cc370a59f9fbcc906c3";
 
+/// The nixpkgs revision a project pins its packages to, given as `github:NixOS/nixpkgs/<rev>`, the URL of the
+/// revision's tarball, or just the revision, e.g. a commit or `nixos-24.11`.
+pub fn parse_nixpkgs_revision(nixpkgs: &str) -> Result<String> {
+    let revision = nixpkgs.trim();
+    let revision = ["github:NixOS/nixpkgs/", "github:nixos/nixpkgs/"]
+        .iter()
+        .find_map(|prefix| revision.strip_prefix(prefix))
+        .or_else(|| {
+            revision
+                .strip_prefix("https://github.com/NixOS/nixpkgs/archive/")
+                .map(|archive| archive.trim_end_matches(".tar.gz"))
+        })
+        .unwrap_or(revision);
+
+    if revision.is_empty() || revision.contains(':') || revision.contains('/') {
+        bail!("Can't pin nixpkgs to {nixpkgs}, use a revision of NixOS/nixpkgs like github:NixOS/nixpkgs/<rev>");
+    }
+    Ok(revision.to_string())
+}
+
+/// Binary caches Nix substitutes packages from besides cache.nixos.org, like a team's Cachix cache, so they don't
+/// have to be built.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NixConfig {
+    /// URLs of the caches, e.g. `https://my-team.cachix.org`.
+    pub substituters: Option<Vec<String>>,
+    /// The keys the caches sign packages with, e.g. `my-team.cachix.org-1:...`.
+    pub trusted_public_keys: Option<Vec<String>>,
+}
+
+impl NixConfig {
+    /// The lines of `nix.conf` adding the caches, if there are any.
+    pub fn get_nix_conf_lines(&self) -> Vec<String> {
+        [
+            ("extra-substituters", &self.substituters),
+            ("extra-trusted-public-keys", &self.trusted_public_keys),
+        ]
+        .iter()
+        .filter_map(|(setting, values)| {
+            values
+                .as_ref()
+                .filter(|values| !values.is_empty())
+                .map(|values| format!("{setting} = {}", values.join(" ")))
+        })
+        .collect()
+    }
+}
+
 /// Contains all the data needed to generate a Nix expression file for installing Nix dependencies.
 #[derive(Eq, PartialEq, Default, Debug, Clone)]
 struct NixGroup {
@@ -185,6 +238,23 @@ fn nix_expression_for_group(group: &NixGroup) -> String {
 mod tests {
     use super::{pkg::Pkg, *};
 
+    #[test]
+    fn test_parse_nixpkgs_revision() {
+        for nixpkgs in [
+            "github:NixOS/nixpkgs/abc123",
+            "https://github.com/NixOS/nixpkgs/archive/abc123.tar.gz",
+            "abc123",
+        ] {
+            assert_eq!(parse_nixpkgs_revision(nixpkgs).unwrap(), "abc123");
+        }
+        assert_eq!(
+            parse_nixpkgs_revision("github:NixOS/nixpkgs/nixos-24.11").unwrap(),
+            "nixos-24.11"
+        );
+        assert!(parse_nixpkgs_revision("github:my-org/nixpkgs/abc123").is_err());
+        assert!(parse_nixpkgs_revision("").is_err());
+    }
+
     #[test]
     fn test_group_nix_packages_by_archive() {
         let mut setup1 = Phase::setup(Some(vec![Pkg::new("foo"), Pkg::new("bar")]));
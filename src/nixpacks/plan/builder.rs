@@ -0,0 +1,254 @@
+use super::{
+    phase::{Phase, StartPhase},
+    validate::validate_build_plan,
+    BuildPlan,
+};
+use crate::nixpacks::{environment::EnvironmentVariables, error::NixpacksError, nix::pkg::Pkg};
+use anyhow::Result;
+
+/// Builds a [`BuildPlan`] one call at a time, checking the dependencies between its phases in [`build`].
+///
+/// ```
+/// use nixpacks::nixpacks::{nix::pkg::Pkg, plan::{phase::Phase, BuildPlan}};
+///
+/// let plan = BuildPlan::builder()
+///     .phase(Phase::builder("setup").nix_pkgs(&[Pkg::new("nodejs")]))
+///     .phase(
+///         Phase::builder("install")
+///             .depends_on("setup")
+///             .cmd("npm ci")
+///             .cache_directory("/root/.npm"),
+///     )
+///     .start_cmd("npm start")
+///     .variable("NODE_ENV", "production")
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// [`build`]: BuildPlanBuilder::build
+#[derive(Clone, Debug, Default)]
+pub struct BuildPlanBuilder {
+    plan: BuildPlan,
+}
+
+impl BuildPlanBuilder {
+    /// Add a phase, replacing any phase with the same name.
+    #[must_use]
+    pub fn phase<P: Into<Phase>>(mut self, phase: P) -> Self {
+        self.plan.add_phase(phase.into());
+        self
+    }
+
+    /// Start the app with this start phase.
+    #[must_use]
+    pub fn start(mut self, start: StartPhase) -> Self {
+        self.plan.set_start_phase(start);
+        self
+    }
+
+    /// Start the app with this command, keeping the rest of the start phase.
+    #[must_use]
+    pub fn start_cmd<S: Into<String>>(mut self, cmd: S) -> Self {
+        self.plan
+            .start_phase
+            .get_or_insert_with(StartPhase::default)
+            .cmd = Some(cmd.into());
+        self
+    }
+
+    /// Set an environment variable in the image, replacing any with the same name.
+    #[must_use]
+    pub fn variable<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.plan
+            .add_variables(EnvironmentVariables::from([(name.into(), value.into())]));
+        self
+    }
+
+    /// Build on this image instead of the Nixpacks base image.
+    #[must_use]
+    pub fn build_image<S: Into<String>>(mut self, image: S) -> Self {
+        self.plan.build_image = Some(image.into());
+        self
+    }
+
+    /// The plan, or an error listing the dependencies on phases that don't exist and the circular dependencies.
+    /// Like in config files, the `setup`, `install`, and `build` phases can be depended on without being added,
+    /// since providers generate them when the plan is merged with theirs.
+    pub fn build(self) -> Result<BuildPlan> {
+        let errors = validate_build_plan(&self.plan)?;
+        if !errors.is_empty() {
+            return Err(NixpacksError::plan_invalid(format!(
+                "Invalid build plan:\n{}",
+                errors
+                    .iter()
+                    .map(|error| format!("  {error}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))
+            .into());
+        }
+
+        Ok(self.plan)
+    }
+}
+
+/// Builds a [`Phase`] one call at a time, for [`BuildPlanBuilder::phase`].
+#[derive(Clone, Debug)]
+pub struct PhaseBuilder {
+    phase: Phase,
+}
+
+impl PhaseBuilder {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            phase: Phase::new(name),
+        }
+    }
+
+    /// Run the phase after another one.
+    #[must_use]
+    pub fn depends_on<S: Into<String>>(mut self, name: S) -> Self {
+        self.phase.depends_on_phase(name);
+        self
+    }
+
+    /// Install these packages with Nix.
+    #[must_use]
+    pub fn nix_pkgs(mut self, pkgs: &[Pkg]) -> Self {
+        self.phase.add_nix_pkgs(pkgs);
+        self
+    }
+
+    /// Install these libraries with Nix.
+    #[must_use]
+    pub fn nix_libs<I: IntoIterator<Item = S>, S: Into<String>>(mut self, libs: I) -> Self {
+        self.phase
+            .add_pkgs_libs(libs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Install these packages with apt.
+    #[must_use]
+    pub fn apt_pkgs<I: IntoIterator<Item = S>, S: Into<String>>(mut self, pkgs: I) -> Self {
+        self.phase
+            .add_apt_pkgs(pkgs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Run this command, after the ones added before it.
+    #[must_use]
+    pub fn cmd<S: Into<String>>(mut self, cmd: S) -> Self {
+        self.phase.add_cmd(cmd);
+        self
+    }
+
+    /// Only copy this file or directory of the app into the image before running the commands, instead of all of it.
+    #[must_use]
+    pub fn include_file<S: Into<String>>(mut self, file: S) -> Self {
+        self.phase.add_file_dependency(file);
+        self
+    }
+
+    /// Keep this directory between builds, without leaving it in the image.
+    #[must_use]
+    pub fn cache_directory<S: Into<String>>(mut self, dir: S) -> Self {
+        self.phase.add_cache_directory(dir);
+        self
+    }
+
+    /// Keep this directory between builds with the incremental cache, and leave it in the image.
+    #[must_use]
+    pub fn persisted_directory<S: Into<String>>(mut self, dir: S) -> Self {
+        self.phase.add_persisted_directory(dir);
+        self
+    }
+
+    /// Add this directory to the `PATH` of the later phases and the app.
+    #[must_use]
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.phase.add_path(path.into());
+        self
+    }
+
+    pub fn build(self) -> Phase {
+        self.phase
+    }
+}
+
+impl From<PhaseBuilder> for Phase {
+    fn from(builder: PhaseBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_plan() {
+        let plan = BuildPlan::builder()
+            .phase(Phase::setup(Some(vec![Pkg::new("nodejs")])))
+            .phase(
+                Phase::builder("install")
+                    .depends_on("setup")
+                    .cmd("npm ci")
+                    .include_file("package-lock.json")
+                    .cache_directory("/root/.npm"),
+            )
+            .start_cmd("npm start")
+            .variable("NODE_ENV", "production")
+            .build()
+            .unwrap();
+
+        let expected = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            nixOverlays = []
+
+            [phases.install]
+            dependsOn = ["setup"]
+            cmds = ["npm ci"]
+            onlyIncludeFiles = ["package-lock.json"]
+            cacheDirectories = ["/root/.npm"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(plan, expected);
+    }
+
+    #[test]
+    fn test_build_plan_with_provider_phases() {
+        // Merged with the plan of the providers, which have a build phase
+        let plan = BuildPlan::builder()
+            .phase(
+                Phase::builder("migrate")
+                    .depends_on("build")
+                    .cmd("rake db:migrate"),
+            )
+            .build();
+        assert!(plan.is_ok());
+    }
+
+    #[test]
+    fn test_build_plan_errors() {
+        let error = BuildPlan::builder()
+            .phase(Phase::builder("a").depends_on("b"))
+            .phase(Phase::builder("b").depends_on("a"))
+            .phase(Phase::builder("c").depends_on("missing"))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid build plan:\n  phases.c.dependsOn: Unknown phase `missing`\n  phases.a.dependsOn: Circular dependency a -> b -> a"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+/// A provider used to build an app, and what it found in the app.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize)]
+pub struct ProviderDetection {
+    pub name: String,
+    /// The files, relative to the app, that the provider detected it by.
+    pub files: Vec<String>,
+    /// The version of the language or framework the provider resolved, if it says.
+    pub version: Option<String>,
+    /// The phases the provider adds to the build plan, in the order they run.
+    pub phases: Vec<String>,
+}
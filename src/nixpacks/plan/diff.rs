@@ -0,0 +1,209 @@
+use super::BuildPlan;
+use anyhow::Result;
+use colored::Colorize;
+use serde_json::{Map, Value};
+use std::{collections::BTreeSet, fmt::Write};
+
+/// A difference between two build plans at a dotted path, such as `phases.setup.nixPkgs`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PlanChange {
+    pub path: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+impl BuildPlan {
+    /// The changes from this plan to another, with lists such as packages and commands compared item by item.
+    pub fn diff(&self, other: &BuildPlan) -> Result<Vec<PlanChange>> {
+        let mut changes = Vec::new();
+        diff_values(
+            "",
+            &plan_to_value(self)?,
+            &plan_to_value(other)?,
+            &mut changes,
+        );
+        Ok(changes)
+    }
+}
+
+/// The colored, diff-like listing of plan changes, emitted by `nixpacks plan diff`.
+pub fn get_diff_string(changes: &[PlanChange]) -> String {
+    if changes.is_empty() {
+        return "No differences".to_string();
+    }
+
+    let mut output = String::new();
+    for change in changes {
+        writeln!(output, "{}", change.path.bold()).unwrap();
+        for value in &change.removed {
+            writeln!(output, "  {}", format!("- {value}").red()).unwrap();
+        }
+        for value in &change.added {
+            writeln!(output, "  {}", format!("+ {value}").green()).unwrap();
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+fn plan_to_value(plan: &BuildPlan) -> Result<Value> {
+    let mut plan = plan.clone();
+    plan.remove_phase_names();
+    Ok(serde_json::to_value(plan)?)
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<PlanChange>) {
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (Value::Object(_) | Value::Null, Value::Object(_) | Value::Null) => {
+            let empty = Map::new();
+            let old = old.as_object().unwrap_or(&empty);
+            let new = new.as_object().unwrap_or(&empty);
+
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_values(
+                    &path,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (Value::Array(_) | Value::Null, Value::Array(_) | Value::Null) => {
+            let old = value_items(old);
+            let new = value_items(new);
+            if old == new {
+                return;
+            }
+
+            let removed = old
+                .iter()
+                .filter(|item| !new.contains(item))
+                .cloned()
+                .collect::<Vec<_>>();
+            let added = new
+                .iter()
+                .filter(|item| !old.contains(item))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            // Same items in a different order, which matters for commands
+            let (removed, added) = if removed.is_empty() && added.is_empty() {
+                (old, new)
+            } else {
+                (removed, added)
+            };
+
+            changes.push(PlanChange {
+                path: path.to_string(),
+                removed,
+                added,
+            });
+        }
+        _ => changes.push(PlanChange {
+            path: path.to_string(),
+            removed: value_items(old),
+            added: value_items(new),
+        }),
+    }
+}
+
+/// The items of a list, or the value itself, as strings.
+fn value_items(value: &Value) -> Vec<String> {
+    match value {
+        Value::Null => Vec::new(),
+        Value::Array(items) => items.iter().flat_map(value_items).collect(),
+        Value::String(s) => vec![s.clone()],
+        _ => vec![value.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        nix::pkg::Pkg,
+        plan::phase::{Phase, StartPhase},
+    };
+
+    fn node_plan(node: &str) -> BuildPlan {
+        let mut install = Phase::install(Some("npm ci".to_string()));
+        install.add_cmd("npm run postinstall");
+        BuildPlan::new(
+            &[Phase::setup(Some(vec![Pkg::new(node)])), install],
+            Some(StartPhase::new("npm run start")),
+        )
+    }
+
+    #[test]
+    fn test_diff_identical_plans() {
+        let changes = node_plan("nodejs-16_x")
+            .diff(&node_plan("nodejs-16_x"))
+            .unwrap();
+        assert!(changes.is_empty());
+        assert_eq!(get_diff_string(&changes), "No differences");
+    }
+
+    #[test]
+    fn test_diff_packages() {
+        let changes = node_plan("nodejs-16_x")
+            .diff(&node_plan("nodejs-18_x"))
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![PlanChange {
+                path: "phases.setup.nixPkgs".to_string(),
+                removed: vec!["nodejs-16_x".to_string()],
+                added: vec!["nodejs-18_x".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_added_phase_and_start() {
+        let old = node_plan("nodejs-16_x");
+        let mut new = node_plan("nodejs-16_x");
+        new.add_phase(Phase::build(Some("npm run build".to_string())));
+        new.set_start_phase(StartPhase::new("node dist/index.js"));
+
+        let changes = old.diff(&new).unwrap();
+        let paths = changes
+            .iter()
+            .map(|change| change.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["phases.build.cmds", "phases.build.dependsOn", "start.cmd"]
+        );
+        assert_eq!(changes[2].removed, vec!["npm run start".to_string()]);
+        assert_eq!(changes[2].added, vec!["node dist/index.js".to_string()]);
+    }
+
+    #[test]
+    fn test_diff_reordered_commands() {
+        let old = node_plan("nodejs-16_x");
+        let mut new = node_plan("nodejs-16_x");
+        new.get_phase_mut("install").unwrap().cmds = Some(vec![
+            "npm run postinstall".to_string(),
+            "npm ci".to_string(),
+        ]);
+
+        // An empty list is the same as no list
+        new.get_phase_mut("setup").unwrap().apt_pkgs = Some(Vec::new());
+
+        let changes = old.diff(&new).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "phases.install.cmds");
+        assert_eq!(changes[0].removed, vec!["npm ci", "npm run postinstall"]);
+        assert_eq!(changes[0].added, vec!["npm run postinstall", "npm ci"]);
+    }
+}
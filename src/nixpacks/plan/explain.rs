@@ -0,0 +1,215 @@
+use super::BuildPlan;
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+/// Where a package, variable, or command in a build plan comes from.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize)]
+pub struct PlanExplanation {
+    /// Dotted path to the item in the plan, e.g. `phases.setup.nixPkgs`.
+    pub path: String,
+    pub value: String,
+    /// The provider, config file, environment, or CLI that set the item, or `nixpacks` for defaults.
+    pub source: String,
+    /// Why the source chose the item, if it says.
+    pub reason: Option<String>,
+}
+
+/// One of the plans merged into a build plan, along with where it comes from.
+pub struct PlanLayer {
+    pub source: String,
+    pub plan: BuildPlan,
+    /// Why the source chose items in its plan, keyed by the item.
+    pub reasons: BTreeMap<String, String>,
+}
+
+impl PlanLayer {
+    pub fn new<S: Into<String>>(source: S, plan: BuildPlan) -> Self {
+        Self {
+            source: source.into(),
+            plan,
+            reasons: BTreeMap::new(),
+        }
+    }
+}
+
+impl BuildPlan {
+    /// The path and value of every item in this plan, with each item of a list on its own.
+    pub fn get_items(&self) -> Result<Vec<(String, String)>> {
+        let mut plan = self.clone();
+        plan.remove_phase_names();
+
+        let mut items = Vec::new();
+        collect_items("", &serde_json::to_value(plan)?, &mut items);
+        Ok(items)
+    }
+
+    /// Explain each item of this plan with the last of the `layers` it's in, since later layers override earlier ones.
+    pub fn explain(&self, layers: &[PlanLayer]) -> Result<Vec<PlanExplanation>> {
+        let layer_items = layers
+            .iter()
+            .map(|layer| Ok(layer.plan.get_items()?.into_iter().collect::<HashSet<_>>()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let explanations = self
+            .get_items()?
+            .into_iter()
+            .map(|(path, value)| {
+                let layer = layers
+                    .iter()
+                    .zip(&layer_items)
+                    .rev()
+                    .find(|(_, items)| items.contains(&(path.clone(), value.clone())))
+                    .map(|(layer, _)| layer);
+
+                let (source, reason) = match layer {
+                    Some(layer) => (layer.source.clone(), layer.reasons.get(&value).cloned()),
+                    None => ("nixpacks".to_string(), get_default_reason(&path)),
+                };
+
+                PlanExplanation {
+                    path,
+                    value,
+                    source,
+                    reason,
+                }
+            })
+            .collect();
+
+        Ok(explanations)
+    }
+}
+
+/// The explanations grouped by where they are in the plan, emitted by `nixpacks plan explain`.
+pub fn get_explain_string(explanations: &[PlanExplanation]) -> String {
+    let mut output = String::new();
+    let mut last_path = "";
+
+    for explanation in explanations {
+        if explanation.path != last_path {
+            writeln!(output, "{}", explanation.path.bold()).unwrap();
+            last_path = &explanation.path;
+        }
+
+        let source = match &explanation.reason {
+            Some(reason) => format!("{}: {reason}", explanation.source),
+            None => explanation.source.clone(),
+        };
+        writeln!(
+            output,
+            "  {}  {}",
+            explanation.value,
+            format!("({source})").dimmed()
+        )
+        .unwrap();
+    }
+
+    output.trim_end().to_string()
+}
+
+fn collect_items(path: &str, value: &Value, items: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                collect_items(&path, field, items);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_items(path, value, items);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) => items.push((path.to_string(), s.clone())),
+        _ => items.push((path.to_string(), value.to_string())),
+    }
+}
+
+/// Why Nixpacks itself adds an item to every plan.
+fn get_default_reason(path: &str) -> Option<String> {
+    let reason = if path == "buildImage" {
+        "default build image"
+    } else if path.ends_with(".nixpkgsArchive") {
+        "nixpkgs revision pinned by this version of Nixpacks"
+    } else if path == "variables.NIXPACKS_METADATA" {
+        "providers used for the build"
+    } else {
+        return None;
+    };
+
+    Some(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        nix::pkg::Pkg,
+        plan::phase::{Phase, StartPhase},
+    };
+
+    #[test]
+    fn test_explain_plan() {
+        let provider_plan = BuildPlan::new(
+            &[
+                Phase::setup(Some(vec![Pkg::new("nodejs-18_x")])),
+                Phase::install(Some("npm ci".to_string())),
+            ],
+            Some(StartPhase::new("npm run start")),
+        );
+        let file_plan = BuildPlan::new(&[], Some(StartPhase::new("node server.js")));
+
+        let mut plan = BuildPlan::merge_plans(&[provider_plan.clone(), file_plan.clone()]);
+        plan.get_phase_mut("setup")
+            .unwrap()
+            .set_nix_archive("abc".to_string());
+
+        let layers = vec![
+            PlanLayer {
+                source: "node".to_string(),
+                plan: provider_plan,
+                reasons: BTreeMap::from([("nodejs-18_x".to_string(), ".nvmrc".to_string())]),
+            },
+            PlanLayer::new("nixpacks.toml", file_plan),
+        ];
+
+        let explanations = plan.explain(&layers).unwrap();
+        let find = |path: &str| {
+            explanations
+                .iter()
+                .find(|explanation| explanation.path == path)
+                .unwrap()
+                .clone()
+        };
+
+        assert_eq!(
+            find("phases.setup.nixPkgs"),
+            PlanExplanation {
+                path: "phases.setup.nixPkgs".to_string(),
+                value: "nodejs-18_x".to_string(),
+                source: "node".to_string(),
+                reason: Some(".nvmrc".to_string()),
+            }
+        );
+        assert_eq!(find("phases.install.cmds").source, "node");
+        assert_eq!(find("phases.install.cmds").reason, None);
+        assert_eq!(find("start.cmd").source, "nixpacks.toml");
+        assert_eq!(find("start.cmd").value, "node server.js");
+        assert_eq!(find("phases.setup.nixpkgsArchive").source, "nixpacks");
+
+        let output = get_explain_string(&explanations);
+        assert!(output.contains("nodejs-18_x"));
+        assert!(output.contains("node: .nvmrc"));
+    }
+}
@@ -3,17 +3,27 @@ use std::path::Path;
 use crate::{
     nixpacks::{
         app::App,
+        buildpacks::get_buildpacks_plan,
         environment::{Environment, EnvironmentVariables},
-        plan::{BuildPlan, PlanGenerator},
+        error::NixpacksError,
+        plan::{phase::Phase, BuildPlan, PlanGenerator, APP_DIR, DISABLED_PROVIDER_PREFIX},
+        tool_versions::get_tool_version_variables,
+        NIX_PACKS_VERSION,
+    },
+    providers::{
+        devcontainer::DevcontainerProvider, node::nx::Nx, procfile::ProcfileProvider, Provider,
     },
-    providers::{procfile::ProcfileProvider, Provider},
 };
 use anyhow::{bail, Context, Ok, Result};
 use colored::Colorize;
+use path_slash::PathExt;
 
 use super::{
+    detect::ProviderDetection,
+    explain::{PlanExplanation, PlanLayer},
     merge::Mergeable,
     utils::{fill_auto_in_vec, remove_autos_from_vec},
+    validate::validate_plan,
 };
 
 const NIXPACKS_METADATA: &str = "NIXPACKS_METADATA";
@@ -23,6 +33,10 @@ const NIXPACKS_METADATA: &str = "NIXPACKS_METADATA";
 pub struct GeneratePlanOptions {
     pub plan: Option<BuildPlan>,
     pub config_file: Option<String>,
+    /// A complete plan, e.g. from `nixpacks plan`, to use instead of detecting one with providers and config files.
+    pub exact_plan: Option<BuildPlan>,
+    /// Subdirectory of the source to detect the app in, e.g. `apps/api`, while the whole source is the build context.
+    pub app_dir: Option<String>,
 }
 
 /// Holds plan options and providers for a build.
@@ -34,6 +48,16 @@ pub struct NixpacksBuildPlanGenerator<'a> {
 /// NixpacksBuildPlanGenerators produce build plans using the options and providers they contain.
 impl<'a> PlanGenerator for NixpacksBuildPlanGenerator<'a> {
     fn generate_plan(&mut self, app: &App, environment: &Environment) -> Result<(BuildPlan, App)> {
+        if self.config.app_dir.is_some() && self.config.exact_plan.is_none() {
+            // Build the whole source, running the plan of the app from its directory
+            let (app_in_dir, environment) = self.get_app_in_dir(app, environment)?;
+            let (mut plan, app_in_dir) = self.get_build_plan(&app_in_dir, &environment)?;
+            let dir = app.strip_source_path(&app_in_dir.source)?;
+            plan.move_to_subdirectory(&dir.to_slash_lossy());
+
+            return Ok((plan, app.clone()));
+        }
+
         // If the provider defines a build plan in the new format, use that
         let plan = self.get_build_plan(app, environment)?;
 
@@ -42,6 +66,7 @@ impl<'a> PlanGenerator for NixpacksBuildPlanGenerator<'a> {
 
     /// Combine detected providers with providers specified in config files, environment variables, and CLI arguments.
     fn get_plan_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+        let (app, env) = &self.get_app_in_dir(app, env)?;
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
         let providers = self.get_all_providers(app, env, plan_before_providers.providers)?;
 
@@ -57,65 +82,225 @@ impl NixpacksBuildPlanGenerator<'_> {
         NixpacksBuildPlanGenerator { providers, config }
     }
 
+    /// The app in the `app_dir` subdirectory of the source and its environment, or the whole source if there isn't one.
+    fn get_app_in_dir(&self, app: &App, env: &Environment) -> Result<(App, Environment)> {
+        let app_dir = match &self.config.app_dir {
+            Some(app_dir) => app_dir,
+            None => {
+                return Ok((
+                    app.clone(),
+                    Environment::new(Environment::clone_variables(env)),
+                ))
+            }
+        };
+
+        // The Node provider builds Nx projects from the workspace root, by name
+        if let Some(name) = Nx::get_project_name(app, app_dir) {
+            let env = Environment::append_variables(
+                env,
+                EnvironmentVariables::from([("NIXPACKS_NX_APP_NAME".to_string(), name)]),
+            );
+            return Ok((app.clone(), env));
+        }
+
+        let app_in_dir = app
+            .subdirectory(app_dir)
+            .with_context(|| format!("App directory `{app_dir}` does not exist"))?;
+        if !app_in_dir.source.starts_with(&app.source) {
+            bail!("App directory `{}` is outside of the source", app_dir);
+        }
+
+        Ok((
+            app_in_dir,
+            Environment::new(Environment::clone_variables(env)),
+        ))
+    }
+
     /// Get a build plan from the provider and by applying a config from the environment.
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<(BuildPlan, App)> {
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
 
-        // Add the variables from the nixpacks.toml to environment
-        let new_env = &Environment::append_variables(
-            env,
-            plan_before_providers.variables.clone().unwrap_or_default(),
-        );
+        let devcontainer_plan = if self.config.exact_plan.is_some() {
+            BuildPlan::default()
+        } else {
+            (DevcontainerProvider {})
+                .get_build_plan(app, env)?
+                .unwrap_or_default()
+        };
 
-        let provider_plan =
-            self.get_plan_from_providers(app, new_env, plan_before_providers.providers.clone())?;
+        // Add the variables from the nixpacks.toml to environment
+        let new_env = &self.append_version_variables(
+            app,
+            &Environment::append_variables(
+                env,
+                plan_before_providers.variables.clone().unwrap_or_default(),
+            ),
+            &devcontainer_plan,
+        )?;
+
+        let (provider_plan, procfile_plan) = if self.config.exact_plan.is_some() {
+            (BuildPlan::default(), BuildPlan::default())
+        } else {
+            (
+                self.get_plan_from_providers(
+                    app,
+                    new_env,
+                    plan_before_providers.providers.clone(),
+                )?,
+                (ProcfileProvider {})
+                    .get_build_plan(app, new_env)?
+                    .unwrap_or_default(),
+            )
+        };
 
-        let procfile_plan = (ProcfileProvider {})
-            .get_build_plan(app, new_env)?
-            .unwrap_or_default();
+        // Only commands from config files, the environment, and the CLI are interpolated, since providers write theirs
+        // for the shell, and an exact plan is already interpolated
+        let mut plan_before_providers = plan_before_providers;
+        if self.config.exact_plan.is_none() {
+            let mut variables = EnvironmentVariables::from([
+                (
+                    "NIXPACKS_SRC".to_string(),
+                    APP_DIR.trim_end_matches('/').to_string(),
+                ),
+                (
+                    "NIXPACKS_VERSION".to_string(),
+                    NIX_PACKS_VERSION.to_string(),
+                ),
+            ]);
+            for plan in [&provider_plan, &devcontainer_plan, &procfile_plan] {
+                variables.extend(plan.variables.clone().unwrap_or_default());
+            }
+            variables.extend(Environment::clone_variables(new_env));
+            plan_before_providers.interpolate_variables(&variables);
+        }
 
-        let mut plan =
-            BuildPlan::merge_plans(&vec![provider_plan, procfile_plan, plan_before_providers]);
+        // Commands from config files, the environment, and the CLI can read any file of the app, not only the ones the
+        // provider's commands need
+        let overridden_phases = plan_before_providers
+            .phases
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, phase)| phase.cmds.is_some() && phase.only_include_files.is_none())
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        let mut plan = BuildPlan::merge_plans(&vec![
+            provider_plan,
+            devcontainer_plan,
+            procfile_plan,
+            plan_before_providers,
+        ]);
+        for name in overridden_phases {
+            if let Some(phase) = plan.get_phase_mut(&name) {
+                phase.only_include_files = None;
+            }
+        }
 
         if !new_env.get_variable_names().is_empty() {
             plan.add_variables(Environment::clone_variables(new_env));
         }
 
+        plan.apply_nixpkgs_pin()?;
         plan.pin(new_env.is_config_variable_truthy("DEBIAN"));
         if plan.clone().phases.unwrap_or_default().is_empty() {
             // try again in a subdir
-            let dir_count = app.paths.clone().iter().filter(|p| p.is_dir()).count();
-            if dir_count == 1 {
+            let dirs = app
+                .paths
+                .iter()
+                .filter(|p| app.includes_directory(&p.to_string_lossy()))
+                .collect::<Vec<_>>();
+            if dirs.len() == 1 {
                 // there is 1 sub dir, try and generate a plan from that
-                let paths = app.paths.clone();
-                let new_dir = paths.iter().find(|p| p.is_dir()).unwrap();
-                return self
-                    .get_build_plan(&App::new(new_dir.display().to_string().as_str())?, env);
+                return self.get_build_plan(&app.subdirectory(&dirs[0].to_string_lossy())?, env);
             }
         }
         Ok((plan, app.clone()))
     }
 
+    /// Add the runtime versions pinned in `.tool-versions` or `mise.toml`, then the language versions from a dev
+    /// container config, to the environment, for providers to install, without overriding versions that are already set.
+    fn append_version_variables(
+        &self,
+        app: &App,
+        env: &Environment,
+        devcontainer_plan: &BuildPlan,
+    ) -> Result<Environment> {
+        if self.config.exact_plan.is_some() {
+            return Ok(Environment::new(Environment::clone_variables(env)));
+        }
+
+        let mut variables = devcontainer_plan.variables.clone().unwrap_or_default();
+        variables.extend(get_tool_version_variables(app)?);
+        let variables = variables
+            .into_iter()
+            .filter(|(name, _)| env.get_variable(name).is_none())
+            .collect();
+        Ok(Environment::append_variables(env, variables))
+    }
+
     /// Generate a build plan based on config files, environment variables, and CLI arguments.
     fn get_plan_before_providers(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
-        let file_plan = self.read_file_plan(app, env)?;
+        let file_plan = match &self.config.exact_plan {
+            Some(exact_plan) => exact_plan.clone(),
+            None => self.read_file_plan(app, env)?,
+        };
+        let buildpacks_plan = self.get_buildpacks_plan(app, env)?;
         let env_plan = BuildPlan::from_environment(env);
         let cli_plan = self.config.plan.clone().unwrap_or_default();
-        let plan_before_providers = BuildPlan::merge_plans(&vec![file_plan, env_plan, cli_plan]);
+        let plan_before_providers =
+            BuildPlan::merge_plans(&vec![buildpacks_plan, file_plan, env_plan, cli_plan]);
 
         Ok(plan_before_providers)
     }
 
-    /// Use each provider's detect method to determine which providers are needed for the build.
-    fn get_detected_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+    /// The plan seeded from Heroku's `app.json` and the `project.toml` of Cloud Native Buildpacks, without the
+    /// variables that are already set.
+    fn get_buildpacks_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        if self.config.exact_plan.is_some() {
+            return Ok(BuildPlan::default());
+        }
+
+        let mut plan = get_buildpacks_plan(app)?;
+        if let Some(variables) = plan.variables.as_mut() {
+            variables.retain(|name, _| env.get_variable(name).is_none());
+        }
+        Ok(plan)
+    }
+
+    /// Use each provider's detect method to determine which providers are needed for the build, skipping disabled ones.
+    ///
+    /// Only the first provider that detects the app is used, unless `NIXPACKS_COMBINE_PROVIDERS` is set to use all of
+    /// them, in the order they are listed in.
+    fn get_detected_providers(
+        &self,
+        app: &App,
+        env: &Environment,
+        disabled_providers: &[String],
+    ) -> Result<Vec<String>> {
         let mut providers = Vec::new();
 
         for provider in self.providers {
-            if provider.detect(app, env)? {
+            if disabled_providers
+                .iter()
+                .any(|name| name == provider.name())
+            {
+                continue;
+            }
+
+            let detected =
+                provider
+                    .detect(app, env)
+                    .map_err(|source| NixpacksError::ProviderDetection {
+                        provider: provider.name().to_string(),
+                        source,
+                    })?;
+            if detected {
                 providers.push(provider.name().to_string());
 
-                // Only match a single provider... for now
-                break;
+                if !env.is_config_variable_truthy("COMBINE_PROVIDERS") {
+                    break;
+                }
             }
         }
 
@@ -123,22 +308,40 @@ impl NixpacksBuildPlanGenerator<'_> {
     }
 
     /// Get a list of providers that will be used to create the plan.
+    ///
+    /// `"..."` in the manual providers is replaced with the detected provider, and providers prefixed with `!` are
+    /// neither detected nor used.
     pub fn get_all_providers(
         &self,
         app: &App,
         env: &Environment,
         manual_providers: Option<Vec<String>>,
     ) -> Result<Vec<String>> {
-        let detected_providers = self.get_detected_providers(app, env)?;
+        let (disabled_providers, manual_providers): (Vec<_>, Vec<_>) = manual_providers
+            .unwrap_or_else(|| vec!["...".to_string()])
+            .into_iter()
+            .partition(|name| name.starts_with(DISABLED_PROVIDER_PREFIX));
+        let disabled_providers = disabled_providers
+            .iter()
+            .map(|name| {
+                name.trim_start_matches(DISABLED_PROVIDER_PREFIX)
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+
+        let detected_providers = self.get_detected_providers(app, env, &disabled_providers)?;
         let provider_names = remove_autos_from_vec(
-            fill_auto_in_vec(
-                Some(detected_providers),
-                Some(manual_providers.unwrap_or_else(|| vec!["...".to_string()])),
-            )
-            .unwrap_or_default(),
+            fill_auto_in_vec(Some(detected_providers), Some(manual_providers)).unwrap_or_default(),
         );
 
-        Ok(provider_names)
+        let mut providers: Vec<String> = Vec::new();
+        for name in provider_names {
+            if !disabled_providers.contains(&name) && !providers.contains(&name) {
+                providers.push(name);
+            }
+        }
+
+        Ok(providers)
     }
 
     /// Use all detected and specified providers to generate a build plan.
@@ -158,50 +361,184 @@ impl NixpacksBuildPlanGenerator<'_> {
         }
 
         let mut plan = BuildPlan::default();
-        let mut count = 0;
-
         let mut metadata = Vec::new();
 
-        for name in provider_names {
+        for (provider, provider_plan) in self.get_provider_plans(app, env, &provider_names)? {
+            let metadata_string = provider
+                .metadata(app, env)?
+                .join_as_comma_separated(provider.name().to_owned());
+            metadata.push(metadata_string);
+
+            plan = BuildPlan::merge(&provider_plan, &plan);
+        }
+
+        if !provider_names.is_empty() {
+            plan.add_variables(EnvironmentVariables::from([(
+                NIXPACKS_METADATA.to_string(),
+                metadata.join(","),
+            )]));
+        }
+
+        Ok(plan)
+    }
+
+    /// The plan of each of the given providers that has one.
+    fn get_provider_plans(
+        &self,
+        app: &App,
+        env: &Environment,
+        provider_names: &[String],
+    ) -> Result<Vec<(&dyn Provider, BuildPlan)>> {
+        let mut plans = Vec::new();
+
+        for (count, name) in provider_names.iter().enumerate() {
             let provider = self.providers.iter().find(|p| p.name() == name);
             if let Some(provider) = provider {
-                if let Some(mut provider_plan) = provider.get_build_plan(app, env)? {
-                    // All but the first provider have their phases prefixed with their name
+                let provider_plan = provider.get_build_plan(app, env).map_err(|source| {
+                    NixpacksError::ProviderDetection {
+                        provider: provider.name().to_string(),
+                        source,
+                    }
+                })?;
+                if let Some(mut provider_plan) = provider_plan {
+                    // All but the first provider have their phases prefixed with their name, and run after the phases
+                    // of the provider before them
                     if count > 0 {
                         provider_plan.prefix_phases(provider.name());
+                        if let Some((_, previous_plan)) = plans.last() {
+                            provider_plan.depend_on_last_phases_of(previous_plan);
+                        }
                     }
 
-                    let metadata_string = provider
-                        .metadata(app, env)?
-                        .join_as_comma_separated(provider.name().to_owned());
-                    metadata.push(metadata_string);
-
-                    plan = BuildPlan::merge(&provider_plan, &plan);
+                    plans.push((*provider, provider_plan));
                 }
             } else if name != "..." && name != "@auto" {
-                bail!("Provider {} not found", name);
+                return Err(NixpacksError::UnknownProvider {
+                    provider: name.clone(),
+                }
+                .into());
             }
-
-            count += 1;
         }
 
-        if count > 0 {
-            plan.add_variables(EnvironmentVariables::from([(
-                NIXPACKS_METADATA.to_string(),
-                metadata.join(","),
-            )]));
+        Ok(plans)
+    }
+
+    /// The providers used to build the app, with the files each one detected it by, the version it resolved, and the
+    /// phases it adds to the build plan.
+    pub fn detect_providers(&self, app: &App, env: &Environment) -> Result<Vec<ProviderDetection>> {
+        let (app, env) = &self.get_app_in_dir(app, env)?;
+        let plan_before_providers = self.get_plan_before_providers(app, env)?;
+        let new_env = &Environment::append_variables(
+            env,
+            plan_before_providers.variables.clone().unwrap_or_default(),
+        );
+        let provider_names =
+            self.get_all_providers(app, new_env, plan_before_providers.providers)?;
+
+        self.get_provider_plans(app, new_env, &provider_names)?
+            .into_iter()
+            .map(|(provider, provider_plan)| {
+                Ok(ProviderDetection {
+                    name: provider.name().to_string(),
+                    files: provider.detection_files(app, new_env)?,
+                    version: provider.version(app, new_env)?,
+                    phases: provider_plan
+                        .get_sorted_phases()?
+                        .iter()
+                        .map(Phase::get_name)
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Explain where each package, variable, and command in the build plan comes from.
+    pub fn explain_plan(&self, app: &App, env: &Environment) -> Result<Vec<PlanExplanation>> {
+        let (app, env) = &self.get_app_in_dir(app, env)?;
+        let (plan, app) = self.get_build_plan(app, env)?;
+        let app = &app;
+
+        let mut layers = Vec::new();
+        if let Some(exact_plan) = &self.config.exact_plan {
+            layers.push(PlanLayer::new("plan file", exact_plan.clone()));
+        } else {
+            let plan_before_providers = self.get_plan_before_providers(app, env)?;
+            let devcontainer = DevcontainerProvider {};
+            let devcontainer_plan = devcontainer.get_build_plan(app, env)?.unwrap_or_default();
+            let new_env = &self.append_version_variables(
+                app,
+                &Environment::append_variables(
+                    env,
+                    plan_before_providers.variables.clone().unwrap_or_default(),
+                ),
+                &devcontainer_plan,
+            )?;
+            let provider_names =
+                self.get_all_providers(app, new_env, plan_before_providers.providers)?;
+
+            for (provider, provider_plan) in
+                self.get_provider_plans(app, new_env, &provider_names)?
+            {
+                layers.push(PlanLayer {
+                    source: provider.name().to_string(),
+                    plan: provider_plan,
+                    reasons: provider.reasons(app, new_env)?,
+                });
+            }
+
+            layers.push(PlanLayer::new(
+                "tool versions",
+                BuildPlan {
+                    variables: Some(get_tool_version_variables(app)?),
+                    ..Default::default()
+                },
+            ));
+
+            layers.push(PlanLayer {
+                source: "devcontainer".to_string(),
+                plan: devcontainer_plan,
+                reasons: devcontainer.reasons(app, new_env)?,
+            });
+
+            let procfile = ProcfileProvider {};
+            layers.push(PlanLayer {
+                source: "Procfile".to_string(),
+                plan: procfile.get_build_plan(app, new_env)?.unwrap_or_default(),
+                reasons: procfile.reasons(app, new_env)?,
+            });
+
+            layers.push(PlanLayer::new(
+                "buildpacks",
+                self.get_buildpacks_plan(app, env)?,
+            ));
+
+            if let Some(file_path) = self.get_config_file(app, env)? {
+                layers.push(PlanLayer::new(file_path, self.read_file_plan(app, env)?));
+            }
         }
 
-        Ok(plan)
+        let mut env_plan = BuildPlan::from_environment(env);
+        env_plan.add_variables(Environment::clone_variables(env));
+        layers.push(PlanLayer::new("environment", env_plan));
+
+        layers.push(PlanLayer::new(
+            "CLI",
+            self.config.plan.clone().unwrap_or_default(),
+        ));
+
+        plan.explain(&layers)
     }
 
-    /// If a supported config file exists, use it to generate a build plan.
-    fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+    /// The config file to use, if one is specified or found in the app.
+    fn get_config_file(&self, app: &App, env: &Environment) -> Result<Option<String>> {
         let file_path = if let Some(file_path) = &self.config.config_file {
             Some(file_path.clone())
         } else if let Some(env_config_file) = env.get_config_variable("CONFIG_FILE") {
             if !app.includes_file(&env_config_file) {
-                bail!("Config file {} does not exist", env_config_file);
+                return Err(NixpacksError::plan_invalid(format!(
+                    "Config file {env_config_file} does not exist"
+                ))
+                .into());
             }
 
             Some(env_config_file)
@@ -209,33 +546,57 @@ impl NixpacksBuildPlanGenerator<'_> {
             Some("nixpacks.toml".to_owned())
         } else if app.includes_file("nixpacks.json") {
             Some("nixpacks.json".to_owned())
+        } else if app.includes_file("nixpacks.yaml") {
+            Some("nixpacks.yaml".to_owned())
+        } else if app.includes_file("nixpacks.yml") {
+            Some("nixpacks.yml".to_owned())
         } else {
             None
         };
 
-        let plan =
-            if let Some(file_path) = file_path {
-                let filename = Path::new(&file_path);
-                let ext = filename.extension().unwrap_or_default();
+        Ok(file_path)
+    }
 
-                let contents = app.read_file(file_path.as_str()).with_context(|| {
-                    format!("Failed to read Nixpacks config file `{file_path}`")
-                })?;
-                let plan = if ext == "toml" {
-                    BuildPlan::from_toml(&contents)
-                } else if ext == "json" {
-                    BuildPlan::from_json(&contents)
-                } else {
-                    bail!("Unknown file type: {}", file_path)
-                };
-
-                Some(plan.with_context(|| {
-                    format!("Failed to parse Nixpacks config file `{file_path}`")
-                })?)
+    /// If a supported config file exists, use it to generate a build plan.
+    fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        let plan = if let Some(file_path) = self.get_config_file(app, env)? {
+            let filename = Path::new(&file_path);
+            let ext = filename.extension().unwrap_or_default();
+
+            let contents = app
+                .read_file(file_path.as_str())
+                .with_context(|| format!("Failed to read Nixpacks config file `{file_path}`"))?;
+            let plan = if ext == "toml" {
+                BuildPlan::from_toml(&contents)
+            } else if ext == "json" {
+                BuildPlan::from_json(&contents)
+            } else if ext == "yaml" || ext == "yml" {
+                BuildPlan::from_yaml(&contents)
             } else {
-                None
+                return Err(
+                    NixpacksError::plan_invalid(format!("Unknown file type: {file_path}")).into(),
+                );
             };
 
+            let plan = plan.map_err(|error| {
+                NixpacksError::PlanInvalid(error.context(format!(
+                    "Failed to parse Nixpacks config file `{file_path}`"
+                )))
+            })?;
+
+            // Typos in field names and phase dependencies are otherwise ignored
+            for error in validate_plan(&contents, &file_path)? {
+                println!(
+                    "{}",
+                    format!(" Warning: {file_path}: {error}").bright_yellow()
+                );
+            }
+
+            Some(plan)
+        } else {
+            None
+        };
+
         if plan.is_some() {
             println!(
                 "{}",
@@ -0,0 +1,123 @@
+use super::BuildPlan;
+use std::fmt::Write;
+
+impl BuildPlan {
+    /// The names of the phases in this plan, and its (dependency, phase) edges.
+    fn get_phase_edges(&self) -> (Vec<String>, Vec<(String, String)>) {
+        let phases = self.phases.clone().unwrap_or_default();
+        let mut names = phases.keys().cloned().collect::<Vec<_>>();
+        let mut edges = Vec::new();
+
+        for (name, phase) in &phases {
+            for dependency in phase.depends_on.clone().unwrap_or_default() {
+                // Dependencies on missing phases are drawn too, to make them easier to spot
+                if !names.contains(&dependency) {
+                    names.push(dependency.clone());
+                }
+                edges.push((dependency, name.clone()));
+            }
+        }
+
+        (names, edges)
+    }
+
+    /// Render the phase dependency graph in the Graphviz DOT language, with edges in the order phases run.
+    pub fn to_dot(&self) -> String {
+        let (names, edges) = self.get_phase_edges();
+
+        let mut dot = "digraph phases {\n".to_string();
+        for name in names {
+            writeln!(dot, "  {name:?};").unwrap();
+        }
+        for (dependency, name) in edges {
+            writeln!(dot, "  {dependency:?} -> {name:?};").unwrap();
+        }
+        dot.push('}');
+
+        dot
+    }
+
+    /// Render the phase dependency graph as a Mermaid flowchart, with edges in the order phases run.
+    pub fn to_mermaid(&self) -> String {
+        let (names, edges) = self.get_phase_edges();
+        let id = |name: &str| name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+
+        let mut mermaid = "flowchart TD".to_string();
+        for name in names {
+            write!(
+                mermaid,
+                "\n  {}[\"{}\"]",
+                id(&name),
+                name.replace('"', "#quot;")
+            )
+            .unwrap();
+        }
+        for (dependency, name) in edges {
+            write!(mermaid, "\n  {} --> {}", id(&dependency), id(&name)).unwrap();
+        }
+
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::plan::phase::Phase;
+
+    fn get_plan() -> BuildPlan {
+        let mut migrate = Phase::new("node:migrate");
+        migrate.depends_on_phase("install");
+        let mut build = Phase::build(None);
+        build.depends_on_phase("node:migrate");
+
+        BuildPlan::new(
+            &[Phase::setup(None), Phase::install(None), migrate, build],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            get_plan().to_dot(),
+            indoc::indoc! {r#"
+                digraph phases {
+                  "build";
+                  "install";
+                  "node:migrate";
+                  "setup";
+                  "install" -> "build";
+                  "node:migrate" -> "build";
+                  "setup" -> "install";
+                  "install" -> "node:migrate";
+                }"#}
+        );
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        assert_eq!(
+            get_plan().to_mermaid(),
+            indoc::indoc! {r#"
+                flowchart TD
+                  build["build"]
+                  install["install"]
+                  node_migrate["node:migrate"]
+                  setup["setup"]
+                  install --> build
+                  node_migrate --> build
+                  setup --> install
+                  install --> node_migrate"#}
+        );
+    }
+
+    #[test]
+    fn test_missing_dependency() {
+        let mut build = Phase::build(None);
+        build.depends_on_phase("test");
+        let plan = BuildPlan::new(&[build], None);
+
+        assert!(plan.to_dot().contains("  \"install\";\n  \"test\";"));
+    }
+}
@@ -0,0 +1,211 @@
+use super::BuildPlan;
+use anyhow::Result;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// The overrides chosen for an app's new `nixpacks.toml`, on top of the plan its providers generate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InitOverrides {
+    pub start_cmd: Option<String>,
+    /// Nix packages installed besides the providers' ones.
+    pub nix_pkgs: Vec<String>,
+    /// Apt packages installed besides the providers' ones.
+    pub apt_pkgs: Vec<String>,
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Write a commented `nixpacks.toml` for an app with the chosen overrides, describing the plan generated for it, so
+/// it can be adjusted by hand later. Settings that weren't overridden are left as comments with what was detected.
+pub fn to_init_config(
+    plan: &BuildPlan,
+    providers: &[String],
+    overrides: &InitOverrides,
+) -> Result<String> {
+    let mut config = String::new();
+    writeln!(
+        config,
+        "# Nixpacks config for this app. See https://nixpacks.com/docs/configuration/file"
+    )?;
+    writeln!(config, "#")?;
+    writeln!(
+        config,
+        "# Detected providers: {}",
+        if providers.is_empty() {
+            "none".to_string()
+        } else {
+            providers.join(", ")
+        }
+    )?;
+    writeln!(
+        config,
+        "# Uncomment to always build with these providers instead of detecting them"
+    )?;
+    writeln!(config, "# providers = {}", toml_list(providers))?;
+
+    if !overrides.variables.is_empty() {
+        writeln!(config, "\n[variables]")?;
+        for (name, value) in &overrides.variables {
+            writeln!(config, "{} = {}", toml_key(name), toml_string(value))?;
+        }
+    }
+
+    let phases = plan.get_sorted_phases()?;
+    // Extra packages go to the phase the providers install theirs in
+    let packages_phase = phases
+        .iter()
+        .find(|phase| phase.nix_pkgs.is_some() || phase.apt_pkgs.is_some())
+        .and_then(|phase| phase.name.clone())
+        .unwrap_or_else(|| "setup".to_string());
+
+    for phase in &phases {
+        let name = phase.get_name();
+        let detected_pkgs = phase.nix_pkgs.clone().unwrap_or_default();
+        let detected_apt_pkgs = phase.apt_pkgs.clone().unwrap_or_default();
+        let cmds = phase.cmds.clone().unwrap_or_default();
+        let adds_packages = name == packages_phase;
+
+        writeln!(config, "\n[phases.{}]", toml_key(&name))?;
+        if !detected_pkgs.is_empty() {
+            writeln!(
+                config,
+                "# Detected Nix packages: {}",
+                detected_pkgs.join(", ")
+            )?;
+        }
+        if adds_packages && !overrides.nix_pkgs.is_empty() {
+            writeln!(
+                config,
+                "nixPkgs = {}",
+                toml_list(&extending(&overrides.nix_pkgs))
+            )?;
+        } else if adds_packages {
+            writeln!(config, "# nixPkgs = [\"...\", \"<package>\"]")?;
+        }
+        if !detected_apt_pkgs.is_empty() {
+            writeln!(
+                config,
+                "# Detected apt packages: {}",
+                detected_apt_pkgs.join(", ")
+            )?;
+        }
+        if adds_packages && !overrides.apt_pkgs.is_empty() {
+            writeln!(
+                config,
+                "aptPkgs = {}",
+                toml_list(&extending(&overrides.apt_pkgs))
+            )?;
+        }
+        if !cmds.is_empty() {
+            writeln!(config, "# cmds = {}", toml_list(&cmds))?;
+        }
+    }
+
+    writeln!(config, "\n[start]")?;
+    let detected_start = plan
+        .start_phase
+        .as_ref()
+        .and_then(|start| start.cmd.clone());
+    match (&overrides.start_cmd, detected_start) {
+        (Some(cmd), detected) => {
+            if let Some(detected) = detected {
+                writeln!(config, "# Detected: {detected}")?;
+            }
+            writeln!(config, "cmd = {}", toml_string(cmd))?;
+        }
+        (None, Some(detected)) => writeln!(config, "# cmd = {}", toml_string(&detected))?,
+        (None, None) => writeln!(config, "# cmd = \"<command>\"")?,
+    }
+
+    Ok(config)
+}
+
+/// The packages, after the ones the providers install.
+fn extending(pkgs: &[String]) -> Vec<String> {
+    std::iter::once("...".to_string())
+        .chain(pkgs.iter().cloned())
+        .collect()
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn toml_list(values: &[String]) -> String {
+    format!(
+        "[{}]",
+        values
+            .iter()
+            .map(|value| toml_string(value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// A key, quoted when it isn't a bare key.
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        nix::pkg::Pkg,
+        plan::phase::{Phase, StartPhase},
+    };
+
+    fn plan() -> BuildPlan {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs")]));
+        setup.add_apt_pkgs(vec!["curl".to_string()]);
+        let install = Phase::install(Some("npm ci".to_string()));
+        BuildPlan::new(&[setup, install], Some(StartPhase::new("npm start")))
+    }
+
+    #[test]
+    fn test_init_config_without_overrides() -> Result<()> {
+        let config = to_init_config(&plan(), &["node".to_string()], &InitOverrides::default())?;
+
+        assert!(config.contains("# providers = [\"node\"]"));
+        assert!(config.contains("# Detected Nix packages: nodejs"));
+        assert!(config.contains("# cmds = [\"npm ci\"]"));
+        assert!(config.contains("# cmd = \"npm start\""));
+        // Nothing is overridden, so the plan stays the one the providers generate
+        assert_eq!(
+            BuildPlan::from_toml(config)?
+                .start_phase
+                .unwrap_or_default()
+                .cmd,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_config_with_overrides() -> Result<()> {
+        let overrides = InitOverrides {
+            start_cmd: Some("node server.js".to_string()),
+            nix_pkgs: vec!["ffmpeg".to_string()],
+            apt_pkgs: Vec::new(),
+            variables: BTreeMap::from([("NODE_ENV".to_string(), "production".to_string())]),
+        };
+        let config = BuildPlan::from_toml(to_init_config(&plan(), &[], &overrides)?)?;
+
+        assert_eq!(
+            config.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["...".to_string(), "ffmpeg".to_string()])
+        );
+        assert_eq!(
+            config.start_phase.unwrap().cmd,
+            Some("node server.js".to_string())
+        );
+        assert_eq!(config.variables.unwrap()["NODE_ENV"], "production");
+        Ok(())
+    }
+}
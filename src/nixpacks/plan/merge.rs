@@ -1,5 +1,6 @@
 use super::{
-    phase::{Phase, StartPhase},
+    super::nix::NixConfig,
+    phase::{Healthcheck, Phase, StartPhase},
     utils::fill_auto_in_vec,
     BuildPlan,
 };
@@ -17,6 +18,8 @@ impl Mergeable for BuildPlan {
 
         new_plan.providers = fill_auto_in_vec(new_plan.providers.clone(), plan2.providers.clone());
         new_plan.build_image = plan2.build_image.or(new_plan.build_image);
+        new_plan.nixpkgs = plan2.nixpkgs.or(new_plan.nixpkgs);
+        new_plan.schema_version = plan2.schema_version.or(new_plan.schema_version);
 
         new_plan.static_assets = match (new_plan.static_assets, plan2.static_assets) {
             (None, assets) | (assets, None) => assets,
@@ -27,6 +30,26 @@ impl Mergeable for BuildPlan {
             }
         };
 
+        new_plan.processes = match (new_plan.processes, plan2.processes) {
+            (None, processes) | (processes, None) => processes,
+            (Some(processes1), Some(processes2)) => {
+                let mut processes = processes1;
+                processes.extend(processes2);
+                Some(processes)
+            }
+        };
+
+        new_plan.nix = match (new_plan.nix, plan2.nix) {
+            (None, nix) | (nix, None) => nix,
+            (Some(nix1), Some(nix2)) => Some(NixConfig {
+                substituters: fill_auto_in_vec(nix1.substituters, nix2.substituters),
+                trusted_public_keys: fill_auto_in_vec(
+                    nix1.trusted_public_keys,
+                    nix2.trusted_public_keys,
+                ),
+            }),
+        };
+
         new_plan.variables = match (new_plan.variables, plan2.variables) {
             (None, vars) | (vars, None) => vars,
             (Some(vars1), Some(vars2)) => {
@@ -84,6 +107,10 @@ impl Mergeable for Phase {
             fill_auto_in_vec(phase.only_include_files.clone(), c2.only_include_files);
         phase.cache_directories =
             fill_auto_in_vec(phase.cache_directories.clone(), c2.cache_directories);
+        phase.persisted_directories = fill_auto_in_vec(
+            phase.persisted_directories.clone(),
+            c2.persisted_directories,
+        );
         phase.paths = fill_auto_in_vec(phase.paths.clone(), c2.paths);
 
         phase
@@ -97,14 +124,33 @@ impl Mergeable for StartPhase {
         let c2 = c2.clone();
         start_phase.cmd = c2.cmd.or_else(|| start_phase.cmd.clone());
         start_phase.run_image = c2.run_image.or_else(|| start_phase.run_image.clone());
+        start_phase.user = c2.user.or_else(|| start_phase.user.clone());
+        start_phase.healthcheck = match (start_phase.healthcheck.clone(), c2.healthcheck) {
+            (Some(h1), Some(h2)) => Some(Healthcheck::merge(&h1, &h2)),
+            (h1, h2) => h2.or(h1),
+        };
         start_phase.only_include_files = fill_auto_in_vec(
             start_phase.only_include_files.clone(),
             c2.only_include_files,
         );
+        start_phase.ports = fill_auto_in_vec(start_phase.ports.clone(), c2.ports);
         start_phase
     }
 }
 
+impl Mergeable for Healthcheck {
+    /// Given two Healthchecks, produce a third Healthcheck with the options of the second overriding the first.
+    fn merge(c1: &Healthcheck, c2: &Healthcheck) -> Healthcheck {
+        let c2 = c2.clone();
+        Healthcheck {
+            cmd: c2.cmd.or_else(|| c1.cmd.clone()),
+            interval: c2.interval.or_else(|| c1.interval.clone()),
+            timeout: c2.timeout.or_else(|| c1.timeout.clone()),
+            retries: c2.retries.or(c1.retries),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -208,4 +254,91 @@ mod test {
             merged
         );
     }
+
+    #[test]
+    fn test_merge_start_user() {
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [start]
+                cmd = "yarn run start"
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [start]
+                user = "app"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        let start = merged.start_phase.unwrap();
+        assert_eq!(start.cmd, Some("yarn run start".to_string()));
+        assert_eq!(start.user, Some("app".to_string()));
+    }
+
+    #[test]
+    fn test_merge_start_healthcheck() {
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [start.healthcheck]
+                cmd = "curl -f localhost:3000"
+                interval = "10s"
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [start.healthcheck]
+                cmd = "curl -f localhost:3000/health"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            merged.start_phase.unwrap().healthcheck,
+            Some(Healthcheck {
+                cmd: Some("curl -f localhost:3000/health".to_string()),
+                interval: Some("10s".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_nix_caches() {
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [nix]
+                substituters = ["https://my-team.cachix.org"]
+                trustedPublicKeys = ["my-team.cachix.org-1:abc="]
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [nix]
+                substituters = ["...", "https://nix-community.cachix.org"]
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            merged.nix,
+            Some(NixConfig {
+                substituters: Some(vec![
+                    "...".to_string(),
+                    "https://my-team.cachix.org".to_string(),
+                    "https://nix-community.cachix.org".to_string(),
+                ]),
+                trusted_public_keys: Some(vec!["my-team.cachix.org-1:abc=".to_string()]),
+            })
+        );
+    }
 }
@@ -0,0 +1,184 @@
+use super::BuildPlan;
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+/// The version of the build plan schema this version of Nixpacks reads and writes, stored in plans as
+/// `schemaVersion`.
+///
+/// - 1: the layout before phases, with top-level `setup`, `install`, and `build` tables.
+/// - 2: phases in `phases`, each named by its key.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The phases that plans of schema version 1 had as top-level tables, in the order they ran.
+const LEGACY_PHASES: &[&str] = &["setup", "install", "build"];
+
+impl BuildPlan {
+    /// Read a serialized plan or config file of any schema version, upgrading it to the current one first, so plans
+    /// stored by older versions of Nixpacks keep working.
+    pub fn migrate(plan: Value) -> Result<BuildPlan> {
+        let mut plan: BuildPlan = serde_json::from_value(upgrade_plan(plan)?)?;
+        plan.resolve_phase_names();
+        Ok(plan)
+    }
+}
+
+/// The schema version of a serialized plan. Plans before versioning are version 2, or 1 if they have the old layout.
+pub fn get_schema_version(plan: &Value) -> Result<u32> {
+    match plan.get("schemaVersion") {
+        Some(version) => match version.as_u64() {
+            Some(version) => Ok(u32::try_from(version)?),
+            None => bail!("schemaVersion must be a number"),
+        },
+        None if LEGACY_PHASES.iter().any(|name| plan.get(name).is_some()) => Ok(1),
+        None => Ok(2),
+    }
+}
+
+/// Whether a serialized plan has to be upgraded before it's read, failing if it's of a schema that's newer than this
+/// version of Nixpacks reads.
+pub fn needs_upgrade(plan: &Value) -> Result<bool> {
+    let version = get_schema_version(plan)?;
+    if version > SCHEMA_VERSION {
+        bail!("The plan has schema version {version}, but this version of Nixpacks reads up to version {SCHEMA_VERSION}. Upgrade Nixpacks to read it");
+    }
+    Ok(version < SCHEMA_VERSION)
+}
+
+/// Upgrade a serialized plan to the current schema, one version at a time.
+pub fn upgrade_plan(mut plan: Value) -> Result<Value> {
+    needs_upgrade(&plan)?;
+
+    if get_schema_version(&plan)? < 2 {
+        plan = upgrade_from_v1(plan);
+    }
+
+    if let Some(plan) = plan.as_object_mut() {
+        plan.insert("schemaVersion".to_string(), Value::from(SCHEMA_VERSION));
+    }
+    Ok(plan)
+}
+
+/// Move the top-level phase tables into `phases`, with the fields they had then renamed, and each depending on the
+/// one before it.
+fn upgrade_from_v1(mut plan: Value) -> Value {
+    let fields = match plan.as_object_mut() {
+        Some(fields) => fields,
+        None => return plan,
+    };
+    // The version of Nixpacks that wrote the plan
+    fields.remove("version");
+
+    let mut previous = None;
+    for name in LEGACY_PHASES {
+        let mut phase = match fields.remove(*name) {
+            Some(Value::Object(phase)) => phase,
+            _ => continue,
+        };
+
+        rename_field(&mut phase, "pkgs", "nixPkgs");
+        rename_field(&mut phase, "libraries", "nixLibs");
+        rename_field(&mut phase, "archive", "nixpkgsArchive");
+        if let Some(cmd) = phase.remove("cmd") {
+            phase
+                .entry("cmds")
+                .or_insert_with(|| Value::Array(vec![cmd]));
+        }
+        if let Some(previous) = previous {
+            phase
+                .entry("dependsOn")
+                .or_insert_with(|| Value::from(vec![previous]));
+        }
+
+        if let Some(phases) = fields
+            .entry("phases")
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+        {
+            phases.insert((*name).to_string(), Value::Object(phase));
+        }
+        previous = Some(*name);
+    }
+
+    plan
+}
+
+fn rename_field(fields: &mut Map<String, Value>, from: &str, to: &str) {
+    if let Some(value) = fields.remove(from) {
+        fields.entry(to).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_legacy_plan() {
+        let plan = BuildPlan::migrate(json!({
+            "version": "0.3.0",
+            "setup": { "pkgs": ["nodejs"], "archive": "abc123" },
+            "install": { "cmd": "npm ci" },
+            "build": { "cmds": ["npm run build"] },
+            "start": { "cmd": "npm start" },
+            "variables": { "NODE_ENV": "production" }
+        }))
+        .unwrap();
+
+        let expected = BuildPlan::from_toml(
+            r#"
+            schemaVersion = 2
+
+            [variables]
+            NODE_ENV = "production"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            nixpkgsArchive = "abc123"
+
+            [phases.install]
+            dependsOn = ["setup"]
+            cmds = ["npm ci"]
+
+            [phases.build]
+            dependsOn = ["install"]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(plan, expected);
+    }
+
+    #[test]
+    fn test_legacy_config_file() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [build]
+            cmd = "make"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(plan.schema_version, Some(SCHEMA_VERSION));
+        assert_eq!(
+            plan.get_phase("build").unwrap().cmds,
+            Some(vec!["make".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_current_plan_unchanged() {
+        let plan = json!({ "phases": { "build": { "cmds": ["make"] } } });
+        assert!(!needs_upgrade(&plan).unwrap());
+        assert_eq!(get_schema_version(&plan).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_newer_schema_version() {
+        let error = BuildPlan::from_json(r#"{ "schemaVersion": 3 }"#).unwrap_err();
+        assert!(error.to_string().contains("reads up to version 2"));
+    }
+}
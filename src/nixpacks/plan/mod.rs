@@ -1,24 +1,37 @@
 use self::{
+    builder::BuildPlanBuilder,
     merge::Mergeable,
+    migrate::{needs_upgrade, SCHEMA_VERSION},
     phase::{Phase, Phases, StartPhase},
     topological_sort::topological_sort,
+    utils::{interpolate_variables, remove_autos_from_vec},
 };
 use super::images::{DEBIAN_BASE_IMAGE, UBUNTU_BASE_IMAGE};
 use crate::nixpacks::{
     app::{App, StaticAssets},
     environment::{Environment, EnvironmentVariables},
+    nix::{parse_nixpkgs_revision, NixConfig},
+    utils::shell_quote,
 };
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
 
+pub mod builder;
 // pub mod config;
+pub mod detect;
+pub mod diff;
+pub mod explain;
 pub mod generator;
+pub mod graph;
+pub mod init;
 pub mod merge;
+pub mod migrate;
 pub mod phase;
 pub mod pretty_print;
 mod topological_sort;
 mod utils;
+pub mod validate;
 
 /// Types that impl this trait can generate build plans.
 pub trait PlanGenerator {
@@ -34,6 +47,10 @@ pub trait PlanGenerator {
 /// Nixpacks is essentially a compiler from {the closure of the direct and indirect dependencies of an application} to a Docker image reifying that closure.
 /// BuildPlans are an intermediate representation of this compiler, and are either compiled to a Dockerfile and then built into an image or are serialized as json or toml to a config file.
 pub struct BuildPlan {
+    /// The version of the schema the plan is written in, see [`migrate::SCHEMA_VERSION`].
+    pub schema_version: Option<u32>,
+
+    #[serde(default, deserialize_with = "deserialize_providers")]
     pub providers: Option<Vec<String>>,
 
     #[serde(rename = "buildImage")]
@@ -48,6 +65,62 @@ pub struct BuildPlan {
 
     #[serde(rename = "start")]
     pub start_phase: Option<StartPhase>,
+
+    /// The command of each process type the app runs, like `web` and `worker` in a Procfile.
+    pub processes: Option<Processes>,
+
+    /// Binary caches to install the Nix packages from.
+    pub nix: Option<NixConfig>,
+
+    /// The nixpkgs revision every phase installs its Nix packages from, e.g. `github:NixOS/nixpkgs/<rev>`.
+    pub nixpkgs: Option<String>,
+}
+
+/// Process types, like `web` or `worker`, and the commands that start them.
+pub type Processes = BTreeMap<String, String>;
+
+/// Marks a provider in `providers` that must not be used, even if it detects the app, e.g. `!staticfile`.
+pub const DISABLED_PROVIDER_PREFIX: &str = "!";
+
+/// Where the app is in the image, and the working directory of its phases.
+pub const APP_DIR: &str = "/app/";
+
+/// The providers to use, as a list, or as a table with the list to `use` and the providers to `disable`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ProvidersConfig {
+    List(Vec<String>),
+    Table {
+        #[serde(rename = "use")]
+        use_providers: Option<Vec<String>>,
+        disable: Option<Vec<String>>,
+    },
+}
+
+/// Read `providers` from either form, with the disabled providers added to the list with [`DISABLED_PROVIDER_PREFIX`].
+fn deserialize_providers<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let providers = match Option::<ProvidersConfig>::deserialize(deserializer)? {
+        Some(ProvidersConfig::List(providers)) => Some(providers),
+        Some(ProvidersConfig::Table {
+            use_providers,
+            disable,
+        }) => {
+            let mut providers = use_providers.unwrap_or_else(|| vec!["...".to_string()]);
+            providers.extend(
+                disable
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|name| format!("{DISABLED_PROVIDER_PREFIX}{name}")),
+            );
+            Some(providers)
+        }
+        None => None,
+    };
+
+    Ok(providers)
 }
 
 impl BuildPlan {
@@ -60,6 +133,11 @@ impl BuildPlan {
         }
     }
 
+    /// Starts building a plan with [`BuildPlanBuilder`], for creating plans in code.
+    pub fn builder() -> BuildPlanBuilder {
+        BuildPlanBuilder::default()
+    }
+
     pub fn get_packages(&self) -> Vec<String> {
         let mut pkgs = Vec::new();
         if let Some(phases) = &self.phases {
@@ -77,18 +155,99 @@ impl BuildPlan {
 
     /// Create a BuildPlan from a toml config file.
     pub fn from_toml<S: Into<String>>(toml: S) -> Result<Self> {
-        let mut plan: BuildPlan = toml::from_str(&toml.into())?;
+        let toml = toml.into();
+        let value = serde_json::to_value(toml::from_str::<toml::Value>(&toml)?)?;
+        if needs_upgrade(&value)? {
+            return BuildPlan::migrate(value);
+        }
+
+        let mut plan: BuildPlan = toml::from_str(&toml)?;
         plan.resolve_phase_names();
         Ok(plan)
     }
 
     /// Create a BuildPlan from a json config file.
     pub fn from_json<S: Into<String>>(json: S) -> Result<Self> {
-        let mut plan: BuildPlan = serde_json::from_str(&json.into())?;
+        let json = json.into();
+        let value: serde_json::Value = serde_json::from_str(&json)?;
+        if needs_upgrade(&value)? {
+            return BuildPlan::migrate(value);
+        }
+
+        let mut plan: BuildPlan = serde_json::from_str(&json)?;
         plan.resolve_phase_names();
         Ok(plan)
     }
 
+    /// Create a BuildPlan from a yaml config file.
+    pub fn from_yaml<S: Into<String>>(yaml: S) -> Result<Self> {
+        let yaml = yaml.into();
+        let value: serde_json::Value = serde_yaml::from_str(&yaml)?;
+        if needs_upgrade(&value)? {
+            return BuildPlan::migrate(value);
+        }
+
+        let mut plan: BuildPlan = serde_yaml::from_str(&yaml)?;
+        plan.resolve_phase_names();
+        Ok(plan)
+    }
+
+    /// Create a BuildPlan from json, toml, or yaml, whichever it's written in.
+    pub fn parse<S: Into<String>>(contents: S) -> Result<Self> {
+        let contents = contents.into();
+        if contents.trim_start().starts_with('{') {
+            BuildPlan::from_json(contents)
+        } else if looks_like_yaml(&contents) {
+            BuildPlan::from_yaml(contents)
+        } else {
+            BuildPlan::from_toml(contents)
+        }
+    }
+
+    /// Create a BuildPlan from a json, toml, or yaml file, depending on its extension.
+    pub fn from_file(file_path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(file_path)
+            .with_context(|| format!("Failed to read plan file `{file_path}`"))?;
+        let plan = match Path::new(file_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            Some("toml") => BuildPlan::from_toml(contents),
+            Some("json") => BuildPlan::from_json(contents),
+            Some("yaml" | "yml") => BuildPlan::from_yaml(contents),
+            _ => bail!("Unknown file type: {}", file_path),
+        };
+
+        plan.with_context(|| format!("Failed to parse plan file `{file_path}`"))
+    }
+
+    /// Replace `${NAME}` with the value of the variable in the commands, cache directories, and start command.
+    pub fn interpolate_variables(&mut self, variables: &EnvironmentVariables) {
+        let interpolate_all = |values: &Option<Vec<String>>| {
+            values.as_ref().map(|values| {
+                values
+                    .iter()
+                    .map(|value| interpolate_variables(value, variables))
+                    .collect()
+            })
+        };
+
+        if let Some(phases) = &mut self.phases {
+            for phase in phases.values_mut() {
+                phase.cmds = interpolate_all(&phase.cmds);
+                phase.cache_directories = interpolate_all(&phase.cache_directories);
+                phase.persisted_directories = interpolate_all(&phase.persisted_directories);
+            }
+        }
+
+        if let Some(start) = &mut self.start_phase {
+            start.cmd = start
+                .cmd
+                .as_ref()
+                .map(|cmd| interpolate_variables(cmd, variables));
+        }
+    }
+
     /// Render a BuildPlan to a toml-formatted string.
     pub fn to_toml(&self) -> Result<String> {
         let mut plan = self.clone();
@@ -103,6 +262,13 @@ impl BuildPlan {
         Ok(serde_json::to_string_pretty(&plan)?)
     }
 
+    /// Render a BuildPlan to a yaml-formatted string.
+    pub fn to_yaml(&self) -> Result<String> {
+        let mut plan = self.clone();
+        plan.remove_phase_names();
+        Ok(serde_yaml::to_string(&plan)?)
+    }
+
     /// Add the given phase to this BuildPlan.
     pub fn add_phase(&mut self, phase: Phase) {
         let phases = self.phases.get_or_insert(BTreeMap::default());
@@ -142,6 +308,14 @@ impl BuildPlan {
         }
     }
 
+    /// Stores the commands of process types the app runs, replacing any with the same names.
+    pub fn add_processes(&mut self, processes: Processes) {
+        match self.processes.as_mut() {
+            Some(existing) => existing.extend(processes),
+            None => self.processes = Some(processes),
+        }
+    }
+
     /// Returns the Phase of this BuildPlan with the given name.
     pub fn get_phase(&self, name: &str) -> Option<&Phase> {
         match self.phases {
@@ -178,6 +352,33 @@ impl BuildPlan {
         Ok(res)
     }
 
+    /// Groups the phases into levels, in dependency order, where each phase only depends on phases in earlier levels.
+    /// The phases of a level don't depend on each other, so they can run at the same time.
+    pub fn get_phase_levels(&self) -> Result<Vec<Vec<Phase>>> {
+        let mut levels: Vec<Vec<Phase>> = Vec::new();
+        let mut phase_levels = BTreeMap::<String, usize>::new();
+
+        for phase in self.get_sorted_phases()? {
+            let level = phase
+                .depends_on
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|dependency| phase_levels.get(dependency))
+                .map(|level| level + 1)
+                .max()
+                .unwrap_or(0);
+
+            phase_levels.insert(phase.get_name(), level);
+            if levels.len() <= level {
+                levels.resize(level + 1, Vec::new());
+            }
+            levels[level].push(phase);
+        }
+
+        Ok(levels)
+    }
+
     /// Given a Phase name, returns a vector containing that Phase and its direct and transitive dependencies.
     pub fn get_phases_with_dependencies(&self, phase_name: &str) -> Phases {
         let p = self.get_phase(phase_name);
@@ -201,6 +402,32 @@ impl BuildPlan {
         phases
     }
 
+    /// Keep only the setup and install phases, with the phases they depend on, and drop the start phase.
+    ///
+    /// These are the layers that only change with the app's packages and lockfiles, which `nixpacks cache warm`
+    /// builds ahead of the app.
+    pub fn keep_install_phases(&mut self) {
+        let names = self
+            .phases
+            .clone()
+            .unwrap_or_default()
+            .into_keys()
+            .filter(|name| {
+                ["setup", "install"]
+                    .iter()
+                    .any(|kind| name == kind || name.ends_with(&format!(":{kind}")))
+            })
+            .collect::<Vec<_>>();
+
+        let mut phases = Phases::new();
+        for name in names {
+            phases.extend(self.get_phases_with_dependencies(&name));
+        }
+
+        self.phases = Some(phases);
+        self.start_phase = None;
+    }
+
     /// Given another BuildPlan, merge its phases with this BuildPlan.
     pub fn add_phases_from_another_plan(
         &mut self,
@@ -301,13 +528,21 @@ impl BuildPlan {
         }
 
         // Start
-        let start = env.get_config_variable("START_CMD").map(StartPhase::new);
+        let mut start = env.get_config_variable("START_CMD").map(StartPhase::new);
+
+        // The app is expected to listen on PORT when it's set
+        if let Some(port) = env.get_variable("PORT") {
+            start
+                .get_or_insert_with(StartPhase::default)
+                .add_port(port.to_string());
+        }
 
         BuildPlan::new(&phases, start)
     }
 
     /// Store the base image and phase dependencies in this BuildPlan, for later reproducibility.
     pub fn pin(&mut self, use_debian: bool) {
+        self.schema_version = Some(SCHEMA_VERSION);
         self.providers = Some(Vec::new());
         if self.build_image.is_none() {
             let base_image = if use_debian {
@@ -327,6 +562,25 @@ impl BuildPlan {
         if let Some(start) = &mut self.start_phase {
             start.pin();
         }
+
+        if let Some(nix) = &mut self.nix {
+            nix.substituters = nix.substituters.take().map(remove_autos_from_vec);
+            nix.trusted_public_keys = nix.trusted_public_keys.take().map(remove_autos_from_vec);
+        }
+    }
+
+    /// Install the Nix packages of every phase from the nixpkgs revision the project pins, replacing the ones the
+    /// providers picked.
+    pub fn apply_nixpkgs_pin(&mut self) -> Result<()> {
+        if let Some(nixpkgs) = &self.nixpkgs {
+            let revision = parse_nixpkgs_revision(nixpkgs)?;
+            for phase in self.phases.get_or_insert_with(Phases::default).values_mut() {
+                if phase.uses_nix() {
+                    phase.set_nix_archive(revision.clone());
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Prefix each phase name with the name of the provider that generated the phase, in the case of multiple providers.
@@ -346,6 +600,103 @@ impl BuildPlan {
         }
     }
 
+    /// Make the phases of this plan that don't depend on any other phase run after the last phases of another plan,
+    /// the ones no other phase of it depends on.
+    ///
+    /// Used for multi-provider builds, so the phases of each provider run in the order the providers are used in.
+    pub fn depend_on_last_phases_of(&mut self, other: &BuildPlan) {
+        let other_phases = other.phases.clone().unwrap_or_default();
+        let last_phases = other_phases
+            .values()
+            .map(Phase::get_name)
+            .filter(|name| {
+                !other_phases.values().any(|phase| {
+                    phase
+                        .depends_on
+                        .as_ref()
+                        .map_or(false, |depends_on| depends_on.contains(name))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(phases) = &mut self.phases {
+            let names = phases.values().map(Phase::get_name).collect::<Vec<_>>();
+            for phase in phases.values_mut() {
+                let is_first = !phase.depends_on.as_ref().map_or(false, |depends_on| {
+                    depends_on.iter().any(|name| names.contains(name))
+                });
+                if is_first {
+                    for name in &last_phases {
+                        phase.depends_on_phase(name);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the plan from a subdirectory of the build context, for apps detected in a subdirectory of a monorepo.
+    ///
+    /// Commands and the start command `cd` into the directory, and the directories they use are relative to it. Phases
+    /// copy the whole source, since installing an app of a workspace needs the manifest and lockfile at its root and
+    /// the other packages of the workspace.
+    pub fn move_to_subdirectory(&mut self, dir: &str) {
+        let dir = dir.trim_start_matches("./").trim_end_matches('/');
+        if dir.is_empty() || dir == "." {
+            return;
+        }
+
+        let quoted_dir = shell_quote(dir);
+        let in_dir = |cmd: &String| {
+            if cmd == "..." {
+                cmd.clone()
+            } else {
+                format!("cd {quoted_dir} && {cmd}")
+            }
+        };
+        let relative_to_dir = |paths: &Option<Vec<String>>| {
+            paths.as_ref().map(|paths| {
+                paths
+                    .iter()
+                    .map(|path| {
+                        if path.starts_with('/') || path.starts_with('~') || path == "..." {
+                            path.clone()
+                        } else if path == "." {
+                            dir.to_string()
+                        } else {
+                            format!("{dir}/{}", path.trim_start_matches("./"))
+                        }
+                    })
+                    .collect()
+            })
+        };
+
+        if let Some(phases) = &mut self.phases {
+            for phase in phases.values_mut() {
+                phase.cmds = phase
+                    .cmds
+                    .as_ref()
+                    .map(|cmds| cmds.iter().map(in_dir).collect());
+                phase.only_include_files = None;
+                phase.cache_directories = relative_to_dir(&phase.cache_directories);
+                phase.persisted_directories = relative_to_dir(&phase.persisted_directories);
+                phase.paths = phase.paths.as_ref().map(|paths| {
+                    paths
+                        .iter()
+                        .map(|path| match path.strip_prefix(APP_DIR) {
+                            Some(path) => format!("{APP_DIR}{dir}/{path}"),
+                            None => path.clone(),
+                        })
+                        .collect()
+                });
+            }
+        }
+
+        if let Some(start) = &mut self.start_phase {
+            start.cmd = start.cmd.as_ref().map(in_dir);
+            start.only_include_files = relative_to_dir(&start.only_include_files);
+        }
+    }
+
     /// Combine plans from multiple sources (environment variables, config files, providers) into a single plan.
     pub fn merge_plans(plans: &[BuildPlan]) -> BuildPlan {
         plans.iter().fold(BuildPlan::default(), |acc, plan| {
@@ -377,6 +728,25 @@ fn split_env_string(s: &str) -> Vec<String> {
         .collect::<Vec<_>>()
 }
 
+/// Whether a plan that isn't JSON is written in YAML rather than TOML, from its first line that isn't a comment. YAML
+/// starts with `---`, a list item, or a `key:` mapping, and TOML with a `[table]` or `key = value`.
+fn looks_like_yaml(contents: &str) -> bool {
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+    if line.starts_with("---") || line.starts_with("- ") {
+        return true;
+    }
+
+    match (line.find(':'), line.find('=')) {
+        (Some(colon), Some(equals)) => colon < equals,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -422,6 +792,17 @@ mod test {
         assert_eq!(result, env_plan);
     }
 
+    #[test]
+    fn get_ports_from_environment() {
+        let env = Environment::from_envs(vec!["PORT=8080"]).unwrap();
+        let env_plan = BuildPlan::from_environment(&env);
+
+        assert_eq!(
+            env_plan.start_phase.unwrap().ports,
+            Some(vec!["8080".to_string()])
+        );
+    }
+
     #[test]
     fn test_get_phases_with_dependencies() {
         let setup = Phase::new("setup");
@@ -443,6 +824,61 @@ mod test {
         assert_eq!(phases.len(), 3);
     }
 
+    #[test]
+    fn test_keep_install_phases() {
+        let setup = Phase::new("setup");
+
+        let mut node_install = Phase::new("node:install");
+        node_install.depends_on_phase("setup");
+
+        let mut build = Phase::new("build");
+        build.depends_on_phase("node:install");
+
+        let mut plan = BuildPlan::new(
+            &[setup, node_install, build],
+            Some(StartPhase::new("npm start")),
+        );
+        plan.keep_install_phases();
+
+        assert_eq!(
+            plan.phases.unwrap().into_keys().collect::<Vec<_>>(),
+            vec!["node:install".to_string(), "setup".to_string()]
+        );
+        assert!(plan.start_phase.is_none());
+    }
+
+    #[test]
+    fn test_get_phase_levels() {
+        let setup = Phase::new("setup");
+
+        let mut node_install = Phase::new("node:install");
+        node_install.depends_on_phase("setup");
+
+        let mut python_install = Phase::new("python:install");
+        python_install.depends_on_phase("setup");
+
+        let mut build = Phase::new("build");
+        build.depends_on_phase("node:install");
+        build.depends_on_phase("python:install");
+
+        let plan = BuildPlan::new(&[setup, node_install, python_install, build], None);
+
+        let levels = plan
+            .get_phase_levels()
+            .unwrap()
+            .iter()
+            .map(|level| level.iter().map(Phase::get_name).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            vec![
+                vec!["setup".to_string()],
+                vec!["node:install".to_string(), "python:install".to_string()],
+                vec!["build".to_string()],
+            ]
+        );
+    }
+
     #[test]
     fn test_pin_build_plan() {
         let mut plan = BuildPlan::from_toml(
@@ -467,6 +903,155 @@ mod test {
         assert!(plan.get_phase("setup").unwrap().nixpkgs_archive.is_some());
     }
 
+    #[test]
+    fn test_apply_nixpkgs_pin() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            nixpkgs = "github:NixOS/nixpkgs/abc123"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+
+            [phases.install]
+            nixPkgs = ["uv"]
+            nixpkgsArchive = "nixos-24.11"
+
+            [phases.build]
+            cmds = ["yarn run build"]
+            "#,
+        )
+        .unwrap();
+
+        plan.apply_nixpkgs_pin().unwrap();
+        plan.pin(false);
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nixpkgs_archive,
+            Some("abc123".to_string())
+        );
+        assert_eq!(
+            plan.get_phase("install").unwrap().nixpkgs_archive,
+            Some("abc123".to_string())
+        );
+        assert_eq!(plan.get_phase("build").unwrap().nixpkgs_archive, None);
+
+        plan.nixpkgs = Some("github:my-org/nixpkgs/abc123".to_string());
+        assert!(plan.apply_nixpkgs_pin().is_err());
+    }
+
+    #[test]
+    fn test_yaml_build_plan() {
+        let plan = BuildPlan::from_yaml(
+            r#"
+            phases:
+              setup:
+                nixPkgs: ["...", "cowsay"]
+              build:
+                cmds:
+                  - yarn run build
+            start:
+              cmd: yarn run start
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.get_phase("build").unwrap().name,
+            Some("build".to_string())
+        );
+        assert_eq!(
+            plan.start_phase.clone().unwrap().cmd,
+            Some("yarn run start".to_string())
+        );
+        assert_eq!(BuildPlan::from_yaml(plan.to_yaml().unwrap()).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_parse_build_plan() {
+        let json = BuildPlan::parse(r#"{ "start": { "cmd": "yarn run start" } }"#).unwrap();
+        let toml = BuildPlan::parse("[start]\ncmd = 'yarn run start'").unwrap();
+        let yaml = BuildPlan::parse("start:\n  cmd: yarn run start").unwrap();
+
+        assert_eq!(
+            json.start_phase.clone().unwrap().cmd,
+            Some("yarn run start".to_string())
+        );
+        assert_eq!(toml, json);
+        assert_eq!(yaml, json);
+    }
+
+    #[test]
+    fn test_looks_like_yaml() {
+        assert!(looks_like_yaml("# A plan\nstart:\n  cmd: FOO=bar ./start"));
+        assert!(looks_like_yaml("---\nstart: {}"));
+        assert!(!looks_like_yaml("[start]\ncmd = 'yarn run start'"));
+        assert!(!looks_like_yaml("providers = ['node:18']"));
+        // A TOML plan with a typo is reported as TOML
+        assert!(!looks_like_yaml("[start\ncmd = 'yarn run start'"));
+    }
+
+    #[test]
+    fn test_parse_providers() {
+        let list = BuildPlan::from_toml("providers = ['node', 'python']").unwrap();
+        assert_eq!(
+            list.providers,
+            Some(vec!["node".to_string(), "python".to_string()])
+        );
+
+        let table = BuildPlan::from_toml("[providers]\ndisable = ['staticfile']").unwrap();
+        assert_eq!(
+            table.providers,
+            Some(vec!["...".to_string(), "!staticfile".to_string()])
+        );
+
+        let table =
+            BuildPlan::from_toml("[providers]\nuse = ['python', '...']\ndisable = ['node']")
+                .unwrap();
+        assert_eq!(
+            table.providers,
+            Some(vec![
+                "python".to_string(),
+                "...".to_string(),
+                "!node".to_string()
+            ])
+        );
+
+        assert_eq!(BuildPlan::from_toml("").unwrap().providers, None);
+    }
+
+    #[test]
+    fn test_parse_start_user() {
+        let plan = BuildPlan::from_toml("[start]\nuser = 'app'").unwrap();
+        assert_eq!(plan.start_phase.unwrap().user, Some("app".to_string()));
+
+        let plan = BuildPlan::from_toml("[start]\nuser = '1000:1000'").unwrap();
+        assert_eq!(
+            plan.start_phase.unwrap().user,
+            Some("1000:1000".to_string())
+        );
+
+        assert!(BuildPlan::from_toml("[start]\nuser = 'app; rm -rf /'").is_err());
+        assert!(BuildPlan::from_toml("[start]\nuser = 'App'").is_err());
+        assert!(BuildPlan::from_json(r#"{ "start": { "user": "$(id)" } }"#).is_err());
+    }
+
+    #[test]
+    fn test_depend_on_last_phases_of() {
+        let first = BuildPlan::new(&[Phase::setup(None), Phase::install(None)], None);
+
+        let mut second = BuildPlan::new(&[Phase::setup(None), Phase::install(None)], None);
+        second.prefix_phases("python");
+        second.depend_on_last_phases_of(&first);
+
+        assert_eq!(
+            second.get_phase("python:setup").unwrap().depends_on,
+            Some(vec!["install".to_string()])
+        );
+        assert_eq!(
+            second.get_phase("python:install").unwrap().depends_on,
+            Some(vec!["python:setup".to_string()])
+        );
+    }
+
     #[test]
     fn test_split_env_string() {
         assert_eq!(
@@ -478,4 +1063,55 @@ mod test {
             vec!["nodejs".to_string(), "yarn".to_string()]
         );
     }
+
+    #[test]
+    fn test_move_to_subdirectory() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [phases.install]
+            cmds = ["npm ci"]
+            cacheDirectories = ["node_modules/.cache", "/root/.npm"]
+            paths = ["/app/node_modules/.bin"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+            onlyIncludeFiles = ["./package.json", "."]
+
+            [start]
+            cmd = "npm run start"
+            "#,
+        )
+        .unwrap();
+
+        plan.move_to_subdirectory("./apps/api/");
+
+        let install = plan.get_phase("install").unwrap();
+        assert_eq!(
+            install.cmds,
+            Some(vec!["cd 'apps/api' && npm ci".to_string()])
+        );
+        assert_eq!(
+            install.cache_directories,
+            Some(vec![
+                "apps/api/node_modules/.cache".to_string(),
+                "/root/.npm".to_string()
+            ])
+        );
+        assert_eq!(
+            install.paths,
+            Some(vec!["/app/apps/api/node_modules/.bin".to_string()])
+        );
+        assert_eq!(plan.get_phase("build").unwrap().only_include_files, None);
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("cd 'apps/api' && npm run start".to_string())
+        );
+
+        let mut plan = BuildPlan::from_toml("[phases.build]\ncmds = ['make']").unwrap();
+        plan.move_to_subdirectory("apps/my app");
+        assert_eq!(
+            plan.get_phase("build").unwrap().cmds,
+            Some(vec!["cd 'apps/my app' && make".to_string()])
+        );
+    }
 }
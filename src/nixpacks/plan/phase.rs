@@ -1,12 +1,14 @@
 use crate::nixpacks::{
-    images::{DEFAULT_BASE_IMAGE, STANDALONE_IMAGE},
+    images::{DEFAULT_BASE_IMAGE, SCRATCH_IMAGE, STANDALONE_IMAGE},
     nix::{pkg::Pkg, NIXPACKS_ARCHIVE_LEGACY_OPENSSL, NIXPKGS_ARCHIVE},
 };
-use serde::{Deserialize, Serialize};
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::{BTreeMap, HashSet};
 use std::hash::Hash;
 
-use super::utils::remove_autos_from_vec;
+use super::{builder::PhaseBuilder, utils::remove_autos_from_vec};
 
 pub type Phases = BTreeMap<String, Phase>;
 
@@ -42,6 +44,10 @@ pub struct Phase {
     #[serde(rename = "cacheDirectories")]
     pub cache_directories: Option<Vec<String>>,
 
+    /// Directories the phase writes that stay in the image, like a virtualenv or build output.
+    /// With an incremental cache image, they're restored before the phase runs and saved after it.
+    pub persisted_directories: Option<Vec<String>>,
+
     #[serde(alias = "envPaths")]
     pub paths: Option<Vec<String>>,
 }
@@ -54,6 +60,51 @@ pub struct StartPhase {
     pub cmd: Option<String>,
     pub run_image: Option<String>,
     pub only_include_files: Option<Vec<String>>,
+    /// Run the app as this user instead of root. It's created if the image doesn't have it.
+    #[serde(default, deserialize_with = "deserialize_user")]
+    pub user: Option<String>,
+    pub healthcheck: Option<Healthcheck>,
+    /// Ports the app likely listens on, e.g. `3000` or `53/udp`, exposed by the image.
+    pub ports: Option<Vec<String>>,
+}
+
+/// Check that a user to run the app as is a plain user name, or a numeric user ID like `1000` or `1000:1000` for
+/// images without `useradd`. It's used in the image's shell commands, so anything else is rejected.
+pub fn validate_user(user: &str) -> Result<()> {
+    let name = Regex::new(r"^[a-z_][a-z0-9_-]*$").expect("BUG: Broken regex");
+    let id = Regex::new(r"^\d+(:\d+)?$").expect("BUG: Broken regex");
+    if !name.is_match(user) && !id.is_match(user) {
+        bail!("Invalid user `{user}`. Use a lowercase user name like `app`, or a numeric user ID");
+    }
+
+    Ok(())
+}
+
+fn deserialize_user<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let user = Option::<String>::deserialize(deserializer)?;
+    if let Some(user) = &user {
+        validate_user(user).map_err(serde::de::Error::custom)?;
+    }
+
+    Ok(user)
+}
+
+/// A command the container engine runs periodically to check that the app is healthy.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Healthcheck {
+    /// Run in a login shell, like the start command. The app is unhealthy when it exits with a non-zero status.
+    pub cmd: Option<String>,
+    /// How long to wait between checks, e.g. `30s`.
+    pub interval: Option<String>,
+    /// How long a check can take before it counts as failed, e.g. `5s`.
+    pub timeout: Option<String>,
+    /// How many failed checks in a row make the app unhealthy.
+    pub retries: Option<u32>,
 }
 
 impl Phase {
@@ -64,6 +115,11 @@ impl Phase {
         }
     }
 
+    /// Starts building a phase with [`PhaseBuilder`], for adding it to a plan in code.
+    pub fn builder<S: Into<String>>(name: S) -> PhaseBuilder {
+        PhaseBuilder::new(name)
+    }
+
     /// Returns the name of this phase.
     pub fn get_name(&self) -> String {
         self.name.clone().unwrap_or_default()
@@ -197,6 +253,16 @@ impl Phase {
         self.cache_directories = Some(new_directories);
     }
 
+    /// Add a directory that stays in the image and is saved to the incremental cache image.
+    pub fn add_persisted_directory<S: Into<String>>(&mut self, dir: S) {
+        let mut new_directories = prevent_duplicates_vec(add_to_option_vec(
+            self.persisted_directories.clone(),
+            dir.into(),
+        ));
+        new_directories.sort();
+        self.persisted_directories = Some(new_directories);
+    }
+
     /// Add the given path to a list of paths that should be present in the built image.
     pub fn add_path(&mut self, path: String) {
         self.paths = Some(add_to_option_vec(self.paths.clone(), path));
@@ -225,6 +291,7 @@ impl Phase {
         self.nix_overlays = pin_option_vec(&self.nix_overlays);
         self.only_include_files = pin_option_vec(&self.only_include_files);
         self.cache_directories = pin_option_vec(&self.cache_directories);
+        self.persisted_directories = pin_option_vec(&self.persisted_directories);
         self.paths = pin_option_vec(&self.paths);
     }
 }
@@ -252,6 +319,16 @@ impl StartPhase {
         self.run_image = Some(STANDALONE_IMAGE.to_string());
     }
 
+    /// Run the StartPhase in an empty image, without a shell. The start command is run directly.
+    pub fn run_in_scratch_image(&mut self) {
+        self.run_image = Some(SCRATCH_IMAGE.to_string());
+    }
+
+    /// Whether the StartPhase runs in an empty image, without a shell.
+    pub fn runs_in_scratch_image(&self) -> bool {
+        self.run_image.as_deref() == Some(SCRATCH_IMAGE)
+    }
+
     /// Add a file to the set of files to copy into the container image.
     pub fn add_file_dependency<S: Into<String>>(&mut self, file: S) {
         self.only_include_files = Some(add_to_option_vec(
@@ -260,9 +337,15 @@ impl StartPhase {
         ));
     }
 
+    /// Add a port the app listens on.
+    pub fn add_port<S: Into<String>>(&mut self, port: S) {
+        self.ports = Some(add_to_option_vec(self.ports.clone(), port.into()));
+    }
+
     /// Store the list of files to include in this phase for later reproducibility.
     pub fn pin(&mut self) {
         self.only_include_files = pin_option_vec(&self.only_include_files);
+        self.ports = pin_option_vec(&self.ports);
     }
 }
 
@@ -1,3 +1,6 @@
+use crate::nixpacks::environment::EnvironmentVariables;
+use regex::{Captures, Regex};
+
 /// Removes all the `"..."`'s or `"@auto"`'s from the `original`
 pub fn remove_autos_from_vec(original: Vec<String>) -> Vec<String> {
     original
@@ -41,6 +44,24 @@ pub fn fill_auto_in_vec(
     }
 }
 
+/// Replaces each `${NAME}` in `s` with the value of the variable, leaving unknown variables for the shell.
+///
+/// `$${NAME}` escapes the replacement and becomes `${NAME}`.
+pub fn interpolate_variables(s: &str, variables: &EnvironmentVariables) -> String {
+    let re = Regex::new(r"\$(\$?)\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    re.replace_all(s, |caps: &Captures| {
+        let name = &caps[2];
+        if !caps[1].is_empty() {
+            format!("${{{name}}}")
+        } else if let Some(value) = variables.get(name) {
+            value.clone()
+        } else {
+            caps[0].to_string()
+        }
+    })
+    .to_string()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -86,4 +107,29 @@ mod test {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_interpolate_variables() {
+        let variables = EnvironmentVariables::from([
+            ("NODE_ENV".to_string(), "production".to_string()),
+            ("NIXPACKS_SRC".to_string(), "/app".to_string()),
+        ]);
+
+        assert_eq!(
+            interpolate_variables("NODE_ENV=${NODE_ENV} npm run build", &variables),
+            "NODE_ENV=production npm run build"
+        );
+        assert_eq!(
+            interpolate_variables("${NIXPACKS_SRC}/.cache", &variables),
+            "/app/.cache"
+        );
+        assert_eq!(
+            interpolate_variables("echo $${NODE_ENV} ${PORT} $NODE_ENV", &variables),
+            "echo ${NODE_ENV} ${PORT} $NODE_ENV"
+        );
+        assert_eq!(
+            interpolate_variables("rails s -p ${PORT:-3000}", &variables),
+            "rails s -p ${PORT:-3000}"
+        );
+    }
 }
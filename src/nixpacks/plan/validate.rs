@@ -0,0 +1,444 @@
+use super::{
+    super::nix::NixConfig,
+    migrate::upgrade_plan,
+    phase::{Healthcheck, Phase, StartPhase},
+    BuildPlan,
+};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+};
+
+/// Phases that providers generate, which config files can depend on without defining them.
+const PROVIDER_PHASES: &[&str] = &["setup", "install", "build"];
+
+/// Older names of phase fields that are still accepted.
+const PHASE_ALIASES: &[&str] = &[
+    "nixPackages",
+    "nixLibraries",
+    "aptPackages",
+    "commands",
+    "envPaths",
+];
+
+/// Fields of config files that aren't part of the plan, like the services read by `nixpacks compose`.
+const CONFIG_FIELDS: &[&str] = &["services"];
+
+/// A problem in a build plan or Nixpacks config file, and the dotted path to where it is.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PlanError {
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Read a plan or config file and check it with [`validate_plan`].
+pub fn validate_plan_file(file_path: &str) -> Result<Vec<PlanError>> {
+    let contents = fs::read_to_string(file_path)
+        .with_context(|| format!("Failed to read plan file `{file_path}`"))?;
+    validate_plan(&contents, file_path)
+}
+
+/// Check a JSON, TOML, or YAML plan, depending on the extension of `file_path`, for unknown fields, dependencies on phases
+/// that don't exist, and circular dependencies, which are otherwise ignored or reported without a location.
+///
+/// Syntax errors and values of the wrong type are returned as errors, with the line they are on.
+pub fn validate_plan(contents: &str, file_path: &str) -> Result<Vec<PlanError>> {
+    let ext = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let value = match ext {
+        "toml" => {
+            BuildPlan::from_toml(contents)
+                .with_context(|| format!("Failed to parse plan file `{file_path}`"))?;
+            serde_json::to_value(toml::from_str::<toml::Value>(contents)?)?
+        }
+        "json" => {
+            BuildPlan::from_json(contents)
+                .with_context(|| format!("Failed to parse plan file `{file_path}`"))?;
+            serde_json::from_str(contents)?
+        }
+        "yaml" | "yml" => {
+            BuildPlan::from_yaml(contents)
+                .with_context(|| format!("Failed to parse plan file `{file_path}`"))?;
+            serde_yaml::from_str(contents)?
+        }
+        _ => bail!("Unknown file type: {}", file_path),
+    };
+    // Checked in the layout it's read in, with the fields of older layouts renamed
+    let value = upgrade_plan(value)?;
+
+    let known = KnownFields::new()?;
+    let mut errors = Vec::new();
+    check_fields(&value, &known.plan, "", &mut errors);
+    if let Some(phases) = value.get("phases").and_then(Value::as_object) {
+        for (name, phase) in phases {
+            check_fields(phase, &known.phase, &format!("phases.{name}"), &mut errors);
+        }
+    }
+    if let Some(start) = value.get("start") {
+        check_fields(start, &known.start, "start", &mut errors);
+        if let Some(healthcheck) = start.get("healthcheck") {
+            check_fields(
+                healthcheck,
+                &known.healthcheck,
+                "start.healthcheck",
+                &mut errors,
+            );
+        }
+    }
+    if let Some(nix) = value.get("nix") {
+        check_fields(nix, &known.nix, "nix", &mut errors);
+    }
+    check_dependencies(&value, &mut errors);
+
+    Ok(errors)
+}
+
+/// Check a plan for dependencies on phases that don't exist and circular dependencies, like [`validate_plan`] does
+/// for files.
+pub fn validate_build_plan(plan: &BuildPlan) -> Result<Vec<PlanError>> {
+    let mut errors = Vec::new();
+    check_dependencies(&serde_json::to_value(plan)?, &mut errors);
+    Ok(errors)
+}
+
+/// The field names accepted in each part of a plan.
+struct KnownFields {
+    plan: BTreeSet<String>,
+    phase: BTreeSet<String>,
+    start: BTreeSet<String>,
+    healthcheck: BTreeSet<String>,
+    nix: BTreeSet<String>,
+}
+
+impl KnownFields {
+    fn new() -> Result<Self> {
+        // Every field is set, so adding one to these structs without updating this fails to compile
+        let plan = BuildPlan {
+            schema_version: Some(0),
+            providers: Some(Vec::new()),
+            build_image: Some(String::new()),
+            variables: Some(BTreeMap::new()),
+            static_assets: Some(BTreeMap::new()),
+            phases: Some(BTreeMap::new()),
+            start_phase: Some(StartPhase::default()),
+            processes: Some(BTreeMap::new()),
+            nix: Some(NixConfig::default()),
+            nixpkgs: Some(String::new()),
+        };
+        let phase = Phase {
+            name: Some(String::new()),
+            depends_on: Some(Vec::new()),
+            nix_pkgs: Some(Vec::new()),
+            nix_libs: Some(Vec::new()),
+            nix_overlays: Some(Vec::new()),
+            nixpkgs_archive: Some(String::new()),
+            apt_pkgs: Some(Vec::new()),
+            cmds: Some(Vec::new()),
+            only_include_files: Some(Vec::new()),
+            cache_directories: Some(Vec::new()),
+            persisted_directories: Some(Vec::new()),
+            paths: Some(Vec::new()),
+        };
+        let start = StartPhase {
+            cmd: Some(String::new()),
+            run_image: Some(String::new()),
+            only_include_files: Some(Vec::new()),
+            user: Some(String::new()),
+            healthcheck: Some(Healthcheck::default()),
+            ports: Some(Vec::new()),
+        };
+        let healthcheck = Healthcheck {
+            cmd: Some(String::new()),
+            interval: Some(String::new()),
+            timeout: Some(String::new()),
+            retries: Some(0),
+        };
+        let nix = NixConfig {
+            substituters: Some(Vec::new()),
+            trusted_public_keys: Some(Vec::new()),
+        };
+
+        let mut phase_fields = field_names(&phase)?;
+        phase_fields.extend(PHASE_ALIASES.iter().map(ToString::to_string));
+
+        let mut plan_fields = field_names(&plan)?;
+        plan_fields.extend(CONFIG_FIELDS.iter().map(ToString::to_string));
+
+        Ok(Self {
+            plan: plan_fields,
+            phase: phase_fields,
+            start: field_names(&start)?,
+            healthcheck: field_names(&healthcheck)?,
+            nix: field_names(&nix)?,
+        })
+    }
+}
+
+fn field_names<T: Serialize>(value: &T) -> Result<BTreeSet<String>> {
+    Ok(serde_json::to_value(value)?
+        .as_object()
+        .map(|fields| fields.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// Report the fields of an object in the plan that aren't in `names`.
+fn check_fields(
+    value: &Value,
+    names: &BTreeSet<String>,
+    location: &str,
+    errors: &mut Vec<PlanError>,
+) {
+    let fields = match value.as_object() {
+        Some(fields) => fields,
+        None => return,
+    };
+
+    for key in fields.keys().filter(|key| !names.contains(*key)) {
+        let suggestion = closest_name(key, names)
+            .map(|name| format!(", did you mean `{name}`?"))
+            .unwrap_or_default();
+        errors.push(PlanError {
+            location: if location.is_empty() {
+                key.clone()
+            } else {
+                format!("{location}.{key}")
+            },
+            message: format!("Unknown field `{key}`{suggestion}"),
+        });
+    }
+}
+
+/// Report dependencies on phases that don't exist, and dependency cycles.
+fn check_dependencies(value: &Value, errors: &mut Vec<PlanError>) {
+    let phases = match value.get("phases").and_then(Value::as_object) {
+        Some(phases) => phases,
+        None => return,
+    };
+
+    let graph = phases
+        .iter()
+        .map(|(name, phase)| {
+            let dependencies = phase
+                .get("dependsOn")
+                .and_then(Value::as_array)
+                .map(|deps| {
+                    deps.iter()
+                        .filter_map(Value::as_str)
+                        .filter(|dep| *dep != "..." && *dep != "@auto")
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            (name.clone(), dependencies)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    for (name, dependencies) in &graph {
+        for dependency in dependencies {
+            if !graph.contains_key(dependency) && !PROVIDER_PHASES.contains(&dependency.as_str()) {
+                errors.push(PlanError {
+                    location: format!("phases.{name}.dependsOn"),
+                    message: format!("Unknown phase `{dependency}`"),
+                });
+            }
+        }
+    }
+
+    let mut reported = BTreeSet::new();
+    for name in graph.keys() {
+        if let Some(cycle) = find_cycle(name, &graph, &mut Vec::new()) {
+            let members = cycle.iter().cloned().collect::<BTreeSet<_>>();
+            if reported.insert(members) {
+                errors.push(PlanError {
+                    location: format!("phases.{name}.dependsOn"),
+                    message: format!("Circular dependency {}", cycle.join(" -> ")),
+                });
+            }
+        }
+    }
+}
+
+/// The path of a cycle back to the first phase of `stack`, depth-first from `name`.
+fn find_cycle(
+    name: &str,
+    graph: &BTreeMap<String, Vec<String>>,
+    stack: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    if stack.first().map_or(false, |start| start == name) {
+        let mut cycle = stack.clone();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    if stack.iter().any(|visited| visited == name) {
+        return None;
+    }
+
+    stack.push(name.to_string());
+    let cycle = graph
+        .get(name)
+        .into_iter()
+        .flatten()
+        .find_map(|dependency| find_cycle(dependency, graph, stack));
+    stack.pop();
+    cycle
+}
+
+/// The known name that a typo was most likely meant to be, if any is close enough.
+fn closest_name<'a>(name: &str, names: &'a BTreeSet<String>) -> Option<&'a String> {
+    names
+        .iter()
+        .map(|candidate| {
+            (
+                edit_distance(&name.to_lowercase(), &candidate.to_lowercase()),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_config() {
+        let errors = validate_plan(
+            indoc::indoc! {r#"
+                [phases.setup]
+                nixPkgs = ["...", "cowsay"]
+
+                [phases.migrate]
+                dependsOn = ["install"]
+                cmds = ["npm run migrate"]
+
+                [phases.build]
+                dependsOn = ["...", "migrate"]
+
+                [start]
+                cmd = "npm run start"
+                healthcheck = { cmd = "curl -f localhost:3000" }
+
+                [nix]
+                substituters = ["https://my-team.cachix.org"]
+                trustedPublicKeys = ["my-team.cachix.org-1:abc="]
+
+                [services.db]
+                image = "postgres:15"
+            "#},
+            "nixpacks.toml",
+        )
+        .unwrap();
+        assert_eq!(errors, Vec::new());
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let errors = validate_plan(
+            r#"{
+                "phases": { "build": { "cmd": ["npm run build"], "aptPackages": ["git"] } },
+                "start": { "cmd": "npm run start", "healthcheck": { "retrys": 3 } },
+                "nix": { "substituter": ["https://my-team.cachix.org"] },
+                "buildImg": "ubuntu"
+            }"#,
+            "plan.json",
+        )
+        .unwrap();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "buildImg: Unknown field `buildImg`, did you mean `buildImage`?",
+                "phases.build.cmd: Unknown field `cmd`, did you mean `cmds`?",
+                "start.healthcheck.retrys: Unknown field `retrys`, did you mean `retries`?",
+                "nix.substituter: Unknown field `substituter`, did you mean `substituters`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_dependency() {
+        let errors = validate_plan(
+            indoc::indoc! {r#"
+                [phases.build]
+                dependsOn = ["instal"]
+            "#},
+            "nixpacks.toml",
+        )
+        .unwrap();
+        assert_eq!(
+            errors,
+            vec![PlanError {
+                location: "phases.build.dependsOn".to_string(),
+                message: "Unknown phase `instal`".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_circular_dependency() {
+        let errors = validate_plan(
+            indoc::indoc! {r#"
+                [phases.a]
+                dependsOn = ["b"]
+
+                [phases.b]
+                dependsOn = ["c"]
+
+                [phases.c]
+                dependsOn = ["a"]
+
+                [phases.d]
+                dependsOn = ["d"]
+            "#},
+            "nixpacks.toml",
+        )
+        .unwrap();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "phases.a.dependsOn: Circular dependency a -> b -> c -> a",
+                "phases.d.dependsOn: Circular dependency d -> d",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let error = validate_plan(
+            indoc::indoc! {r#"
+                [phases.build]
+                cmds = "npm run build"
+            "#},
+            "nixpacks.toml",
+        )
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("line 2"));
+    }
+}
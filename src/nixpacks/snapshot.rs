@@ -0,0 +1,139 @@
+use anyhow::Result;
+use ignore::WalkBuilder;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// How often an app directory is checked for changes while watching it.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The modification time and size of every file of an app, keyed by its path relative to the source directory.
+pub type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+/// Files that differ between two snapshots.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Files that were added or modified.
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// List the files of an app, relative to its source directory.
+///
+/// Files excluded by `.gitignore` and the `.git` directory itself are skipped.
+pub fn app_files(source: &Path) -> Result<Vec<PathBuf>> {
+    let walker = WalkBuilder::new(source)
+        .follow_links(false)
+        // this includes hidden directories & files
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry?;
+        if entry.file_type().map_or(false, |t| t.is_file()) {
+            files.push(entry.path().strip_prefix(source)?.to_path_buf());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Record the state of the files in the app directory, skipping those excluded by `.gitignore`.
+pub fn snapshot(source: &Path) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+    for file in app_files(source)? {
+        // The file may have been removed since it was listed
+        if let Ok(metadata) = fs::metadata(source.join(&file)) {
+            snapshot.insert(file, (metadata.modified()?, metadata.len()));
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Compare two snapshots of the app directory.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Changes {
+    let mut changes = Changes {
+        changed: new
+            .iter()
+            .filter(|(file, state)| old.get(*file) != Some(state))
+            .map(|(file, _)| file.clone())
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|file| !new.contains_key(*file))
+            .cloned()
+            .collect(),
+    };
+    changes.changed.sort();
+    changes.removed.sort();
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_app_files_respects_gitignore() {
+        let dir = TempDir::new("nixpacks-snapshot").unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules/left-pad")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".gitignore"), "node_modules\n").unwrap();
+        fs::write(root.join(".env.example"), "PORT=3000").unwrap();
+        fs::write(root.join("src/index.js"), "").unwrap();
+        fs::write(root.join("node_modules/left-pad/index.js"), "").unwrap();
+        fs::write(root.join(".git/HEAD"), "").unwrap();
+
+        assert_eq!(
+            app_files(root).unwrap(),
+            vec![
+                PathBuf::from(".env.example"),
+                PathBuf::from(".gitignore"),
+                PathBuf::from("src/index.js"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+        let old = Snapshot::from([
+            (PathBuf::from("index.js"), (t0, 10)),
+            (PathBuf::from("package.json"), (t0, 20)),
+            (PathBuf::from("old.js"), (t0, 30)),
+        ]);
+        let new = Snapshot::from([
+            (PathBuf::from("index.js"), (t1, 10)),
+            (PathBuf::from("package.json"), (t0, 20)),
+            (PathBuf::from("src/new.js"), (t1, 5)),
+        ]);
+
+        assert_eq!(
+            diff(&old, &new),
+            Changes {
+                changed: vec![PathBuf::from("index.js"), PathBuf::from("src/new.js")],
+                removed: vec![PathBuf::from("old.js")],
+            }
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+}
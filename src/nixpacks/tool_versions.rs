@@ -0,0 +1,147 @@
+use super::{app::App, environment::EnvironmentVariables};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// asdf's file of the versions of each tool.
+pub const TOOL_VERSIONS_FILE: &str = ".tool-versions";
+
+/// mise's config files, in the order mise prefers them.
+pub const MISE_FILES: &[&str] = &["mise.toml", ".mise.toml"];
+
+/// The names of tools in asdf and mise, with the config variable that picks the version their provider installs.
+const TOOLS: &[(&[&str], &str)] = &[
+    (&["nodejs", "node"], "NODE_VERSION"),
+    (&["python"], "PYTHON_VERSION"),
+    (&["ruby"], "RUBY_VERSION"),
+    (&["golang", "go"], "GO_VERSION"),
+];
+
+/// Versions that don't pin one, so the provider's default or the app's other version files are used.
+const UNPINNED_VERSIONS: &[&str] = &["latest", "lts", "system"];
+
+/// The runtime versions pinned in `mise.toml` or `.tool-versions`, as the `NIXPACKS_*_VERSION` variables of the
+/// providers. When a tool has several versions, the first one is used, like asdf and mise do.
+pub fn get_tool_version_variables(app: &App) -> Result<EnvironmentVariables> {
+    let mut variables = if app.includes_file(TOOL_VERSIONS_FILE) {
+        to_variables(parse_tool_versions(&app.read_file(TOOL_VERSIONS_FILE)?))
+    } else {
+        EnvironmentVariables::new()
+    };
+
+    // mise reads `.tool-versions` too, but its own config takes precedence
+    if let Some(file) = MISE_FILES.iter().find(|file| app.includes_file(file)) {
+        let mise_versions = parse_mise_toml(&app.read_file(file)?)
+            .with_context(|| format!("Error reading {file}"))?;
+        variables.extend(to_variables(mise_versions));
+    }
+
+    Ok(variables)
+}
+
+/// The variables of the tools that have providers and pinned versions.
+fn to_variables(versions: BTreeMap<String, String>) -> EnvironmentVariables {
+    versions
+        .into_iter()
+        .filter(|(_, version)| !UNPINNED_VERSIONS.contains(&version.as_str()))
+        .filter_map(|(tool, version)| {
+            TOOLS
+                .iter()
+                .find(|(names, _)| names.contains(&tool.as_str()))
+                .map(|(_, variable)| (format!("NIXPACKS_{variable}"), version))
+        })
+        .collect()
+}
+
+/// Each tool in a `.tool-versions` file, e.g. `nodejs 18.17.0`, with its first version.
+fn parse_tool_versions(contents: &str) -> BTreeMap<String, String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Each tool in the `[tools]` table of a mise config, with its first version. Versions are strings, lists of
+/// strings, or tables with a `version`.
+fn parse_mise_toml(contents: &str) -> Result<BTreeMap<String, String>> {
+    let config: toml::Value = toml::from_str(contents)?;
+    let tools = match config.get("tools").and_then(toml::Value::as_table) {
+        Some(tools) => tools,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    Ok(tools
+        .iter()
+        .filter_map(|(tool, version)| {
+            let version = match version {
+                toml::Value::String(version) => Some(version.as_str()),
+                toml::Value::Array(versions) => versions.first().and_then(toml::Value::as_str),
+                toml::Value::Table(options) => options.get("version").and_then(toml::Value::as_str),
+                _ => None,
+            }?;
+            Some((tool.clone(), version.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_versions() {
+        let versions = parse_tool_versions(
+            "# Runtimes\nnodejs 18.17.0\npython 3.11.4 3.10.0 # fallback\n\nterraform 1.5.0\n",
+        );
+        assert_eq!(
+            versions,
+            BTreeMap::from([
+                ("nodejs".to_string(), "18.17.0".to_string()),
+                ("python".to_string(), "3.11.4".to_string()),
+                ("terraform".to_string(), "1.5.0".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_mise_toml() -> Result<()> {
+        let versions = parse_mise_toml(
+            r#"
+            [env]
+            NODE_ENV = "production"
+
+            [tools]
+            node = "20"
+            python = ["3.11", "3.10"]
+            ruby = { version = "3.2.2" }
+            "#,
+        )?;
+        assert_eq!(
+            versions,
+            BTreeMap::from([
+                ("node".to_string(), "20".to_string()),
+                ("python".to_string(), "3.11".to_string()),
+                ("ruby".to_string(), "3.2.2".to_string()),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_tool_version_variables() -> Result<()> {
+        let variables = get_tool_version_variables(&App::new("./examples/node-tool-versions")?)?;
+        assert_eq!(
+            variables,
+            EnvironmentVariables::from([
+                ("NIXPACKS_NODE_VERSION".to_string(), "18".to_string()),
+                ("NIXPACKS_PYTHON_VERSION".to_string(), "3.11".to_string()),
+            ])
+        );
+
+        Ok(())
+    }
+}
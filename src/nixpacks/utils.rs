@@ -0,0 +1,15 @@
+/// Quote a string so that it is passed to the shell as a single argument.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/home/me/my app"), "'/home/me/my app'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
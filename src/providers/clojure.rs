@@ -20,8 +20,12 @@ impl Provider for ClojureProvider {
         "clojure"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(self.is_using_lein(app) || self.is_using_tools_build(app))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["project.clj", "build.clj"]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -24,8 +24,12 @@ impl Provider for CobolProvider {
         "cobol"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.has_match("*.cbl"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        app.find_relative_files("*.cbl")
     }
 
     fn get_build_plan(
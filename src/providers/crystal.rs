@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use super::Provider;
 use crate::nixpacks::{
@@ -10,14 +10,35 @@ use crate::nixpacks::{
         BuildPlan,
     },
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
 
+/// Static builds are cross compiled for musl, and linked against these libraries built for it
+const STATIC_PKGS: &[&str] = &[
+    "musl",
+    "pkgsStatic.boehmgc",
+    "pkgsStatic.libevent",
+    "pkgsStatic.libyaml",
+    "pkgsStatic.openssl",
+    "pkgsStatic.pcre2",
+    "pkgsStatic.zlib",
+];
+
 // https://github.com/crystal-lang/shards/blob/master/docs/shard.yml.adoc
 #[derive(Deserialize, Debug)]
 pub struct ShardYaml {
     pub name: String,
-    pub targets: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, ShardTarget>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, BTreeMap<String, serde_yaml::Value>>,
+    #[serde(default)]
+    pub development_dependencies: BTreeMap<String, BTreeMap<String, serde_yaml::Value>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ShardTarget {
+    pub main: String,
 }
 
 pub struct CrystalProvider {}
@@ -27,23 +48,54 @@ impl Provider for CrystalProvider {
         "crystal"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("shard.yml"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
-        let setup = Phase::setup(Some(vec![Pkg::new("crystal"), Pkg::new("shards")]));
-        let install = Phase::install(Some("shards install".to_string()));
-        let build = Phase::build(Some("shards build".to_string()));
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["shard.yml"]))
+    }
 
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let config = CrystalProvider::get_config(app)?;
-        let target_names = config.targets.keys().cloned().collect::<Vec<_>>();
-        let start = StartPhase::new(format!(
-            "./bin/{}",
-            target_names
-                .get(0)
-                .ok_or_else(|| anyhow::anyhow!("Unable to get executable name"))?
-        ));
+        let (target_name, target) = CrystalProvider::get_target(&config, env)?;
+        let is_static = env.is_config_variable_truthy("STATIC");
+
+        let mut setup = Phase::setup(Some(vec![Pkg::new("crystal"), Pkg::new("shards")]));
+        if is_static {
+            setup.add_nix_pkgs(
+                &STATIC_PKGS
+                    .iter()
+                    .map(|pkg| Pkg::new(pkg))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let mut install = Phase::install(Some("shards install".to_string()));
+        // Only run install if shard.yml or shard.lock have changed
+        if !CrystalProvider::uses_path_dependencies(&config) {
+            install.only_include_files =
+                Some(app.find_existing_paths(&["shard.yml", "shard.lock"]));
+        }
+
+        let build = if is_static {
+            // Crystal prints the command to link the cross compiled object, which runs with musl-gcc
+            let mut build = Phase::build(Some("mkdir -p bin".to_string()));
+            build.add_cmd(format!(
+                "CC=musl-gcc crystal build {} -o bin/{target_name} --release --static --cross-compile --target x86_64-linux-musl --link-flags \"-L/root/.nix-profile/lib\" > /tmp/link.sh && sh /tmp/link.sh",
+                target.main
+            ));
+            build
+        } else if env.get_config_variable("CRYSTAL_TARGET").is_some() {
+            Phase::build(Some(format!("shards build {target_name} --release")))
+        } else {
+            Phase::build(Some("shards build --release".to_string()))
+        };
+
+        let mut start = StartPhase::new(format!("./bin/{target_name}"));
+        if is_static {
+            start.run_in_scratch_image();
+        }
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
         Ok(Some(plan))
@@ -51,8 +103,73 @@ impl Provider for CrystalProvider {
 }
 
 impl CrystalProvider {
+    /// Whether a dependency is installed from a directory, possibly in the app.
+    fn uses_path_dependencies(config: &ShardYaml) -> bool {
+        config
+            .dependencies
+            .values()
+            .chain(config.development_dependencies.values())
+            .any(|dependency| dependency.contains_key("path"))
+    }
+
     fn get_config(app: &App) -> Result<ShardYaml> {
         app.read_yaml::<ShardYaml>("shard.yml")
             .context("Reading shard.yml")
     }
+
+    /// The target set with `NIXPACKS_CRYSTAL_TARGET`, the one named after the shard, or else the first target.
+    fn get_target<'a>(
+        config: &'a ShardYaml,
+        env: &Environment,
+    ) -> Result<(&'a String, &'a ShardTarget)> {
+        if let Some(name) = env.get_config_variable("CRYSTAL_TARGET") {
+            return match config.targets.get_key_value(&name) {
+                Some(target) => Ok(target),
+                None => bail!(
+                    "Target {name} not found in shard.yml, available targets are: {}",
+                    config
+                        .targets
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+        }
+
+        config
+            .targets
+            .get_key_value(&config.name)
+            .or_else(|| config.targets.iter().next())
+            .ok_or_else(|| anyhow::anyhow!("Unable to get executable name"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_target() -> Result<()> {
+        let config = CrystalProvider::get_config(&App::new("./examples/crystal-static")?)?;
+
+        let (name, target) = CrystalProvider::get_target(&config, &Environment::default())?;
+        assert_eq!(name, "crystal-static");
+        assert_eq!(target.main, "src/crystal_static.cr");
+
+        let (name, target) = CrystalProvider::get_target(
+            &config,
+            &Environment::from_envs(vec!["NIXPACKS_CRYSTAL_TARGET=worker"])?,
+        )?;
+        assert_eq!(name, "worker");
+        assert_eq!(target.main, "src/worker.cr");
+
+        assert!(CrystalProvider::get_target(
+            &config,
+            &Environment::from_envs(vec!["NIXPACKS_CRYSTAL_TARGET=missing"])?,
+        )
+        .is_err());
+
+        Ok(())
+    }
 }
@@ -30,8 +30,16 @@ impl Provider for CSharpProvider {
         "c#"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(!app.find_files("*.csproj")?.is_empty())
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        app.find_relative_files("*.csproj")
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(Some(CSharpProvider::get_sdk_version(app, env)?))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
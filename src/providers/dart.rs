@@ -26,8 +26,12 @@ impl Provider for DartProvider {
         "dart"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("pubspec.yaml"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["pubspec.yaml"]))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
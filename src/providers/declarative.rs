@@ -0,0 +1,224 @@
+use super::Provider;
+use crate::nixpacks::{app::App, environment::Environment, plan::BuildPlan};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+/// The directory of the app that declarative providers are read from.
+pub const PROVIDERS_DIR: &str = "providers.d";
+
+/// When a declarative provider detects an app.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeclarativeDetection {
+    /// Globs of files, relative to the app, of which any must match.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Files that must exist and match a regex, e.g. a lockfile with a dependency in it.
+    #[serde(default)]
+    pub contents: Vec<ContentsRule>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ContentsRule {
+    pub file: String,
+    pub regex: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeclarativeConfig {
+    name: Option<String>,
+    #[serde(default)]
+    detect: DeclarativeDetection,
+    #[serde(flatten)]
+    plan: BuildPlan,
+}
+
+/// A provider declared in a TOML file in the app's `providers.d` directory, with the rules it detects the app by and
+/// the plan it adds, in the format of `nixpacks.toml`.
+///
+/// ```toml
+/// [detect]
+/// files = ["*.lua"]
+///
+/// [phases.setup]
+/// nixPkgs = ["lua"]
+///
+/// [start]
+/// cmd = "lua main.lua"
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeclarativeProvider {
+    name: String,
+    detect: DeclarativeDetection,
+    plan: BuildPlan,
+}
+
+impl DeclarativeProvider {
+    /// Read the providers in the app's `providers.d` directory, in the order of their file names.
+    pub fn find_providers(app: &App) -> Result<Vec<DeclarativeProvider>> {
+        let mut files = app.find_relative_files(&format!("{PROVIDERS_DIR}/*.toml"))?;
+        files.sort();
+
+        files
+            .iter()
+            .map(|file| {
+                let contents = app.read_file(file)?;
+                DeclarativeProvider::from_toml(file, &contents)
+                    .with_context(|| format!("Reading provider {file}"))
+            })
+            .collect()
+    }
+
+    /// Parse a provider, named after its file unless it has a `name`.
+    fn from_toml(file: &str, contents: &str) -> Result<DeclarativeProvider> {
+        let config: DeclarativeConfig = toml::from_str(contents)?;
+        for rule in &config.detect.contents {
+            Regex::new(&rule.regex).with_context(|| format!("Invalid regex for {}", rule.file))?;
+        }
+
+        let name = config.name.unwrap_or_else(|| {
+            Path::new(file)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        });
+        let mut plan = config.plan;
+        plan.providers = None;
+        plan.resolve_phase_names();
+
+        Ok(DeclarativeProvider {
+            name,
+            detect: config.detect,
+            plan,
+        })
+    }
+
+    fn get_matching_files(&self, app: &App) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        for pattern in &self.detect.files {
+            files.extend(app.find_relative_files(pattern)?);
+        }
+        Ok(files)
+    }
+
+    fn contents_match(&self, app: &App) -> Result<bool> {
+        for rule in &self.detect.contents {
+            if !app.includes_file(&rule.file) {
+                return Ok(false);
+            }
+            let re = Regex::new(&rule.regex)?;
+            if !re.is_match(&app.read_file(&rule.file)?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Provider for DeclarativeProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        if self.detect.files.is_empty() && self.detect.contents.is_empty() {
+            return Ok(false);
+        }
+
+        let files_match = self.detect.files.is_empty() || !self.get_matching_files(app)?.is_empty();
+        Ok(files_match && self.contents_match(app)?)
+    }
+
+    fn detection_files(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+        if !self.detect(app, env)? {
+            return Ok(Vec::new());
+        }
+
+        let mut files = self.get_matching_files(app)?;
+        for rule in &self.detect.contents {
+            if !files.contains(&rule.file) {
+                files.push(rule.file.clone());
+            }
+        }
+        Ok(files)
+    }
+
+    fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        Ok(Some(self.plan.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_providers() -> Result<()> {
+        let app = App::new("./examples/declarative-provider")?;
+        let providers = DeclarativeProvider::find_providers(&app)?;
+        assert_eq!(providers.len(), 1);
+
+        let provider = &providers[0];
+        let env = Environment::default();
+        assert_eq!(provider.name(), "lua");
+        assert!(provider.detect(&app, &env)?);
+        assert_eq!(
+            provider.detection_files(&app, &env)?,
+            vec!["main.lua".to_string()]
+        );
+
+        let plan = provider.get_build_plan(&app, &env)?.unwrap();
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["lua".to_string()])
+        );
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("lua main.lua".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_contents_rules() -> Result<()> {
+        let app = App::new("./examples/declarative-provider")?;
+        let env = Environment::default();
+
+        let provider = DeclarativeProvider::from_toml(
+            "hello.toml",
+            r#"
+            [[detect.contents]]
+            file = "main.lua"
+            regex = 'print\("Hello'
+            "#,
+        )?;
+        assert_eq!(provider.name(), "hello");
+        assert!(provider.detect(&app, &env)?);
+
+        let provider = DeclarativeProvider::from_toml(
+            "goodbye.toml",
+            r#"
+            [[detect.contents]]
+            file = "main.lua"
+            regex = "Goodbye"
+            "#,
+        )?;
+        assert!(!provider.detect(&app, &env)?);
+
+        assert!(DeclarativeProvider::from_toml(
+            "invalid.toml",
+            r#"
+            [[detect.contents]]
+            file = "main.lua"
+            regex = "("
+            "#,
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -15,6 +15,10 @@ use path_slash::PathBufExt;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+/// An import of a module from deno.land, which only Deno apps have
+const DENO_LAND_IMPORT: &str =
+    r##"import .+ from (?:"|'|`)https://deno.land/[^"`']+\.(?:ts|js|tsx|jsx)(?:"|'|`);?"##;
+
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct DenoTasks {
     pub start: Option<String>,
@@ -33,15 +37,30 @@ impl Provider for DenoProvider {
     }
 
     fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        let re = Regex::new(
-            r##"import .+ from (?:"|'|`)https://deno.land/[^"`']+\.(?:ts|js|tsx|jsx)(?:"|'|`);?"##,
-        )
-        .unwrap();
+        let re = Regex::new(DENO_LAND_IMPORT).unwrap();
         Ok(app.includes_file("deno.json")
             || app.includes_file("deno.jsonc")
             || app.find_match(&re, "**/*.{ts,tsx,js,jsx}")?)
     }
 
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        let config_files = app.find_existing_paths(&["deno.json", "deno.jsonc"]);
+        if !config_files.is_empty() {
+            return Ok(config_files);
+        }
+
+        // The files that import from deno.land
+        let re = Regex::new(DENO_LAND_IMPORT)?;
+        let mut files = Vec::new();
+        for file in app.find_relative_files("**/*.{ts,tsx,js,jsx}")? {
+            if re.is_match(&app.read_file(&file)?) {
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
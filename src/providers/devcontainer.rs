@@ -0,0 +1,339 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+pub const DEVCONTAINER_FILES: &[&str] = &[".devcontainer/devcontainer.json", ".devcontainer.json"];
+
+/// Features that install a language, with the config variable that picks the version its provider installs.
+const LANGUAGE_FEATURES: &[(&str, &str)] = &[
+    ("node", "NODE_VERSION"),
+    ("python", "PYTHON_VERSION"),
+    ("ruby", "RUBY_VERSION"),
+    ("rust", "RUST_VERSION"),
+    ("java", "JDK_VERSION"),
+    ("dotnet", "CSHARP_SDK_VERSION"),
+];
+
+/// Features that install tools, with the Nix packages that provide them.
+const TOOL_FEATURES: &[(&str, &[&str])] = &[
+    ("git", &["git"]),
+    ("git-lfs", &["git-lfs"]),
+    ("github-cli", &["gh"]),
+    ("aws-cli", &["awscli2"]),
+    ("azure-cli", &["azure-cli"]),
+    ("terraform", &["terraform"]),
+    ("kubectl-helm-minikube", &["kubectl", "kubernetes-helm"]),
+];
+
+/// Feature versions that mean the default rather than a specific version.
+const DEFAULT_VERSIONS: &[&str] = &["latest", "lts", "none", "os-provided"];
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DevContainer {
+    #[serde(default)]
+    features: BTreeMap<String, Value>,
+    post_create_command: Option<Value>,
+    #[serde(default)]
+    forward_ports: Vec<Value>,
+}
+
+/// Seeds the plan from a dev container config, so projects set up for dev containers build with the same tools.
+pub struct DevcontainerProvider {}
+
+impl Provider for DevcontainerProvider {
+    fn name(&self) -> &'static str {
+        "devcontainer"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(DevcontainerProvider::get_config_file(app).is_some())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(DEVCONTAINER_FILES))
+    }
+
+    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        let devcontainer = match DevcontainerProvider::read_config(app)? {
+            Some(devcontainer) => devcontainer,
+            None => return Ok(None),
+        };
+
+        let mut plan = BuildPlan::default();
+
+        let variables = DevcontainerProvider::get_variables(&devcontainer);
+        if !variables.is_empty() {
+            plan.add_variables(variables);
+        }
+
+        let pkgs = DevcontainerProvider::get_nix_pkgs(&devcontainer);
+        if !pkgs.is_empty() {
+            let mut setup = Phase::new("setup");
+            setup.nix_pkgs = Some([vec!["...".to_string()], pkgs].concat());
+            plan.add_phase(setup);
+        }
+
+        let cmds = DevcontainerProvider::get_post_create_cmds(&devcontainer);
+        if !cmds.is_empty() {
+            let mut install = Phase::install(None);
+            install.cmds = Some([vec!["...".to_string()], cmds].concat());
+            // The commands can read any file of the app, not only the ones the detected install needs
+            install.only_include_files = Some(vec![".".to_string()]);
+            plan.add_phase(install);
+        }
+
+        let ports = DevcontainerProvider::get_ports(&devcontainer);
+        if !ports.is_empty() {
+            plan.set_start_phase(StartPhase {
+                ports: Some(ports),
+                ..Default::default()
+            });
+        }
+
+        Ok(Some(plan))
+    }
+
+    fn reasons(&self, app: &App, _env: &Environment) -> Result<BTreeMap<String, String>> {
+        let mut reasons = BTreeMap::new();
+        if let (Some(file), Some(devcontainer)) = (
+            DevcontainerProvider::get_config_file(app),
+            DevcontainerProvider::read_config(app)?,
+        ) {
+            for pkg in DevcontainerProvider::get_nix_pkgs(&devcontainer) {
+                reasons.insert(pkg, format!("a feature in {file} installs it"));
+            }
+            for cmd in DevcontainerProvider::get_post_create_cmds(&devcontainer) {
+                reasons.insert(cmd, format!("postCreateCommand in {file}"));
+            }
+        }
+        Ok(reasons)
+    }
+}
+
+impl DevcontainerProvider {
+    fn get_config_file(app: &App) -> Option<&'static str> {
+        DEVCONTAINER_FILES
+            .iter()
+            .find(|file| app.includes_file(file))
+            .copied()
+    }
+
+    fn read_config(app: &App) -> Result<Option<DevContainer>> {
+        match DevcontainerProvider::get_config_file(app) {
+            Some(file) => {
+                let contents = app.read_file(file)?;
+                let devcontainer = serde_json::from_str(&strip_jsonc(&contents))
+                    .with_context(|| format!("Error reading {file} as JSON"))?;
+                Ok(Some(devcontainer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The feature's name without its registry and version, e.g. `node` for `ghcr.io/devcontainers/features/node:1`.
+    fn feature_name(id: &str) -> &str {
+        let name = id.rsplit('/').next().unwrap_or(id);
+        name.split([':', '@']).next().unwrap_or(name)
+    }
+
+    /// The versions of the languages installed with features, for the providers of those languages to use.
+    fn get_variables(devcontainer: &DevContainer) -> EnvironmentVariables {
+        let mut variables = EnvironmentVariables::new();
+        for (id, options) in &devcontainer.features {
+            let name = DevcontainerProvider::feature_name(id);
+            let version = match options {
+                Value::String(version) => Some(version.as_str()),
+                Value::Object(options) => options.get("version").and_then(Value::as_str),
+                _ => None,
+            };
+
+            if let (Some((_, variable)), Some(version)) = (
+                LANGUAGE_FEATURES
+                    .iter()
+                    .find(|(feature, _)| *feature == name),
+                version,
+            ) {
+                if !DEFAULT_VERSIONS.contains(&version) {
+                    variables.insert(format!("NIXPACKS_{variable}"), version.to_string());
+                }
+            }
+        }
+        variables
+    }
+
+    fn get_nix_pkgs(devcontainer: &DevContainer) -> Vec<String> {
+        devcontainer
+            .features
+            .keys()
+            .filter_map(|id| {
+                let name = DevcontainerProvider::feature_name(id);
+                TOOL_FEATURES.iter().find(|(feature, _)| *feature == name)
+            })
+            .flat_map(|(_, pkgs)| pkgs.iter().map(ToString::to_string))
+            .collect()
+    }
+
+    /// The commands of `postCreateCommand`, which is a shell command, a command and its arguments, or an object of
+    /// either that run in parallel, which are run one after the other instead.
+    fn get_post_create_cmds(devcontainer: &DevContainer) -> Vec<String> {
+        fn to_cmd(value: &Value) -> Option<String> {
+            match value {
+                Value::String(cmd) => Some(cmd.clone()),
+                Value::Array(args) => Some(
+                    args.iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                _ => None,
+            }
+        }
+
+        match &devcontainer.post_create_command {
+            Some(Value::Object(cmds)) => cmds.values().filter_map(to_cmd).collect(),
+            Some(cmd) => to_cmd(cmd).into_iter().collect(),
+            None => Vec::new(),
+        }
+        .into_iter()
+        .filter(|cmd| !cmd.trim().is_empty())
+        .collect()
+    }
+
+    /// Forwarded ports of the app itself, skipping ones of other services, like `db:5432`.
+    fn get_ports(devcontainer: &DevContainer) -> Vec<String> {
+        devcontainer
+            .forward_ports
+            .iter()
+            .filter_map(|port| match port {
+                Value::Number(port) => Some(port.to_string()),
+                Value::String(port) if !port.contains(':') => Some(port.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Remove the comments and trailing commas that dev container configs allow, but JSON doesn't.
+fn strip_jsonc(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    stripped.push(escaped);
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            (',', _) => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+                if !matches!(rest, Some('}' | ']')) {
+                    stripped.push(c);
+                }
+            }
+            _ => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_jsonc() {
+        let contents = r#"{
+            // The image
+            "image": "mcr.microsoft.com/devcontainers/base", /* inline */
+            "url": "https://example.com",
+            "forwardPorts": [3000,],
+        }"#;
+        let value: Value = serde_json::from_str(&strip_jsonc(contents)).unwrap();
+        assert_eq!(value["url"], "https://example.com");
+        assert_eq!(value["forwardPorts"], serde_json::json!([3000]));
+    }
+
+    #[test]
+    fn test_feature_name() {
+        assert_eq!(
+            DevcontainerProvider::feature_name("ghcr.io/devcontainers/features/node:1"),
+            "node"
+        );
+        assert_eq!(
+            DevcontainerProvider::feature_name("github-cli"),
+            "github-cli"
+        );
+    }
+
+    #[test]
+    fn test_devcontainer_plan() -> Result<()> {
+        let plan = DevcontainerProvider {}
+            .get_build_plan(
+                &App::new("./examples/devcontainer")?,
+                &Environment::default(),
+            )?
+            .unwrap();
+
+        assert_eq!(
+            plan.variables
+                .as_ref()
+                .unwrap()
+                .get("NIXPACKS_NODE_VERSION"),
+            Some(&"18".to_string())
+        );
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nix_pkgs,
+            Some(vec!["...".to_string(), "gh".to_string()])
+        );
+        assert_eq!(
+            plan.get_phase("install").unwrap().cmds,
+            Some(vec!["...".to_string(), "npm run prepare-db".to_string()])
+        );
+        assert_eq!(
+            plan.start_phase.unwrap().ports,
+            Some(vec!["3000".to_string()])
+        );
+
+        Ok(())
+    }
+}
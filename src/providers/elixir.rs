@@ -19,8 +19,12 @@ impl Provider for ElixirProvider {
         "elixir"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("mix.exs"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["mix.exs"]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -39,6 +43,16 @@ impl Provider for ElixirProvider {
         let mut install_phase = Phase::install(Some("mix local.hex --force".to_string()));
         install_phase.add_cmd("mix local.rebar --force");
         install_phase.add_cmd("mix deps.get --only prod");
+        // Only run install if mix.exs or mix.lock have changed, unless the dependencies include the app's own
+        // directories, like in an umbrella project, or mix.exs reads other files, like a `VERSION` file
+        let mix_exs = app.read_file("mix.exs")?;
+        if !mix_exs.contains("apps_path")
+            && !mix_exs.contains("path:")
+            && !mix_exs.contains("File.")
+        {
+            install_phase.only_include_files =
+                Some(app.find_existing_paths(&["mix.exs", "mix.lock"]));
+        }
         plan.add_phase(install_phase);
 
         // Build Phase
@@ -53,17 +67,47 @@ impl Provider for ElixirProvider {
             build_phase.add_cmd("mix ecto.migrate");
             build_phase.add_cmd("mix run priv/repo/seeds.exs");
         }
+
+        let release = ElixirProvider::get_release_name(app, &mix_exs_content)?;
+        if release.is_some() {
+            build_phase.add_cmd("mix release");
+        }
         plan.add_phase(build_phase);
 
         // Start Phase
-        let start_phase = StartPhase::new("mix phx.server".to_string());
-        plan.set_start_phase(start_phase);
+        let start_cmd = match release {
+            // `mix phx.gen.release` adds a script that also starts the Phoenix server
+            Some(release) if app.includes_file("rel/overlays/bin/server") => {
+                format!("_build/prod/rel/{release}/bin/server")
+            }
+            Some(release) => format!("_build/prod/rel/{release}/bin/{release} start"),
+            None => "mix phx.server".to_string(),
+        };
+        plan.set_start_phase(StartPhase::new(start_cmd));
 
         Ok(Some(plan))
     }
 }
 
 impl ElixirProvider {
+    /// The release to build, if mix.exs configures releases or `mix phx.gen.release` added a `rel` directory.
+    fn get_release_name(app: &App, mix_exs_content: &str) -> Result<Option<String>> {
+        let releases_regex = Regex::new(r"releases:\s*\[\s*([a-z_][a-zA-Z0-9_]*):")?;
+        if let Some(captures) = releases_regex.captures(mix_exs_content) {
+            return Ok(Some(captures[1].to_string()));
+        }
+
+        // Without a release config, the release is named after the app
+        let app_regex = Regex::new(r"app:\s*:([a-z_][a-zA-Z0-9_]*)")?;
+        if app.includes_directory("rel") {
+            return Ok(app_regex
+                .captures(mix_exs_content)
+                .map(|captures| captures[1].to_string()));
+        }
+
+        Ok(None)
+    }
+
     fn get_nix_elixir_package(app: &App, env: &Environment) -> Result<Pkg> {
         fn as_default(v: Option<Match>) -> &str {
             match v {
@@ -46,6 +46,10 @@ impl Provider for GleamProvider {
         Ok(app.has_match("gleam.toml") && app.has_match("manifest.toml"))
     }
 
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["gleam.toml", "manifest.toml"]))
+    }
+
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = self.get_setup(app, env);
         let install = self.get_install(app, env)?;
@@ -9,6 +9,7 @@ use crate::nixpacks::{
     },
 };
 use anyhow::Result;
+use regex::Regex;
 
 pub struct GolangProvider {}
 
@@ -17,37 +18,69 @@ const AVAILABLE_GO_VERSIONS: &[(&str, &str)] = &[("1.17", "go"), ("1.18", "go_1_
 const DEFAULT_GO_PKG_NAME: &str = "go";
 
 const GO_BUILD_CACHE_DIR: &str = "/root/.cache/go-build";
+const GO_MOD_CACHE_DIR: &str = "/root/go/pkg/mod";
+
+/// Modules that can't be built without cgo
+const CGO_MODULES: &[&str] = &["github.com/mattn/go-sqlite3"];
 
 impl Provider for GolangProvider {
     fn name(&self) -> &str {
         "go"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("main.go") || app.includes_file("go.mod"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["main.go", "go.mod"]))
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(Some(self.get_go_pkg(app, env)?))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
-        let go_mod = self.read_go_mod_if_exists(app)?;
-        let nix_pkg = GolangProvider::get_nix_golang_pkg(go_mod.as_ref())?;
-        plan.add_phase(Phase::setup(Some(vec![Pkg::new(&nix_pkg)])));
+        let nix_pkg = self.get_go_pkg(app, env)?;
+        let uses_cgo = GolangProvider::uses_cgo(app, env)?;
+        let is_static = env.is_config_variable_truthy("STATIC");
+
+        let mut setup = Phase::setup(Some(vec![Pkg::new(&nix_pkg)]));
+        if is_static && uses_cgo {
+            setup.add_nix_pkgs(&[Pkg::new("musl"), Pkg::new("musl.dev")]);
+        }
+        plan.add_phase(setup);
 
         if app.includes_file("go.mod") {
             let mut install = Phase::install(Some("go mod download".to_string()));
             install.add_cache_directory(GO_BUILD_CACHE_DIR.to_string());
+            install.add_cache_directory(GO_MOD_CACHE_DIR.to_string());
+            // Only run install if go.mod or go.sum have changed
+            if !GolangProvider::uses_local_modules(app)? {
+                install.only_include_files = Some(app.find_existing_paths(&["go.mod", "go.sum"]));
+            }
             plan.add_phase(install);
         }
 
+        let build_cmd = match (is_static, uses_cgo) {
+            // Link against musl so the C dependencies can be linked statically too
+            (true, true) => format!(
+                "CC=musl-gcc go build -tags osusergo,netgo -ldflags '-s -w -linkmode external -extldflags \"-static\"' -o {BINARY_NAME}"
+            ),
+            (true, false) => format!("go build -ldflags '-s -w' -o {BINARY_NAME}"),
+            _ => format!("go build -o {BINARY_NAME}"),
+        };
         let mut build = if app.includes_file("go.mod") {
-            Phase::build(Some(format!("go build -o {BINARY_NAME}")))
+            Phase::build(Some(build_cmd))
         } else if app.includes_file("main.go") {
-            Phase::build(Some(format!("go build -o {BINARY_NAME} main.go")))
+            Phase::build(Some(format!("{build_cmd} main.go")))
         } else {
             Phase::build(None)
         };
         build.add_cache_directory(GO_BUILD_CACHE_DIR.to_string());
+        build.add_cache_directory(GO_MOD_CACHE_DIR.to_string());
         build.depends_on_phase("setup");
         plan.add_phase(build);
 
@@ -55,10 +88,11 @@ impl Provider for GolangProvider {
 
         if has_go_files {
             let mut start = StartPhase::new(format!("./{BINARY_NAME}"));
-            let cgo = env.get_variable("CGO_ENABLED").unwrap_or("0");
 
-            // Only run in a new image if CGO_ENABLED=0 (default)
-            if cgo != "1" {
+            // A static binary needs nothing else, and without cgo the binary doesn't need the libc of the build image
+            if is_static {
+                start.run_in_scratch_image();
+            } else if !uses_cgo {
                 start.run_in_slim_image();
             }
             plan.set_start_phase(start);
@@ -66,7 +100,7 @@ impl Provider for GolangProvider {
 
         plan.add_variables(EnvironmentVariables::from([(
             "CGO_ENABLED".to_string(),
-            "0".to_string(),
+            if uses_cgo { "1" } else { "0" }.to_string(),
         )]));
 
         Ok(Some(plan))
@@ -82,6 +116,57 @@ impl GolangProvider {
         }
     }
 
+    /// The Go package for the version in `NIXPACKS_GO_VERSION`, otherwise the one in go.mod.
+    pub fn get_go_pkg(&self, app: &App, env: &Environment) -> Result<String> {
+        if let Some(version) = env.get_config_variable("GO_VERSION") {
+            // Only the minor version picks a package, e.g. 1.18 for 1.18.3
+            let version = version
+                .trim()
+                .trim_start_matches("go")
+                .split('.')
+                .take(2)
+                .collect::<Vec<_>>()
+                .join(".");
+            return Ok(
+                version_number_to_pkg(&version).unwrap_or_else(|| DEFAULT_GO_PKG_NAME.to_string())
+            );
+        }
+
+        let go_mod = self.read_go_mod_if_exists(app)?;
+        GolangProvider::get_nix_golang_pkg(go_mod.as_ref())
+    }
+
+    /// Whether the app needs cgo, either because `CGO_ENABLED=1` is set or because it, or a module known to, imports C.
+    pub fn uses_cgo(app: &App, env: &Environment) -> Result<bool> {
+        if let Some(cgo) = env.get_variable("CGO_ENABLED") {
+            return Ok(cgo == "1");
+        }
+
+        let re_import_c = Regex::new(r#"(?m)^import\s+"C""#).expect("BUG: Broken regex");
+        if app.find_match(&re_import_c, "**/*.go")? {
+            return Ok(true);
+        }
+
+        if app.includes_file("go.mod") {
+            let go_mod = app.read_file("go.mod")?;
+            if CGO_MODULES.iter().any(|module| go_mod.contains(module)) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Whether the app is part of a workspace, or replaces modules with directories, possibly in the app.
+    fn uses_local_modules(app: &App) -> Result<bool> {
+        if app.includes_file("go.work") {
+            return Ok(true);
+        }
+
+        let re_local_replace = Regex::new(r"=>\s*\.{0,2}/").expect("BUG: Broken regex");
+        Ok(re_local_replace.is_match(&app.read_file("go.mod")?))
+    }
+
     pub fn get_nix_golang_pkg(go_mod_contents: Option<&String>) -> Result<String> {
         if go_mod_contents.is_some() {
             let mut lines = go_mod_contents.as_ref().unwrap().lines();
@@ -147,4 +232,43 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_uses_cgo() -> Result<()> {
+        assert!(!GolangProvider::uses_cgo(
+            &App::new("./examples/go")?,
+            &Environment::default()
+        )?);
+        assert!(GolangProvider::uses_cgo(
+            &App::new("./examples/go-cgo")?,
+            &Environment::default()
+        )?);
+        assert!(!GolangProvider::uses_cgo(
+            &App::new("./examples/go-cgo")?,
+            &Environment::from_envs(vec!["CGO_ENABLED=0"])?
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_version_variable() -> Result<()> {
+        let app = App::new("./examples/go")?;
+        assert_eq!(
+            GolangProvider {}.get_go_pkg(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_GO_VERSION=1.18.3"])?
+            )?,
+            "go_1_18".to_string()
+        );
+        assert_eq!(
+            GolangProvider {}.get_go_pkg(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_GO_VERSION=1.8"])?
+            )?,
+            DEFAULT_GO_PKG_NAME.to_string()
+        );
+
+        Ok(())
+    }
 }
@@ -27,6 +27,12 @@ impl Provider for HaskellStackProvider {
         Ok(app.includes_file("package.yaml") && app.has_match("**/*.hs"))
     }
 
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        let mut files = app.find_existing_paths(&["package.yaml"]);
+        files.extend(app.find_relative_files("**/*.hs")?);
+        Ok(files)
+    }
+
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
         let mut setup = Phase::setup(Some(vec![Pkg::new("stack")]));
         setup.add_apt_pkgs(vec![
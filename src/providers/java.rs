@@ -14,23 +14,37 @@ use regex::Regex;
 pub struct JavaProvider {}
 
 const DEFAULT_JDK_VERSION: u32 = 17;
+const AVAILABLE_JDK_VERSIONS: &[u32] = &[8, 11, 17, 19];
 const DEFAULT_GRADLE_VERSION: u32 = 7;
 
+const GRADLE_CACHE_DIR: &str = "/root/.gradle";
+const MAVEN_CACHE_DIR: &str = "/root/.m2/repository";
+
 impl Provider for JavaProvider {
     fn name(&self) -> &str {
         "java"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("pom.xml")
-            || app.includes_directory("pom.atom")
-            || app.includes_directory("pom.clj")
-            || app.includes_directory("pom.groovy")
-            || app.includes_file("pom.rb")
-            || app.includes_file("pom.scala")
-            || app.includes_file("pom.yaml")
-            || app.includes_file("pom.yml")
-            || app.includes_file("gradlew"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&[
+            "pom.xml",
+            "pom.atom",
+            "pom.clj",
+            "pom.groovy",
+            "pom.rb",
+            "pom.scala",
+            "pom.yaml",
+            "pom.yml",
+            "gradlew",
+        ]))
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(Some(self.get_jdk_version(app, env)?.to_string()))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -47,7 +61,7 @@ impl Provider for JavaProvider {
             }
 
             build.add_cmd(format!("{gradle_exe} build -x check"));
-            build.add_cache_directory("/root/.gradle");
+            build.add_cache_directory(GRADLE_CACHE_DIR);
             build.depends_on_phase("setup");
 
             (setup, build)
@@ -60,7 +74,7 @@ impl Provider for JavaProvider {
             let mvn_exe = self.get_maven_exe(app);
             let mut build = Phase::build(Some(format!("{mvn_exe} -DoutputFile=target/mvn-dependency-list.log -B -DskipTests clean dependency:list install"
             )));
-            build.add_cache_directory(".m2/repository");
+            build.add_cache_directory(MAVEN_CACHE_DIR);
             build.depends_on_phase("setup");
 
             (setup, build)
@@ -176,6 +190,7 @@ impl JavaProvider {
 
     fn get_gradle_pkg(&self, gradle_version: u32) -> Result<Pkg> {
         let pkg = match gradle_version {
+            8 => Pkg::new("gradle_8"),
             7 => Pkg::new("gradle"),
             6 => Pkg::new("gradle_6"),
             5 => Pkg::new("gradle_5"),
@@ -192,6 +207,11 @@ impl JavaProvider {
             return Ok(jdk_version.parse::<u32>()?);
         }
 
+        // Otherwise use the version the build compiles for
+        if let Some(jdk_version) = self.get_build_jdk_version(app)? {
+            return JavaProvider::get_available_jdk_version(jdk_version);
+        }
+
         if self.is_using_gradle(app) {
             let gradle_version = self.get_gradle_version(app, env)?;
 
@@ -206,6 +226,59 @@ impl JavaProvider {
         Ok(DEFAULT_JDK_VERSION)
     }
 
+    /// The Java version from a Gradle toolchain block or the `maven.compiler.release` property of `pom.xml`.
+    fn get_build_jdk_version(&self, app: &App) -> Result<Option<u32>> {
+        if self.is_using_gradle(app) {
+            for file in ["build.gradle", "build.gradle.kts"] {
+                if app.includes_file(file) {
+                    let file_content = app.read_file(file)?;
+                    if let Some(version) = JavaProvider::parse_gradle_toolchain(&file_content) {
+                        return Ok(Some(version));
+                    }
+                }
+            }
+        } else if app.includes_file("pom.xml") {
+            return Ok(JavaProvider::parse_maven_compiler_release(
+                &app.read_file("pom.xml")?,
+            ));
+        }
+
+        Ok(None)
+    }
+
+    /// Parses `languageVersion = JavaLanguageVersion.of(17)` or `languageVersion.set(JavaLanguageVersion.of(17))`.
+    fn parse_gradle_toolchain(build_gradle: &str) -> Option<u32> {
+        Regex::new(r#"languageVersion[\s\S]{0,16}JavaLanguageVersion\.of\(\s*["']?(\d+)["']?\s*\)"#)
+            .expect("BUG: Broken regex")
+            .captures(build_gradle)
+            .and_then(|c| c.get(1))
+            .and_then(|version| version.as_str().parse::<u32>().ok())
+    }
+
+    fn parse_maven_compiler_release(pom: &str) -> Option<u32> {
+        Regex::new(r"<maven\.compiler\.release>\s*(?:1\.)?(\d+)\s*</maven\.compiler\.release>")
+            .expect("BUG: Broken regex")
+            .captures(pom)
+            .and_then(|c| c.get(1))
+            .and_then(|version| version.as_str().parse::<u32>().ok())
+    }
+
+    /// The oldest available JDK that can compile for a Java version. An older JDK can't compile for later versions, so
+    /// those are an error.
+    fn get_available_jdk_version(version: u32) -> Result<u32> {
+        match AVAILABLE_JDK_VERSIONS
+            .iter()
+            .copied()
+            .find(|available| *available >= version)
+        {
+            Some(available) => Ok(available),
+            None => bail!(
+                "The build targets Java {version}, but the newest available JDK is {}. Set NIXPACKS_JDK_VERSION to build with another JDK",
+                AVAILABLE_JDK_VERSIONS[AVAILABLE_JDK_VERSIONS.len() - 1]
+            ),
+        }
+    }
+
     fn get_gradle_version(&self, app: &App, env: &Environment) -> Result<u32> {
         // If the Gradle version is manually specified, use that
         if let Some(gradle_version) = env.get_config_variable("GRADLE_VERSION") {
@@ -288,6 +361,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_gradle_toolchain() {
+        assert_eq!(
+            JavaProvider::parse_gradle_toolchain(
+                "java {\n    toolchain {\n        languageVersion = JavaLanguageVersion.of(11)\n    }\n}"
+            ),
+            Some(11)
+        );
+        assert_eq!(
+            JavaProvider::parse_gradle_toolchain(
+                "java {\n    toolchain {\n        languageVersion.set(JavaLanguageVersion.of(\"17\"))\n    }\n}"
+            ),
+            Some(17)
+        );
+        assert_eq!(
+            JavaProvider::parse_gradle_toolchain("sourceCompatibility = 1.8"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_maven_compiler_release() {
+        assert_eq!(
+            JavaProvider::parse_maven_compiler_release(
+                "<properties>\n    <maven.compiler.release>11</maven.compiler.release>\n</properties>"
+            ),
+            Some(11)
+        );
+        assert_eq!(
+            JavaProvider::parse_maven_compiler_release(
+                "<maven.compiler.release>${java.version}</maven.compiler.release>"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_available_jdk_version() {
+        assert_eq!(JavaProvider::get_available_jdk_version(8).unwrap(), 8);
+        assert_eq!(JavaProvider::get_available_jdk_version(15).unwrap(), 17);
+        assert!(JavaProvider::get_available_jdk_version(21).is_err());
+    }
+
     #[test]
     fn test_get_gradle_pkg() {
         let java = JavaProvider {};
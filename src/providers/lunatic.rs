@@ -27,6 +27,10 @@ impl Provider for LunaticProvider {
         app.find_match(&re_runner, ".cargo/config.toml")
     }
 
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["Cargo.toml", ".cargo/config.toml"]))
+    }
+
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = LunaticProvider::get_setup(app, env)?;
         let build = LunaticProvider::get_build(app, env)?;
@@ -1,12 +1,15 @@
 use crate::nixpacks::{app::App, environment::Environment, plan::BuildPlan};
 use anyhow::Result;
+use std::collections::BTreeMap;
 
 pub mod clojure;
 pub mod cobol;
 pub mod crystal;
 pub mod csharp;
 pub mod dart;
+pub mod declarative;
 pub mod deno;
+pub mod devcontainer;
 pub mod elixir;
 pub mod fsharp;
 pub mod gleam;
@@ -16,24 +19,45 @@ pub mod java;
 pub mod lunatic;
 pub mod node;
 pub mod php;
+pub mod plugin;
 pub mod procfile;
 pub mod python;
+pub mod r;
 pub mod ruby;
 pub mod rust;
 pub mod scala;
 pub mod staticfile;
+pub mod staticsite;
 pub mod swift;
 pub mod zig;
 
+/// Detects a kind of app and generates the build plan for it. Programs embedding nixpacks can implement it for their
+/// own kinds of apps and pass them to `generate_build_plan_with_providers`.
 pub trait Provider: Send + Sync {
+    /// The name the provider is chosen by in `providers` and shown as in the build output, e.g. `node`.
     fn name(&self) -> &str;
+    /// Whether the provider builds this app. The first provider detecting the app is used.
     fn detect(&self, _app: &App, _env: &Environment) -> Result<bool> {
         Ok(false)
     }
+    /// The provider's part of the build plan, merged with the plans of config files and the CLI.
     fn get_build_plan(&self, _app: &App, _environment: &Environment) -> Result<Option<BuildPlan>>;
+    /// Values, like the framework, added after the provider's name to the `NIXPACKS_METADATA` variable of the image.
     fn metadata(&self, _app: &App, _env: &Environment) -> Result<ProviderMetadata> {
         Ok(ProviderMetadata::default())
     }
+    /// Why the provider chose packages and commands in its plan, keyed by the package or command, for `nixpacks plan explain`.
+    fn reasons(&self, _app: &App, _env: &Environment) -> Result<BTreeMap<String, String>> {
+        Ok(BTreeMap::new())
+    }
+    /// The files, relative to the app, that made the provider detect it, for `nixpacks detect --format json`.
+    fn detection_files(&self, _app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    /// The version of the language or framework the provider resolved for the app, for `nixpacks detect --format json`.
+    fn version(&self, _app: &App, _env: &Environment) -> Result<Option<String>> {
+        Ok(None)
+    }
 }
 
 #[derive(Default)]
@@ -1,4 +1,7 @@
-use self::{nx::Nx, turborepo::Turborepo};
+use self::{
+    nx::Nx,
+    turborepo::{pnpm_workspaces, Turborepo},
+};
 use super::Provider;
 use crate::nixpacks::plan::merge::Mergeable;
 use crate::nixpacks::{
@@ -11,13 +14,14 @@ use crate::nixpacks::{
     },
 };
 use anyhow::Result;
+use globset::GlobBuilder;
 use node_semver::Range;
 use path_slash::PathExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-mod nx;
+pub mod nx;
 mod turborepo;
 
 pub const NODE_OVERLAY: &str = "https://github.com/railwayapp/nix-npm-overlay/archive/main.tar.gz";
@@ -25,6 +29,22 @@ pub const NODE_OVERLAY: &str = "https://github.com/railwayapp/nix-npm-overlay/ar
 const DEFAULT_NODE_VERSION: u32 = 16;
 const AVAILABLE_NODE_VERSIONS: &[u32] = &[14, 16, 18];
 
+// https://gist.github.com/winuxue/cfef08e2f5fe9dfc16a1d67a4ad38a01
+const PUPPETEER_APT_PKGS: &[&str] = &[
+    "libnss3",
+    "libatk1.0-0",
+    "libatk-bridge2.0-0",
+    "libcups2",
+    "libgbm1",
+    "libasound2",
+    "libpangocairo-1.0-0",
+    "libxss1",
+    "libgtk-3-0",
+    "libxshmfence1",
+    "libglu1",
+];
+const CANVAS_LIBS: &[&str] = &["libuuid", "libGL"];
+
 const YARN_CACHE_DIR: &str = "/usr/local/share/.cache/yarn/v6";
 const PNPM_CACHE_DIR: &str = "/root/.local/share/pnpm/store/v3";
 const NPM_CACHE_DIR: &str = "/root/.npm";
@@ -32,6 +52,29 @@ const BUN_CACHE_DIR: &str = "/root/.bun";
 const CYPRESS_CACHE_DIR: &str = "/root/.cache/Cypress";
 const NODE_MODULES_CACHE_DIR: &str = "node_modules/.cache";
 
+/// The files installing the dependencies reads, besides package.json.
+const INSTALL_FILES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    ".npmrc",
+    ".yarnrc",
+    ".yarnrc.yml",
+    ".yarn",
+    ".pnpmfile.cjs",
+];
+
+/// Scripts of package.json run while installing the dependencies.
+const INSTALL_SCRIPTS: &[&str] = &["preinstall", "install", "postinstall", "prepare"];
+
+/// Version prefixes of dependencies installed from the app's own files.
+const LOCAL_DEPENDENCY_PROTOCOLS: &[&str] = &["file:", "link:", "portal:", "patch:"];
+
+/// Where `pnpm deploy` copies the app's package of a pnpm workspace, with only its production dependencies.
+const PNPM_DEPLOY_DIR: &str = ".pnpm-deploy";
+
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 struct TsConfigJson {
     #[serde(rename = "compilerOptions")]
@@ -102,8 +145,19 @@ impl Provider for NodeProvider {
         "node"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("package.json"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["package.json"]))
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
+        Ok(Some(
+            NodeProvider::get_nix_node_pkg(&package_json, app, env)?.name,
+        ))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -115,22 +169,9 @@ impl Provider for NodeProvider {
         }
 
         if NodeProvider::uses_node_dependency(app, "puppeteer") {
-            // https://gist.github.com/winuxue/cfef08e2f5fe9dfc16a1d67a4ad38a01
-            setup.add_apt_pkgs(vec![
-                "libnss3".to_string(),
-                "libatk1.0-0".to_string(),
-                "libatk-bridge2.0-0".to_string(),
-                "libcups2".to_string(),
-                "libgbm1".to_string(),
-                "libasound2".to_string(),
-                "libpangocairo-1.0-0".to_string(),
-                "libxss1".to_string(),
-                "libgtk-3-0".to_string(),
-                "libxshmfence1".to_string(),
-                "libglu1".to_string(),
-            ]);
+            setup.add_apt_pkgs(PUPPETEER_APT_PKGS.iter().map(ToString::to_string).collect());
         } else if NodeProvider::uses_node_dependency(app, "canvas") {
-            setup.add_pkgs_libs(vec!["libuuid".to_string(), "libGL".to_string()]);
+            setup.add_pkgs_libs(CANVAS_LIBS.iter().map(ToString::to_string).collect());
         }
 
         // Install
@@ -158,6 +199,13 @@ impl Provider for NodeProvider {
             install.add_cache_directory((*CYPRESS_CACHE_DIR).to_string());
         }
 
+        // Only run install if package.json or the lockfiles have changed
+        if let Some(files) = NodeProvider::get_install_files(app) {
+            for file in files {
+                install.add_file_dependency(file);
+            }
+        }
+
         // Build
         let mut build = Phase::build(NodeProvider::get_build_cmd(app, env)?);
 
@@ -184,13 +232,132 @@ impl Provider for NodeProvider {
         NodeProvider::cache_tsbuildinfo_file(app, &mut build);
 
         // Start
-        let start = NodeProvider::get_start_cmd(app, env)?.map(StartPhase::new);
+        let mut start = NodeProvider::get_start_cmd(app, env)?.map(StartPhase::new);
+        if let Some(start) = &mut start {
+            // Next.js and Nuxt listen on 3000 unless PORT is set
+            if NodeProvider::uses_node_dependency(app, "next")
+                || NodeProvider::uses_node_dependency(app, "nuxt")
+            {
+                start.add_port("3000");
+            }
+        }
+
+        let mut phases = vec![setup, install, build];
+
+        // Prune a pnpm workspace to the app's package, so that a slim runtime only has its production dependencies
+        if let Some(name) = NodeProvider::get_pnpm_app_name(app, env) {
+            let mut prune = Phase::new("prune");
+            prune.depends_on_phase("build");
+            prune.add_cmd(format!(
+                "rm -rf {PNPM_DEPLOY_DIR} && pnpm --filter {name} --prod deploy {PNPM_DEPLOY_DIR}"
+            ));
+            phases.push(prune);
 
-        let mut plan = BuildPlan::new(&vec![setup, install, build], start);
+            let mut pruned_start =
+                StartPhase::new(format!("cd {PNPM_DEPLOY_DIR} && pnpm run start"));
+            pruned_start.add_file_dependency(PNPM_DEPLOY_DIR);
+            pruned_start.ports = start.and_then(|start| start.ports);
+            start = Some(pruned_start);
+        }
+
+        let mut plan = BuildPlan::new(&phases, start);
         plan.add_variables(NodeProvider::get_node_environment_variables());
 
         Ok(Some(plan))
     }
+
+    fn reasons(&self, app: &App, env: &Environment) -> Result<BTreeMap<String, String>> {
+        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
+        let package_manager = NodeProvider::get_package_manager(app);
+        let lockfile = match package_manager.as_str() {
+            "pnpm" => "pnpm-lock.yaml",
+            "yarn" => "yarn.lock",
+            "bun" => "bun.lockb",
+            _ => "package-lock.json",
+        };
+        let package_manager_reason = if app.includes_file(lockfile) {
+            format!("{lockfile} exists")
+        } else {
+            "npm is the default package manager".to_string()
+        };
+
+        let mut reasons = BTreeMap::new();
+        let node_pkg = NodeProvider::get_nix_node_pkg(&package_json, app, env)?;
+        for pkg in NodeProvider::get_nix_packages(app, env)? {
+            let reason = if pkg.name == node_pkg.name {
+                NodeProvider::get_node_version_reason(&package_json, app, env)
+            } else {
+                package_manager_reason.clone()
+            };
+            reasons.insert(pkg.to_nix_string(), reason);
+        }
+
+        if NodeProvider::uses_node_dependency(app, "prisma") {
+            reasons.insert("openssl".to_string(), "uses prisma".to_string());
+        }
+        if NodeProvider::uses_node_dependency(app, "puppeteer") {
+            for pkg in PUPPETEER_APT_PKGS {
+                reasons.insert((*pkg).to_string(), "uses puppeteer".to_string());
+            }
+        } else if NodeProvider::uses_node_dependency(app, "canvas") {
+            for lib in CANVAS_LIBS {
+                reasons.insert((*lib).to_string(), "uses canvas".to_string());
+            }
+        }
+
+        if NodeProvider::uses_corepack(app, env)? {
+            reasons.insert(
+                "npm install -g corepack && corepack enable".to_string(),
+                "packageManager in package.json".to_string(),
+            );
+        }
+        if let Some(install_cmd) = NodeProvider::get_install_command(app) {
+            reasons.insert(install_cmd, package_manager_reason.clone());
+        }
+
+        let monorepo_reason = if Nx::is_nx_monorepo(app, env) {
+            "Nx monorepo"
+        } else {
+            "Turborepo monorepo"
+        };
+        if let Some(build_cmd) = NodeProvider::get_build_cmd(app, env)? {
+            let reason = if build_cmd == format!("{package_manager} run build") {
+                "scripts.build in package.json"
+            } else {
+                monorepo_reason
+            };
+            reasons.insert(build_cmd, reason.to_string());
+        }
+        if let Some(start_cmd) = NodeProvider::get_start_cmd(app, env)? {
+            let reason = if start_cmd == format!("{package_manager} run start") {
+                "scripts.start in package.json".to_string()
+            } else if let Some(main) = package_json
+                .main
+                .filter(|main| start_cmd.ends_with(&format!(" {main}")))
+            {
+                format!("main in package.json is {main}")
+            } else if start_cmd.ends_with(" index.js") || start_cmd.ends_with(" index.ts") {
+                format!(
+                    "{} exists",
+                    start_cmd.split(' ').next_back().unwrap_or_default()
+                )
+            } else {
+                monorepo_reason.to_string()
+            };
+            reasons.insert(start_cmd, reason);
+        }
+
+        if NodeProvider::uses_node_dependency(app, "next")
+            || NodeProvider::uses_node_dependency(app, "nuxt")
+        {
+            reasons.insert(
+                "3000".to_string(),
+                "Next.js and Nuxt listen on 3000".to_string(),
+            );
+        }
+
+        Ok(reasons)
+    }
 }
 
 impl NodeProvider {
@@ -247,6 +414,13 @@ impl NodeProvider {
             }
         }
 
+        if let Some(name) = NodeProvider::get_pnpm_app_name(app, env) {
+            // Also build the workspace packages the app depends on
+            return Ok(Some(format!(
+                "pnpm --filter {name}... run --if-present build"
+            )));
+        }
+
         if NodeProvider::has_script(app, "build")? {
             let pkg_manager = NodeProvider::get_package_manager(app);
             Ok(Some(format!("{pkg_manager} run build")))
@@ -292,6 +466,29 @@ impl NodeProvider {
         Ok(None)
     }
 
+    /// Where the Node version picked by `get_nix_node_pkg` comes from.
+    fn get_node_version_reason(
+        package_json: &PackageJson,
+        app: &App,
+        environment: &Environment,
+    ) -> String {
+        let has_engine = package_json
+            .engines
+            .as_ref()
+            .map_or(false, |engines| engines.contains_key("node"));
+
+        if environment.get_config_variable("NODE_VERSION").is_some() {
+            "NIXPACKS_NODE_VERSION is set"
+        } else if has_engine {
+            "engines.node in package.json"
+        } else if app.includes_file(".nvmrc") {
+            ".nvmrc"
+        } else {
+            "default Node version"
+        }
+        .to_string()
+    }
+
     /// Parses the package.json engines field and returns a Nix package if available
     pub fn get_nix_node_pkg(
         package_json: &PackageJson,
@@ -329,6 +526,54 @@ impl NodeProvider {
         return Ok(Pkg::new(node_pkg.as_str()));
     }
 
+    /// The directories of the packages in npm, Yarn, and pnpm workspaces and of Nx projects, relative to the app.
+    pub fn get_workspace_dirs(app: &App) -> Result<Vec<String>> {
+        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
+        let mut globs = match package_json.workspaces {
+            Some(Workspaces::Array(globs)) => globs,
+            // Yarn also accepts `{ "packages": [...] }`
+            Some(Workspaces::Unknown(value)) => {
+                serde_json::from_value(value["packages"].clone()).unwrap_or_default()
+            }
+            None => Vec::new(),
+        };
+        if app.includes_file("pnpm-workspace.yaml") {
+            globs.extend(pnpm_workspaces(app)?);
+        }
+
+        let mut dirs = BTreeSet::new();
+        for glob in globs.iter().filter(|glob| !glob.starts_with('!')) {
+            let glob = glob.trim_start_matches("./").trim_end_matches('/');
+            // Unlike with `App::find_directories`, `*` in a workspace doesn't match nested directories
+            let matcher = GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()?
+                .compile_matcher();
+            for dir in app.find_directories(glob)? {
+                let dir = app.strip_source_path(&dir)?;
+                if matcher.is_match(&dir)
+                    && app.includes_file(&dir.join("package.json").to_string_lossy())
+                {
+                    dirs.insert(dir.to_slash_lossy().to_string());
+                }
+            }
+        }
+        dirs.extend(Nx::get_project_dirs(app)?);
+
+        Ok(dirs.into_iter().collect())
+    }
+
+    /// The package of a pnpm workspace to build, named by `NIXPACKS_PNPM_APP_NAME`.
+    pub fn get_pnpm_app_name(app: &App, env: &Environment) -> Option<String> {
+        if NodeProvider::get_package_manager(app) == "pnpm"
+            && app.includes_file("pnpm-workspace.yaml")
+        {
+            env.get_config_variable("PNPM_APP_NAME")
+        } else {
+            None
+        }
+    }
+
     pub fn get_package_manager(app: &App) -> String {
         let mut pkg_manager = "npm";
         if app.includes_file("pnpm-lock.yaml") {
@@ -377,7 +622,43 @@ impl NodeProvider {
         Some(install_cmd)
     }
 
-    fn get_package_manager_cache_dir(app: &App) -> String {
+    /// The files installing the dependencies needs, or `None` when it needs the rest of the app too, like for
+    /// workspaces, install scripts, and local dependencies.
+    pub fn get_install_files(app: &App) -> Option<Vec<String>> {
+        let package_json: PackageJson = app.read_json("package.json").ok()?;
+        let has_install_scripts = package_json.scripts.as_ref().map_or(false, |scripts| {
+            INSTALL_SCRIPTS
+                .iter()
+                .any(|script| scripts.contains_key(*script))
+        });
+        let has_local_deps = [&package_json.dependencies, &package_json.dev_dependencies]
+            .iter()
+            .filter_map(|deps| deps.as_ref())
+            .flat_map(|deps| deps.values())
+            .any(|version| {
+                LOCAL_DEPENDENCY_PROTOCOLS
+                    .iter()
+                    .any(|protocol| version.starts_with(protocol))
+            });
+        // Prisma generates its client from the schema when it's installed
+        if package_json.workspaces.is_some()
+            || app.includes_file("pnpm-workspace.yaml")
+            || has_install_scripts
+            || has_local_deps
+            || app
+                .read_file("package.json")
+                .map_or(true, |contents| contents.contains("patchedDependencies"))
+            || NodeProvider::uses_node_dependency(app, "prisma")
+        {
+            return None;
+        }
+
+        let mut files = vec!["package.json".to_string()];
+        files.append(&mut app.find_existing_paths(INSTALL_FILES));
+        Some(files)
+    }
+
+    pub fn get_package_manager_cache_dir(app: &App) -> String {
         let package_manager = NodeProvider::get_package_manager(app);
         if package_manager == "yarn" {
             (*YARN_CACHE_DIR).to_string()
@@ -928,4 +1209,104 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_reasons() -> Result<()> {
+        let reasons = NodeProvider {}
+            .reasons(&App::new("./examples/node-nvmrc")?, &Environment::default())?;
+        assert_eq!(reasons.get("nodejs-14_x"), Some(&".nvmrc".to_string()));
+        assert_eq!(
+            reasons.get("npm ci"),
+            Some(&"package-lock.json exists".to_string())
+        );
+        assert_eq!(
+            reasons.get("npm run start"),
+            Some(&"scripts.start in package.json".to_string())
+        );
+
+        let reasons = NodeProvider {}.reasons(
+            &App::new("./examples/node-nvmrc")?,
+            &Environment::from_envs(vec!["NIXPACKS_NODE_VERSION=18"])?,
+        )?;
+        assert_eq!(
+            reasons.get("nodejs-18_x"),
+            Some(&"NIXPACKS_NODE_VERSION is set".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_workspace_dirs() -> Result<()> {
+        assert_eq!(
+            NodeProvider::get_workspace_dirs(&App::new("./examples/node-monorepo")?)?,
+            vec!["packages/client", "packages/server"]
+        );
+        assert!(
+            NodeProvider::get_workspace_dirs(&App::new("./examples/node-nx")?)?
+                .contains(&"apps/express-app".to_string())
+        );
+        assert!(NodeProvider::get_workspace_dirs(&App::new("./examples/node")?)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pnpm_workspace_prune() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-workspace")?;
+        let plan = NodeProvider {}
+            .get_build_plan(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_PNPM_APP_NAME=api"])?,
+            )?
+            .unwrap();
+
+        assert_eq!(
+            plan.get_phase("prune").unwrap().cmds,
+            Some(vec![
+                "rm -rf .pnpm-deploy && pnpm --filter api --prod deploy .pnpm-deploy".to_string()
+            ])
+        );
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("cd .pnpm-deploy && pnpm run start".to_string())
+        );
+
+        let plan = NodeProvider {}
+            .get_build_plan(&app, &Environment::default())?
+            .unwrap();
+        assert!(plan.get_phase("prune").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_install_files() -> Result<()> {
+        assert_eq!(
+            NodeProvider::get_install_files(&App::new("./examples/node-npm")?),
+            Some(vec![
+                "package.json".to_string(),
+                "package-lock.json".to_string()
+            ])
+        );
+        assert_eq!(
+            NodeProvider::get_install_files(&App::new("./examples/node-yarn-berry")?),
+            Some(vec![
+                "package.json".to_string(),
+                "yarn.lock".to_string(),
+                ".yarnrc.yml".to_string(),
+                ".yarn".to_string()
+            ])
+        );
+        assert_eq!(
+            NodeProvider::get_install_files(&App::new("./examples/node-monorepo")?),
+            None
+        );
+        assert_eq!(
+            NodeProvider::get_install_files(&App::new("./examples/node-prisma-postgres")?),
+            None
+        );
+
+        Ok(())
+    }
 }
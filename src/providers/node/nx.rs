@@ -1,8 +1,9 @@
 // Code relating to NX Monorepos
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use anyhow::Result;
+use path_slash::PathExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -45,6 +46,11 @@ pub struct Configuration {
     pub production: Option<Value>,
 }
 
+#[derive(Debug, Serialize, PartialEq, Eq, Deserialize)]
+pub struct WorkspaceJson {
+    pub projects: BTreeMap<String, Value>,
+}
+
 pub struct Nx {}
 
 const NX_APP_NAME_ENV_VAR: &str = "NX_APP_NAME";
@@ -140,4 +146,65 @@ impl Nx {
 
         Ok(None)
     }
+
+    /// The directories of the projects in the Nx workspace that can be built, relative to the app.
+    pub fn get_project_dirs(app: &App) -> Result<Vec<String>> {
+        let mut dirs = Vec::new();
+        if let Ok(workspace_json) = app.read_json::<WorkspaceJson>("workspace.json") {
+            dirs.extend(
+                workspace_json
+                    .projects
+                    .values()
+                    .filter_map(Nx::get_project_root),
+            );
+        }
+        for file in app.find_files("apps/*/project.json")? {
+            if let Some(dir) = app.strip_source_path(&file)?.parent() {
+                dirs.push(dir.to_slash_lossy().to_string());
+            }
+        }
+
+        Ok(dirs
+            .into_iter()
+            .filter(|dir| Nx::get_project_name(app, dir).is_some())
+            .collect())
+    }
+
+    /// The name of the Nx project in a directory of the app, if it's one that can be built with `NX_APP_NAME`.
+    pub fn get_project_name(app: &App, dir: &str) -> Option<String> {
+        if !app.includes_file("nx.json") {
+            return None;
+        }
+
+        let dir = dir.trim_start_matches("./").trim_end_matches('/');
+        let name = app
+            .read_json::<WorkspaceJson>("workspace.json")
+            .ok()
+            .and_then(|workspace_json| {
+                workspace_json
+                    .projects
+                    .into_iter()
+                    .find(|(_, project)| Nx::get_project_root(project).as_deref() == Some(dir))
+                    .map(|(name, _)| name)
+            })
+            .or_else(|| {
+                dir.strip_prefix("apps/")
+                    .filter(|name| !name.contains('/'))
+                    .map(ToString::to_string)
+            })?;
+
+        if Nx::get_nx_project_json_for_app(app, &name).is_ok() {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Projects in workspace.json are either their directory, or an object with the directory as its root.
+    fn get_project_root(project: &Value) -> Option<String> {
+        project
+            .as_str()
+            .or_else(|| project.get("root").and_then(Value::as_str))
+            .map(ToString::to_string)
+    }
 }
@@ -17,6 +17,25 @@ use anyhow::Result;
 
 const DEFAULT_PHP_VERSION: &str = "8.2";
 
+/// Extensions compiled into PHP, which Nix has no package for
+const BUILT_IN_EXTENSIONS: &[&str] = &[
+    "core",
+    "date",
+    "hash",
+    "pcre",
+    "random",
+    "reflection",
+    "spl",
+    "standard",
+];
+
+/// Frameworks whose apps are served from their `public` directory through a front controller.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum PhpFramework {
+    Laravel,
+    Symfony,
+}
+
 pub struct PhpProvider;
 
 impl Provider for PhpProvider {
@@ -24,15 +43,19 @@ impl Provider for PhpProvider {
         "php"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("composer.json") || app.includes_file("index.php"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["composer.json", "index.php"]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = PhpProvider::get_setup(app, env)?;
-        let install = PhpProvider::get_install(app);
+        let install = PhpProvider::get_install(app, env);
         let build = PhpProvider::get_build(app);
-        let start = PhpProvider::get_start(app);
+        let start = PhpProvider::get_start(app, env);
 
         let mut plan = BuildPlan::new(
             &vec![Some(setup), Some(install), build]
@@ -74,6 +97,9 @@ impl PhpProvider {
             Pkg::new("libmysqlclient"),
             Pkg::new(&format!("{}Packages.composer", &php_pkg)),
         ];
+        if PhpProvider::uses_built_in_server(env) {
+            pkgs.retain(|pkg| pkg.name != "perl" && pkg.name != "nginx");
+        }
         let ext_pkgs: Vec<String> = php_extensions
             .iter()
             .map(|extension| format!("{}Extensions.{extension}", &php_pkg))
@@ -96,10 +122,11 @@ impl PhpProvider {
         Ok(phase)
     }
 
-    fn get_install(app: &App) -> Phase {
-        let mut install = Phase::install(Some(
-            "mkdir -p /var/log/nginx && mkdir -p /var/cache/nginx".to_string(),
-        ));
+    fn get_install(app: &App, env: &Environment) -> Phase {
+        let mut install = Phase::install(None);
+        if !PhpProvider::uses_built_in_server(env) {
+            install.add_cmd("mkdir -p /var/log/nginx && mkdir -p /var/cache/nginx");
+        }
         if app.includes_file("composer.json") {
             install.add_cmd("composer install --ignore-platform-reqs".to_string());
         };
@@ -126,8 +153,15 @@ impl PhpProvider {
         None
     }
 
-    fn get_start(app: &App) -> StartPhase {
-        if app.includes_file("nginx.conf") {
+    fn get_start(app: &App, env: &Environment) -> StartPhase {
+        if PhpProvider::uses_built_in_server(env) {
+            let root_dir = env
+                .get_config_variable("PHP_ROOT_DIR")
+                .or_else(|| PhpProvider::get_root_dir(app).map(ToString::to_string))
+                .unwrap_or_else(|| "/app".to_string());
+
+            StartPhase::new(format!("php -S 0.0.0.0:${{PORT}} -t {root_dir}"))
+        } else if app.includes_file("nginx.conf") {
             StartPhase::new(format!(
                 "php-fpm -y {} & nginx -c /app/nginx.conf",
                 app.asset_path("php-fpm.conf")
@@ -164,16 +198,52 @@ impl PhpProvider {
     fn environment_variables(app: &App) -> EnvironmentVariables {
         let mut vars = EnvironmentVariables::new();
         vars.insert("PORT".to_string(), "80".to_string());
-        if app.includes_file("artisan") {
-            vars.insert("IS_LARAVEL".to_string(), "yes".to_string());
-            vars.insert(
-                "NIXPACKS_PHP_ROOT_DIR".to_string(),
-                "/app/public".to_string(),
-            );
+        match PhpProvider::get_framework(app) {
+            Some(PhpFramework::Laravel) => {
+                vars.insert("IS_LARAVEL".to_string(), "yes".to_string());
+            }
+            Some(PhpFramework::Symfony) => {
+                // Route everything that isn't a file through the front controller
+                vars.insert(
+                    "NIXPACKS_PHP_FALLBACK_PATH".to_string(),
+                    "/index.php".to_string(),
+                );
+            }
+            None => {}
+        }
+        if let Some(root_dir) = PhpProvider::get_root_dir(app) {
+            vars.insert("NIXPACKS_PHP_ROOT_DIR".to_string(), root_dir.to_string());
         }
         vars
     }
 
+    fn get_framework(app: &App) -> Option<PhpFramework> {
+        if app.includes_file("artisan") {
+            return Some(PhpFramework::Laravel);
+        }
+
+        let composer_json: ComposerJson = app.read_json("composer.json").ok()?;
+        if composer_json
+            .require
+            .contains_key("symfony/framework-bundle")
+            || composer_json.require.contains_key("symfony/runtime")
+        {
+            return Some(PhpFramework::Symfony);
+        }
+
+        None
+    }
+
+    fn get_root_dir(app: &App) -> Option<&'static str> {
+        PhpProvider::get_framework(app).map(|_| "/app/public")
+    }
+
+    /// Whether `NIXPACKS_PHP_SERVER=builtin` serves the app with PHP's built-in web server instead of php-fpm and Nginx.
+    fn uses_built_in_server(env: &Environment) -> bool {
+        env.get_config_variable("PHP_SERVER")
+            .map_or(false, |server| server == "builtin")
+    }
+
     fn get_php_package(app: &App) -> Result<String> {
         let version = PhpProvider::get_php_version(app)?;
         Ok(format!("php{}", version.replace('.', "")))
@@ -211,21 +281,90 @@ impl PhpProvider {
         let version = PhpProvider::get_php_version(app)?;
         let mut extensions = Vec::new();
         for extension in composer_json.require.keys() {
-            // ext-json is included by default in PHP >= 8.0 (and not available in Nix) so skip over it
-            if extension.starts_with("ext-") && (version == "7.4" || extension != "ext-json") {
-                extensions.push(
-                    extension
-                        .strip_prefix("ext-")
-                        .unwrap_or(extension)
-                        .to_string(),
-                );
+            if let Some(extension) = extension.strip_prefix("ext-") {
+                if let Some(extension) = PhpProvider::get_nix_extension(extension, &version) {
+                    if !extensions.contains(&extension) {
+                        extensions.push(extension);
+                    }
+                }
             }
         }
         Ok(extensions)
     }
+
+    /// The name of a Composer platform extension, like `ext-pdo_mysql`, in Nix's PHP extension set.
+    fn get_nix_extension(extension: &str, php_version: &str) -> Option<String> {
+        let extension = extension.to_lowercase().replace('-', "_");
+
+        // ext-json is included by default in PHP >= 8.0 (and not available in Nix) so skip over it
+        if BUILT_IN_EXTENSIONS.contains(&extension.as_str())
+            || (extension == "json" && php_version != "7.4")
+        {
+            return None;
+        }
+
+        let extension = match extension.as_str() {
+            "zend_opcache" | "zendopcache" => "opcache".to_string(),
+            _ => extension,
+        };
+
+        Some(extension)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct ComposerJson {
     require: HashMap<String, String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_nix_extension() {
+        assert_eq!(
+            PhpProvider::get_nix_extension("pdo_mysql", "8.2"),
+            Some("pdo_mysql".to_string())
+        );
+        assert_eq!(
+            PhpProvider::get_nix_extension("Zend-OPcache", "8.2"),
+            Some("opcache".to_string())
+        );
+        assert_eq!(PhpProvider::get_nix_extension("spl", "8.2"), None);
+        assert_eq!(PhpProvider::get_nix_extension("json", "8.2"), None);
+        assert_eq!(
+            PhpProvider::get_nix_extension("json", "7.4"),
+            Some("json".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_framework() -> Result<()> {
+        assert_eq!(
+            PhpProvider::get_framework(&App::new("./examples/php-laravel")?),
+            Some(PhpFramework::Laravel)
+        );
+        assert_eq!(
+            PhpProvider::get_framework(&App::new("./examples/php-api")?),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_built_in_server() -> Result<()> {
+        let start = PhpProvider::get_start(
+            &App::new("./examples/php-laravel")?,
+            &Environment::from_envs(vec!["NIXPACKS_PHP_SERVER=builtin"])?,
+        );
+
+        assert_eq!(
+            start.cmd,
+            Some("php -S 0.0.0.0:${PORT} -t /app/public".to_string())
+        );
+
+        Ok(())
+    }
+}
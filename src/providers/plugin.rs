@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    plan::BuildPlan,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const PLUGIN_PREFIX: &str = "nixpacks-provider-";
+
+/// WebAssembly plugins are run with this WASI runtime, which must be on `PATH` too
+const WASM_RUNTIME: &str = "wasmtime";
+
+/// What a plugin is told about the app, as JSON on stdin.
+#[derive(Serialize, Debug)]
+pub struct PluginInput {
+    /// The absolute path of the app
+    pub path: String,
+    /// Every file in the app, relative to its path, skipping the ones ignored by `.gitignore`
+    pub files: Vec<String>,
+    pub variables: EnvironmentVariables,
+}
+
+/// The answer of a plugin to `detect`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PluginDetection {
+    pub detected: bool,
+    /// The files the app was detected by, relative to its path
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// The version of the language or framework the plugin resolved
+    pub version: Option<String>,
+}
+
+/// A provider outside of Nixpacks, from an executable or WebAssembly module named `nixpacks-provider-{name}` on
+/// `PATH`.
+///
+/// It is run as `nixpacks-provider-{name} detect` and `nixpacks-provider-{name} plan`, with the app as JSON on stdin,
+/// and prints `{"detected": true}` or a build plan in the format of `nixpacks plan` as JSON on stdout.
+#[derive(Debug, Clone)]
+pub struct PluginProvider {
+    name: String,
+    path: PathBuf,
+    /// The answers to `detect` by app path, so that the plugin is only run once per app
+    detections: Arc<Mutex<HashMap<PathBuf, PluginDetection>>>,
+}
+
+impl PluginProvider {
+    /// Find the plugins on `PATH`. Like a shell, the first of plugins with the same name is used.
+    pub fn find_plugins() -> Vec<PluginProvider> {
+        let paths = env::var_os("PATH").unwrap_or_default();
+        PluginProvider::find_plugins_in(&paths)
+    }
+
+    fn find_plugins_in(paths: &OsString) -> Vec<PluginProvider> {
+        let mut plugins: Vec<PluginProvider> = Vec::new();
+
+        for dir in env::split_paths(paths) {
+            let mut entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .collect::<Vec<_>>(),
+                Err(_) => continue,
+            };
+            entries.sort();
+
+            for path in entries {
+                let name = match PluginProvider::get_plugin_name(&path) {
+                    Some(name) => name,
+                    None => continue,
+                };
+
+                if !plugins.iter().any(|plugin| plugin.name == name) {
+                    plugins.push(PluginProvider {
+                        name,
+                        path,
+                        detections: Arc::default(),
+                    });
+                }
+            }
+        }
+
+        plugins
+    }
+
+    /// The name of the provider, if the path is a plugin.
+    fn get_plugin_name(path: &Path) -> Option<String> {
+        let file_name = path.file_name()?.to_str()?;
+        let name = file_name.strip_prefix(PLUGIN_PREFIX)?;
+
+        if let Some(name) = name.strip_suffix(".wasm") {
+            return (path.is_file() && !name.is_empty()).then(|| name.to_string());
+        }
+
+        (is_executable(path) && !name.is_empty()).then(|| name.to_string())
+    }
+
+    fn run(&self, command: &str, app: &App, env: &Environment) -> Result<String> {
+        let input = PluginInput {
+            path: app.source.to_string_lossy().to_string(),
+            files: app
+                .find_files("**/*")?
+                .iter()
+                .map(|file| {
+                    app.strip_source_path(file)
+                        .map(|file| file.to_string_lossy().to_string())
+                })
+                .collect::<Result<Vec<_>>>()?,
+            variables: Environment::clone_variables(env),
+        };
+
+        let mut cmd = if self.path.extension().map_or(false, |ext| ext == "wasm") {
+            let mut cmd = Command::new(WASM_RUNTIME);
+            cmd.arg("run")
+                .arg(format!("--dir={}", app.source.display()))
+                .arg(&self.path);
+            cmd
+        } else {
+            Command::new(&self.path)
+        };
+
+        let mut child = cmd
+            .arg(command)
+            .current_dir(&app.source)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Running provider plugin {}", self.path.display()))?;
+
+        // The input is written while the output is read, so that plugins printing before they read it, or not reading
+        // it at all, don't block on a full pipe
+        let input = serde_json::to_string(&input)?;
+        let writer = child.stdin.take().map(|mut stdin| {
+            thread::spawn(move || match stdin.write_all(input.as_bytes()) {
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+                result => result,
+            })
+        });
+
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            writer
+                .join()
+                .map_err(|_| anyhow::anyhow!("Writing the input of provider plugin {}", self.name))?
+                .with_context(|| format!("Writing the input of provider plugin {}", self.name))?;
+        }
+        if !output.status.success() {
+            bail!(
+                "Provider plugin {} failed to {command} the app with {}",
+                self.name,
+                output.status
+            );
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    fn get_detection(&self, app: &App, env: &Environment) -> Result<PluginDetection> {
+        // Plugins run in the app's directory, which apps in memory don't have
+        if app.is_in_memory() {
+            return Ok(PluginDetection {
+                detected: false,
+                files: Vec::new(),
+                version: None,
+            });
+        }
+
+        if let Some(detection) = self.detections.lock().unwrap().get(&app.source) {
+            return Ok(detection.clone());
+        }
+
+        let output = self.run("detect", app, env)?;
+        let detection: PluginDetection = serde_json::from_str(&output)
+            .with_context(|| format!("Reading the detection of provider plugin {}", self.name))?;
+        self.detections
+            .lock()
+            .unwrap()
+            .insert(app.source.clone(), detection.clone());
+
+        Ok(detection)
+    }
+}
+
+impl Provider for PluginProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(self.get_detection(app, env)?.detected)
+    }
+
+    fn detection_files(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+        Ok(self.get_detection(app, env)?.files)
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(self.get_detection(app, env)?.version)
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let output = self.run("plan", app, env)?;
+        if output.trim().is_empty() || output.trim() == "null" {
+            return Ok(None);
+        }
+
+        let plan = BuildPlan::from_json(output)
+            .with_context(|| format!("Reading the plan of provider plugin {}", self.name))?;
+
+        Ok(Some(plan))
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).map_or(false, |metadata| {
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    })
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map_or(false, |ext| ext == "exe" || ext == "bat" || ext == "cmd")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    fn write_plugin(dir: &Path, name: &str, script: &str) -> Result<()> {
+        let path = dir.join(name);
+        fs::write(&path, script)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_plugins() -> Result<()> {
+        let first = TempDir::new("plugins")?;
+        let second = TempDir::new("plugins")?;
+        write_plugin(first.path(), "nixpacks-provider-first", "#!/bin/sh\n")?;
+        write_plugin(second.path(), "nixpacks-provider-first", "#!/bin/sh\n")?;
+        write_plugin(second.path(), "nixpacks-provider-second", "#!/bin/sh\n")?;
+        fs::write(second.path().join("nixpacks-provider-module.wasm"), "")?;
+        fs::write(second.path().join("nixpacks-provider-not-executable"), "")?;
+        write_plugin(second.path(), "nixpacks", "#!/bin/sh\n")?;
+
+        let paths = env::join_paths([first.path(), second.path()])?;
+        let plugins = PluginProvider::find_plugins_in(&paths);
+
+        assert_eq!(
+            plugins
+                .iter()
+                .map(|plugin| plugin.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["first", "module", "second"]
+        );
+        assert_eq!(
+            plugins[0].path,
+            first.path().join("nixpacks-provider-first")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_plan() -> Result<()> {
+        let dir = TempDir::new("plugins")?;
+        write_plugin(
+            dir.path(),
+            "nixpacks-provider-make",
+            indoc::indoc! {r#"
+                #!/bin/sh
+                input=$(cat)
+                if [ "$1" = "detect" ]; then
+                    case "$input" in
+                        *'"Makefile"'*) echo '{"detected": true, "files": ["Makefile"]}' ;;
+                        *) echo '{"detected": false}' ;;
+                    esac
+                else
+                    echo '{"phases": {"build": {"cmds": ["make"]}}, "start": {"cmd": "./out"}}'
+                fi
+            "#},
+        )?;
+        let plugins = PluginProvider::find_plugins_in(&dir.path().as_os_str().to_os_string());
+        let plugin = &plugins[0];
+
+        let env = Environment::default();
+        assert!(!plugin.detect(&App::new("./examples/go")?, &env)?);
+        assert!(plugin
+            .detection_files(&App::new("./examples/go")?, &env)?
+            .is_empty());
+
+        let plan = plugin
+            .get_build_plan(&App::new("./examples/go")?, &env)?
+            .unwrap();
+        assert_eq!(
+            plan.get_phase("build").and_then(|build| build.cmds.clone()),
+            Some(vec!["make".to_string()])
+        );
+        assert_eq!(
+            plan.start_phase.and_then(|start| start.cmd),
+            Some("./out".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_detects_once() -> Result<()> {
+        let dir = TempDir::new("plugins")?;
+        let runs = dir.path().join("runs");
+        write_plugin(
+            dir.path(),
+            "nixpacks-provider-counted",
+            &format!(
+                "#!/bin/sh\necho run >> {}\necho '{{\"detected\": true, \"version\": \"1.0\"}}'\n",
+                runs.display()
+            ),
+        )?;
+        let plugins = PluginProvider::find_plugins_in(&dir.path().as_os_str().to_os_string());
+        let plugin = &plugins[0];
+
+        let app = App::new("./examples/go")?;
+        let env = Environment::default();
+        assert!(plugin.detect(&app, &env)?);
+        assert!(plugin.detection_files(&app, &env)?.is_empty());
+        assert_eq!(plugin.version(&app, &env)?, Some("1.0".to_string()));
+        assert_eq!(fs::read_to_string(runs)?, "run\n");
+
+        Ok(())
+    }
+}
@@ -6,10 +6,10 @@ use crate::nixpacks::{
     environment::Environment,
     plan::{
         phase::{Phase, StartPhase},
-        BuildPlan,
+        BuildPlan, Processes,
     },
 };
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 
 pub struct ProcfileProvider {}
 
@@ -18,7 +18,7 @@ impl Provider for ProcfileProvider {
         "deno"
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
         if let Some(release_cmd) = ProcfileProvider::get_release_cmd(app)? {
@@ -32,35 +32,46 @@ impl Provider for ProcfileProvider {
             plan.add_phase(release);
         };
 
-        if let Some(start_cmd) = ProcfileProvider::get_start_cmd(app)? {
+        let processes = ProcfileProvider::get_processes(app)?;
+        if let Some(start_cmd) = ProcfileProvider::get_start_cmd(&processes, env)? {
             let start_phase = StartPhase::new(start_cmd);
             plan.set_start_phase(start_phase);
         }
+        if !processes.is_empty() {
+            plan.add_processes(processes);
+        }
 
         Ok(Some(plan))
     }
 }
 
 impl ProcfileProvider {
-    fn get_start_cmd(app: &App) -> Result<Option<String>> {
+    /// Every process in the Procfile except `release`, which runs as a phase instead.
+    fn get_processes(app: &App) -> Result<Processes> {
         if app.includes_file("Procfile") {
-            let mut procfile: HashMap<String, String> =
-                app.read_yaml("Procfile").context("Reading Procfile")?;
+            let mut procfile: Processes = app.read_yaml("Procfile").context("Reading Procfile")?;
             procfile.remove("release");
-
-            if procfile.is_empty() {
-                Ok(None)
-            } else if let Some(cmd) = procfile.get("web") {
-                Ok(Some(cmd.to_string()))
-            } else {
-                let process = procfile.values().collect::<Vec<_>>()[0].to_string();
-                Ok(Some(process))
-            }
+            Ok(procfile)
         } else {
-            Ok(None)
+            Ok(Processes::new())
         }
     }
 
+    /// The process chosen with `NIXPACKS_PROCESS`, otherwise `web`, or the first process.
+    fn get_start_cmd(processes: &Processes, env: &Environment) -> Result<Option<String>> {
+        if let Some(name) = env.get_config_variable("PROCESS") {
+            return match processes.get(&name) {
+                Some(cmd) => Ok(Some(cmd.to_string())),
+                None => bail!("Process `{}` is not in the Procfile", name),
+            };
+        }
+
+        Ok(processes
+            .get("web")
+            .or_else(|| processes.values().next())
+            .cloned())
+    }
+
     fn get_release_cmd(app: &App) -> Result<Option<String>> {
         if app.includes_file("Procfile") {
             let procfile: HashMap<String, String> =
@@ -75,3 +86,44 @@ impl ProcfileProvider {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_plan(envs: Vec<&str>) -> Result<BuildPlan> {
+        let app = App::new("./examples/python-procfile")?;
+        let env = Environment::from_envs(envs)?;
+        Ok(ProcfileProvider {}.get_build_plan(&app, &env)?.unwrap())
+    }
+
+    #[test]
+    fn test_processes() -> Result<()> {
+        let plan = get_plan(Vec::new())?;
+        assert_eq!(
+            plan.processes,
+            Some(Processes::from([
+                ("web".to_string(), "python src/main.py".to_string()),
+                ("worker".to_string(), "echo \"another process\"".to_string()),
+            ]))
+        );
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("python src/main.py".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chosen_process() -> Result<()> {
+        let plan = get_plan(vec!["NIXPACKS_PROCESS=worker"])?;
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("echo \"another process\"".to_string())
+        );
+        assert!(get_plan(vec!["NIXPACKS_PROCESS=clock"]).is_err());
+
+        Ok(())
+    }
+}
@@ -13,8 +13,8 @@ use crate::{
 use anyhow::{bail, Context, Ok, Result};
 use regex::{Match, Regex};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::result::Result::Ok as OkResult;
-use std::{collections::HashMap, fs};
 
 use super::{Provider, ProviderMetadata};
 
@@ -23,6 +23,9 @@ const POETRY_VERSION: &str = "1.3.1";
 const PDM_VERSION: &str = "2.7.4";
 const PIP_CACHE_DIR: &str = "/root/.cache/pip";
 const PDM_CACHE_DIR: &str = "/root/.cache/pdm";
+const UV_CACHE_DIR: &str = "/root/.cache/uv";
+// uv isn't in the nixpkgs revision the other packages are pinned to
+const UV_NIXPKGS_ARCHIVE: &str = "nixos-24.11";
 const DEFAULT_POETRY_PYTHON_PKG_NAME: &str = "python310";
 
 pub struct PythonProvider {}
@@ -32,12 +35,16 @@ impl Provider for PythonProvider {
         "python"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        let has_python = app.includes_file("main.py")
-            || app.includes_file("requirements.txt")
-            || app.includes_file("pyproject.toml")
-            || app.includes_file("Pipfile");
-        Ok(has_python)
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["main.py", "requirements.txt", "pyproject.toml", "Pipfile"]))
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(Some(PythonProvider::get_nix_python_package(app, env)?.name))
     }
 
     fn metadata(&self, app: &App, env: &Environment) -> Result<ProviderMetadata> {
@@ -45,12 +52,14 @@ impl Provider for PythonProvider {
         let is_using_postgres = PythonProvider::is_using_postgres(app, env)?;
         let is_poetry = app.includes_file("poetry.lock");
         let is_pdm = app.includes_file("pdm.lock");
+        let is_uv = app.includes_file("uv.lock");
 
         Ok(ProviderMetadata::from(vec![
             (is_django, "django"),
             (is_using_postgres, "postgres"),
             (is_poetry, "poetry"),
             (is_pdm, "pdm"),
+            (is_uv, "uv"),
         ]))
     }
 
@@ -86,6 +95,20 @@ impl Provider for PythonProvider {
             )]));
         }
 
+        if app.includes_file("uv.lock") {
+            plan.add_variables(EnvironmentVariables::from([
+                // Install into the same virtual environment as the other package managers
+                (
+                    "UV_PROJECT_ENVIRONMENT".to_string(),
+                    "/opt/venv".to_string(),
+                ),
+                // Use the Python from the setup phase instead of downloading one
+                ("UV_PYTHON_DOWNLOADS".to_string(), "never".to_string()),
+                // The cache directory is mounted, so packages can't be hard linked from it
+                ("UV_LINK_MODE".to_string(), "copy".to_string()),
+            ]));
+        }
+
         Ok(Some(plan))
     }
 }
@@ -154,12 +177,28 @@ impl PythonProvider {
         let create_env = format!("python -m venv --copies {env_loc}");
         let activate_env = format!(". {env_loc}/bin/activate");
 
-        if app.includes_file("requirements.txt") {
+        if app.includes_file("pyproject.toml") && app.includes_file("uv.lock") {
+            // uv creates the virtual environment itself, at UV_PROJECT_ENVIRONMENT
+            let mut install_phase = Phase::install(Some("uv sync --no-dev --frozen".to_string()));
+            install_phase.add_nix_pkgs(&[Pkg::new("uv")]);
+            install_phase.set_nix_archive(UV_NIXPKGS_ARCHIVE.to_string());
+
+            install_phase.add_path(format!("{env_loc}/bin"));
+            install_phase.add_persisted_directory(env_loc);
+            install_phase.add_cache_directory(UV_CACHE_DIR.to_string());
+
+            return Ok(Some(install_phase));
+        } else if app.includes_file("requirements.txt") {
             let mut install_phase = Phase::install(Some(format!(
                 "{create_env} && {activate_env} && pip install -r requirements.txt"
             )));
+            // Only run install if requirements.txt has changed
+            if !PythonProvider::requirements_use_local_files(app)? {
+                install_phase.add_file_dependency("requirements.txt".to_string());
+            }
 
             install_phase.add_path(format!("{env_loc}/bin"));
+            install_phase.add_persisted_directory(env_loc);
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
             return Ok(Some(install_phase));
@@ -171,6 +210,7 @@ impl PythonProvider {
                 )));
 
                 install_phase.add_path(format!("{env_loc}/bin"));
+                install_phase.add_persisted_directory(env_loc);
 
                 install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
@@ -182,6 +222,7 @@ impl PythonProvider {
                 )));
 
                 install_phase.add_path(format!("{env_loc}/bin"));
+                install_phase.add_persisted_directory(env_loc);
 
                 install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
                 install_phase.add_cache_directory(PDM_CACHE_DIR.to_string());
@@ -194,6 +235,7 @@ impl PythonProvider {
 
             install_phase.add_file_dependency("pyproject.toml".to_string());
             install_phase.add_path(format!("{env_loc}/bin"));
+            install_phase.add_persisted_directory(env_loc);
 
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
@@ -212,6 +254,7 @@ impl PythonProvider {
             let mut install_phase = Phase::install(Some(cmd));
 
             install_phase.add_path(format!("{env_loc}/bin"));
+            install_phase.add_persisted_directory(env_loc);
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
             return Ok(Some(install_phase));
@@ -224,9 +267,11 @@ impl PythonProvider {
         if PythonProvider::is_django(app, env)? {
             let app_name = PythonProvider::get_django_app_name(app, env)?;
 
-            return Ok(Some(StartPhase::new(format!(
-                "python manage.py migrate && gunicorn {app_name}"
-            ))));
+            // gunicorn binds to 8000 unless PORT is set
+            let mut start =
+                StartPhase::new(format!("python manage.py migrate && gunicorn {app_name}"));
+            start.add_port("8000");
+            return Ok(Some(start));
         }
 
         if app.includes_file("pyproject.toml") {
@@ -278,9 +323,7 @@ impl PythonProvider {
 
         // Search all settings.py matches
         for path in paths {
-            let path_buf = fs::canonicalize(path)?;
-
-            if let Some(p) = path_buf.to_str() {
+            if let Some(p) = path.to_str() {
                 let f = app.read_file(p)?;
                 if let Some(value) = re.captures(f.as_str()) {
                     // Get the first and only match
@@ -427,6 +470,21 @@ impl PythonProvider {
 
         Ok(is_used)
     }
+
+    /// Whether requirements.txt installs packages from the app's files, or includes other requirements files.
+    fn requirements_use_local_files(app: &App) -> Result<bool> {
+        let requirements = app.read_file("requirements.txt")?;
+        Ok(requirements
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .any(|line| {
+                line.starts_with('-')
+                    || line.starts_with('.')
+                    || line.starts_with('/')
+                    || line.contains("file:")
+            }))
+    }
 }
 
 #[cfg(test)]
@@ -510,6 +568,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_uv_install() -> Result<()> {
+        let install = PythonProvider {}
+            .install(&App::new("./examples/python-uv")?, &Environment::default())?
+            .unwrap();
+
+        assert_eq!(
+            install.cmds,
+            Some(vec!["uv sync --no-dev --frozen".to_string()])
+        );
+        assert_eq!(install.nix_pkgs, Some(vec!["uv".to_string()]));
+        assert_eq!(
+            install.cache_directories,
+            Some(vec![UV_CACHE_DIR.to_string()])
+        );
+        assert_eq!(
+            install.persisted_directories,
+            Some(vec!["/opt/venv".to_string()])
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_numpy_detection() -> Result<()> {
         assert!(!PythonProvider::uses_dep(
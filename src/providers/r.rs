@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Packages installed from GitHub are put here, since the Nix library is read only
+const R_LIBRARY_DIR: &str = "/app/.R/library";
+
+// https://rstudio.github.io/renv/articles/lockfile.html
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct RenvLock {
+    #[serde(default)]
+    pub packages: BTreeMap<String, RenvPackage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct RenvPackage {
+    pub package: String,
+    pub source: Option<String>,
+    pub remote_username: Option<String>,
+    pub remote_repo: Option<String>,
+    pub remote_sha: Option<String>,
+}
+
+pub struct RProvider {}
+
+impl Provider for RProvider {
+    fn name(&self) -> &'static str {
+        "r"
+    }
+
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["renv.lock", "app.R", "plumber.R"]))
+    }
+
+    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        let (mut packages, github_packages) = RProvider::get_packages(app)?;
+        if !github_packages.is_empty() && !packages.contains(&"remotes".to_string()) {
+            packages.push("remotes".to_string());
+        }
+
+        let setup = Phase::setup(Some(vec![Pkg::new(&format!(
+            "(rWrapper.override {{ packages = with rPackages; [ {} ]; }})",
+            packages.join(" ")
+        ))]));
+
+        let mut install = Phase::install(None);
+        if !github_packages.is_empty() {
+            install.add_cmd(format!("mkdir -p {R_LIBRARY_DIR}"));
+            install.add_cmd(format!(
+                "Rscript -e 'remotes::install_github(c({}), lib = \"{R_LIBRARY_DIR}\", upgrade = \"never\")'",
+                github_packages
+                    .iter()
+                    .map(|package| format!("\"{package}\""))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        let start = RProvider::get_start(app).map(StartPhase::new);
+
+        let mut plan = BuildPlan::new(&[setup, install], start);
+        plan.add_variables(EnvironmentVariables::from([
+            ("R_LIBS_USER".to_string(), R_LIBRARY_DIR.to_string()),
+            // Don't let renv's autoloader in .Rprofile replace the Nix library with the project library
+            ("RENV_ACTIVATE_PROJECT".to_string(), "FALSE".to_string()),
+        ]));
+
+        Ok(Some(plan))
+    }
+}
+
+impl RProvider {
+    fn get_renv_lock(app: &App) -> Result<RenvLock> {
+        app.read_json::<RenvLock>("renv.lock")
+            .context("Reading renv.lock")
+    }
+
+    /// The names of the packages in `rPackages`, and the `user/repo@sha` of packages from GitHub, from `renv.lock` or
+    /// else the packages the app loads with `library()` or `require()`.
+    fn get_packages(app: &App) -> Result<(Vec<String>, Vec<String>)> {
+        let (mut packages, github_packages) = if app.includes_file("renv.lock") {
+            RProvider::get_renv_packages(RProvider::get_renv_lock(app)?)
+        } else {
+            (RProvider::get_library_packages(app)?, Vec::new())
+        };
+
+        // The server that starts the app is needed even if it's not loaded explicitly
+        let server = if app.includes_file("plumber.R") {
+            Some("plumber")
+        } else if app.includes_file("app.R") {
+            Some("shiny")
+        } else {
+            None
+        };
+        if let Some(server) = server {
+            if !packages.iter().any(|package| package == server) {
+                packages.push(server.to_string());
+            }
+        }
+
+        Ok((packages, github_packages))
+    }
+
+    fn get_renv_packages(renv_lock: RenvLock) -> (Vec<String>, Vec<String>) {
+        let mut packages = Vec::new();
+        let mut github_packages = Vec::new();
+
+        for package in renv_lock.packages.into_values() {
+            // renv isn't needed to load the packages
+            if package.package == "renv" {
+                continue;
+            }
+
+            match (
+                package.source.as_deref(),
+                package.remote_username,
+                package.remote_repo,
+            ) {
+                (Some("GitHub"), Some(username), Some(repo)) => {
+                    github_packages.push(match package.remote_sha {
+                        Some(sha) => format!("{username}/{repo}@{sha}"),
+                        None => format!("{username}/{repo}"),
+                    });
+                }
+                _ => packages.push(RProvider::get_nix_package(&package.package)),
+            }
+        }
+
+        (packages, github_packages)
+    }
+
+    fn get_library_packages(app: &App) -> Result<Vec<String>> {
+        let library_re =
+            Regex::new(r#"(?:library|require)\(\s*["']?([A-Za-z][A-Za-z0-9.]*)["']?"#)?;
+
+        let mut packages = Vec::new();
+        for file in ["app.R", "plumber.R", "ui.R", "server.R", "global.R"] {
+            if !app.includes_file(file) {
+                continue;
+            }
+
+            for capture in library_re.captures_iter(&app.read_file(file)?) {
+                let package = RProvider::get_nix_package(&capture[1]);
+                if !packages.contains(&package) {
+                    packages.push(package);
+                }
+            }
+        }
+
+        Ok(packages)
+    }
+
+    /// The name of an R package in `rPackages`, where dots are replaced with underscores.
+    fn get_nix_package(package: &str) -> String {
+        package.replace('.', "_")
+    }
+
+    fn get_start(app: &App) -> Option<String> {
+        if app.includes_file("plumber.R") {
+            Some("Rscript -e \"plumber::pr_run(plumber::pr('plumber.R'), host = '0.0.0.0', port = as.numeric(Sys.getenv('PORT', 8000)))\"".to_string())
+        } else if app.includes_file("app.R") {
+            Some("Rscript -e \"shiny::runApp('.', host = '0.0.0.0', port = as.numeric(Sys.getenv('PORT', 3838)))\"".to_string())
+        } else if app.includes_file("main.R") {
+            Some("Rscript main.R".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_nix_package() {
+        assert_eq!(RProvider::get_nix_package("shiny"), "shiny");
+        assert_eq!(RProvider::get_nix_package("data.table"), "data_table");
+    }
+
+    #[test]
+    fn test_renv_packages() -> Result<()> {
+        let (packages, github_packages) =
+            RProvider::get_packages(&App::new("./examples/r-shiny")?)?;
+        assert_eq!(packages, vec!["data_table", "shiny"]);
+        assert!(github_packages.is_empty());
+
+        let renv_lock: RenvLock = serde_json::from_str(
+            r#"{
+                "Packages": {
+                    "cli": {
+                        "Package": "cli",
+                        "Source": "GitHub",
+                        "RemoteUsername": "r-lib",
+                        "RemoteRepo": "cli",
+                        "RemoteSha": "abc123"
+                    },
+                    "renv": { "Package": "renv", "Source": "Repository" },
+                    "R6": { "Package": "R6", "Source": "Repository" }
+                }
+            }"#,
+        )?;
+        let (packages, github_packages) = RProvider::get_renv_packages(renv_lock);
+        assert_eq!(packages, vec!["R6"]);
+        assert_eq!(github_packages, vec!["r-lib/cli@abc123"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_library_packages() -> Result<()> {
+        let (packages, github_packages) =
+            RProvider::get_packages(&App::new("./examples/r-plumber")?)?;
+
+        assert_eq!(packages, vec!["plumber", "jsonlite"]);
+        assert!(github_packages.is_empty());
+
+        Ok(())
+    }
+}
@@ -34,14 +34,22 @@ impl Provider for RubyProvider {
         "ruby"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Gemfile"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["Gemfile"]))
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        Ok(self.get_ruby_version(app, env).ok())
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = self.get_setup(app, env)?;
         let install = self.get_install(app, env)?;
-        let build = self.get_build(app)?;
+        let build = self.get_build(app, env)?;
         let start = self.get_start(app)?;
 
         let mut plan = BuildPlan::new(
@@ -98,6 +106,11 @@ impl RubyProvider {
             setup.add_apt_pkgs(vec![String::from("libicu-dev")]);
         }
 
+        let uses_jemalloc = env.is_config_variable_truthy("RUBY_JEMALLOC");
+        if uses_jemalloc {
+            setup.add_apt_pkgs(vec![String::from("libjemalloc-dev")]);
+        }
+
         let ruby_version = self.get_ruby_version(app, env)?;
         let ruby_version = ruby_version.trim_start_matches("ruby-");
 
@@ -133,12 +146,17 @@ impl RubyProvider {
         );
 
         let bundler_version = self.get_bundler_version(app);
+        let configure_opts = if uses_jemalloc {
+            "RUBY_CONFIGURE_OPTS=--with-jemalloc "
+        } else {
+            ""
+        };
 
         setup.add_cmd(format!(
             "curl -fsSL https://github.com/rbenv/rbenv-installer/raw/HEAD/bin/rbenv-installer | bash -s stable \
             && printf '\\neval \"$(~/.rbenv/bin/rbenv init -)\"' >> /root/.profile \
             && . /root/.profile \
-            && rbenv install {ruby_version} \
+            && {configure_opts}rbenv install {ruby_version} \
             && rbenv global {ruby_version} \
             && gem install {bundler_version}"
         ));
@@ -160,7 +178,7 @@ impl RubyProvider {
 
         install.add_cmd("bundle install".to_string());
 
-        if self.uses_gem_dep(app, "bootsnap") {
+        if self.uses_gem_dep(app, "bootsnap") && self.get_bootsnap_precompile(env) != Some(false) {
             install.add_cmd("bundle exec bootsnap precompile --gemfile");
         }
 
@@ -173,7 +191,7 @@ impl RubyProvider {
         Ok(Some(install))
     }
 
-    fn get_build(&self, app: &App) -> Result<Option<Phase>> {
+    fn get_build(&self, app: &App, env: &Environment) -> Result<Option<Phase>> {
         let mut build = Phase::build(None);
 
         // Only compile assets if a Rails app have an asset pipeline gem
@@ -181,19 +199,46 @@ impl RubyProvider {
         // do not come with the asset pipelines because they have no assets.
         // [0] https://guides.rubyonrails.org/api_app.html
         if self.is_rails_app(app) && self.uses_asset_pipeline(app)? {
-            build.add_cmd("bundle exec rake assets:precompile".to_string());
+            // Compiling assets loads the app, which needs a secret key base even though it isn't used
+            build.add_cmd(
+                "SECRET_KEY_BASE_DUMMY=1 SECRET_KEY_BASE=${SECRET_KEY_BASE:-dummy} bundle exec rake assets:precompile"
+                    .to_string(),
+            );
         }
 
-        if self.is_rails_app(app) && self.uses_gem_dep(app, "bootsnap") {
-            build.add_cmd("bundle exec bootsnap precompile app/ lib/");
+        // Rails apps precompile bootsnap caches unless turned off, other apps only when turned on
+        let bootsnap_precompile = self
+            .get_bootsnap_precompile(env)
+            .unwrap_or_else(|| self.is_rails_app(app));
+        if bootsnap_precompile && self.uses_gem_dep(app, "bootsnap") {
+            let dirs = ["app/", "lib/"]
+                .into_iter()
+                .filter(|dir| app.includes_directory(dir))
+                .collect::<Vec<_>>();
+            if !dirs.is_empty() {
+                build.add_cmd(format!(
+                    "bundle exec bootsnap precompile {}",
+                    dirs.join(" ")
+                ));
+            }
         }
 
         Ok(Some(build))
     }
 
+    /// Whether `NIXPACKS_RUBY_BOOTSNAP_PRECOMPILE` turns precompiling bootsnap caches on or off, if it is set.
+    fn get_bootsnap_precompile(&self, env: &Environment) -> Option<bool> {
+        env.get_config_variable("RUBY_BOOTSNAP_PRECOMPILE")
+            .map(|value| matches!(value.as_str(), "1" | "true"))
+    }
+
     fn get_start(&self, app: &App) -> Result<Option<StartPhase>> {
         if let Some(start_cmd) = self.get_start_command(app) {
-            Ok(Some(StartPhase::new(start_cmd)))
+            let mut start = StartPhase::new(start_cmd);
+            if self.is_rails_app(app) {
+                start.add_port("3000");
+            }
+            Ok(Some(start))
         } else {
             Ok(None)
         }
@@ -384,4 +429,46 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jemalloc() -> Result<()> {
+        let setup = RubyProvider::get_setup(
+            &RubyProvider {},
+            &App::new("./examples/ruby")?,
+            &Environment::from_envs(vec!["NIXPACKS_RUBY_JEMALLOC=1"])?,
+        )?
+        .unwrap();
+
+        assert!(setup
+            .apt_pkgs
+            .unwrap_or_default()
+            .contains(&"libjemalloc-dev".to_string()));
+        assert!(setup.cmds.unwrap_or_default()[0]
+            .contains("RUBY_CONFIGURE_OPTS=--with-jemalloc rbenv install 3.1.2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bootsnap_precompile() -> Result<()> {
+        let app = App::new("./examples/ruby-rails-postgres")?;
+        let build =
+            RubyProvider::get_build(&RubyProvider {}, &app, &Environment::default())?.unwrap();
+        assert!(build
+            .cmds
+            .unwrap_or_default()
+            .contains(&"bundle exec bootsnap precompile app/ lib/".to_string()));
+
+        let env = Environment::from_envs(vec!["NIXPACKS_RUBY_BOOTSNAP_PRECOMPILE=0"])?;
+        let install = RubyProvider::get_install(&RubyProvider {}, &app, &env)?.unwrap();
+        let build = RubyProvider::get_build(&RubyProvider {}, &app, &env)?.unwrap();
+        assert!(!install
+            .cmds
+            .unwrap_or_default()
+            .iter()
+            .chain(build.cmds.unwrap_or_default().iter())
+            .any(|cmd| cmd.contains("bootsnap")));
+
+        Ok(())
+    }
 }
@@ -12,8 +12,10 @@ use crate::nixpacks::{
     },
 };
 use anyhow::{Context, Result};
-use cargo_toml::{Manifest, Workspace};
+use cargo_toml::{AbstractFilesystem, DepsSet, Manifest, OptionalFile, Workspace};
+use path_slash::PathExt;
 use regex::Regex;
+use std::{collections::HashSet, io};
 
 const RUST_OVERLAY: &str = "https://github.com/oxalica/rust-overlay/archive/master.tar.gz";
 const DEFAULT_RUST_PACKAGE: &str = "rust-bin.stable.latest.default";
@@ -29,16 +31,34 @@ impl Provider for RustProvider {
         "rust"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Cargo.toml"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["Cargo.toml"]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = RustProvider::get_setup(app, env)?;
-        let build = RustProvider::get_build(app, env)?;
+        let mut build = RustProvider::get_build(app, env)?;
         let start = RustProvider::get_start(app, env)?;
 
-        let mut plan = BuildPlan::new(&vec![setup, build], start);
+        let mut phases = vec![setup];
+        if let Some(install) = RustProvider::get_install(app, env)? {
+            phases.push(install);
+
+            // The compiled dependencies live in the install layer, which a target cache would hide
+            build.depends_on = Some(vec!["install".to_string()]);
+            build.cache_directories = build.cache_directories.map(|dirs| {
+                dirs.into_iter()
+                    .filter(|dir| dir != CARGO_TARGET_CACHE_DIR)
+                    .collect()
+            });
+        }
+        phases.push(build);
+
+        let mut plan = BuildPlan::new(&phases, start);
         plan.add_variables(EnvironmentVariables::from([(
             "ROCKET_ADDRESS".to_string(),
             "0.0.0.0".to_string(),
@@ -88,38 +108,23 @@ impl RustProvider {
         build.add_cmd("mkdir -p bin");
         build.depends_on = Some(vec!["setup".to_string()]);
 
-        let mut build_cmd = "cargo build --release".to_string();
-
         // Default binary suffix (.wasm || none)
         let bin_suffix = RustProvider::get_bin_suffix(app, env, None);
+        let release_dir = match RustProvider::get_target(app, env)? {
+            Some(target) => format!("target/{target}/release"),
+            None => "target/release".to_string(),
+        };
 
-        if let Some(target) = RustProvider::get_target(app, env)? {
-            if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-                write!(build_cmd, " --package {workspace} --target {target}")?;
+        if let Some(build_cmd) = RustProvider::get_cargo_build_cmd(app, env)? {
+            build.add_cmd(build_cmd);
 
-                build.add_cmd(build_cmd);
-                build.add_cmd(format!(
-                    "cp target/{target}/release/{workspace}{bin_suffix} bin"
-                ));
+            if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
+                build.add_cmd(format!("cp {release_dir}/{workspace}{bin_suffix} bin"));
             } else if let Some(bins) = RustProvider::get_bins(app)? {
-                write!(build_cmd, " --target {target}")?;
-
-                build.add_cmd(build_cmd);
-
                 for bin in bins {
-                    build.add_cmd(format!("cp target/{target}/release/{bin}{bin_suffix} bin"));
+                    build.add_cmd(format!("cp {release_dir}/{bin}{bin_suffix} bin"));
                 }
             }
-        } else if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-            write!(build_cmd, " --package {workspace}")?;
-            build.add_cmd(build_cmd);
-            build.add_cmd(format!("cp target/release/{workspace}{bin_suffix} bin"));
-        } else if let Some(bins) = RustProvider::get_bins(app)? {
-            build.add_cmd(build_cmd);
-
-            for bin in bins {
-                build.add_cmd(format!("cp target/release/{bin}{bin_suffix} bin"));
-            }
         }
 
         build.add_cache_directory(CARGO_GIT_CACHE_DIR.to_string());
@@ -133,6 +138,86 @@ impl RustProvider {
         Ok(build)
     }
 
+    /// Builds only the dependencies, from the manifests and lockfile with stub sources in place of
+    /// the app's code, so that the layer is reused until a dependency changes.
+    fn get_install(app: &App, env: &Environment) -> Result<Option<Phase>> {
+        let build_cmd = match RustProvider::get_cargo_build_cmd(app, env)? {
+            Some(build_cmd) => build_cmd,
+            None => return Ok(None),
+        };
+
+        let skeleton = CargoSkeleton::from_app(app)?;
+        if skeleton.packages.is_empty() {
+            return Ok(None);
+        }
+
+        let mut install = Phase::install(None);
+        for manifest in &skeleton.manifests {
+            install.add_file_dependency(manifest.clone());
+        }
+        for file in ["Cargo.lock", ".cargo/config.toml", ".cargo/config"] {
+            if app.includes_file(file) {
+                install.add_file_dependency(file);
+            }
+        }
+
+        let mut stub_dirs = skeleton
+            .libs
+            .iter()
+            .chain(skeleton.mains.iter())
+            .filter_map(|file| file.rsplit_once('/').map(|(dir, _)| dir.to_string()))
+            .collect::<Vec<_>>();
+        stub_dirs.sort();
+        stub_dirs.dedup();
+        if !stub_dirs.is_empty() {
+            install.add_cmd(format!("mkdir -p {}", stub_dirs.join(" ")));
+        }
+        if !skeleton.libs.is_empty() {
+            install.add_cmd(format!("touch {}", skeleton.libs.join(" ")));
+        }
+        if !skeleton.mains.is_empty() {
+            install.add_cmd(format!(
+                "for file in {}; do echo 'fn main() {{}}' > $file; done",
+                skeleton.mains.join(" ")
+            ));
+        }
+
+        install.add_cmd(build_cmd);
+
+        // Remove what was built from the stubs so the app's own crates are rebuilt from the real sources
+        let mut clean_cmd = "cargo clean --release".to_string();
+        if let Some(target) = RustProvider::get_target(app, env)? {
+            write!(clean_cmd, " --target {target}")?;
+        }
+        for package in &skeleton.packages {
+            write!(clean_cmd, " --package {package}")?;
+        }
+        install.add_cmd(clean_cmd);
+
+        install.add_cache_directory(CARGO_GIT_CACHE_DIR.to_string());
+        install.add_cache_directory(CARGO_REGISTRY_CACHE_DIR.to_string());
+        // The compiled dependencies stay in the install layer, so they're saved instead of cached
+        install.add_persisted_directory(CARGO_TARGET_CACHE_DIR);
+
+        Ok(Some(install))
+    }
+
+    fn get_cargo_build_cmd(app: &App, env: &Environment) -> Result<Option<String>> {
+        let mut build_cmd = "cargo build --release".to_string();
+
+        if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
+            write!(build_cmd, " --package {workspace}")?;
+        } else if RustProvider::get_bins(app)?.is_none() {
+            return Ok(None);
+        }
+
+        if let Some(target) = RustProvider::get_target(app, env)? {
+            write!(build_cmd, " --target {target}")?;
+        }
+
+        Ok(Some(build_cmd))
+    }
+
     fn get_bin_suffix(app: &App, env: &Environment, _: Option<String>) -> String {
         // wasm32-wasi binaries are created with .wasm
         if RustProvider::should_make_wasm32_wasi(app, env) {
@@ -376,7 +461,7 @@ impl RustProvider {
         let find_binary = |member: &str| -> Result<Option<String>> {
             let mut manifest = app.read_toml::<Manifest>(&format!("{member}/Cargo.toml"))?;
 
-            manifest.complete_from_path(&app.source.join(format!("{member}/Cargo.toml")))?;
+            manifest.complete_from_abstract_filesystem(MemberDir { app, member })?;
 
             if let Some(package) = manifest.package {
                 if !manifest.bin.is_empty() || manifest.lib.is_none() {
@@ -425,9 +510,206 @@ impl RustProvider {
     }
 }
 
+/// The parts of a Cargo project needed to build its dependencies without its code.
+#[derive(Default, Debug)]
+struct CargoSkeleton {
+    /// Directories of the local packages, relative to the app
+    dirs: Vec<String>,
+    /// Names of the local packages
+    packages: Vec<String>,
+    manifests: Vec<String>,
+    /// Library targets, stubbed with an empty file
+    libs: Vec<String>,
+    /// Binaries, build scripts, examples, tests and benches, stubbed with an empty `main`
+    mains: Vec<String>,
+}
+
+impl CargoSkeleton {
+    fn from_app(app: &App) -> Result<CargoSkeleton> {
+        let mut skeleton = CargoSkeleton::default();
+        let mut dirs = vec![String::new()];
+
+        if let Some(workspace) =
+            RustProvider::parse_cargo_toml(app)?.and_then(|manifest| manifest.workspace)
+        {
+            for member in workspace
+                .members
+                .iter()
+                .filter(|member| !workspace.exclude.contains(member))
+            {
+                // a member can have globs
+                if member.contains('*') || member.contains('?') {
+                    for member in app.find_directories(member)? {
+                        dirs.push(app.strip_source_path(&member)?.to_slash_lossy().to_string());
+                    }
+                } else {
+                    dirs.push(member.trim_end_matches('/').to_string());
+                }
+            }
+
+            dirs.extend(CargoSkeleton::path_dependencies(
+                "",
+                &workspace.dependencies,
+            ));
+        }
+
+        // Path dependencies are local packages too, even when they aren't workspace members
+        let mut i = 0;
+        while i < dirs.len() {
+            let dir = dirs[i].clone();
+            i += 1;
+
+            if skeleton.dirs.contains(&dir) {
+                continue;
+            }
+
+            if let Some(manifest) = skeleton.add_package(app, &dir)? {
+                for deps in [
+                    &manifest.dependencies,
+                    &manifest.dev_dependencies,
+                    &manifest.build_dependencies,
+                ] {
+                    dirs.extend(CargoSkeleton::path_dependencies(&dir, deps));
+                }
+            }
+        }
+
+        Ok(skeleton)
+    }
+
+    fn add_package(&mut self, app: &App, dir: &str) -> Result<Option<Manifest>> {
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{dir}/")
+        };
+        let manifest_path = format!("{prefix}Cargo.toml");
+        if !app.includes_file(&manifest_path) {
+            return Ok(None);
+        }
+
+        let manifest: Manifest = app
+            .read_toml(&manifest_path)
+            .with_context(|| format!("Reading {manifest_path}"))?;
+
+        self.dirs.push(dir.to_string());
+        self.manifests.push(manifest_path);
+
+        let package = match &manifest.package {
+            Some(package) => package,
+            None => return Ok(Some(manifest)),
+        };
+        self.packages.push(package.name.clone());
+
+        let mut libs = vec![];
+        let mut mains = vec![];
+
+        match manifest.lib.as_ref().and_then(|lib| lib.path.clone()) {
+            Some(path) => libs.push(path),
+            None if app.includes_file(&format!("{prefix}src/lib.rs")) => {
+                libs.push("src/lib.rs".to_string());
+            }
+            None => {}
+        }
+
+        if app.includes_file(&format!("{prefix}src/main.rs")) {
+            mains.push("src/main.rs".to_string());
+        }
+        if app.includes_directory(&format!("{prefix}src/bin")) {
+            for bin in app.find_files(&format!("{prefix}src/bin/**/*.rs"))? {
+                let bin = app.strip_source_path(&bin)?.to_slash_lossy().to_string();
+                mains.push(bin.trim_start_matches(&prefix).to_string());
+            }
+        }
+
+        // Explicit targets must exist even when they aren't built
+        for (kind, products) in [
+            ("src/bin", &manifest.bin),
+            ("examples", &manifest.example),
+            ("tests", &manifest.test),
+            ("benches", &manifest.bench),
+        ] {
+            for product in products {
+                if let Some(path) = &product.path {
+                    mains.push(path.clone());
+                } else if let Some(name) = &product.name {
+                    mains.push(format!("{kind}/{name}.rs"));
+                }
+            }
+        }
+
+        match &package.build {
+            Some(OptionalFile::Path(path)) => mains.push(path.to_slash_lossy().to_string()),
+            Some(OptionalFile::Flag(false)) => {}
+            _ if app.includes_file(&format!("{prefix}build.rs")) => {
+                mains.push("build.rs".to_string());
+            }
+            _ => {}
+        }
+
+        for file in libs {
+            let file = format!("{prefix}{}", file.trim_start_matches("./"));
+            if !self.libs.contains(&file) {
+                self.libs.push(file);
+            }
+        }
+        for file in mains {
+            let file = format!("{prefix}{}", file.trim_start_matches("./"));
+            if !self.mains.contains(&file) && !self.libs.contains(&file) {
+                self.mains.push(file);
+            }
+        }
+
+        Ok(Some(manifest))
+    }
+
+    /// Directories of the path dependencies that are inside the app, relative to the app.
+    fn path_dependencies(dir: &str, deps: &DepsSet) -> Vec<String> {
+        deps.values()
+            .filter_map(|dep| dep.detail().and_then(|detail| detail.path.clone()))
+            .filter_map(|path| {
+                let mut parts = dir
+                    .split('/')
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>();
+
+                for part in path.split('/') {
+                    match part {
+                        "" | "." => {}
+                        ".." => {
+                            parts.pop()?;
+                        }
+                        part => parts.push(part),
+                    }
+                }
+
+                Some(parts.join("/"))
+            })
+            .collect()
+    }
+}
+
+/// A workspace member's directory, for `cargo_toml` to find its targets in the app's files, which may be in memory.
+struct MemberDir<'a> {
+    app: &'a App,
+    member: &'a str,
+}
+
+impl AbstractFilesystem for MemberDir<'_> {
+    fn file_names_in(&self, rel_path: &str) -> io::Result<HashSet<Box<str>>> {
+        Ok(self
+            .app
+            .read_directory(&format!("{}/{rel_path}", self.member))?
+            .into_iter()
+            .map(String::into_boxed_str)
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use cargo_toml::{Dependency, DependencyDetail};
     use std::collections::BTreeMap;
 
     #[test]
@@ -496,4 +778,60 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_install_workspace_dependencies() -> Result<()> {
+        let install = RustProvider::get_install(
+            &App::new("./examples/rust-cargo-workspaces")?,
+            &Environment::from_envs(vec!["NIXPACKS_NO_MUSL=1"])?,
+        )?
+        .unwrap();
+
+        assert_eq!(
+            install.only_include_files,
+            Some(vec![
+                "Cargo.toml".to_string(),
+                "binary/Cargo.toml".to_string(),
+                "library/Cargo.toml".to_string()
+            ])
+        );
+        assert_eq!(
+            install.cmds,
+            Some(vec![
+                "mkdir -p binary/src library/src".to_string(),
+                "touch library/src/lib.rs".to_string(),
+                "for file in binary/src/main.rs; do echo 'fn main() {}' > $file; done".to_string(),
+                "cargo build --release --package binary".to_string(),
+                "cargo clean --release --package binary --package library".to_string()
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_dependencies() {
+        let deps = DepsSet::from([
+            (
+                "library".to_string(),
+                Dependency::Detailed(DependencyDetail {
+                    path: Some("../library".to_string()),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "outside".to_string(),
+                Dependency::Detailed(DependencyDetail {
+                    path: Some("../../outside".to_string()),
+                    ..Default::default()
+                }),
+            ),
+            ("serde".to_string(), Dependency::Simple("1.0".to_string())),
+        ]);
+
+        assert_eq!(
+            CargoSkeleton::path_dependencies("binary", &deps),
+            vec!["library".to_string()]
+        );
+    }
 }
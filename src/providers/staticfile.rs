@@ -29,12 +29,12 @@ impl Provider for StaticfileProvider {
         "staticfile"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Staticfile")
-            || app.includes_directory("public")
-            || app.includes_directory("index")
-            || app.includes_directory("dist")
-            || app.includes_file("index.html"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["Staticfile", "public", "index", "dist", "index.html"]))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -43,12 +43,14 @@ impl Provider for StaticfileProvider {
 
         // shell command to edit 0.0.0.0:80 to $PORT
         let shell_cmd = "[[ -z \"${PORT}\" ]] && echo \"Environment variable PORT not found. Using PORT 80\" || sed -i \"s/0.0.0.0:80/$PORT/g\"";
-        let start = StartPhase::new(format!(
+        let mut start = StartPhase::new(format!(
             "{shell_cmd} {conf_location} && nginx -c {conf_location}",
             shell_cmd = shell_cmd,
             conf_location = app.asset_path("nginx.conf"),
         ));
 
+        start.add_port("80");
+
         let static_assets = StaticfileProvider::get_static_assets(app, env)?;
 
         let mut plan = BuildPlan::new(&vec![setup], Some(start));
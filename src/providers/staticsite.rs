@@ -0,0 +1,313 @@
+use super::{node::NodeProvider, Provider};
+use crate::nixpacks::{
+    app::{App, StaticAssets, ASSETS_DIR},
+    environment::{Environment, EnvironmentVariables},
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{bail, Result};
+use indoc::formatdoc;
+use regex::Regex;
+
+const ASTRO_CONFIG_FILES: &[&str] = &[
+    "astro.config.mjs",
+    "astro.config.js",
+    "astro.config.cjs",
+    "astro.config.mts",
+    "astro.config.ts",
+];
+
+/// Static site generators, whose output is served as is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum SiteGenerator {
+    Hugo,
+    Zola,
+    Jekyll,
+    Astro,
+}
+
+/// The web server that serves the site in the final image.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum SiteServer {
+    Nginx,
+    Caddy,
+}
+
+pub struct StaticSiteProvider {}
+
+impl Provider for StaticSiteProvider {
+    fn name(&self) -> &'static str {
+        "staticsite"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(StaticSiteProvider::get_generator(app)?.is_some())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(match StaticSiteProvider::get_generator(app)? {
+            Some(generator) => app.find_existing_paths(&generator.config_files()),
+            None => Vec::new(),
+        })
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let generator = match StaticSiteProvider::get_generator(app)? {
+            Some(generator) => generator,
+            None => return Ok(None),
+        };
+        let server = StaticSiteProvider::get_server(env)?;
+        let output_dir = env
+            .get_config_variable("STATIC_SITE_DIR")
+            .unwrap_or_else(|| generator.output_dir().to_string());
+
+        let mut setup = Phase::setup(Some(vec![Pkg::new(match server {
+            SiteServer::Nginx => "nginx",
+            SiteServer::Caddy => "caddy",
+        })]));
+        let mut install = Phase::install(None);
+        let mut build = Phase::build(None);
+        let mut variables = EnvironmentVariables::new();
+
+        match generator {
+            SiteGenerator::Hugo => {
+                setup.add_nix_pkgs(&[Pkg::new("hugo")]);
+                build.add_cmd("hugo --gc --minify");
+            }
+            SiteGenerator::Zola => {
+                setup.add_nix_pkgs(&[Pkg::new("zola")]);
+                build.add_cmd("zola build");
+            }
+            SiteGenerator::Jekyll => {
+                if app.includes_file("Gemfile") {
+                    setup.add_nix_pkgs(&[Pkg::new("ruby")]);
+                    install.add_cmd("bundle install");
+                    // Nix's gem directory is read only
+                    variables.insert("BUNDLE_PATH".to_string(), "/root/.bundle".to_string());
+                    build.add_cmd("bundle exec jekyll build");
+                } else {
+                    setup.add_nix_pkgs(&[Pkg::new("jekyll")]);
+                    build.add_cmd("jekyll build");
+                }
+                variables.insert("JEKYLL_ENV".to_string(), "production".to_string());
+            }
+            SiteGenerator::Astro => {
+                setup.add_nix_pkgs(&NodeProvider::get_nix_packages(app, env)?);
+                if let Some(install_cmd) = NodeProvider::get_install_command(app) {
+                    install.add_cmd(install_cmd);
+                }
+                install.add_cache_directory(NodeProvider::get_package_manager_cache_dir(app));
+                build.add_cmd(format!(
+                    "{} run build",
+                    NodeProvider::get_package_manager(app)
+                ));
+                variables.extend(NodeProvider::get_node_environment_variables());
+            }
+        }
+
+        // Only the built site and the server config end up in the final image
+        let mut start = StartPhase::new(match server {
+            SiteServer::Nginx => format!(
+                "mkdir -p /var/log/nginx /var/cache/nginx && sed -i \"s/0.0.0.0:80/0.0.0.0:${{PORT:-80}}/\" {conf} && nginx -c {conf}",
+                conf = app.asset_path("nginx.conf")
+            ),
+            SiteServer::Caddy => format!(
+                "caddy run --config {} --adapter caddyfile",
+                app.asset_path("Caddyfile")
+            ),
+        });
+        start.run_in_slim_image();
+        start.add_file_dependency(output_dir.clone());
+        start.add_file_dependency(ASSETS_DIR);
+        start.add_port("80");
+
+        let mut plan = BuildPlan::new(&[setup, install, build], Some(start));
+        plan.add_static_assets(StaticSiteProvider::get_static_assets(server, &output_dir));
+        plan.add_variables(variables);
+
+        Ok(Some(plan))
+    }
+}
+
+impl SiteGenerator {
+    /// The files the generator is detected by.
+    fn config_files(self) -> Vec<&'static str> {
+        match self {
+            SiteGenerator::Hugo => vec!["hugo.toml", "hugo.yaml", "hugo.json", "config.toml"],
+            SiteGenerator::Zola => vec!["config.toml"],
+            SiteGenerator::Jekyll => vec!["_config.yml", "Gemfile"],
+            SiteGenerator::Astro => [ASTRO_CONFIG_FILES, &["package.json"]].concat(),
+        }
+    }
+
+    fn output_dir(self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo | SiteGenerator::Zola => "public",
+            SiteGenerator::Jekyll => "_site",
+            SiteGenerator::Astro => "dist",
+        }
+    }
+}
+
+impl StaticSiteProvider {
+    fn get_generator(app: &App) -> Result<Option<SiteGenerator>> {
+        if app.includes_file("hugo.toml")
+            || app.includes_file("hugo.yaml")
+            || app.includes_file("hugo.json")
+            || (app.includes_file("config.toml")
+                && app.read_file("config.toml")?.contains("baseURL"))
+        {
+            return Ok(Some(SiteGenerator::Hugo));
+        }
+
+        if app.includes_file("config.toml")
+            && app.read_file("config.toml")?.contains("base_url")
+            && (app.includes_directory("templates") || app.includes_directory("content"))
+        {
+            return Ok(Some(SiteGenerator::Zola));
+        }
+
+        if app.includes_file("_config.yml")
+            && (!app.includes_file("Gemfile") || app.read_file("Gemfile")?.contains("jekyll"))
+        {
+            return Ok(Some(SiteGenerator::Jekyll));
+        }
+
+        // Astro apps that render on a server need Node to run, so are left to the Node provider
+        if let Some(config_file) = ASTRO_CONFIG_FILES
+            .iter()
+            .find(|file| app.includes_file(file))
+        {
+            let config = app.read_file(config_file)?;
+            let is_server = Regex::new(r#"output\s*:\s*["'](server|hybrid)["']|adapter\s*:"#)?
+                .is_match(&config);
+            if app.includes_file("package.json") && !is_server {
+                return Ok(Some(SiteGenerator::Astro));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn get_server(env: &Environment) -> Result<SiteServer> {
+        match env.get_config_variable("STATIC_SITE_SERVER").as_deref() {
+            None | Some("nginx") => Ok(SiteServer::Nginx),
+            Some("caddy") => Ok(SiteServer::Caddy),
+            Some(server) => bail!("Unknown static site server {server}, use nginx or caddy"),
+        }
+    }
+
+    fn get_static_assets(server: SiteServer, output_dir: &str) -> StaticAssets {
+        let mut assets = StaticAssets::new();
+
+        match server {
+            SiteServer::Nginx => {
+                let nginx_conf = formatdoc! {"
+                    daemon off;
+                    error_log /dev/stdout info;
+                    worker_processes auto;
+                    events {{
+                        worker_connections 1024;
+                    }}
+
+                    http {{
+                        include /nix/store/*-user-environment/conf/mime.types;
+                        access_log /dev/stdout;
+                        default_type application/octet-stream;
+                        sendfile on;
+                        keepalive_timeout 60;
+                        types_hash_max_size 4096;
+                        server {{
+                            listen 0.0.0.0:80;
+                            gzip on;
+                            root /app/{output_dir};
+                            index index.html;
+                            error_page 404 /404.html;
+                            location / {{
+                                try_files $uri $uri/ $uri.html =404;
+                            }}
+                        }}
+                    }}
+                "};
+                assets.insert("nginx.conf".to_string(), nginx_conf);
+            }
+            SiteServer::Caddy => {
+                let caddyfile = formatdoc! {"
+                    {{
+                        admin off
+                        auto_https off
+                    }}
+
+                    :{{$PORT:80}} {{
+                        root * /app/{output_dir}
+                        encode gzip
+                        try_files {{path}} {{path}}/ {{path}}.html
+                        file_server
+                        handle_errors {{
+                            @404 expression {{err.status_code}} == 404
+                            rewrite @404 /404.html
+                            file_server
+                        }}
+                    }}
+                "};
+                assets.insert("Caddyfile".to_string(), caddyfile);
+            }
+        }
+
+        assets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_generator() -> Result<()> {
+        assert_eq!(
+            StaticSiteProvider::get_generator(&App::new("./examples/staticsite-hugo")?)?,
+            Some(SiteGenerator::Hugo)
+        );
+        assert_eq!(
+            StaticSiteProvider::get_generator(&App::new("./examples/staticsite-zola")?)?,
+            Some(SiteGenerator::Zola)
+        );
+        assert_eq!(
+            StaticSiteProvider::get_generator(&App::new("./examples/staticsite-jekyll")?)?,
+            Some(SiteGenerator::Jekyll)
+        );
+        assert_eq!(
+            StaticSiteProvider::get_generator(&App::new("./examples/staticsite-astro")?)?,
+            Some(SiteGenerator::Astro)
+        );
+        assert_eq!(
+            StaticSiteProvider::get_generator(&App::new("./examples/ruby-rails-postgres")?)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_server() -> Result<()> {
+        assert_eq!(
+            StaticSiteProvider::get_server(&Environment::default())?,
+            SiteServer::Nginx
+        );
+        assert_eq!(
+            StaticSiteProvider::get_server(&Environment::from_envs(vec![
+                "NIXPACKS_STATIC_SITE_SERVER=caddy"
+            ])?)?,
+            SiteServer::Caddy
+        );
+        assert!(StaticSiteProvider::get_server(&Environment::from_envs(vec![
+            "NIXPACKS_STATIC_SITE_SERVER=apache"
+        ])?)
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -10,6 +10,7 @@ use crate::nixpacks::{
 };
 use anyhow::{bail, Result};
 use path_slash::PathExt;
+use regex::Regex;
 
 const DEFAULT_SWIFT_VERSION: &str = "5.4.2";
 
@@ -27,8 +28,11 @@ const AVAILABLE_SWIFT_VERSIONS: &[(&str, &str)] = &[
     ("5.1.1", "9986226d5182c368b7be1db1ab2f7488508b5a87"),
     ("5.4", "c82b46413401efa740a0b994f52e9903a4f6dcd5"),
     ("5.4.2", "c82b46413401efa740a0b994f52e9903a4f6dcd5"),
+    ("5.8", "nixos-24.11"),
 ];
 
+const BUILD_DIR: &str = ".build";
+
 pub struct SwiftProvider {}
 
 impl Provider for SwiftProvider {
@@ -36,8 +40,16 @@ impl Provider for SwiftProvider {
         "swift"
     }
 
-    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("Package.swift"))
+    fn detect(&self, app: &App, env: &Environment) -> Result<bool> {
+        Ok(!self.detection_files(app, env)?.is_empty())
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        Ok(app.find_existing_paths(&["Package.swift"]))
+    }
+
+    fn version(&self, app: &App, _env: &Environment) -> Result<Option<String>> {
+        Ok(Some(SwiftProvider::get_swift_version(app)?))
     }
 
     fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
@@ -73,16 +85,16 @@ impl Provider for SwiftProvider {
         if app.includes_file("Package.resolved") {
             install.add_file_dependency("Package.resolved".to_string());
         }
+        install.add_cache_directory(BUILD_DIR);
 
+        // The build directory is a cache, so the executable is copied out of it
         let name = SwiftProvider::get_executable_name(app)?;
         let mut build = Phase::build(Some(
             "CC=clang++ swift build -c release --static-swift-stdlib".to_string(),
         ));
-        build.add_cmd(format!(
-            "cp ./.build/release/{name} ./{name} && rm -rf ./.build"
-        ));
+        build.add_cmd(format!("cp ./{BUILD_DIR}/release/{name} ./{name}"));
+        build.add_cache_directory(BUILD_DIR);
 
-        let name = SwiftProvider::get_executable_name(app)?;
         let start = StartPhase::new(format!("./{name}"));
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
@@ -126,7 +138,17 @@ impl SwiftProvider {
         }
     }
 
+    /// The executable target or product declared in `Package.swift`, or else the target with a `main.swift` file.
     fn get_executable_name(app: &App) -> Result<String> {
+        if app.includes_file("Package.swift") {
+            let package_swift = app.read_file("Package.swift")?;
+            let executable = Regex::new(r#"\.executable(?:Target)?\(\s*name:\s*"([^"]+)""#)?
+                .captures(&package_swift);
+            if let Some(name) = executable.and_then(|c| c.get(1)) {
+                return Ok(name.as_str().to_string());
+            }
+        }
+
         let raw_paths = app.find_files("Sources/**/main.swift")?;
         let paths = raw_paths
             .iter()
@@ -148,13 +170,27 @@ impl SwiftProvider {
         Ok(names[1].to_string())
     }
 
+    /// The nixpkgs revision with the version, or else the oldest newer version, so tools versions like `5.6` work.
     fn version_number_to_rev(version: &str) -> Option<String> {
         let matched_version = AVAILABLE_SWIFT_VERSIONS
             .iter()
-            .find(|(ver, _rev)| *ver == version);
+            .find(|(ver, _rev)| *ver == version)
+            .or_else(|| {
+                let version = SwiftProvider::parse_version(version)?;
+                AVAILABLE_SWIFT_VERSIONS.iter().find(|(ver, _rev)| {
+                    SwiftProvider::parse_version(ver).map_or(false, |ver| ver >= version)
+                })
+            });
 
         matched_version.map(|(_ver, rev)| (*rev).to_string())
     }
+
+    fn parse_version(version: &str) -> Option<Vec<u32>> {
+        version
+            .split('.')
+            .map(|part| part.trim().parse::<u32>().ok())
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -170,4 +206,35 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_executable_name() -> Result<()> {
+        assert_eq!(
+            SwiftProvider::get_executable_name(&App::new("./examples/swift")?)?,
+            "swift"
+        );
+        assert_eq!(
+            SwiftProvider::get_executable_name(&App::new("./examples/swift-vapor")?)?,
+            "Run"
+        );
+        assert_eq!(
+            SwiftProvider::get_executable_name(&App::new("./examples/swift-hummingbird")?)?,
+            "App"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_number_to_rev() {
+        assert_eq!(
+            SwiftProvider::version_number_to_rev("5.4"),
+            Some("c82b46413401efa740a0b994f52e9903a4f6dcd5".to_string())
+        );
+        assert_eq!(
+            SwiftProvider::version_number_to_rev("5.6"),
+            Some("nixos-24.11".to_string())
+        );
+        assert_eq!(SwiftProvider::version_number_to_rev("6.0"), None);
+    }
 }
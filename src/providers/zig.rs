@@ -9,6 +9,7 @@ use crate::nixpacks::{
     },
 };
 use anyhow::Result;
+use regex::Regex;
 use std::{env::consts::ARCH, ffi::OsStr};
 
 pub struct ZigProvider;
@@ -16,17 +17,60 @@ pub struct ZigProvider;
 //TODO: CHANGE THIS WHEN ZIG IS UPDATED OR EVERYTHING WILL BREAK!
 const GYRO_VERSION: &str = "0.6.0";
 
+const ZIG_CACHE_DIR: &str = "/root/.cache/zig";
+
+// Zig 0.11 and later aren't in the default nixpkgs revision
+const ZIG_NIXPKGS_ARCHIVE: &str = "nixos-24.11";
+
+/// Zig versions, with their Nix package and the nixpkgs archive it is in, if not the default one
+const AVAILABLE_ZIG_VERSIONS: &[(&str, &str, Option<&str>)] = &[
+    ("0.9", "zig_0_9", None),
+    ("0.10", "zig_0_10", None),
+    ("0.11", "zig_0_11", Some(ZIG_NIXPKGS_ARCHIVE)),
+    ("0.12", "zig_0_12", Some(ZIG_NIXPKGS_ARCHIVE)),
+    ("0.13", "zig_0_13", Some(ZIG_NIXPKGS_ARCHIVE)),
+];
+
 impl Provider for ZigProvider {
     fn name(&self) -> &str {
         "zig"
     }
 
     fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.has_match("*.zig") || app.has_match("**/*.zig") || app.has_match("gyro.zzz"))
+        Ok(app.has_match("*.zig")
+            || app.has_match("**/*.zig")
+            || app.includes_file("build.zig.zon")
+            || app.has_match("gyro.zzz"))
+    }
+
+    fn detection_files(&self, app: &App, _env: &Environment) -> Result<Vec<String>> {
+        let mut files = app.find_existing_paths(&["build.zig.zon", "gyro.zzz"]);
+        files.extend(app.find_relative_files("**/*.zig")?);
+        Ok(files)
+    }
+
+    fn version(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        ZigProvider::get_zig_version(app, env)
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
-        let mut setup = Phase::setup(Some(vec![Pkg::new("zig")]));
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let version = ZigProvider::get_zig_version(app, env)?;
+        let zig_version = version.and_then(|version| {
+            AVAILABLE_ZIG_VERSIONS
+                .iter()
+                .find(|(available, _, _)| *available == version.as_str())
+        });
+
+        let mut setup = match zig_version {
+            Some((_, pkg, archive)) => {
+                let mut setup = Phase::setup(Some(vec![Pkg::new(pkg)]));
+                if let Some(archive) = archive {
+                    setup.set_nix_archive((*archive).to_string());
+                }
+                setup
+            }
+            None => Phase::setup(Some(vec![Pkg::new("zig")])),
+        };
 
         if app.includes_file("gyro.zzz") {
             setup.add_nix_pkgs(&[Pkg::new("wget")]);
@@ -46,15 +90,28 @@ impl Provider for ZigProvider {
             install.add_cmd(format!("{gyro_exe_path} fetch"));
         }
 
-        let build = Phase::build(Some("zig build -Drelease-safe=true".to_string()));
+        // Zig 0.11 replaced the release options with a single optimize option, and added build.zig.zon
+        let uses_optimize_option = app.includes_file("build.zig.zon")
+            || zig_version.map_or(false, |(version, _, _)| {
+                ZigProvider::parse_version(version) >= ZigProvider::parse_version("0.11")
+            });
+        let mut build = if uses_optimize_option {
+            Phase::build(Some("zig build -Doptimize=ReleaseSafe".to_string()))
+        } else {
+            Phase::build(Some("zig build -Drelease-safe=true".to_string()))
+        };
+        build.add_cache_directory(ZIG_CACHE_DIR);
 
-        let start = StartPhase::new(format!(
-            "./zig-out/bin/{}",
-            app.source
+        let exe_name = match ZigProvider::get_exe_name(app) {
+            Some(exe_name) => exe_name,
+            None => app
+                .source
                 .file_name()
                 .map(OsStr::to_str)
                 .map_or("*", Option::unwrap)
-        ));
+                .to_string(),
+        };
+        let start = StartPhase::new(format!("./zig-out/bin/{exe_name}"));
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
         Ok(Some(plan))
@@ -62,6 +119,59 @@ impl Provider for ZigProvider {
 }
 
 impl ZigProvider {
+    /// The `major.minor` Zig version from `NIXPACKS_ZIG_VERSION` or `minimum_zig_version` in `build.zig.zon`, matched
+    /// to the oldest available version that is at least as new.
+    fn get_zig_version(app: &App, env: &Environment) -> Result<Option<String>> {
+        let version = if let Some(version) = env.get_config_variable("ZIG_VERSION") {
+            version
+        } else if app.includes_file("build.zig.zon") {
+            let build_zig_zon = app.read_file("build.zig.zon")?;
+            match Regex::new(r#"\.minimum_zig_version\s*=\s*"([^"]+)""#)?
+                .captures(&build_zig_zon)
+                .and_then(|c| c.get(1))
+            {
+                Some(version) => version.as_str().to_string(),
+                None => return Ok(None),
+            }
+        } else {
+            return Ok(None);
+        };
+
+        let version = ZigProvider::parse_version(&version);
+        let available = AVAILABLE_ZIG_VERSIONS
+            .iter()
+            .map(|(available, _, _)| *available)
+            .find(|available| ZigProvider::parse_version(available) >= version)
+            .or_else(|| AVAILABLE_ZIG_VERSIONS.last().map(|(latest, _, _)| *latest));
+
+        Ok(available.map(ToString::to_string))
+    }
+
+    /// Parses the major and minor version of `0.12.0` or `0.12.0-dev.1+abc`.
+    fn parse_version(version: &str) -> (u32, u32) {
+        let mut parts = version
+            .trim()
+            .split(['.', '-'])
+            .map(|part| part.parse::<u32>().unwrap_or_default());
+
+        (
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default(),
+        )
+    }
+
+    /// The name of the executable `build.zig` installs, from `b.addExecutable(.{ .name = "app", ... })` or
+    /// `b.addExecutable("app", ...)` before Zig 0.11.
+    fn get_exe_name(app: &App) -> Option<String> {
+        let build_zig = app.read_file("build.zig").ok()?;
+
+        Regex::new(r#"addExecutable\(\s*(?:\.\{\s*\.name\s*=\s*)?"([^"]+)""#)
+            .expect("BUG: Broken regex")
+            .captures(&build_zig)
+            .and_then(|c| c.get(1))
+            .map(|name| name.as_str().to_string())
+    }
+
     pub fn get_gyro_download_url() -> String {
         let gyro_supported_archs: Vec<&str> = vec!["x86_64", "aarch64", "i386"];
         if gyro_supported_archs.contains(&ARCH) {
@@ -73,3 +183,46 @@ impl ZigProvider {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_zig_version() -> Result<()> {
+        assert_eq!(
+            ZigProvider::get_zig_version(&App::new("./examples/zig")?, &Environment::default())?,
+            None
+        );
+        assert_eq!(
+            ZigProvider::get_zig_version(
+                &App::new("./examples/zig-zon")?,
+                &Environment::default()
+            )?,
+            Some("0.13".to_string())
+        );
+        assert_eq!(
+            ZigProvider::get_zig_version(
+                &App::new("./examples/zig")?,
+                &Environment::from_envs(vec!["NIXPACKS_ZIG_VERSION=0.11.0"])?
+            )?,
+            Some("0.11".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_exe_name() -> Result<()> {
+        assert_eq!(
+            ZigProvider::get_exe_name(&App::new("./examples/zig-gyro")?),
+            Some("zig-gyro".to_string())
+        );
+        assert_eq!(
+            ZigProvider::get_exe_name(&App::new("./examples/zig-zon")?),
+            Some("hello".to_string())
+        );
+
+        Ok(())
+    }
+}
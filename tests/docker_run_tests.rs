@@ -345,12 +345,57 @@ async fn test_elixir_no_ecto() {
     assert!(run_image(&name, None).await.contains("Hello from Phoenix"));
 }
 
+#[tokio::test]
+async fn test_elixir_release() {
+    let name = simple_build("./examples/elixir-release").await;
+    assert!(run_image(&name, None)
+        .await
+        .contains("Hello from Elixir release"));
+}
+
 #[tokio::test]
 async fn test_node() {
     let name = simple_build("./examples/node").await;
     assert!(run_image(&name, None).await.contains("Hello from Node"));
 }
 
+#[tokio::test]
+async fn test_declarative_provider() {
+    let name = simple_build("./examples/declarative-provider").await;
+    assert!(run_image(&name, None).await.contains("Hello from Lua"));
+}
+
+#[tokio::test]
+async fn test_devcontainer() {
+    let name = simple_build("./examples/devcontainer").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from the dev container"));
+    assert!(output.contains("Node v18"));
+}
+
+#[tokio::test]
+async fn test_heroku_app_json() {
+    let name = simple_build("./examples/heroku-app-json").await;
+    assert!(run_image(&name, None).await.contains("Hello from app.json"));
+}
+
+#[tokio::test]
+async fn test_node_tool_versions() {
+    let name = simple_build("./examples/node-tool-versions").await;
+    assert!(run_image(&name, None).await.contains("Hello from Node v18"));
+}
+
+#[tokio::test]
+async fn test_node_pnpm_workspace() {
+    let name = build_with_build_time_env_vars(
+        "./examples/node-pnpm-workspace",
+        vec!["NIXPACKS_PNPM_APP_NAME=api"],
+    )
+    .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from pnpm workspace"));
+}
+
 #[tokio::test]
 async fn test_node_nx_default_app() {
     let name = simple_build("./examples/node-nx").await;
@@ -606,6 +651,39 @@ async fn test_python() {
     assert!(output.contains("Hello from Python"));
 }
 
+#[tokio::test]
+async fn test_python_node_assets() {
+    let name = build_with_build_time_env_vars(
+        "./examples/python-node-assets",
+        vec!["NIXPACKS_COMBINE_PROVIDERS=1"],
+    )
+    .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from the Node asset pipeline"));
+    assert!(output.contains("Hello from Python"));
+}
+
+#[tokio::test]
+async fn test_prefer_dockerfile() {
+    let name = Uuid::new_v4().to_string();
+    create_docker_image(
+        "./examples/dockerfile-passthrough",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+        &DockerBuilderOptions {
+            name: Some(name.clone()),
+            quiet: true,
+            prefer_dockerfile: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from the app's Dockerfile"));
+}
+
 #[tokio::test]
 async fn test_python_procfile() {
     let name = simple_build("./examples/python-procfile").await;
@@ -701,6 +779,13 @@ async fn test_python_pdm() {
     assert!(output.contains("Hello from Python-PDM"));
 }
 
+#[tokio::test]
+async fn test_python_uv() {
+    let name = simple_build("./examples/python-uv").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-uv"));
+}
+
 #[tokio::test]
 async fn test_python_numpy() {
     let name = simple_build("./examples/python-numpy").await;
@@ -791,6 +876,21 @@ async fn test_go() {
     assert!(output.contains("Hello from Go"));
 }
 
+#[tokio::test]
+async fn test_go_cgo() {
+    let name = simple_build("./examples/go-cgo").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from cgo!"));
+}
+
+#[tokio::test]
+async fn test_go_static() {
+    let name =
+        build_with_build_time_env_vars("./examples/go-static", vec!["NIXPACKS_STATIC=1"]).await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from a static Go binary!"));
+}
+
 #[tokio::test]
 async fn test_go_custom_version() {
     let name = simple_build("./examples/go-custom-version").await;
@@ -812,6 +912,15 @@ async fn test_crystal() {
     assert!(output.contains("Hello from Crystal"));
 }
 
+#[tokio::test]
+async fn test_crystal_static() {
+    let name =
+        build_with_build_time_env_vars("./examples/crystal-static", vec!["NIXPACKS_STATIC=1"])
+            .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from a static Crystal binary!"));
+}
+
 #[tokio::test]
 async fn test_cowsay() {
     let name = Uuid::new_v4().to_string();
@@ -838,6 +947,38 @@ async fn test_staticfile() {
     assert!(output.contains("start worker process"));
 }
 
+#[tokio::test]
+async fn test_staticsite_hugo() {
+    let name = simple_build("./examples/staticsite-hugo").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_staticsite_zola_caddy() {
+    let name = build_with_build_time_env_vars(
+        "./examples/staticsite-zola",
+        vec!["NIXPACKS_STATIC_SITE_SERVER=caddy"],
+    )
+    .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("serving initial configuration"));
+}
+
+#[tokio::test]
+async fn test_staticsite_jekyll() {
+    let name = simple_build("./examples/staticsite-jekyll").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_staticsite_astro() {
+    let name = simple_build("./examples/staticsite-astro").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
 #[tokio::test]
 async fn test_swift() {
     let name = Uuid::new_v4().to_string();
@@ -859,6 +1000,13 @@ async fn test_swift() {
     assert!(output.contains("Hello from swift"));
 }
 
+#[tokio::test]
+async fn test_swift_hummingbird() {
+    let name = simple_build("./examples/swift-hummingbird").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Hummingbird"));
+}
+
 #[tokio::test]
 async fn test_dart() {
     let name = simple_build("./examples/dart").await;
@@ -894,6 +1042,13 @@ async fn test_zig() {
     assert!(output.contains("Hello from Zig"));
 }
 
+#[tokio::test]
+async fn test_zig_zon() {
+    let name = simple_build("./examples/zig-zon").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Zig 0.13"));
+}
+
 #[tokio::test]
 async fn test_zig_gyro() {
     let name = simple_build("./examples/zig-gyro").await;
@@ -902,6 +1057,20 @@ async fn test_zig_gyro() {
     assert!(output.contains("The URI scheme of GitHub is https."));
 }
 
+#[tokio::test]
+async fn test_r_shiny() {
+    let name = simple_build("./examples/r-shiny").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Shiny"));
+}
+
+#[tokio::test]
+async fn test_r_plumber() {
+    let name = simple_build("./examples/r-plumber").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Plumber"));
+}
+
 #[tokio::test]
 async fn test_ruby_2() {
     let name = simple_build("./examples/ruby-2").await;
@@ -1,4 +1,20 @@
-use nixpacks::{generate_build_plan, nixpacks::plan::generator::GeneratePlanOptions};
+use anyhow::Result;
+use nixpacks::{
+    detect_providers, generate_build_plan, generate_build_plan_with_providers,
+    nixpacks::{
+        app::App,
+        app_source::MemorySource,
+        environment::Environment,
+        error::NixpacksError,
+        plan::{
+            detect::ProviderDetection,
+            generator::GeneratePlanOptions,
+            phase::{Phase, StartPhase},
+            BuildPlan,
+        },
+    },
+    Provider,
+};
 use std::env::consts::ARCH;
 
 test_helper::generate_plan_tests!();
@@ -18,6 +34,242 @@ fn test_custom_plan_path() {
     assert_plan_snapshot!(plan);
 }
 
+struct MakefileProvider {}
+
+impl Provider for MakefileProvider {
+    fn name(&self) -> &str {
+        "make"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("package.json"))
+    }
+
+    fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        let mut build = Phase::build(Some("make".to_string()));
+        build.depends_on = None;
+        Ok(Some(BuildPlan::new(
+            &[build],
+            Some(StartPhase::new("make run")),
+        )))
+    }
+}
+
+#[test]
+fn test_embedded_provider() {
+    let plan = generate_build_plan_with_providers(
+        "./examples/node-npm",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+        &[&MakefileProvider {}],
+    )
+    .unwrap();
+
+    assert_eq!(
+        plan.variables.clone().unwrap().get("NIXPACKS_METADATA"),
+        Some(&"make".to_string())
+    );
+    assert_eq!(
+        plan.get_phase("build").unwrap().cmds,
+        Some(vec!["make".to_string()])
+    );
+    assert_eq!(plan.start_phase.unwrap().cmd, Some("make run".to_string()));
+}
+
+#[test]
+fn test_exact_plan() {
+    let plan = simple_gen_plan("./examples/node-npm");
+    let exact_plan = generate_build_plan(
+        "./examples/node-yarn",
+        Vec::new(),
+        &GeneratePlanOptions {
+            exact_plan: Some(plan.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(exact_plan, plan);
+}
+
+fn gen_plan_with_providers(path: &str, providers: &[&str]) -> BuildPlan {
+    generate_build_plan(
+        path,
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan {
+                providers: Some(providers.iter().map(ToString::to_string).collect()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_memory_source() {
+    let mut source = MemorySource::new();
+    for entry in walkdir::WalkDir::new("./examples/node-npm") {
+        let entry = entry.unwrap();
+        if entry.file_type().is_file() {
+            let path = entry.path().strip_prefix("./examples/node-npm").unwrap();
+            source = source.file(path.to_string_lossy(), std::fs::read(entry.path()).unwrap());
+        }
+    }
+
+    let plan = generate_build_plan(source, Vec::new(), &GeneratePlanOptions::default()).unwrap();
+    assert_eq!(
+        plan,
+        generate_build_plan(
+            "./examples/node-npm",
+            Vec::new(),
+            &GeneratePlanOptions::default()
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_unknown_provider_error() {
+    let error = generate_build_plan(
+        "./examples/node-npm",
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan {
+                providers: Some(vec!["cobolt".to_string()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(matches!(
+        error,
+        NixpacksError::UnknownProvider { provider } if provider == "cobolt"
+    ));
+}
+
+#[test]
+fn test_force_providers() {
+    let plan = gen_plan_with_providers("./examples/node-python", &["python", "node"]);
+    let metadata = plan.variables.unwrap()["NIXPACKS_METADATA"].clone();
+
+    assert!(metadata.starts_with("python"));
+    assert!(metadata.contains("node"));
+}
+
+#[test]
+fn test_disable_providers() {
+    let plan = gen_plan_with_providers("./examples/node-python", &["...", "!node"]);
+    let metadata = plan.variables.unwrap()["NIXPACKS_METADATA"].clone();
+
+    assert_eq!(metadata, "python");
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("./start.sh".to_string())
+    );
+}
+
+#[test]
+fn test_detect_providers() {
+    let detections = detect_providers(
+        "./examples/node-python",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        detections,
+        vec![
+            ProviderDetection {
+                name: "node".to_string(),
+                files: vec!["package.json".to_string()],
+                version: Some("nodejs-16_x".to_string()),
+                phases: vec![
+                    "setup".to_string(),
+                    "install".to_string(),
+                    "build".to_string()
+                ],
+            },
+            ProviderDetection {
+                name: "python".to_string(),
+                files: Vec::new(),
+                version: Some("python38".to_string()),
+                phases: vec!["python:setup".to_string(), "python:install".to_string()],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_custom_install_includes_all_files() {
+    let plan = generate_build_plan(
+        "./examples/node",
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan::new(
+                &[Phase::install(Some(
+                    "npm ci && node scripts/gen.js".to_string(),
+                ))],
+                None,
+            )),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let install = plan.get_phase("install").unwrap();
+    assert_eq!(
+        install.cmds,
+        Some(vec!["npm ci && node scripts/gen.js".to_string()])
+    );
+    assert_eq!(install.only_include_files, None);
+}
+
+#[test]
+fn test_app_dir() {
+    let plan = generate_build_plan(
+        "./examples/node-turborepo",
+        Vec::new(),
+        &GeneratePlanOptions {
+            app_dir: Some("apps/web".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let build = plan.get_phase("build").unwrap();
+    assert_eq!(
+        build.cmds,
+        Some(vec!["cd 'apps/web' && npm run build".to_string()])
+    );
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("cd 'apps/web' && npm run start".to_string())
+    );
+}
+
+#[test]
+fn test_app_dir_nx_project() {
+    let plan = generate_build_plan(
+        "./examples/node-nx",
+        Vec::new(),
+        &GeneratePlanOptions {
+            app_dir: Some("apps/express-app".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("node dist/apps/express-app/main.js".to_string())
+    );
+}
+
 #[test]
 fn test_custom_rust_version() {
     let plan = simple_gen_plan("./examples/rust-custom-version");